@@ -0,0 +1,687 @@
+use css::{Value, Unit};
+use style::StyledNode;
+use super::{BlockMargins, BoxType, CollapsedMargin, LayoutBox};
+use super::float::FloatContext;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) enum Alignment {
+    Start,
+    End,
+    Center,
+    Stretch,
+    Baseline,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+}
+
+/// An alignment keyword of `justify-content`, `align-content`,
+/// `align-items`, `align-self` or their `justify-*` counterparts in a grid,
+/// with `normal` and anything not understood as `default`.
+pub(super) fn alignment(style: &StyledNode, name: &str, default: Alignment) -> Alignment {
+    match &*keyword(style, name) {
+        "flex-start" | "start" | "self-start" | "left" => Alignment::Start,
+        "flex-end" | "end" | "self-end" | "right" => Alignment::End,
+        "center" => Alignment::Center,
+        "stretch" => Alignment::Stretch,
+        "baseline" => Alignment::Baseline,
+        "space-between" => Alignment::SpaceBetween,
+        "space-around" => Alignment::SpaceAround,
+        "space-evenly" => Alignment::SpaceEvenly,
+        _ => default,
+    }
+}
+
+pub(super) fn keyword(style: &StyledNode, name: &str) -> String {
+    match style.value(name) {
+        Some(Value::Keyword(keyword)) => keyword,
+        _ => String::new(),
+    }
+}
+
+/// Where the first of `count` things goes and the space between them when
+/// `free` space is distributed by `justify-content` or `align-content`.
+/// The `space-*` values fall back to `start` or `center` when there is no
+/// free space to distribute.
+pub(super) fn distribute(alignment: Alignment, free: f32, count: usize) -> (f32, f32) {
+    let count = count as f32;
+    match alignment {
+        Alignment::End => (free, 0.0),
+        Alignment::Center => (free / 2.0, 0.0),
+        Alignment::SpaceBetween if free > 0.0 && count > 1.0 => (0.0, free / (count - 1.0)),
+        Alignment::SpaceAround if free > 0.0 => (free / count / 2.0, free / count),
+        Alignment::SpaceEvenly if free > 0.0 => (free / (count + 1.0), free / (count + 1.0)),
+        Alignment::SpaceAround | Alignment::SpaceEvenly => (free / 2.0, 0.0),
+        _ => (0.0, 0.0),
+    }
+}
+
+/// The `row-gap` and `column-gap` of a container, with percentages of
+/// `width` and `height`.
+pub(super) fn gaps(style: &StyledNode, width: f32, height: f32) -> (f32, f32) {
+    let zero = Value::Length(0.0, Unit::Px);
+    (style.lookup("row-gap", &zero).to_px_of(height), style.lookup("column-gap", &zero).to_px_of(width))
+}
+
+/// The `flex-grow`, `flex-shrink` and `flex-basis` of an item.
+fn flex_factors(style: &StyledNode) -> (f32, f32, Value) {
+    let number = |name: &str, default: f32| match style.value(name) {
+        Some(Value::Number(n)) => n,
+        _ => default,
+    };
+    (number("flex-grow", 0.0), number("flex-shrink", 1.0), style.lookup("flex-basis", &Value::Keyword("auto".to_string())))
+}
+
+/// A flex item during layout. Sizes are of the content box, along the main
+/// axis unless they say otherwise.
+struct FlexItem {
+    /// The index of the item's box among the container's children.
+    index: usize,
+    grow: f32,
+    shrink: f32,
+    base_size: f32,
+    hypothetical_size: f32,
+    min_size: f32,
+    max_size: f32,
+    /// The used size, once flexible lengths are resolved.
+    size: f32,
+    frozen: bool,
+    /// The margins, other than `auto` ones, borders and padding along the
+    /// main axis and across it.
+    main_edges: f32,
+    cross_edges: f32,
+    /// Which margins are `auto`: at the main start and end, and the cross
+    /// start and end.
+    auto_margins: (bool, bool, bool, bool),
+    cross_size: f32,
+    /// Whether the cross size comes from `width` or `height`.
+    definite_cross: bool,
+    min_cross: f32,
+    max_cross: f32,
+    /// The distance from the cross start of the margin box to the first
+    /// baseline, in a row.
+    baseline: f32,
+    align: Alignment,
+}
+
+impl FlexItem {
+    fn outer_size(&self) -> f32 {
+        self.size + self.main_edges
+    }
+
+    fn outer_hypothetical_size(&self) -> f32 {
+        self.hypothetical_size + self.main_edges
+    }
+
+    fn outer_cross_size(&self) -> f32 {
+        self.cross_size + self.cross_edges
+    }
+
+    fn factor(&self, growing: bool) -> f32 {
+        if growing { self.grow } else { self.shrink }
+    }
+}
+
+/// Resolve the flexible lengths of the items on a line, as in section 9.7:
+/// grow or shrink them in proportion to their flex factors to fill the
+/// `available` space, freezing those that hit their minimum or maximum and
+/// sharing out the rest again until every item is frozen.
+fn resolve_flexible_lengths(items: &mut [FlexItem], line: &[usize], available: Option<f32>, gaps: f32) {
+    let available = match available {
+        Some(available) => available - gaps,
+        None => {
+            for &i in line {
+                items[i].size = items[i].hypothetical_size;
+            }
+            return;
+        }
+    };
+    let growing = line.iter().map(|&i| items[i].outer_hypothetical_size()).sum::<f32>() < available;
+    for &i in line {
+        let item = &mut items[i];
+        item.size = item.base_size;
+        item.frozen = item.factor(growing) == 0.0
+            || (growing && item.base_size > item.hypothetical_size)
+            || (!growing && item.base_size < item.hypothetical_size);
+        if item.frozen {
+            item.size = item.hypothetical_size;
+        }
+    }
+    let free_space = |items: &[FlexItem]| {
+        available - line.iter().map(|&i| items[i].outer_size()).sum::<f32>()
+    };
+    let initial_free_space = free_space(items);
+
+    while line.iter().any(|&i| !items[i].frozen) {
+        let unfrozen: Vec<usize> = line.iter().cloned().filter(|&i| !items[i].frozen).collect();
+        for &i in &unfrozen {
+            items[i].size = items[i].base_size;
+        }
+        let mut free = free_space(items);
+        let factors: f32 = unfrozen.iter().map(|&i| items[i].factor(growing)).sum();
+        if factors < 1.0 && (initial_free_space * factors).abs() < free.abs() {
+            free = initial_free_space * factors;
+        }
+
+        if growing {
+            for &i in &unfrozen {
+                items[i].size += free * items[i].grow / factors;
+            }
+        } else {
+            // Items shrink in proportion to their size as well.
+            let scaled: f32 = unfrozen.iter().map(|&i| items[i].shrink * items[i].base_size).sum();
+            if scaled > 0.0 {
+                for &i in &unfrozen {
+                    items[i].size += free * items[i].shrink * items[i].base_size / scaled;
+                }
+            }
+        }
+
+        let mut violation = 0.0;
+        let mut clamped = vec![];
+        for &i in &unfrozen {
+            let item = &mut items[i];
+            let size = item.size.min(item.max_size).max(item.min_size).max(0.0);
+            violation += size - item.size;
+            clamped.push((i, size - item.size));
+            item.size = size;
+        }
+        for (i, adjustment) in clamped {
+            items[i].frozen = violation == 0.0
+                || (violation > 0.0 && adjustment > 0.0)
+                || (violation < 0.0 && adjustment < 0.0);
+        }
+    }
+}
+
+/// The first baseline in a laid out box, from the first line box found in
+/// normal flow.
+pub(super) fn first_baseline(layout_box: &LayoutBox) -> Option<f32> {
+    match layout_box.lines.first() {
+        Some(line) => Some(line.rect.y + line.baseline),
+        None => layout_box.children.iter()
+            .filter(|child| !child.is_absolutely_positioned() && !child.is_floated())
+            .filter(|child| !matches!(child.box_type, BoxType::MarkerNode(..)))
+            .filter_map(first_baseline)
+            .next(),
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Whether this is an anonymous item wrapping nothing but white space,
+    /// which a flex or grid container leaves out.
+    pub(super) fn is_empty_anonymous_block(&self) -> bool {
+        matches!(self.box_type, BoxType::AnonymousBlock(_)) && !self.has_inline_content()
+    }
+
+    /// `size_limit` for a flex or grid item, which for an anonymous item is
+    /// always `None`.
+    pub(super) fn item_size(&self, name: &str, edges: f32, basis: Option<f32>) -> Option<f32> {
+        match self.box_type {
+            BoxType::AnonymousBlock(_) => None,
+            _ => self.size_limit(name, edges, basis),
+        }
+    }
+
+    /// Set the margins, borders and padding of a flex or grid item, with
+    /// percentages of the container's `width` and `auto` margins as zero.
+    /// Returns which margins are `auto`: top, right, bottom and left.
+    pub(super) fn set_item_edges(&mut self, width: f32) -> (bool, bool, bool, bool) {
+        let style = match self.box_type {
+            BoxType::AnonymousBlock(_) => return (false, false, false, false),
+            _ => self.get_style_node(),
+        };
+        let zero = Value::Length(0.0, Unit::Px);
+        let margin = |name: &str| match style.lookup(name, &zero) {
+            Value::Keyword(ref keyword) if keyword == "auto" => (0.0, true),
+            value => (value.to_px_of(width), false),
+        };
+        let (top, top_auto) = margin("margin-top");
+        let (right, right_auto) = margin("margin-right");
+        let (bottom, bottom_auto) = margin("margin-bottom");
+        let (left, left_auto) = margin("margin-left");
+
+        let d = &mut self.dimensions;
+        d.margin.top = top;
+        d.margin.right = right;
+        d.margin.bottom = bottom;
+        d.margin.left = left;
+        d.border.top = style.lookup("border-top-width", &zero).to_px();
+        d.border.right = style.lookup("border-right-width", &zero).to_px();
+        d.border.bottom = style.lookup("border-bottom-width", &zero).to_px();
+        d.border.left = style.lookup("border-left-width", &zero).to_px();
+        d.padding.top = style.lookup("padding-top", &zero).to_px_of(width);
+        d.padding.right = style.lookup("padding-right", &zero).to_px_of(width);
+        d.padding.bottom = style.lookup("padding-bottom", &zero).to_px_of(width);
+        d.padding.left = style.lookup("padding-left", &zero).to_px_of(width);
+        (top_auto, right_auto, bottom_auto, left_auto)
+    }
+
+    /// Lay out a flex or grid item at a content width and, if it is
+    /// definite, a content height, with the top left of its margin box at
+    /// the origin.
+    pub(super) fn layout_item(&mut self, width: f32, height: Option<f32>) {
+        {
+            let d = &mut self.dimensions;
+            d.content.x = d.margin.left + d.border.left + d.padding.left;
+            d.content.y = d.margin.top + d.border.top + d.padding.top;
+            d.content.width = width;
+            d.content.height = 0.0;
+        }
+        match self.box_type {
+            BoxType::AnonymousBlock(_) => self.layout_inline_children(None, &mut FloatContext::default()),
+            _ => {
+                self.layout_contents(height, &mut FloatContext::default());
+            }
+        }
+        if let Some(height) = height {
+            self.dimensions.content.height = height;
+        }
+    }
+
+    /// The flex items in the order they are laid out in, by `order` and
+    /// then source order, with their sizes worked out up to the
+    /// hypothetical main size.
+    fn collect_flex_items(&mut self, row: bool, main_size: Option<f32>, cross_size: Option<f32>) -> Vec<FlexItem> {
+        let container = self.get_style_node();
+        let width = self.dimensions.content.width;
+        let align_items = alignment(container, "align-items", Alignment::Stretch);
+        let (main_name, cross_name) = if row { ("width", "height") } else { ("height", "width") };
+
+        let mut items = vec![];
+        let mut orders = vec![];
+        for (index, child) in self.children.iter_mut().enumerate() {
+            if child.is_absolutely_positioned() || child.is_empty_anonymous_block() {
+                continue;
+            }
+            let (top, right, bottom, left) = child.set_item_edges(width);
+            let d = child.dimensions;
+            let horizontal = d.border.left + d.border.right + d.padding.left + d.padding.right;
+            let vertical = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+            let (main_bp, cross_bp) = if row { (horizontal, vertical) } else { (vertical, horizontal) };
+            let (main_edges, cross_edges) = if row {
+                (main_bp + d.margin.left + d.margin.right, cross_bp + d.margin.top + d.margin.bottom)
+            } else {
+                (main_bp + d.margin.top + d.margin.bottom, cross_bp + d.margin.left + d.margin.right)
+            };
+            let auto_margins = if row { (left, right, top, bottom) } else { (top, bottom, left, right) };
+
+            let (grow, shrink, basis, align, order) = match child.box_type {
+                BoxType::AnonymousBlock(_) => (0.0, 1.0, Value::Keyword("auto".to_string()), align_items, 0),
+                _ => {
+                    let style = child.get_style_node();
+                    let (grow, shrink, basis) = flex_factors(style);
+                    let order = match style.value("order") {
+                        Some(Value::Number(order)) => order as i32,
+                        _ => 0,
+                    };
+                    (grow, shrink, basis, alignment(style, "align-self", align_items), order)
+                }
+            };
+            let stretched = align == Alignment::Stretch && !auto_margins.2 && !auto_margins.3;
+
+            let min_cross = child.item_size(&format!("min-{}", cross_name), cross_bp, cross_size).unwrap_or(0.0);
+            let max_cross = child.item_size(&format!("max-{}", cross_name), cross_bp, cross_size)
+                .unwrap_or(f32::INFINITY);
+            let definite_cross = child.item_size(cross_name, cross_bp, cross_size);
+
+            // A column's items are as wide as they will be before their
+            // heights are measured.
+            let mut cross = definite_cross.unwrap_or(0.0);
+            let mut content_main = 0.0;
+            if !row {
+                if definite_cross.is_none() {
+                    let available = width - cross_edges;
+                    cross = if stretched { available.max(0.0) } else { child.shrink_to_fit(available) };
+                }
+                cross = cross.min(max_cross).max(min_cross);
+                child.layout_item(cross, None);
+                content_main = child.dimensions.content.height;
+            }
+            let (min_content, max_content) = if row { child.content_intrinsic_widths() } else { (content_main, content_main) };
+
+            let specified = child.item_size(main_name, main_bp, main_size);
+            let base_size = match basis {
+                Value::Length(..) | Value::Number(_) => Some(child.content_size(basis.to_px(), main_bp)),
+                Value::Percentage(p) => main_size.map(|size| child.content_size(p * size / 100.0, main_bp)),
+                Value::Keyword(ref keyword) if keyword == "auto" => specified,
+                _ => None,
+            }.unwrap_or(max_content);
+
+            let max_size = child.item_size(&format!("max-{}", main_name), main_bp, main_size).unwrap_or(f32::INFINITY);
+            // The automatic minimum size keeps an item from shrinking below
+            // its min-content size, unless it is a scroll container.
+            let scrolls = match child.box_type {
+                BoxType::AnonymousBlock(_) => false,
+                _ => !matches!(&*keyword(child.get_style_node(), "overflow"), "" | "visible" | "clip"),
+            };
+            let min_size = match child.item_size(&format!("min-{}", main_name), main_bp, main_size) {
+                Some(min_size) => min_size,
+                None if scrolls => 0.0,
+                None => specified.map_or(min_content, |size| size.min(min_content)).min(max_size),
+            };
+
+            orders.push(order);
+            items.push(FlexItem {
+                index,
+                grow,
+                shrink,
+                base_size,
+                hypothetical_size: base_size.min(max_size).max(min_size),
+                min_size,
+                max_size,
+                size: 0.0,
+                frozen: false,
+                main_edges,
+                cross_edges,
+                auto_margins,
+                cross_size: cross,
+                definite_cross: definite_cross.is_some(),
+                min_cross,
+                max_cross,
+                baseline: 0.0,
+                align,
+            });
+        }
+
+        // The sort is stable, so items of equal order keep source order.
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        order.sort_by_key(|&i| orders[i]);
+        let mut items: Vec<Option<FlexItem>> = items.into_iter().map(Some).collect();
+        order.into_iter().map(|i| items[i].take().unwrap()).collect()
+    }
+
+    /// Lay out the items of a flex container, whose width and position are
+    /// known, following section 9 of CSS Flexible Box Layout, and set the
+    /// content height to fit them.
+    pub(super) fn layout_flex_items(&mut self, definite_height: Option<f32>) -> BlockMargins {
+        let style = self.get_style_node();
+        let direction = keyword(style, "flex-direction");
+        let row = !direction.starts_with("column");
+        let reverse = direction.ends_with("-reverse");
+        let wrap = keyword(style, "flex-wrap");
+        let wraps = wrap == "wrap" || wrap == "wrap-reverse";
+        let content = self.dimensions.content;
+        let (main_size, cross_size) = if row {
+            (Some(content.width), definite_height)
+        } else {
+            (definite_height, Some(content.width))
+        };
+
+        let (row_gap, column_gap) = gaps(style, content.width, definite_height.unwrap_or(0.0));
+        let (main_gap, cross_gap) = if row { (column_gap, row_gap) } else { (row_gap, column_gap) };
+
+        for child in &mut self.children {
+            if child.is_absolutely_positioned() {
+                child.set_static_position(content.x, content.y);
+            }
+        }
+        let mut items = self.collect_flex_items(row, main_size, cross_size);
+
+        // Break the items into lines.
+        let mut lines: Vec<Vec<usize>> = vec![];
+        let mut line_size = 0.0;
+        for (i, item) in items.iter().enumerate() {
+            let size = item.outer_hypothetical_size();
+            match lines.last_mut() {
+                Some(line) if !wraps || line_size + main_gap + size <= main_size.unwrap_or(f32::INFINITY) => {
+                    line.push(i);
+                    line_size += main_gap + size;
+                }
+                _ => {
+                    lines.push(vec![i]);
+                    line_size = size;
+                }
+            }
+        }
+
+        for line in &lines {
+            let gaps = main_gap * (line.len() - 1) as f32;
+            resolve_flexible_lengths(&mut items, line, main_size, gaps);
+        }
+
+        // Lay out each item at its main size to find its cross size.
+        for item in &mut items {
+            let child = &mut self.children[item.index];
+            if row {
+                let height = if item.definite_cross { Some(item.cross_size) } else { None };
+                child.layout_item(item.size, height);
+                item.cross_size = child.dimensions.content.height.min(item.max_cross).max(item.min_cross);
+                let margin_box = child.dimensions.margin_box();
+                item.baseline = first_baseline(child)
+                    .unwrap_or(margin_box.y + margin_box.height - child.dimensions.margin.bottom) - margin_box.y;
+            } else {
+                child.layout_item(item.cross_size, Some(item.size));
+            }
+        }
+
+        // A single line fills the container, and otherwise each line is as
+        // big across as its items, with the ones aligned to the baseline
+        // lined up.
+        let line_cross_sizes: Vec<f32> = lines.iter().map(|line| {
+            let (mut ascent, mut descent, mut size) = (0.0f32, 0.0f32, 0.0f32);
+            for &i in line {
+                let item = &items[i];
+                if row && item.align == Alignment::Baseline {
+                    ascent = ascent.max(item.baseline);
+                    descent = descent.max(item.outer_cross_size() - item.baseline);
+                } else {
+                    size = size.max(item.outer_cross_size());
+                }
+            }
+            size.max(ascent + descent)
+        }).collect();
+        let cross_gaps = cross_gap * (lines.len().max(1) - 1) as f32;
+        let inner_cross = match cross_size {
+            Some(size) => size,
+            None => {
+                let size = line_cross_sizes.iter().sum::<f32>() + cross_gaps;
+                if row { self.clamp_height(size, None) } else { size }
+            }
+        };
+        let mut line_cross_sizes = if wraps { line_cross_sizes } else { vec![inner_cross] };
+
+        // Share out the space left over across by `align-content`.
+        let free = inner_cross - line_cross_sizes.iter().sum::<f32>() - cross_gaps;
+        let align_content = alignment(style, "align-content", Alignment::Stretch);
+        let (mut line_position, between) = if align_content == Alignment::Stretch {
+            if free > 0.0 {
+                let extra = free / line_cross_sizes.len() as f32;
+                for size in &mut line_cross_sizes {
+                    *size += extra;
+                }
+            }
+            (0.0, 0.0)
+        } else {
+            distribute(align_content, free, lines.len())
+        };
+        let line_positions: Vec<f32> = line_cross_sizes.iter().map(|size| {
+            let position = line_position;
+            line_position += size + cross_gap + between;
+            position
+        }).collect();
+
+        // Stretch items with an `auto` size across to fill their line.
+        for (line, &line_cross) in lines.iter().zip(&line_cross_sizes) {
+            for &i in line {
+                let item = &mut items[i];
+                if item.align != Alignment::Stretch || item.definite_cross
+                    || item.auto_margins.2 || item.auto_margins.3 {
+                    continue;
+                }
+                item.cross_size = (line_cross - item.cross_edges).min(item.max_cross).max(item.min_cross).max(0.0);
+                let child = &mut self.children[item.index];
+                if row {
+                    child.layout_item(item.size, Some(item.cross_size));
+                } else {
+                    child.layout_item(item.cross_size, Some(item.size));
+                }
+            }
+        }
+
+        let inner_main = match main_size {
+            Some(size) => size,
+            None => {
+                let longest = lines.iter().map(|line| {
+                    line.iter().map(|&i| items[i].outer_size()).sum::<f32>()
+                        + main_gap * (line.len() - 1) as f32
+                }).fold(0.0, f32::max);
+                self.clamp_height(longest, None)
+            }
+        };
+        let justify_content = alignment(style, "justify-content", Alignment::Start);
+
+        for ((line, &line_cross), &line_position) in lines.iter().zip(&line_cross_sizes).zip(&line_positions) {
+            let used = line.iter().map(|&i| items[i].outer_size()).sum::<f32>()
+                + main_gap * (line.len() - 1) as f32;
+            let free = inner_main - used;
+            // Free space goes to `auto` margins before `justify-content`.
+            let auto_margins: usize = line.iter()
+                .map(|&i| items[i].auto_margins.0 as usize + items[i].auto_margins.1 as usize)
+                .sum();
+            let auto_margin = if free > 0.0 && auto_margins > 0 { free / auto_margins as f32 } else { 0.0 };
+            let (mut position, between) = if auto_margins > 0 && free > 0.0 {
+                (0.0, 0.0)
+            } else {
+                distribute(justify_content, free, line.len())
+            };
+            let line_ascent = line.iter()
+                .filter(|&&i| items[i].align == Alignment::Baseline)
+                .map(|&i| items[i].baseline)
+                .fold(0.0, f32::max);
+
+            for &i in line {
+                let item = &items[i];
+                let (main_start, main_end) = (
+                    if item.auto_margins.0 { auto_margin } else { 0.0 },
+                    if item.auto_margins.1 { auto_margin } else { 0.0 },
+                );
+                let outer_main = item.outer_size() + main_start + main_end;
+                let mut main_position = position;
+                position += outer_main + main_gap + between;
+                if reverse {
+                    main_position = inner_main - main_position - outer_main;
+                }
+
+                let cross_free = line_cross - item.outer_cross_size();
+                let (cross_start, cross_end) = match item.auto_margins {
+                    (_, _, true, true) if cross_free > 0.0 => (cross_free / 2.0, cross_free / 2.0),
+                    (_, _, true, _) if cross_free > 0.0 => (cross_free, 0.0),
+                    (_, _, _, true) if cross_free > 0.0 => (0.0, cross_free),
+                    _ => (0.0, 0.0),
+                };
+                let offset = if item.auto_margins.2 || item.auto_margins.3 {
+                    0.0
+                } else {
+                    match item.align {
+                        Alignment::End => cross_free,
+                        Alignment::Center => cross_free / 2.0,
+                        Alignment::Baseline if row => line_ascent - item.baseline,
+                        _ => 0.0,
+                    }
+                };
+                let mut cross_position = line_position + offset;
+                if wrap == "wrap-reverse" {
+                    cross_position = inner_cross - cross_position - item.outer_cross_size() - cross_start - cross_end;
+                }
+
+                let child = &mut self.children[item.index];
+                let d = &mut child.dimensions;
+                let (dx, dy) = if row {
+                    d.margin.left += main_start;
+                    d.margin.right += main_end;
+                    d.margin.top += cross_start;
+                    d.margin.bottom += cross_end;
+                    (main_position + main_start, cross_position + cross_start)
+                } else {
+                    d.margin.top += main_start;
+                    d.margin.bottom += main_end;
+                    d.margin.left += cross_start;
+                    d.margin.right += cross_end;
+                    (cross_position + cross_start, main_position + main_start)
+                };
+                child.translate(content.x + dx, content.y + dy);
+            }
+        }
+
+        let d = &mut self.dimensions;
+        d.content.height = if row { inner_cross } else { inner_main };
+        BlockMargins {
+            top: CollapsedMargin::new(d.margin.top),
+            bottom: CollapsedMargin::new(d.margin.bottom),
+            collapses_through: false,
+        }
+    }
+
+    /// The min-content and max-content widths of a flex container's content:
+    /// the items side by side in a row, or the widest of them in a column.
+    pub(super) fn flex_intrinsic_widths(&self) -> (f32, f32) {
+        let style = self.get_style_node();
+        let row = !keyword(style, "flex-direction").starts_with("column");
+        let wraps = keyword(style, "flex-wrap").starts_with("wrap");
+        let gap = gaps(style, 0.0, 0.0).1;
+        let items: Vec<(f32, f32)> = self.children.iter()
+            .filter(|child| !child.is_absolutely_positioned() && !child.is_empty_anonymous_block())
+            .map(LayoutBox::intrinsic_widths)
+            .collect();
+        let gaps = gap * (items.len().max(1) - 1) as f32;
+        let widest_min = items.iter().map(|w| w.0).fold(0.0, f32::max);
+        let widest_max = items.iter().map(|w| w.1).fold(0.0, f32::max);
+        match (row, wraps) {
+            (true, true) => (widest_min, items.iter().map(|w| w.1).sum::<f32>() + gaps),
+            (true, false) => (items.iter().map(|w| w.0).sum::<f32>() + gaps, items.iter().map(|w| w.1).sum::<f32>() + gaps),
+            (false, _) => (widest_min, widest_max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use boxes::tests::layout;
+    use boxes::Rect;
+
+    const CSS: &str = "body { margin: 0 } .f { display: flex; width: 400px } .f > div { height: 10px }";
+
+    fn rects(html: &str, ids: &[&str]) -> Vec<Rect> {
+        let rects = layout(html, CSS, 800.0);
+        ids.iter().map(|id| rects[*id]).collect()
+    }
+
+    fn spans(html: &str, ids: &[&str]) -> Vec<(f32, f32)> {
+        rects(html, ids).iter().map(|rect| (rect.x, rect.width)).collect()
+    }
+
+    #[test]
+    fn grow() {
+        let html = "<div class=f><div id=a style='flex: 1'></div><div id=b style='flex: 2 1 0'></div>\
+                    <div id=c style='width: 100px'></div></div>";
+        assert_eq!(spans(html, &["a", "b", "c"]), [(0.0, 100.0), (100.0, 200.0), (300.0, 100.0)]);
+        // Free space is shared after the bases and the gaps.
+        let html = "<div class=f style='gap: 20px'><div id=a style='flex: 1 1 100px'></div>\
+                    <div id=b style='flex: 1 1 20px'></div></div>";
+        assert_eq!(spans(html, &["a", "b"]), [(0.0, 230.0), (250.0, 150.0)]);
+    }
+
+    #[test]
+    fn shrink() {
+        // Items shrink in proportion to their shrink factors times their bases.
+        let html = "<div class=f><div id=a style='flex-basis: 300px'></div>\
+                    <div id=b style='flex: 0 1.5 200px'></div></div>";
+        assert_eq!(spans(html, &["a", "b"]), [(0.0, 250.0), (250.0, 150.0)]);
+        let html = "<div class=f><div id=a style='width: 300px; flex-shrink: 0'></div>\
+                    <div id=b style='width: 200px'></div></div>";
+        assert_eq!(spans(html, &["a", "b"]), [(0.0, 300.0), (300.0, 100.0)]);
+    }
+
+    #[test]
+    fn wrap_and_column() {
+        let html = "<div class=f style='flex-wrap: wrap'><div id=a style='width: 300px'></div>\
+                    <div id=b style='width: 300px'></div></div>";
+        let wrapped = rects(html, &["a", "b"]);
+        assert_eq!((wrapped[0].x, wrapped[0].y, wrapped[1].x, wrapped[1].y), (0.0, 0.0, 0.0, 10.0));
+        let html = "<div class=f style='flex-direction: column; height: 100px'><div id=a style='flex: 1'></div>\
+                    <div id=b style='flex: 3'></div></div>";
+        let rects = rects(html, &["a", "b"]);
+        assert_eq!((rects[0].height, rects[1].y, rects[1].height, rects[1].width), (25.0, 25.0, 75.0, 400.0));
+    }
+}
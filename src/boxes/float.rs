@@ -0,0 +1,188 @@
+use style::{Clear, Float};
+use super::{BoxType, LayoutBox, Rect, TablePart};
+
+/// The floats placed so far in a block formatting context, as margin boxes
+/// in the coordinates of the page.
+#[derive(Default)]
+pub(super) struct FloatContext {
+    floats: Vec<(Float, Rect)>,
+}
+
+impl FloatContext {
+    pub fn len(&self) -> usize {
+        self.floats.len()
+    }
+
+    /// Move the floats placed since there were `start` of them, along with
+    /// the boxes they belong to.
+    pub fn translate_from(&mut self, start: usize, dy: f32) {
+        for &mut (_, ref mut rect) in &mut self.floats[start..] {
+            rect.y += dy;
+        }
+    }
+
+    /// The part of `space` left between the floats on either side over the
+    /// band from `y` to `y + height`.
+    pub fn available(&self, space: Rect, y: f32, height: f32) -> Rect {
+        let (mut left, mut right) = (space.x, space.x + space.width);
+        for &(side, rect) in self.overlapping(y, height) {
+            match side {
+                Float::Left => left = left.max(rect.x + rect.width),
+                Float::Right => right = right.min(rect.x),
+                Float::None => {}
+            }
+        }
+        Rect { x: left, y, width: (right - left).max(0.0), height }
+    }
+
+    /// The first bottom edge of a float over the band from `y` to
+    /// `y + height`, where the space beside the floats next changes.
+    pub fn next_bottom(&self, y: f32, height: f32) -> Option<f32> {
+        self.overlapping(y, height)
+            .map(|&(_, rect)| rect.y + rect.height)
+            .fold(None, |lowest: Option<f32>, bottom| Some(lowest.map_or(bottom, |l| l.min(bottom))))
+    }
+
+    fn overlapping(&self, y: f32, height: f32) -> impl Iterator<Item = &(Float, Rect)> {
+        self.floats.iter()
+            .filter(move |&&(_, rect)| rect.y + rect.height > y && (rect.y <= y || rect.y < y + height))
+    }
+
+    /// The bottom edge of the lowest float that `clear` moves a box below.
+    pub fn clearance(&self, clear: Clear) -> Option<f32> {
+        self.floats.iter()
+            .filter(|&&(side, _)| clear.clears(side))
+            .map(|&(_, rect)| rect.y + rect.height)
+            .fold(None, |lowest: Option<f32>, bottom| Some(lowest.map_or(bottom, |l| l.max(bottom))))
+    }
+
+    /// The bottom edge of the lowest float.
+    pub fn bottom(&self) -> Option<f32> {
+        self.clearance(Clear::Both)
+    }
+
+    /// Place a float with a margin box of `width` by `height` as high as it
+    /// fits within `space`, but not above `y` or the top of an earlier
+    /// float, and as far to its side as it can go. A float too wide to fit
+    /// anywhere goes below the other floats.
+    pub fn place(&mut self, side: Float, width: f32, height: f32, y: f32, space: Rect) -> Rect {
+        let mut y = match self.floats.last() {
+            Some(&(_, last)) => y.max(last.y),
+            None => y,
+        };
+        let mut room = self.available(space, y, height);
+        while room.width < width {
+            match self.next_bottom(y, height) {
+                Some(bottom) => y = bottom,
+                None => break,
+            }
+            room = self.available(space, y, height);
+        }
+        let x = match side {
+            Float::Right => room.x + room.width - width,
+            _ => room.x,
+        };
+        let rect = Rect { x, y, width, height };
+        self.floats.push((side, rect));
+        rect
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Whether this box is a float, taken out of the flow and placed by
+    /// `place_float`.
+    pub(super) fn is_floated(&self) -> bool {
+        match self.box_type {
+            BoxType::BlockNode(node) | BoxType::ReplacedNode(node) | BoxType::FlexNode(node)
+            | BoxType::GridNode(node) | BoxType::TableNode(TablePart::Table, node) => node.float() != Float::None,
+            _ => false,
+        }
+    }
+
+    /// Move a laid out float to the highest place within `space` it fits at
+    /// or below `y`, and add it to `floats`.
+    pub(super) fn place_float(&mut self, floats: &mut FloatContext, space: Rect, y: f32) {
+        let style = self.get_style_node();
+        let y = floats.clearance(style.clear()).map_or(y, |bottom| y.max(bottom));
+        let margin_box = self.dimensions.margin_box();
+        let rect = floats.place(style.float(), margin_box.width, margin_box.height, y, space);
+        self.translate(rect.x - margin_box.x, rect.y - margin_box.y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use boxes::LayoutBox;
+    use boxes::tests::{layout, with_layout};
+
+    const CSS: &str = "body { margin: 0 } div { height: 10px } .l { float: left } .r { float: right }";
+
+    fn rect(html: &str, id: &str) -> (f32, f32, f32, f32) {
+        let rect = layout(html, CSS, 400.0)[id];
+        (rect.x, rect.y, rect.width, rect.height)
+    }
+
+    /// Where the text of each line in the layout of `html` starts and ends.
+    fn lines(html: &str) -> Vec<(f32, f32)> {
+        fn collect(layout_box: &LayoutBox, lines: &mut Vec<(f32, f32)>) {
+            for line in &layout_box.lines {
+                let (first, last) = (&line.fragments[0].rect, &line.fragments[line.fragments.len() - 1].rect);
+                lines.push((first.x, last.x + last.width));
+            }
+            for child in &layout_box.children {
+                collect(child, lines);
+            }
+        }
+        with_layout(html, CSS, 400.0, |root| {
+            let mut lines = vec![];
+            collect(root, &mut lines);
+            lines
+        })
+    }
+
+    #[test]
+    fn floats_go_to_their_side() {
+        let html = "<div id=a class=l style='width: 50px'></div><div id=b class=l style='width: 50px'></div>\
+                    <div id=c class=r style='width: 50px'></div><div id=d class=r style='width: 50px'></div>";
+        assert_eq!(rect(html, "a"), (0.0, 0.0, 50.0, 10.0));
+        assert_eq!(rect(html, "b"), (50.0, 0.0, 50.0, 10.0));
+        assert_eq!(rect(html, "c"), (350.0, 0.0, 50.0, 10.0));
+        assert_eq!(rect(html, "d"), (300.0, 0.0, 50.0, 10.0));
+        // A float that doesn't fit beside the others goes below them, and a
+        // left float below any earlier left float it isn't to the right of.
+        let html = "<div class=l style='width: 150px'></div><div class=l style='width: 150px; height: 20px'></div>\
+                    <div id=a class=l style='width: 150px'></div>";
+        assert_eq!(rect(html, "a"), (0.0, 20.0, 150.0, 10.0));
+        // Floats don't take up space in the flow.
+        assert_eq!(rect("<div class=l style='width: 50px'></div><div id=a></div>", "a"), (0.0, 0.0, 400.0, 10.0));
+    }
+
+    #[test]
+    fn line_boxes_are_shortened() {
+        let html = "<div class=l style='width: 100px; height: 20px'></div><p style='margin: 0'>x<br>x<br>x</p>";
+        let starts: Vec<f32> = lines(html).iter().map(|line| line.0).collect();
+        assert_eq!(starts, [100.0, 100.0, 0.0]);
+        let html = "<div class=r style='width: 100px; height: 30px'></div>\
+                    <p style='margin: 0; text-align: right'>x</p>";
+        assert_eq!(lines(html)[0].1, 300.0);
+    }
+
+    #[test]
+    fn clearance() {
+        let html = "<div class=l style='height: 30px'></div><div class=r style='height: 50px'></div>\
+                    <div id=a style='clear: left'></div>";
+        assert_eq!(rect(html, "a").1, 30.0);
+        assert_eq!(rect(&html.replace("clear: left", "clear: right"), "a").1, 50.0);
+        assert_eq!(rect(&html.replace("clear: left", "clear: both"), "a").1, 50.0);
+        assert_eq!(rect(&html.replace("clear: left", "clear: none"), "a").1, 0.0);
+    }
+
+    #[test]
+    fn formatting_context_roots_contain_their_floats() {
+        let html = "<section id=s style='overflow: hidden'><div class=l style='width: 50px; height: 40px'></div>\
+                    </section>";
+        assert_eq!(rect(html, "s").3, 40.0);
+        assert_eq!(rect(&html.replace("overflow: hidden", "display: flow-root"), "s").3, 40.0);
+        assert_eq!(rect(&html.replace("overflow: hidden", ""), "s").3, 0.0);
+    }
+}
@@ -0,0 +1,824 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use css::{Value, Unit};
+use style::StyledNode;
+use super::{BlockMargins, BoxType, CollapsedMargin, LayoutBox};
+use super::flex::{alignment, distribute, first_baseline, gaps, Alignment};
+
+/// One end of the range a track is sized within.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Breadth {
+    Fixed(f32),
+    /// A share of the free space, in `fr`.
+    Flex(f32),
+    Auto,
+    MinContent,
+    MaxContent,
+}
+
+/// The sizing function of a track: the least and the most it may take.
+#[derive(Clone, Copy, Debug)]
+struct TrackSize {
+    min: Breadth,
+    max: Breadth,
+}
+
+const AUTO_TRACK: TrackSize = TrackSize { min: Breadth::Auto, max: Breadth::Auto };
+
+fn breadth(value: &Value, basis: Option<f32>) -> Breadth {
+    match *value {
+        Value::Length(fr, Unit::Fr) => Breadth::Flex(fr),
+        Value::Length(..) | Value::Number(_) => Breadth::Fixed(value.to_px()),
+        Value::Percentage(p) => basis.map_or(Breadth::Auto, |basis| Breadth::Fixed(p * basis / 100.0)),
+        Value::Keyword(ref keyword) if keyword == "min-content" => Breadth::MinContent,
+        Value::Keyword(ref keyword) if keyword == "max-content" => Breadth::MaxContent,
+        _ => Breadth::Auto,
+    }
+}
+
+/// A track size like `100px`, `1fr` or `minmax(50px, 1fr)`, with
+/// percentages of `basis`. A flexible size alone is `minmax(auto, 1fr)`.
+fn track_size(value: &Value, basis: Option<f32>) -> TrackSize {
+    match *value {
+        Value::Function(ref name, ref arguments) if name == "minmax" && arguments.len() == 2 => {
+            let min = match breadth(&arguments[0], basis) {
+                Breadth::Flex(_) => Breadth::Auto,
+                min => min,
+            };
+            TrackSize { min, max: breadth(&arguments[1], basis) }
+        }
+        _ => match breadth(value, basis) {
+            max @ Breadth::Flex(_) => TrackSize { min: Breadth::Auto, max },
+            size => TrackSize { min: size, max: size },
+        },
+    }
+}
+
+/// The components of a value that may be a list, with `none` as no
+/// components at all.
+fn components(value: Option<Value>) -> Vec<Value> {
+    match value {
+        Some(Value::List(values)) => values,
+        Some(Value::Keyword(ref keyword)) if keyword == "none" => vec![],
+        Some(value) => vec![value],
+        None => vec![],
+    }
+}
+
+/// The explicit tracks of `grid-template-columns` or `grid-template-rows`,
+/// with percentages of `basis`, and the range of tracks repeated by
+/// `auto-fit`, which collapse if no item is placed in them. An `auto-fill`
+/// or `auto-fit` repetition is repeated as many times as fits in `basis`
+/// with the `gap` between tracks, or once if `basis` is indefinite.
+fn template_tracks(value: Option<Value>, basis: Option<f32>, gap: f32) -> (Vec<TrackSize>, Option<Range<usize>>) {
+    let mut tracks = vec![];
+    let mut auto_repeat = None;
+    for component in components(value) {
+        match component {
+            Value::Function(ref name, ref arguments) if name == "repeat" && arguments.len() == 2 => {
+                let repeated: Vec<TrackSize> = components(Some(arguments[1].clone())).iter()
+                    .map(|value| track_size(value, basis))
+                    .collect();
+                match arguments[0] {
+                    Value::Number(count) => {
+                        for _ in 0..count.max(1.0) as usize {
+                            tracks.extend(&repeated);
+                        }
+                    }
+                    Value::Keyword(ref keyword) => auto_repeat = Some((tracks.len(), repeated, keyword == "auto-fit")),
+                    _ => {}
+                }
+            }
+            value => tracks.push(track_size(&value, basis)),
+        }
+    }
+    let (position, repeated, fit) = match auto_repeat {
+        Some(auto_repeat) => auto_repeat,
+        None => return (tracks, None),
+    };
+
+    // Each track counts as its fixed maximum, or else its fixed minimum.
+    let fixed = |track: &TrackSize| match (track.max, track.min) {
+        (Breadth::Fixed(size), _) | (_, Breadth::Fixed(size)) => size,
+        _ => 0.0,
+    };
+    let repetition = repeated.iter().map(fixed).sum::<f32>();
+    let used = |count: usize| {
+        let n = tracks.len() + count * repeated.len();
+        tracks.iter().map(fixed).sum::<f32>() + count as f32 * repetition + gap * (n.max(1) - 1) as f32
+    };
+    let mut count = 1;
+    if let Some(basis) = basis {
+        if repetition + gap > 0.0 {
+            while used(count + 1) <= basis {
+                count += 1;
+            }
+        }
+    }
+    let inserted: Vec<TrackSize> = repeated.iter().cycle().take(count * repeated.len()).cloned().collect();
+    let range = position..position + inserted.len();
+    tracks.splice(position..position, inserted);
+    (tracks, if fit { Some(range) } else { None })
+}
+
+/// Named grid areas, with the lines each starts and ends at in rows and in
+/// columns.
+type Areas = HashMap<String, [(usize, usize); 2]>;
+
+/// The areas named by `grid-template-areas`, and the number of rows and
+/// columns the template makes up.
+fn template_areas(value: Option<Value>) -> (Areas, [usize; 2]) {
+    let mut areas = Areas::new();
+    let mut counts = [0, 0];
+    for (row, value) in components(value).into_iter().enumerate() {
+        if let Value::String(cells) = value {
+            counts[0] = row + 1;
+            for (column, name) in cells.split_whitespace().enumerate() {
+                counts[1] = counts[1].max(column + 1);
+                // A run of dots is a cell that belongs to no area.
+                if name.chars().all(|c| c == '.') {
+                    continue;
+                }
+                let area = areas.entry(name.to_string()).or_insert([(row, row + 1), (column, column + 1)]);
+                area[0] = (area[0].0.min(row), area[0].1.max(row + 1));
+                area[1] = (area[1].0.min(column), area[1].1.max(column + 1));
+            }
+        }
+    }
+    (areas, counts)
+}
+
+/// Where an item starts or ends along an axis, from a property like
+/// `grid-row-start`.
+#[derive(Clone, Debug)]
+enum GridLine {
+    Auto,
+    /// A line number, counting back from the end if negative.
+    Line(i32),
+    Span(usize),
+    /// The start or end line of a named area, or the line `name-start` or
+    /// `name-end`.
+    Named(String),
+}
+
+fn grid_line(value: &Value) -> GridLine {
+    match *value {
+        Value::Number(n) if n != 0.0 => GridLine::Line(n as i32),
+        Value::List(ref parts) if parts.contains(&Value::Keyword("span".to_string())) => {
+            let count = parts.iter().filter_map(|part| match *part {
+                Value::Number(n) => Some(n.max(1.0) as usize),
+                _ => None,
+            }).next();
+            GridLine::Span(count.unwrap_or(1))
+        }
+        Value::Keyword(ref keyword) if keyword == "auto" || keyword == "span" => GridLine::Auto,
+        Value::Keyword(ref name) => GridLine::Named(name.clone()),
+        _ => GridLine::Auto,
+    }
+}
+
+/// The start and end lines of an item along an axis, 0 for rows and 1 for
+/// columns, from `grid-row-start` and the like.
+fn placement(style: &StyledNode, axis: usize) -> (GridLine, GridLine) {
+    let name = if axis == 0 { "row" } else { "column" };
+    let line = |edge: &str| style.value(&format!("grid-{}-{}", name, edge)).map_or(GridLine::Auto, |value| grid_line(&value));
+    (line("start"), line("end"))
+}
+
+/// The index of a line, counting from zero, along an axis with `explicit`
+/// tracks, or `None` if the line is `auto`, a span, or names no area.
+fn resolve_line(line: &GridLine, areas: &Areas, axis: usize, explicit: usize, start: bool) -> Option<usize> {
+    match *line {
+        GridLine::Line(n) if n > 0 => Some(n as usize - 1),
+        GridLine::Line(n) => Some((explicit as i32 + 1 + n).max(0) as usize),
+        GridLine::Named(ref name) => {
+            let (area, start) = if name.ends_with("-start") {
+                (&name[..name.len() - 6], true)
+            } else if name.ends_with("-end") {
+                (&name[..name.len() - 4], false)
+            } else {
+                (&name[..], start)
+            };
+            areas.get(area).map(|area| if start { area[axis].0 } else { area[axis].1 })
+        }
+        _ => None,
+    }
+}
+
+/// A grid item during layout.
+struct GridItem {
+    /// The index of the item's box among the container's children.
+    index: usize,
+    /// The first row and column the item is in, once it is placed, and how
+    /// many rows and columns it spans.
+    start: [Option<usize>; 2],
+    span: [usize; 2],
+    /// Which margins are `auto`: top, right, bottom and left.
+    auto_margins: (bool, bool, bool, bool),
+    justify: Alignment,
+    align: Alignment,
+    /// The content width, once the columns are sized.
+    width: f32,
+}
+
+impl GridItem {
+    fn tracks(&self, axis: usize) -> Range<usize> {
+        let start = self.start[axis].unwrap();
+        start..start + self.span[axis]
+    }
+}
+
+fn fits(occupied: &HashSet<[usize; 2]>, start: [usize; 2], span: [usize; 2]) -> bool {
+    (0..span[0]).all(|row| (0..span[1]).all(|column| !occupied.contains(&[start[0] + row, start[1] + column])))
+}
+
+fn occupy(occupied: &mut HashSet<[usize; 2]>, start: [usize; 2], span: [usize; 2]) {
+    for row in 0..span[0] {
+        for column in 0..span[1] {
+            occupied.insert([start[0] + row, start[1] + column]);
+        }
+    }
+}
+
+/// Place the items without a definite row and column, following the
+/// auto-placement algorithm of section 8.5, filling rows or in `column`
+/// flow filling columns, and if `dense` going back to fill holes. Returns
+/// the number of rows and columns in the grid, at least the `explicit`
+/// ones.
+fn place_items(items: &mut [GridItem], explicit: [usize; 2], column_flow: bool, dense: bool) -> [usize; 2] {
+    let (major, minor) = if column_flow { (1, 0) } else { (0, 1) };
+    let at = |m: usize, n: usize| {
+        let mut position = [0, 0];
+        position[major] = m;
+        position[minor] = n;
+        position
+    };
+    let mut occupied = HashSet::new();
+    for item in items.iter().filter(|item| item.start[0].is_some() && item.start[1].is_some()) {
+        occupy(&mut occupied, [item.start[0].unwrap(), item.start[1].unwrap()], item.span);
+    }
+
+    // Items locked to a row, or a column in column flow, go in the first
+    // place along it they fit.
+    let mut cursors = HashMap::new();
+    for item in items.iter_mut().filter(|item| item.start[major].is_some() && item.start[minor].is_none()) {
+        let m = item.start[major].unwrap();
+        let mut n = if dense { 0 } else { *cursors.get(&m).unwrap_or(&0) };
+        while !fits(&occupied, at(m, n), item.span) {
+            n += 1;
+        }
+        item.start[minor] = Some(n);
+        occupy(&mut occupied, at(m, n), item.span);
+        cursors.insert(m, n + item.span[minor]);
+    }
+
+    let minor_count = items.iter()
+        .map(|item| item.start[minor].unwrap_or(0) + item.span[minor])
+        .fold(explicit[minor], usize::max);
+    let mut cursor = (0, 0);
+    for item in items.iter_mut().filter(|item| item.start[major].is_none()) {
+        if dense {
+            cursor = (0, 0);
+        }
+        match item.start[minor] {
+            Some(n) => {
+                if n < cursor.1 {
+                    cursor.0 += 1;
+                }
+                cursor.1 = n;
+                while !fits(&occupied, at(cursor.0, n), item.span) {
+                    cursor.0 += 1;
+                }
+            }
+            None => loop {
+                if cursor.1 + item.span[minor] > minor_count {
+                    cursor = (cursor.0 + 1, 0);
+                } else if fits(&occupied, at(cursor.0, cursor.1), item.span) {
+                    break;
+                } else {
+                    cursor.1 += 1;
+                }
+            },
+        }
+        item.start[major] = Some(cursor.0);
+        item.start[minor] = Some(cursor.1);
+        occupy(&mut occupied, at(cursor.0, cursor.1), item.span);
+        cursor.1 += item.span[minor];
+    }
+
+    let mut counts = explicit;
+    for item in items.iter() {
+        for (axis, count) in counts.iter_mut().enumerate() {
+            *count = (*count).max(item.start[axis].unwrap() + item.span[axis]);
+        }
+    }
+    counts
+}
+
+/// A track being sized: its base size, and its growth limit, which is
+/// infinite until the items in it have been measured.
+struct Track {
+    size: TrackSize,
+    base: f32,
+    limit: f32,
+}
+
+impl Track {
+    fn flex(&self) -> Option<f32> {
+        match self.size.max {
+            Breadth::Flex(factor) => Some(factor),
+            _ => None,
+        }
+    }
+}
+
+/// The size of `1fr` when flexible tracks share out `space` with the rest,
+/// as in section 12.7.1. A flexible track whose base size is more than its
+/// share is treated as inflexible.
+fn find_fr_size(tracks: &[Track], space: f32) -> f32 {
+    let mut inflexible = vec![false; tracks.len()];
+    loop {
+        let mut leftover = space;
+        let mut factors = 0.0;
+        for (track, &inflexible) in tracks.iter().zip(&inflexible) {
+            match track.flex() {
+                Some(factor) if !inflexible => factors += factor,
+                _ => leftover -= track.base,
+            }
+        }
+        let fr = leftover.max(0.0) / f32::max(factors, 1.0);
+        let mut changed = false;
+        for (track, inflexible) in tracks.iter().zip(&mut inflexible) {
+            match track.flex() {
+                Some(factor) if !*inflexible && track.base > fr * factor => {
+                    *inflexible = true;
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+        if !changed {
+            return fr;
+        }
+    }
+}
+
+/// Size the tracks along one axis, following the track sizing algorithm of
+/// section 12 in simplified form. `contributions` are the first track, the
+/// number of tracks spanned, and the min-content and max-content
+/// contributions of each item. Without `available` space the tracks take
+/// their max-content sizes; with it, free space goes to tracks up to their
+/// growth limits, then to flexible tracks, and if `stretch` then to `auto`
+/// tracks.
+fn size_tracks(sizes: &[TrackSize], contributions: &[(usize, usize, f32, f32)], available: Option<f32>,
+               gap: f32, stretch: bool) -> Vec<f32> {
+    let mut tracks: Vec<Track> = sizes.iter().map(|&size| {
+        let base = match size.min {
+            Breadth::Fixed(min) => min,
+            _ => 0.0,
+        };
+        let limit = match size.max {
+            Breadth::Fixed(max) => max.max(base),
+            Breadth::Flex(_) => base,
+            _ => f32::INFINITY,
+        };
+        Track { size, base, limit }
+    }).collect();
+    let gaps = gap * (tracks.len().max(1) - 1) as f32;
+
+    // Grow the intrinsically sized tracks to fit the items in them, items
+    // spanning fewer tracks first. An item spanning several tracks, one of
+    // them flexible, is left to the flexible track.
+    let mut ordered: Vec<&(usize, usize, f32, f32)> = contributions.iter().collect();
+    ordered.sort_by_key(|contribution| contribution.1);
+    for &&(start, span, min, max) in &ordered {
+        let range = start..start + span;
+        if span > 1 && tracks[range.clone()].iter().any(|track| track.flex().is_some()) {
+            continue;
+        }
+        let spanned_gaps = gap * (span - 1) as f32;
+
+        let growing: Vec<usize> = range.clone()
+            .filter(|&i| !matches!(tracks[i].size.min, Breadth::Fixed(_)))
+            .collect();
+        let wanted = if span == 1 && tracks[start].size.min == Breadth::MaxContent { max } else { min };
+        let extra = wanted - tracks[range.clone()].iter().map(|track| track.base).sum::<f32>() - spanned_gaps;
+        if extra > 0.0 && !growing.is_empty() {
+            for &i in &growing {
+                tracks[i].base += extra / growing.len() as f32;
+            }
+        }
+
+        let growing: Vec<usize> = range.clone()
+            .filter(|&i| matches!(tracks[i].size.max, Breadth::Auto | Breadth::MinContent | Breadth::MaxContent))
+            .collect();
+        let wanted = if span == 1 && tracks[start].size.max == Breadth::MinContent { min } else { max };
+        let limit = |track: &Track| if track.limit.is_finite() { track.limit } else { track.base };
+        let extra = wanted - tracks[range].iter().map(limit).sum::<f32>() - spanned_gaps;
+        if extra > 0.0 && !growing.is_empty() {
+            for &i in &growing {
+                tracks[i].limit = limit(&tracks[i]) + extra / growing.len() as f32;
+            }
+        }
+    }
+    for track in &mut tracks {
+        if track.limit.is_infinite() {
+            track.limit = track.base;
+        }
+        track.limit = track.limit.max(track.base);
+    }
+
+    match available {
+        Some(available) => {
+            let mut free = available - gaps - tracks.iter().map(|track| track.base).sum::<f32>();
+            loop {
+                let growing: Vec<usize> = (0..tracks.len())
+                    .filter(|&i| tracks[i].flex().is_none() && tracks[i].base < tracks[i].limit)
+                    .collect();
+                if growing.is_empty() || free < 0.01 {
+                    break;
+                }
+                let share = free / growing.len() as f32;
+                for i in growing {
+                    let grow = share.min(tracks[i].limit - tracks[i].base);
+                    tracks[i].base += grow;
+                    free -= grow;
+                }
+            }
+        }
+        None => {
+            for track in &mut tracks {
+                track.base = track.limit;
+            }
+        }
+    }
+
+    if tracks.iter().any(|track| track.flex().is_some()) {
+        let fr = match available {
+            Some(available) => find_fr_size(&tracks, available - gaps),
+            None => {
+                // Without space to fill, `1fr` is as big as it needs to be
+                // for every flexible track and the items in them.
+                let mut fr = tracks.iter()
+                    .filter_map(|track| track.flex().map(|factor| track.base / factor.max(1.0)))
+                    .fold(0.0, f32::max);
+                for &(start, span, _, max) in contributions {
+                    let range = &tracks[start..start + span];
+                    let factors: f32 = range.iter().filter_map(Track::flex).sum();
+                    if factors > 0.0 {
+                        let fixed = range.iter().filter(|track| track.flex().is_none()).map(|track| track.base).sum::<f32>();
+                        fr = fr.max((max - fixed - gap * (span - 1) as f32) / factors.max(1.0));
+                    }
+                }
+                fr
+            }
+        };
+        for track in &mut tracks {
+            if let Some(factor) = track.flex() {
+                track.base = track.base.max(fr * factor);
+            }
+        }
+    }
+
+    if let (true, Some(available)) = (stretch, available) {
+        let free = available - gaps - tracks.iter().map(|track| track.base).sum::<f32>();
+        let auto: Vec<usize> = (0..tracks.len()).filter(|&i| tracks[i].size.max == Breadth::Auto).collect();
+        if free > 0.0 && !auto.is_empty() {
+            let share = free / auto.len() as f32;
+            for i in auto {
+                tracks[i].base += share;
+            }
+        }
+    }
+    tracks.into_iter().map(|track| track.base).collect()
+}
+
+/// Where each track starts along an axis, with free space in `available`
+/// distributed by `justify-content` or `align-content`.
+fn track_positions(sizes: &[f32], available: Option<f32>, gap: f32, alignment: Alignment) -> Vec<f32> {
+    let used = sizes.iter().sum::<f32>() + gap * (sizes.len().max(1) - 1) as f32;
+    let free = available.map_or(0.0, |available| available - used);
+    let (mut position, between) = distribute(alignment, free, sizes.len());
+    sizes.iter().map(|size| {
+        let start = position;
+        position += size + gap + between;
+        start
+    }).collect()
+}
+
+/// The size of an area spanning `tracks`, with the gaps between them.
+fn area_size(sizes: &[f32], tracks: Range<usize>, gap: f32) -> f32 {
+    gap * (tracks.len() - 1) as f32 + sizes[tracks].iter().sum::<f32>()
+}
+
+/// The items of a grid container, placed, and its rows and columns.
+struct Grid {
+    items: Vec<GridItem>,
+    rows: Vec<TrackSize>,
+    columns: Vec<TrackSize>,
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Set up the explicit grid from the templates, with percentages of
+    /// `basis` in rows and columns and the given `gaps` between them, place
+    /// the items in it, and add the implicit tracks they need.
+    fn build_grid(&self, basis: [Option<f32>; 2], gaps: [f32; 2]) -> Grid {
+        let style = self.get_style_node();
+        let (areas, area_counts) = template_areas(style.value("grid-template-areas"));
+        let (rows, row_fit) = template_tracks(style.value("grid-template-rows"), basis[0], gaps[0]);
+        let (columns, column_fit) = template_tracks(style.value("grid-template-columns"), basis[1], gaps[1]);
+        let explicit = [rows.len().max(area_counts[0]), columns.len().max(area_counts[1])];
+
+        let justify_items = alignment(style, "justify-items", Alignment::Stretch);
+        let align_items = alignment(style, "align-items", Alignment::Stretch);
+        let mut items = vec![];
+        let mut orders = vec![];
+        for (index, child) in self.children.iter().enumerate() {
+            if child.is_absolutely_positioned() || child.is_empty_anonymous_block() {
+                continue;
+            }
+            let mut item = GridItem {
+                index,
+                start: [None, None],
+                span: [1, 1],
+                auto_margins: (false, false, false, false),
+                justify: justify_items,
+                align: align_items,
+                width: 0.0,
+            };
+            let mut order = 0;
+            if !matches!(child.box_type, BoxType::AnonymousBlock(_)) {
+                let style = child.get_style_node();
+                for (axis, &explicit) in explicit.iter().enumerate() {
+                    let (start, end) = placement(style, axis);
+                    let span = |line: &GridLine| match *line {
+                        GridLine::Span(n) => n,
+                        _ => 1,
+                    };
+                    let (first, count) = match (resolve_line(&start, &areas, axis, explicit, true),
+                                                resolve_line(&end, &areas, axis, explicit, false)) {
+                        (Some(first), Some(last)) if last > first => (Some(first), last - first),
+                        (Some(first), Some(last)) if last < first => (Some(last), first - last),
+                        (Some(first), _) => (Some(first), span(&end)),
+                        (None, Some(last)) => (Some(last.saturating_sub(span(&start))), span(&start)),
+                        (None, None) => (None, span(&start).max(span(&end))),
+                    };
+                    item.start[axis] = first;
+                    item.span[axis] = count;
+                }
+                item.justify = alignment(style, "justify-self", justify_items);
+                item.align = alignment(style, "align-self", align_items);
+                if let Some(Value::Number(n)) = style.value("order") {
+                    order = n as i32;
+                }
+            }
+            items.push(item);
+            orders.push(order);
+        }
+
+        // The sort is stable, so items of equal order keep source order.
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        order.sort_by_key(|&i| orders[i]);
+        let mut items: Vec<Option<GridItem>> = items.into_iter().map(Some).collect();
+        let mut items: Vec<GridItem> = order.into_iter().map(|i| items[i].take().unwrap()).collect();
+
+        let flow: Vec<Value> = components(style.value("grid-auto-flow"));
+        let column_flow = flow.contains(&Value::Keyword("column".to_string()));
+        let dense = flow.contains(&Value::Keyword("dense".to_string()));
+        let counts = place_items(&mut items, explicit, column_flow, dense);
+
+        // Tracks beyond the templates take their sizes from
+        // `grid-auto-rows` and `grid-auto-columns`, in turn.
+        let implicit = |tracks: Vec<TrackSize>, fit: Option<Range<usize>>, axis: usize, name: &str| {
+            let mut auto: Vec<TrackSize> = components(style.value(name)).iter()
+                .map(|value| track_size(value, basis[axis]))
+                .collect();
+            if auto.is_empty() {
+                auto.push(AUTO_TRACK);
+            }
+            let explicit = tracks.len();
+            let mut tracks: Vec<TrackSize> = tracks.into_iter()
+                .chain((explicit..counts[axis]).map(|i| auto[(i - explicit) % auto.len()]))
+                .collect();
+            for i in fit.unwrap_or(0..0) {
+                if !items.iter().any(|item| item.tracks(axis).contains(&i)) {
+                    tracks[i] = TrackSize { min: Breadth::Fixed(0.0), max: Breadth::Fixed(0.0) };
+                }
+            }
+            tracks
+        };
+        let rows = implicit(rows, row_fit, 0, "grid-auto-rows");
+        let columns = implicit(columns, column_fit, 1, "grid-auto-columns");
+        Grid { items, rows, columns }
+    }
+
+    /// Lay out the items of a grid container, whose width and position are
+    /// known, following CSS Grid Layout: place them in the grid, size the
+    /// columns and then the rows to fit them, and align each in its area.
+    /// The content height is set to fit the rows.
+    pub(super) fn layout_grid_items(&mut self, definite_height: Option<f32>) -> BlockMargins {
+        let style = self.get_style_node();
+        let content = self.dimensions.content;
+        let (row_gap, column_gap) = gaps(style, content.width, definite_height.unwrap_or(0.0));
+        for child in &mut self.children {
+            if child.is_absolutely_positioned() {
+                child.set_static_position(content.x, content.y);
+            }
+        }
+        let Grid { mut items, rows, columns } = self.build_grid([definite_height, Some(content.width)],
+                                                                [row_gap, column_gap]);
+
+        let contributions: Vec<(usize, usize, f32, f32)> = items.iter_mut().map(|item| {
+            let child = &mut self.children[item.index];
+            item.auto_margins = child.set_item_edges(content.width);
+            let (min, max) = child.intrinsic_widths();
+            (item.start[1].unwrap(), item.span[1], min, max)
+        }).collect();
+        let justify_content = alignment(style, "justify-content", Alignment::Stretch);
+        let column_sizes = size_tracks(&columns, &contributions, Some(content.width), column_gap,
+                                       justify_content == Alignment::Stretch);
+
+        // Lay out each item at its width to find the height it needs.
+        let contributions: Vec<(usize, usize, f32, f32)> = items.iter_mut().map(|item| {
+            let child = &mut self.children[item.index];
+            let area_width = area_size(&column_sizes, item.tracks(1), column_gap);
+            let d = child.dimensions;
+            let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+            let available = area_width - d.margin.left - d.margin.right - edges;
+            let stretched = item.justify == Alignment::Stretch && !item.auto_margins.1 && !item.auto_margins.3;
+            item.width = match child.item_size("width", edges, Some(area_width)) {
+                Some(width) => width,
+                None if stretched => available.max(0.0),
+                None => child.shrink_to_fit(available),
+            };
+            if !matches!(child.box_type, BoxType::AnonymousBlock(_)) {
+                item.width = child.clamp_width(item.width, edges, area_width);
+            }
+            let vertical = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+            let height = child.item_size("height", vertical, None);
+            child.layout_item(item.width, height);
+            let height = child.dimensions.margin_box().height;
+            (item.start[0].unwrap(), item.span[0], height, height)
+        }).collect();
+        let align_content = alignment(style, "align-content", Alignment::Stretch);
+        let row_sizes = size_tracks(&rows, &contributions, definite_height, row_gap,
+                                    align_content == Alignment::Stretch);
+
+        let column_positions = track_positions(&column_sizes, Some(content.width), column_gap, justify_content);
+        let row_positions = track_positions(&row_sizes, definite_height, row_gap, align_content);
+
+        // Lay out each item in its area, then line up those aligned to the
+        // baseline of the row they start in.
+        let mut baselines = vec![];
+        for item in &items {
+            let child = &mut self.children[item.index];
+            let area_height = area_size(&row_sizes, item.tracks(0), row_gap);
+            let d = child.dimensions;
+            let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+            let stretched = item.align == Alignment::Stretch && !item.auto_margins.0 && !item.auto_margins.2;
+            let height = match child.item_size("height", edges, Some(area_height)) {
+                Some(height) => Some(height),
+                None if stretched => Some((area_height - d.margin.top - d.margin.bottom - edges).max(0.0)),
+                None => None,
+            };
+            let height = match child.box_type {
+                BoxType::AnonymousBlock(_) => height,
+                _ => height.map(|height| child.clamp_height(height, Some(area_height))),
+            };
+            child.layout_item(item.width, height);
+            let margin_box = child.dimensions.margin_box();
+            let baseline = first_baseline(child).unwrap_or(margin_box.y + margin_box.height - d.margin.bottom);
+            baselines.push(baseline - margin_box.y);
+        }
+        let mut row_ascents = vec![0.0f32; row_sizes.len()];
+        for (item, &baseline) in items.iter().zip(&baselines) {
+            if item.align == Alignment::Baseline {
+                let row = item.start[0].unwrap();
+                row_ascents[row] = row_ascents[row].max(baseline);
+            }
+        }
+
+        for (item, &baseline) in items.iter().zip(&baselines) {
+            let (row, column) = (item.start[0].unwrap(), item.start[1].unwrap());
+            let area_width = area_size(&column_sizes, item.tracks(1), column_gap);
+            let area_height = area_size(&row_sizes, item.tracks(0), row_gap);
+            let child = &mut self.children[item.index];
+            let margin_box = child.dimensions.margin_box();
+            let free_x = (area_width - margin_box.width).max(0.0);
+            let free_y = (area_height - margin_box.height).max(0.0);
+
+            // Free space goes to `auto` margins before alignment.
+            let (_, right, _, left) = item.auto_margins;
+            let (margin_left, margin_right) = match (left, right) {
+                (true, true) => (free_x / 2.0, free_x / 2.0),
+                (true, false) => (free_x, 0.0),
+                (false, true) => (0.0, free_x),
+                (false, false) => (0.0, 0.0),
+            };
+            let dx = if left || right {
+                margin_left
+            } else {
+                match item.justify {
+                    Alignment::End => free_x,
+                    Alignment::Center => free_x / 2.0,
+                    _ => 0.0,
+                }
+            };
+            let (top, _, bottom, _) = item.auto_margins;
+            let (margin_top, margin_bottom) = match (top, bottom) {
+                (true, true) => (free_y / 2.0, free_y / 2.0),
+                (true, false) => (free_y, 0.0),
+                (false, true) => (0.0, free_y),
+                (false, false) => (0.0, 0.0),
+            };
+            let dy = if top || bottom {
+                margin_top
+            } else {
+                match item.align {
+                    Alignment::End => free_y,
+                    Alignment::Center => free_y / 2.0,
+                    Alignment::Baseline => (row_ascents[row] - baseline).min(free_y),
+                    _ => 0.0,
+                }
+            };
+
+            let d = &mut child.dimensions;
+            d.margin.left += margin_left;
+            d.margin.right += margin_right;
+            d.margin.top += margin_top;
+            d.margin.bottom += margin_bottom;
+            child.translate(content.x + column_positions[column] + dx, content.y + row_positions[row] + dy);
+        }
+
+        let d = &mut self.dimensions;
+        d.content.height = definite_height
+            .unwrap_or_else(|| row_sizes.iter().sum::<f32>() + row_gap * (row_sizes.len().max(1) - 1) as f32);
+        BlockMargins {
+            top: CollapsedMargin::new(d.margin.top),
+            bottom: CollapsedMargin::new(d.margin.bottom),
+            collapses_through: false,
+        }
+    }
+
+    /// The min-content and max-content widths of a grid container's
+    /// content: its columns sized to the items' min-content and then
+    /// max-content contributions.
+    pub(super) fn grid_intrinsic_widths(&self) -> (f32, f32) {
+        let column_gap = gaps(self.get_style_node(), 0.0, 0.0).1;
+        let grid = self.build_grid([None, None], [0.0, column_gap]);
+        let contributions: Vec<(usize, usize, f32, f32)> = grid.items.iter().map(|item| {
+            let (min, max) = self.children[item.index].intrinsic_widths();
+            (item.start[1].unwrap(), item.span[1], min, max)
+        }).collect();
+        let min_contributions: Vec<(usize, usize, f32, f32)> = contributions.iter()
+            .map(|&(start, span, min, _)| (start, span, min, min))
+            .collect();
+        let width = |contributions: &[(usize, usize, f32, f32)]| {
+            let sizes = size_tracks(&grid.columns, contributions, None, column_gap, false);
+            sizes.iter().sum::<f32>() + column_gap * (sizes.len().max(1) - 1) as f32
+        };
+        (width(&min_contributions), width(&contributions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use boxes::tests::layout;
+
+    const CSS: &str = "body { margin: 0 } .g { display: grid; width: 400px } .g > div { height: 10px }";
+
+    /// The positions and sizes of the boxes with the ids `ids`.
+    fn areas(html: &str, ids: &[&str]) -> Vec<(f32, f32, f32, f32)> {
+        let rects = layout(html, CSS, 800.0);
+        ids.iter().map(|id| (rects[*id].x, rects[*id].y, rects[*id].width, rects[*id].height)).collect()
+    }
+
+    #[test]
+    fn fixed_and_flexible_tracks() {
+        let html = "<div class=g style='grid-template-columns: 100px 1fr 2fr; column-gap: 30px'>\
+                    <div id=a></div><div id=b></div><div id=c></div><div id=d></div></div>";
+        assert_eq!(areas(html, &["a", "b", "c", "d"]),
+                   [(0.0, 0.0, 100.0, 10.0), (130.0, 0.0, 80.0, 10.0), (240.0, 0.0, 160.0, 10.0),
+                    (0.0, 10.0, 100.0, 10.0)]);
+        let html = "<div class=g style='grid-template-columns: repeat(4, 1fr)'><div id=a></div></div>";
+        assert_eq!(areas(html, &["a"]), [(0.0, 0.0, 100.0, 10.0)]);
+    }
+
+    #[test]
+    fn placement() {
+        let html = "<div class=g style='grid-template-columns: 100px 100px 200px; grid-template-rows: 20px 30px'>\
+                    <div id=a style='grid-column: 2 / span 2; grid-row: 2'></div><div id=b></div></div>";
+        assert_eq!(areas(html, &["a", "b"]), [(100.0, 20.0, 300.0, 10.0), (0.0, 0.0, 100.0, 10.0)]);
+        let html = "<div class=g style=\"grid-template-columns: 50px 150px; grid-template-areas: 'x y' 'z z'\">\
+                    <div id=a style='grid-area: z'></div><div id=b style='grid-area: y'></div></div>";
+        assert_eq!(areas(html, &["a", "b"]), [(0.0, 10.0, 200.0, 10.0), (50.0, 0.0, 150.0, 10.0)]);
+    }
+
+    #[test]
+    fn auto_tracks_fit_content() {
+        let html = "<div class=g style='grid-template-columns: auto 1fr'>\
+                    <div id=a><span style='display: inline-block; width: 70px'></span></div>\
+                    <div id=b></div></div>";
+        assert_eq!(areas(html, &["a", "b"]), [(0.0, 0.0, 70.0, 10.0), (70.0, 0.0, 330.0, 10.0)]);
+    }
+}
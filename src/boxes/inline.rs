@@ -0,0 +1,765 @@
+use std::ptr;
+
+use css::{Value, Unit};
+use node::NodeType;
+use style::{Position, StyledNode, WhiteSpace};
+use super::{BoxType, LayoutBox, Rect, TablePart};
+use super::flex::first_baseline;
+use super::float::FloatContext;
+use super::positioned::relative_offset;
+
+/// The number of spaces between tab stops in preserved white space.
+const TAB_SIZE: usize = 8;
+
+/// A line of inline content.
+pub struct LineBox<'a> {
+    pub rect: Rect,
+    /// The distance from the top of the line to its baseline.
+    pub baseline: f32,
+    pub fragments: Vec<Fragment<'a>>,
+}
+
+/// A run of text placed on a line, all from the same text node.
+pub struct Fragment<'a> {
+    pub rect: Rect,
+    pub text: String,
+    pub style: &'a StyledNode<'a>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PieceKind {
+    Word,
+    Space,
+    /// A forced line break, from a `<br>` or a preserved newline.
+    Break,
+    /// The margin, border and padding at the start or end of an inline box.
+    Edge,
+    /// A float, and an atomic inline such as an inline block or a replaced
+    /// element, each with its index among the boxes in the inline content.
+    Float(usize),
+    Atomic(usize),
+}
+
+/// The `vertical-align` of an inline box or atomic inline. The lengths are
+/// measured from the baseline of the parent, in its font.
+#[derive(Clone, Copy, PartialEq)]
+enum VerticalAlign {
+    /// Raised this far: `baseline`, `sub`, `super`, lengths and percentages.
+    Baseline(f32),
+    /// The middle at this height, half the parent's x-height.
+    Middle(f32),
+    /// The top at the parent's ascent.
+    TextTop(f32),
+    /// The bottom at the parent's descent.
+    TextBottom(f32),
+    /// The top or bottom at that of the line box.
+    Top,
+    Bottom,
+}
+
+impl VerticalAlign {
+    /// The ascent and descent from the line's baseline of a box `height`
+    /// tall with its own baseline `baseline` below its top, when the boxes
+    /// around it raise its parent's baseline by `shift`. Boxes aligned with
+    /// the line box itself have none.
+    fn extent(self, height: f32, baseline: f32, shift: f32) -> Option<(f32, f32)> {
+        let top = match self {
+            VerticalAlign::Baseline(raise) => baseline + raise,
+            VerticalAlign::Middle(middle) => middle + height / 2.0,
+            VerticalAlign::TextTop(ascent) => ascent,
+            VerticalAlign::TextBottom(descent) => height - descent,
+            VerticalAlign::Top | VerticalAlign::Bottom => return None,
+        };
+        Some((top + shift, height - top - shift))
+    }
+}
+
+/// The `vertical-align` of `style`, in a parent with the style `parent`.
+/// Text is measured as in `vertical_extent`, with an x-height of half the
+/// font size.
+fn vertical_align(style: &StyledNode, parent: &StyledNode) -> VerticalAlign {
+    let font_size = parent.font_size();
+    match style.value("vertical-align") {
+        Some(Value::Keyword(ref align)) => match align.as_ref() {
+            "sub" => VerticalAlign::Baseline(-0.2 * font_size),
+            "super" => VerticalAlign::Baseline(0.4 * font_size),
+            "middle" => VerticalAlign::Middle(0.25 * font_size),
+            "text-top" => VerticalAlign::TextTop(0.8 * font_size),
+            "text-bottom" => VerticalAlign::TextBottom(0.2 * font_size),
+            "top" => VerticalAlign::Top,
+            "bottom" => VerticalAlign::Bottom,
+            _ => VerticalAlign::Baseline(0.0),
+        },
+        Some(Value::Length(length, Unit::Px)) => VerticalAlign::Baseline(length),
+        Some(Value::Percentage(p)) => VerticalAlign::Baseline(p * style.line_height() / 100.0),
+        _ => VerticalAlign::Baseline(0.0),
+    }
+}
+
+/// The distance from the top of a laid out atomic inline's margin box to
+/// its baseline: the last line of an inline block, unless it clips its
+/// content, the first baseline of a flex, grid or table container, and
+/// otherwise the bottom margin edge.
+fn atomic_baseline(layout_box: &LayoutBox) -> f32 {
+    let margin_box = layout_box.dimensions.margin_box();
+    let baseline = match layout_box.box_type {
+        BoxType::BlockNode(style) => match style.value("overflow") {
+            None => last_baseline(layout_box),
+            Some(Value::Keyword(ref overflow)) if overflow == "visible" => last_baseline(layout_box),
+            _ => None,
+        },
+        BoxType::FlexNode(_) | BoxType::GridNode(_) | BoxType::TableNode(..) => first_baseline(layout_box),
+        _ => None,
+    };
+    baseline.map_or(margin_box.height, |baseline| baseline - margin_box.y)
+}
+
+/// The baseline of the last line box in normal flow in a laid out box.
+fn last_baseline(layout_box: &LayoutBox) -> Option<f32> {
+    match layout_box.lines.last() {
+        Some(line) => Some(line.rect.y + line.baseline),
+        None => layout_box.children.iter().rev()
+            .filter(|child| !child.is_absolutely_positioned() && !child.is_floated())
+            .filter_map(last_baseline)
+            .next(),
+    }
+}
+
+/// The unit of line breaking: a word, a space, a break or an inline box
+/// edge, already through white space collapsing.
+struct Piece<'a> {
+    kind: PieceKind,
+    text: String,
+    width: f32,
+    style: &'a StyledNode<'a>,
+    /// How far relatively positioned inline boxes around the piece move it.
+    offset: (f32, f32),
+    /// How far the inline boxes around the piece raise its baseline.
+    shift: f32,
+    /// The alignment of an atomic inline.
+    align: VerticalAlign,
+}
+
+impl<'a> Piece<'a> {
+    fn white_space(&self) -> WhiteSpace {
+        self.style.white_space()
+    }
+
+    /// A space that is removed at the start and end of a line.
+    fn is_collapsible_space(&self) -> bool {
+        self.kind == PieceKind::Space && self.white_space().collapses_spaces()
+    }
+
+    /// A space that doesn't count towards the width of the line it ends.
+    fn hangs(&self) -> bool {
+        self.kind == PieceKind::Space && self.white_space() != WhiteSpace::BreakSpaces
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self.kind, PieceKind::Float(_))
+    }
+
+    fn is_atomic(&self) -> bool {
+        matches!(self.kind, PieceKind::Atomic(_))
+    }
+
+    /// Whether a line may break after this piece.
+    fn allows_break_after(&self) -> bool {
+        (self.kind == PieceKind::Space || self.is_atomic()) && self.white_space().wraps()
+    }
+}
+
+/// Whether a line may break after `pieces[i]`. A run of hanging spaces
+/// hangs at the end of a line as a whole, so the break comes after the
+/// last of them.
+fn breaks_after(pieces: &[Piece], i: usize) -> bool {
+    pieces[i].allows_break_after() && !(pieces[i].hangs() && pieces.get(i + 1).is_some_and(|p| p.hangs()))
+}
+
+/// The width of the spaces that hang at the end of `pieces`.
+fn trailing_hanging<'p, 'a: 'p, I>(pieces: I) -> f32
+        where I: DoubleEndedIterator<Item = &'p Piece<'a>> {
+    pieces.rev().take_while(|p| p.hangs()).map(|p| p.width).sum()
+}
+
+/// The advance width of a character. There is no font machinery, so text is
+/// measured as if set in a monospace font half as wide as it is tall, with
+/// wide East Asian characters taking a full em.
+fn char_width(c: char, font_size: f32) -> f32 {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200D | 0xFEFF => 0.0,
+        0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x20000..=0x3FFFD => font_size,
+        _ => font_size / 2.0,
+    }
+}
+
+pub(super) fn text_width(text: &str, font_size: f32) -> f32 {
+    text.chars().map(|c| char_width(c, font_size)).sum()
+}
+
+/// Splits inline content into pieces, applying the white space processing
+/// rules of CSS Text. Collapsing happens across inline box boundaries, so the
+/// state is carried over from one text node to the next.
+struct PieceBuilder<'a> {
+    pieces: Vec<Piece<'a>>,
+    /// Whether the last piece ends in a collapsible space (or is the start of
+    /// a line), so a following collapsible space is removed.
+    after_space: bool,
+    /// The column within a preserved line, for expanding tabs.
+    column: usize,
+    /// The offset of the relatively positioned inline boxes being collected,
+    /// relative to the content box of the block containing the lines.
+    offset: (f32, f32),
+    /// The baseline shift of the inline boxes being collected.
+    shift: f32,
+    containing_block: Rect,
+    /// The number of floats and atomic inlines collected so far.
+    boxes: usize,
+}
+
+impl<'a> PieceBuilder<'a> {
+    fn push(&mut self, kind: PieceKind, text: &str, style: &'a StyledNode<'a>) {
+        let width = text_width(text, style.font_size());
+        self.push_with_width(kind, text, width, style);
+    }
+
+    fn push_with_width(&mut self, kind: PieceKind, text: &str, width: f32, style: &'a StyledNode<'a>) {
+        self.pieces.push(Piece {
+            kind,
+            text: text.to_string(),
+            width,
+            style,
+            offset: self.offset,
+            shift: self.shift,
+            align: VerticalAlign::Baseline(0.0),
+        });
+    }
+
+    fn push_word_char(&mut self, c: char, style: &'a StyledNode<'a>) {
+        if let Some(last) = self.pieces.last_mut() {
+            if last.kind == PieceKind::Word && ptr::eq(last.style, style) {
+                last.text.push(c);
+                last.width += char_width(c, style.font_size());
+                return;
+            }
+        }
+        self.push(PieceKind::Word, &c.to_string(), style);
+    }
+
+    fn push_break(&mut self, style: &'a StyledNode<'a>) {
+        self.push_with_width(PieceKind::Break, "", 0.0, style);
+        self.after_space = true;
+        self.column = 0;
+    }
+
+    fn push_text(&mut self, text: &str, style: &'a StyledNode<'a>) {
+        let white_space = style.white_space();
+        for c in text.chars() {
+            match c {
+                '\n' if white_space.preserves_newlines() => {
+                    // Collapsible spaces before a preserved newline go away.
+                    if white_space.collapses_spaces() {
+                        while self.pieces.last().is_some_and(|p| p.is_collapsible_space()) {
+                            self.pieces.pop();
+                        }
+                    }
+                    self.push_break(style);
+                }
+                ' ' | '\t' | '\n' | '\r' | '\x0C' if white_space.collapses_spaces() => {
+                    if !self.after_space {
+                        self.push(PieceKind::Space, " ", style);
+                        self.after_space = true;
+                    }
+                }
+                '\t' => {
+                    let spaces = TAB_SIZE - self.column % TAB_SIZE;
+                    let width = spaces as f32 * char_width(' ', style.font_size());
+                    self.push_with_width(PieceKind::Space, "\t", width, style);
+                    self.column += spaces;
+                    self.after_space = false;
+                }
+                ' ' => {
+                    self.push(PieceKind::Space, " ", style);
+                    self.column += 1;
+                    self.after_space = false;
+                }
+                c => {
+                    self.push_word_char(c, style);
+                    self.column += 1;
+                    self.after_space = false;
+                }
+            }
+        }
+    }
+
+    fn push_edge(&mut self, width: f32, style: &'a StyledNode<'a>) {
+        if width != 0.0 {
+            self.push_with_width(PieceKind::Edge, "", width, style);
+        }
+    }
+
+    fn collect(&mut self, layout_box: &LayoutBox<'a>) {
+        for child in &layout_box.children {
+            let style = match child.box_type {
+                BoxType::InlineNode(style) => style,
+                BoxType::MarkerNode(style, ref text) => {
+                    self.push_text(text, style);
+                    continue;
+                }
+                BoxType::BlockNode(style) | BoxType::ReplacedNode(style) | BoxType::FlexNode(style)
+                | BoxType::GridNode(style) | BoxType::TableNode(TablePart::Table, style) if child.is_floated() => {
+                    self.push_with_width(PieceKind::Float(self.boxes), "", 0.0, style);
+                    self.boxes += 1;
+                    continue;
+                }
+                BoxType::BlockNode(style) | BoxType::ReplacedNode(style) | BoxType::FlexNode(style)
+                | BoxType::GridNode(style) | BoxType::TableNode(TablePart::Table, style)
+                    if !child.is_absolutely_positioned() => {
+                    self.push_with_width(PieceKind::Atomic(self.boxes), "", 0.0, style);
+                    self.pieces.last_mut().unwrap().align = vertical_align(style, layout_box.inherited_style());
+                    self.boxes += 1;
+                    self.after_space = false;
+                    continue;
+                }
+                _ => continue,
+            };
+            match style.node.node_type {
+                NodeType::Text(ref text) => self.push_text(text, style),
+                NodeType::Element(ref elem) if elem.tag_name == "br" => self.push_break(style),
+                NodeType::Element(_) => {
+                    let zero = Value::Length(0.0, Unit::Px);
+                    let edge = |side: &str| {
+                        style.lookup(&format!("margin-{}", side), &zero).to_px()
+                            + style.lookup(&format!("border-{}-width", side), &zero).to_px()
+                            + style.lookup(&format!("padding-{}", side), &zero).to_px()
+                    };
+                    let (outer_offset, outer_shift) = (self.offset, self.shift);
+                    if style.position() == Position::Relative {
+                        let (dx, dy) = relative_offset(style, self.containing_block);
+                        self.offset = (outer_offset.0 + dx, outer_offset.1 + dy);
+                    }
+                    // An inline box is as tall as its strut, and one aligned
+                    // with the line box stays on the baseline.
+                    let (ascent, descent) = vertical_extent(style);
+                    let align = vertical_align(style, layout_box.inherited_style());
+                    if let Some((top, _)) = align.extent(ascent + descent, ascent, 0.0) {
+                        self.shift += top - ascent;
+                    }
+                    self.push_edge(edge("left"), style);
+                    self.collect(child);
+                    self.push_edge(edge("right"), style);
+                    self.offset = outer_offset;
+                    self.shift = outer_shift;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    fn collect_pieces(&self) -> Vec<Piece<'a>> {
+        let mut builder = PieceBuilder {
+            pieces: vec![],
+            after_space: true,
+            column: 0,
+            offset: (0.0, 0.0),
+            shift: 0.0,
+            containing_block: self.dimensions.content,
+            boxes: 0,
+        };
+        builder.collect(self);
+        builder.pieces
+    }
+
+    /// Whether there is anything but collapsible white space and floats to
+    /// put on a line.
+    pub(super) fn has_inline_content(&self) -> bool {
+        self.collect_pieces().iter().any(|p| !p.is_collapsible_space() && !p.is_float())
+    }
+
+    /// The floats and atomic inlines among this box's inline content, in
+    /// the order `collect_pieces` finds them.
+    fn inline_boxes(&self) -> Vec<&LayoutBox<'a>> {
+        let mut boxes = vec![];
+        for child in &self.children {
+            match child.box_type {
+                BoxType::InlineNode(_) => boxes.extend(child.inline_boxes()),
+                _ if child.is_floated() => boxes.push(child),
+                BoxType::BlockNode(_) | BoxType::ReplacedNode(_) | BoxType::FlexNode(_) | BoxType::GridNode(_)
+                | BoxType::TableNode(TablePart::Table, _) if !child.is_absolutely_positioned() => boxes.push(child),
+                _ => {}
+            }
+        }
+        boxes
+    }
+
+    fn inline_boxes_mut(&mut self) -> Vec<&mut LayoutBox<'a>> {
+        let mut boxes = vec![];
+        for child in &mut self.children {
+            match child.box_type {
+                BoxType::InlineNode(_) => boxes.extend(child.inline_boxes_mut()),
+                _ if child.is_floated() => boxes.push(child),
+                BoxType::BlockNode(_) | BoxType::ReplacedNode(_) | BoxType::FlexNode(_) | BoxType::GridNode(_)
+                | BoxType::TableNode(TablePart::Table, _) if !child.is_absolutely_positioned() => boxes.push(child),
+                _ => {}
+            }
+        }
+        boxes
+    }
+
+    /// The min-content and max-content widths of this box's inline content:
+    /// the widest word, and the widest line when only forced breaks are
+    /// taken.
+    pub(super) fn inline_intrinsic_widths(&self) -> (f32, f32) {
+        let pieces = self.collect_pieces();
+        let boxes = self.inline_boxes();
+        let (mut min, mut max) = (0.0f32, 0.0f32);
+        let (mut chunk, mut line) = (0.0, 0.0);
+        let mut hanging = 0.0;
+        for (i, piece) in pieces.iter().enumerate() {
+            if let PieceKind::Float(index) | PieceKind::Atomic(index) = piece.kind {
+                // There are break opportunities around atomic inlines, and
+                // floats are beside the lines.
+                let (box_min, box_max) = boxes[index].intrinsic_widths();
+                if piece.is_atomic() {
+                    min = min.max(chunk - hanging);
+                    chunk = 0.0;
+                    hanging = 0.0;
+                }
+                min = min.max(box_min);
+                line += box_max;
+                continue;
+            }
+            if piece.kind == PieceKind::Break {
+                min = min.max(chunk - hanging);
+                max = max.max(line - hanging);
+                chunk = 0.0;
+                line = 0.0;
+                hanging = 0.0;
+                continue;
+            }
+            if line == 0.0 && piece.is_collapsible_space() {
+                continue;
+            }
+            chunk += piece.width;
+            line += piece.width;
+            hanging = if piece.hangs() { hanging + piece.width } else { 0.0 };
+            if breaks_after(&pieces, i) {
+                min = min.max(chunk - hanging);
+                chunk = 0.0;
+            }
+        }
+        (min.max(chunk - hanging), max.max(line - hanging))
+    }
+
+    /// Lay out this box's inline children into line boxes, filling the width
+    /// of the content box less the floats beside each line, and set the
+    /// content height to fit them. Floats among the children are placed in
+    /// `floats` at the top of the line they come on if they fit there, and
+    /// otherwise below it.
+    pub(super) fn layout_inline_children(&mut self, containing_height: Option<f32>, floats: &mut FloatContext) {
+        let mut pieces = self.collect_pieces();
+
+        let container = self.inherited_style();
+        let containing_block = self.dimensions;
+        let content = containing_block.content;
+        // Floats are placed before the line height is known, so the space
+        // for a line is what is free over the height of the strut.
+        let strut = container.line_height();
+        let mut boxes = self.inline_boxes_mut();
+        for layout_box in &mut boxes {
+            layout_box.layout_shrink_to_fit(containing_block, containing_height);
+        }
+        for piece in &mut pieces {
+            if let PieceKind::Atomic(index) = piece.kind {
+                piece.width = boxes[index].dimensions.margin_box().width;
+            }
+        }
+        // Floats that didn't fit on the line they came on.
+        let mut deferred: Vec<usize> = vec![];
+
+        let mut lines: Vec<LineBox<'a>> = vec![];
+        let mut y = content.y;
+        let mut space = floats.available(content, y, strut);
+        let mut line: Vec<&Piece<'a>> = vec![];
+        let mut x = 0.0;
+
+        let mut i = 0;
+        while i < pieces.len() {
+            if pieces[i].kind == PieceKind::Break {
+                let line_box = finish_line(&mut line, &mut boxes, pieces[i].style, container, space, y);
+                y += line_box.rect.height;
+                lines.push(line_box);
+                place_floats(&mut boxes, &mut deferred, floats, content, y);
+                space = floats.available(content, y, strut);
+                x = 0.0;
+                i += 1;
+                continue;
+            }
+            if let PieceKind::Float(index) = pieces[i].kind {
+                let has_content = line.iter().any(|p| !p.is_collapsible_space());
+                let width = boxes[index].dimensions.margin_box().width;
+                if deferred.is_empty() && (!has_content || x + width <= space.width) {
+                    boxes[index].place_float(floats, content, y);
+                    space = floats.available(content, y, strut);
+                } else {
+                    deferred.push(index);
+                }
+                i += 1;
+                continue;
+            }
+
+            // The next unbreakable chunk runs up to a break opportunity.
+            let mut end = i;
+            while end < pieces.len() && pieces[end].kind != PieceKind::Break && !pieces[end].is_float() {
+                if end > i && pieces[end].is_atomic() && pieces[end - 1].white_space().wraps() {
+                    break;
+                }
+                end += 1;
+                if breaks_after(&pieces, end - 1) {
+                    break;
+                }
+            }
+            let chunk = &pieces[i..end];
+            // Spaces at the end of the chunk hang, along with those before
+            // it on the line if it is nothing but spaces.
+            let hanging = trailing_hanging(line.iter().cloned().chain(chunk));
+            let chunk_width: f32 = chunk.iter().map(|p| p.width).sum();
+
+            let has_content = line.iter().any(|p| !p.is_collapsible_space());
+            if has_content && x + chunk_width - hanging > space.width {
+                let line_box = finish_line(&mut line, &mut boxes, container, container, space, y);
+                y += line_box.rect.height;
+                lines.push(line_box);
+                place_floats(&mut boxes, &mut deferred, floats, content, y);
+                space = floats.available(content, y, strut);
+                x = 0.0;
+            }
+            // A line too narrow beside floats for its first chunk moves down
+            // until the floats end or the chunk fits.
+            if !line.iter().any(|p| !p.is_collapsible_space()) {
+                while chunk_width - hanging > space.width {
+                    match floats.next_bottom(y, strut) {
+                        Some(bottom) => y = bottom,
+                        None => break,
+                    }
+                    space = floats.available(content, y, strut);
+                }
+            }
+            for piece in chunk {
+                if line.is_empty() && piece.is_collapsible_space() {
+                    continue;
+                }
+                x += piece.width;
+                line.push(piece);
+            }
+            i = end;
+        }
+        if !line.is_empty() {
+            let line_box = finish_line(&mut line, &mut boxes, container, container, space, y);
+            if !line_box.fragments.is_empty() || line_box.rect.width > 0.0 {
+                y += line_box.rect.height;
+                lines.push(line_box);
+            }
+        }
+        place_floats(&mut boxes, &mut deferred, floats, content, y);
+
+        self.dimensions.content.height = y - content.y;
+        self.lines = lines;
+    }
+}
+
+/// Place the `deferred` floats, in order, at or below `y`.
+fn place_floats(boxes: &mut [&mut LayoutBox], deferred: &mut Vec<usize>,
+                floats: &mut FloatContext, content: Rect, y: f32) {
+    for index in deferred.drain(..) {
+        boxes[index].place_float(floats, content, y);
+    }
+}
+
+/// The space above and below the baseline that a piece of text with this
+/// style needs: its ascent and descent plus half the leading each.
+pub(super) fn vertical_extent(style: &StyledNode) -> (f32, f32) {
+    let font_size = style.font_size();
+    let half_leading = (style.line_height() - font_size) / 2.0;
+    (0.8 * font_size + half_leading, 0.2 * font_size + half_leading)
+}
+
+/// The ascent and descent of a piece from the baseline of the line, or
+/// `None` for an atomic inline aligned with the line box.
+fn piece_extent(piece: &Piece, boxes: &[&mut LayoutBox]) -> Option<(f32, f32)> {
+    match piece.kind {
+        PieceKind::Atomic(index) => {
+            let height = boxes[index].dimensions.margin_box().height;
+            piece.align.extent(height, atomic_baseline(boxes[index]), piece.shift)
+        }
+        _ => {
+            let (ascent, descent) = vertical_extent(piece.style);
+            Some((ascent + piece.shift, descent - piece.shift))
+        }
+    }
+}
+
+/// Turn the pieces of a line into a line box at height `y`, emptying `line`.
+/// `end_style` is the style of whatever ended the line, whose line height
+/// counts even if the line is otherwise empty. `space` is the room left for
+/// the line beside any floats. Atomic inlines on the line are moved from
+/// `boxes` into place as their `vertical-align` says.
+fn finish_line<'a>(line: &mut Vec<&Piece<'a>>, boxes: &mut [&mut LayoutBox<'a>], end_style: &'a StyledNode<'a>,
+                   container: &'a StyledNode<'a>, space: Rect, y: f32) -> LineBox<'a> {
+    while line.last().is_some_and(|p| p.is_collapsible_space()) {
+        line.pop();
+    }
+    let width = line.iter().map(|p| p.width).sum::<f32>() - trailing_hanging(line.iter().cloned());
+
+    // The container's strut sets a minimum line height. Boxes aligned with
+    // the top or bottom of the line box only make it taller if they don't
+    // fit in it otherwise.
+    let (mut ascent, mut descent) = vertical_extent(container);
+    let extents = line.iter().filter_map(|p| piece_extent(p, boxes));
+    for (a, d) in extents.chain(Some(vertical_extent(end_style))) {
+        ascent = ascent.max(a);
+        descent = descent.max(d);
+    }
+    for piece in line.iter() {
+        if let PieceKind::Atomic(index) = piece.kind {
+            let height = boxes[index].dimensions.margin_box().height;
+            match piece.align {
+                VerticalAlign::Top => descent = descent.max(height - ascent),
+                VerticalAlign::Bottom => ascent = ascent.max(height - descent),
+                _ => {}
+            }
+        }
+    }
+
+    let offset = match container.value("text-align") {
+        Some(Value::Keyword(ref align)) if align == "right" || align == "end" => space.width - width,
+        Some(Value::Keyword(ref align)) if align == "center" => (space.width - width) / 2.0,
+        _ => 0.0,
+    };
+
+    let mut fragments: Vec<Fragment<'a>> = vec![];
+    let mut x = space.x + offset.max(0.0);
+    for piece in line.drain(..) {
+        if piece.kind == PieceKind::Word || piece.kind == PieceKind::Space {
+            let (a, d) = vertical_extent(piece.style);
+            let (dx, dy) = (piece.offset.0, piece.offset.1 - piece.shift);
+            let merge = fragments.last().is_some_and(|f| {
+                ptr::eq(f.style, piece.style) && f.rect.x + f.rect.width == x + dx
+            });
+            if merge {
+                let fragment = fragments.last_mut().unwrap();
+                fragment.text.push_str(&piece.text);
+                fragment.rect.width += piece.width;
+            } else {
+                fragments.push(Fragment {
+                    rect: Rect { x: x + dx, y: y + dy + ascent - a, width: piece.width, height: a + d },
+                    text: piece.text.clone(),
+                    style: piece.style,
+                });
+            }
+        } else if let PieceKind::Atomic(index) = piece.kind {
+            let (dx, dy) = piece.offset;
+            let margin_box = boxes[index].dimensions.margin_box();
+            let top = match piece.align {
+                VerticalAlign::Top => 0.0,
+                VerticalAlign::Bottom => ascent + descent - margin_box.height,
+                _ => ascent - piece_extent(piece, boxes).unwrap().0,
+            };
+            boxes[index].translate(x + dx - margin_box.x, y + dy + top - margin_box.y);
+        }
+        x += piece.width;
+    }
+
+    LineBox {
+        rect: Rect { x: space.x, y, width, height: ascent + descent },
+        baseline: ascent,
+        fragments,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use boxes::LayoutBox;
+    use boxes::tests::{layout, with_layout};
+
+    /// The text of each line in the layout of `html` styled by `css`.
+    fn lines(html: &str, css: &str) -> Vec<String> {
+        fn collect(layout_box: &LayoutBox, lines: &mut Vec<String>) {
+            for line in &layout_box.lines {
+                lines.push(line.fragments.iter().map(|fragment| &*fragment.text).collect());
+            }
+            for child in &layout_box.children {
+                collect(child, lines);
+            }
+        }
+        with_layout(html, css, 800.0, |root| {
+            let mut lines = vec![];
+            collect(root, &mut lines);
+            lines
+        })
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(lines("<div>aaaa bbbb cccc</div>", "div { width: 100px }"), ["aaaa bbbb", "cccc"]);
+        assert_eq!(lines("<div>aaaa<br>b</div>", ""), ["aaaa", "b"]);
+        assert_eq!(lines("<div>aaaa   bbbb</div>", "div { white-space: pre }"), ["aaaa   bbbb"]);
+    }
+
+    #[test]
+    fn preserved_spaces_hang_as_a_run() {
+        let css = "div { white-space: pre-wrap; width: 100px }";
+        assert_eq!(lines("<div>aaaa   bbbb   cccc</div>", css), ["aaaa   bbbb   ", "cccc"]);
+        let css = "div { white-space: break-spaces; width: 100px }";
+        assert_eq!(lines("<div>aaaa   bbbb   cccc</div>", css), ["aaaa   bbbb ", "  cccc"]);
+    }
+
+    /// The positions and sizes of the boxes with the ids `ids`, to a tenth
+    /// of a pixel.
+    fn rects(html: &str, ids: &[&str]) -> Vec<(f32, f32, f32, f32)> {
+        let css = "body { margin: 0 } .b { display: inline-block }";
+        let rects = layout(html, css, 400.0);
+        let round = |value: f32| (value * 10.0).round() / 10.0;
+        ids.iter().map(|id| {
+            let rect = rects[*id];
+            (round(rect.x), round(rect.y), round(rect.width), round(rect.height))
+        }).collect()
+    }
+
+    #[test]
+    fn inline_blocks() {
+        // Empty inline blocks sit on the baseline by their bottom edges.
+        let html = "<div><span id=a class=b style='width: 50px; height: 20px'></span>\
+                    <span id=b class=b style='width: 30px; height: 10px'></span></div>";
+        assert_eq!(rects(html, &["a", "b"]), [(0.0, 0.0, 50.0, 20.0), (50.0, 10.0, 30.0, 10.0)]);
+        // Those with text shrink to fit it, and sit by its baseline.
+        let html = "<div>x<span id=a class=b>xx yy</span><span id=b class=b style='height: 30px'>y</span></div>";
+        assert_eq!(rects(html, &["a", "b"]), [(8.0, 0.0, 40.0, 19.2), (48.0, 0.0, 8.0, 30.0)]);
+        // An inline block that doesn't fit goes on the next line whole.
+        let html = "<div><span id=a class=b style='width: 300px; height: 5px'></span>\
+                    <span id=b class=b style='width: 200px; height: 5px'></span></div>";
+        assert_eq!(rects(html, &["a", "b"]), [(0.0, 9.4, 300.0, 5.0), (0.0, 28.6, 200.0, 5.0)]);
+    }
+
+    #[test]
+    fn replaced_elements_on_lines() {
+        let html = "<div>x<img id=a width=10 height=30>y<img id=b width=10 height=10></div>";
+        assert_eq!(rects(html, &["a", "b"]), [(8.0, 0.0, 10.0, 30.0), (26.0, 20.0, 10.0, 10.0)]);
+    }
+
+    #[test]
+    fn vertical_align() {
+        let html = "<div><span id=a class=b style='height: 40px; width: 10px'></span>\
+                    <span id=b class=b style='height: 10px; width: 10px; vertical-align: top'></span>\
+                    <span id=c class=b style='height: 10px; width: 10px; vertical-align: bottom'></span>\
+                    <span id=d class=b style='height: 10px; width: 10px; vertical-align: middle'></span>\
+                    <span id=e class=b style='height: 10px; width: 10px'></span></div>";
+        // The baseline is at 40, and the line's bottom below it by the
+        // strut's descent. The middle is half an x-height above the baseline.
+        let tops: Vec<f32> = rects(html, &["a", "b", "c", "d", "e"]).iter().map(|rect| rect.1).collect();
+        assert_eq!(tops, [0.0, 0.0, 34.8, 31.0, 30.0]);
+    }
+}
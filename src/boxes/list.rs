@@ -0,0 +1,377 @@
+use css::Value;
+use node::NodeType;
+use style::{Display, StyledNode};
+use super::{BoxType, LayoutBox, Rect};
+use super::flex::first_baseline;
+use super::inline::{Fragment, LineBox, text_width, vertical_extent};
+
+/// An instance of a CSS counter.
+struct Counter {
+    name: String,
+    value: i32,
+    /// How deep in the tree the element that made it is.
+    depth: usize,
+}
+
+/// The counters in scope while the box tree is built, innermost last.
+#[derive(Default)]
+pub(super) struct Counters {
+    counters: Vec<Counter>,
+    depth: usize,
+}
+
+impl Counters {
+    /// Apply an element's `counter-reset`, `counter-set` and
+    /// `counter-increment`, in that order. A list item also counts itself
+    /// in the `list-item` counter, unless it sets it or increments it
+    /// itself.
+    pub(super) fn update(&mut self, style: &StyledNode) {
+        let mut resets = counter_changes(style, "counter-reset", 0);
+        let mut sets = counter_changes(style, "counter-set", 0);
+        let mut increments = counter_changes(style, "counter-increment", 1);
+
+        // `<ol start>` and `<li value>` set where the numbering goes.
+        if let Some(start) = integer_attribute(style, "ol", "start") {
+            for reset in resets.iter_mut().filter(|(name, _)| name == "list-item") {
+                reset.1 = start - 1;
+            }
+        }
+        let mentioned = sets.iter().chain(&increments).any(|(name, _)| name == "list-item");
+        if style.display() == Display::ListItem && !mentioned {
+            match integer_attribute(style, "li", "value") {
+                Some(value) => sets.push(("list-item".to_string(), value)),
+                None => increments.push(("list-item".to_string(), 1)),
+            }
+        }
+
+        for (name, value) in resets {
+            // A counter reset again by a later sibling is replaced.
+            let depth = self.depth;
+            let sibling = self.counters.iter_mut().rev()
+                .take_while(|counter| counter.depth == depth)
+                .find(|counter| counter.name == name);
+            match sibling {
+                Some(counter) => counter.value = value,
+                None => self.counters.push(Counter { name, value, depth }),
+            }
+        }
+        for (name, value) in sets {
+            *self.innermost(&name) = value;
+        }
+        for (name, value) in increments {
+            *self.innermost(&name) += value;
+        }
+    }
+
+    /// The value of the innermost counter called `name`, made on the
+    /// current element at zero if there isn't one.
+    fn innermost(&mut self, name: &str) -> &mut i32 {
+        let index = match self.counters.iter().rposition(|counter| counter.name == name) {
+            Some(index) => index,
+            None => {
+                self.counters.push(Counter { name: name.to_string(), value: 0, depth: self.depth });
+                self.counters.len() - 1
+            }
+        };
+        &mut self.counters[index].value
+    }
+
+    /// Start on the children of the element last updated, returning what
+    /// to pass to `leave` after them.
+    pub(super) fn enter(&mut self) -> usize {
+        self.depth += 1;
+        self.counters.len()
+    }
+
+    /// Finish the children of an element. The counters they made go out of
+    /// scope.
+    pub(super) fn leave(&mut self, scope: usize) {
+        self.depth -= 1;
+        self.counters.truncate(scope);
+    }
+
+    /// The values of the counters called `name` in scope, outermost first.
+    fn values(&self, name: &str) -> Vec<i32> {
+        self.counters.iter().filter(|counter| counter.name == name).map(|counter| counter.value).collect()
+    }
+
+    /// The text of a list item's marker: the `content` of its `::marker` if
+    /// that is set, or the `list-item` counter in its `list-style-type`.
+    /// Content a marker can't show is ignored, as if it were `normal`.
+    fn marker_text(&self, list_item: &StyledNode, marker: &StyledNode) -> Option<String> {
+        let content = match marker.value("content") {
+            Some(Value::List(values)) => values,
+            Some(Value::Keyword(ref keyword)) if keyword == "normal" => vec![],
+            Some(Value::Keyword(ref keyword)) if keyword == "none" => return None,
+            Some(value) => vec![value],
+            None => vec![],
+        };
+        if let Some(text) = self.content_text(&content) {
+            return Some(text);
+        }
+        let value = self.values("list-item").last().cloned().unwrap_or(0);
+        match list_style_type(list_item) {
+            Value::String(text) => Some(text),
+            Value::Keyword(ref style) if style == "none" => None,
+            Value::Keyword(ref style) if is_symbolic(style) => Some(format!("{} ", format_counter(value, style))),
+            Value::Keyword(ref style) => Some(format!("{}. ", format_counter(value, style))),
+            _ => Some(format!("{}. ", value)),
+        }
+    }
+
+    /// The text of strings, `counter()` and `counters()` in `content`, or
+    /// `None` if there are none or anything else is in it.
+    fn content_text(&self, content: &[Value]) -> Option<String> {
+        if content.is_empty() {
+            return None;
+        }
+        let mut text = String::new();
+        for value in content {
+            match *value {
+                Value::String(ref string) => text.push_str(string),
+                Value::Function(ref function, ref arguments) if function == "counter" => {
+                    let (name, style) = match arguments[..] {
+                        [ref name] => (keyword(name)?, "decimal".to_string()),
+                        [ref name, ref style] => (keyword(name)?, keyword(style)?),
+                        _ => return None,
+                    };
+                    let value = self.values(&name).last().cloned().unwrap_or(0);
+                    text.push_str(&format_counter(value, &style));
+                }
+                Value::Function(ref function, ref arguments) if function == "counters" => {
+                    let (name, separator, style) = match arguments[..] {
+                        [ref name, Value::String(ref separator)] => (keyword(name)?, separator, "decimal".to_string()),
+                        [ref name, Value::String(ref separator), ref style] => {
+                            (keyword(name)?, separator, keyword(style)?)
+                        }
+                        _ => return None,
+                    };
+                    let values = self.values(&name);
+                    let values = if values.is_empty() { vec![0] } else { values };
+                    let formatted: Vec<String> = values.iter().map(|&value| format_counter(value, &style)).collect();
+                    text.push_str(&formatted.join(separator));
+                }
+                _ => return None,
+            }
+        }
+        Some(text)
+    }
+}
+
+fn keyword(value: &Value) -> Option<String> {
+    match *value {
+        Value::Keyword(ref keyword) => Some(keyword.clone()),
+        _ => None,
+    }
+}
+
+/// The counters and values in a `counter-reset`, `counter-set` or
+/// `counter-increment`, like `chapter section 2`, where a counter without
+/// a value gets `default`. A value that isn't like that is invalid, and
+/// changes no counters.
+fn counter_changes(style: &StyledNode, name: &str, default: i32) -> Vec<(String, i32)> {
+    let values = match style.value(name) {
+        Some(Value::Keyword(ref keyword)) if keyword == "none" => vec![],
+        Some(Value::List(values)) => values,
+        Some(value) => vec![value],
+        None => vec![],
+    };
+    let mut changes: Vec<(String, i32)> = vec![];
+    let mut has_value = true;
+    for value in values {
+        match value {
+            Value::Keyword(counter) if counter != "none" => {
+                changes.push((counter, default));
+                has_value = false;
+            }
+            Value::Number(n) if !has_value && n.fract() == 0.0 => {
+                if let Some(change) = changes.last_mut() {
+                    change.1 = n as i32;
+                }
+                has_value = true;
+            }
+            _ => return vec![],
+        }
+    }
+    changes
+}
+
+/// An integer attribute of an element with the HTML tag `tag`.
+fn integer_attribute(style: &StyledNode, tag: &str, name: &str) -> Option<i32> {
+    match style.node.node_type {
+        NodeType::Element(ref element) if element.is_html() && element.tag_name == tag => {
+            element.attribute(name).and_then(|value| value.trim().parse().ok())
+        }
+        _ => None,
+    }
+}
+
+/// The `list-style-type` of a list item: a counter style keyword or a
+/// string.
+fn list_style_type(style: &StyledNode) -> Value {
+    style.lookup("list-style-type", &Value::Keyword("disc".to_string()))
+}
+
+/// Whether a list item's marker goes inside its first line rather than
+/// beside it.
+fn is_inside(style: &StyledNode) -> bool {
+    style.value("list-style-position") == Some(Value::Keyword("inside".to_string()))
+}
+
+/// Whether a counter style is a symbol rather than a number, so a marker
+/// doesn't put a period after it.
+fn is_symbolic(style: &str) -> bool {
+    matches!(style, "disc" | "circle" | "square" | "disclosure-open" | "disclosure-closed")
+}
+
+/// A counter value in a counter style. Values a style can't show, like
+/// zero in `lower-alpha`, fall back to `decimal`, as do unknown styles.
+fn format_counter(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "•".to_string(),
+        "circle" => "◦".to_string(),
+        "square" => "▪".to_string(),
+        "disclosure-open" => "▾".to_string(),
+        "disclosure-closed" => "▸".to_string(),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+        "decimal-leading-zero" if (-9..0).contains(&value) => format!("-0{}", -value),
+        "lower-roman" if (1..4000).contains(&value) => roman(value).to_lowercase(),
+        "upper-roman" if (1..4000).contains(&value) => roman(value),
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value, &('a'..='z').collect::<Vec<_>>()),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value, &('A'..='Z').collect::<Vec<_>>()),
+        // The Greek alphabet, without the final sigma.
+        "lower-greek" if value > 0 => {
+            alphabetic(value, &('α'..='ω').filter(|&c| c != 'ς').collect::<Vec<_>>())
+        }
+        _ => value.to_string(),
+    }
+}
+
+fn roman(mut value: i32) -> String {
+    const NUMERALS: [(i32, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"),
+        (50, "L"), (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut text = String::new();
+    for &(step, numeral) in &NUMERALS {
+        while value >= step {
+            text.push_str(numeral);
+            value -= step;
+        }
+    }
+    text
+}
+
+/// A positive value counted in `letters` like spreadsheet columns: a to z,
+/// then aa, ab and so on.
+fn alphabetic(mut value: i32, letters: &[char]) -> String {
+    let base = letters.len() as i32;
+    let mut text = vec![];
+    while value > 0 {
+        value -= 1;
+        text.push(letters[(value % base) as usize]);
+        value /= base;
+    }
+    text.iter().rev().collect()
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Add the marker of a list item whose counters are up to date: beside
+    /// its first line, or at the start of its inline content if it is
+    /// `inside`.
+    pub(super) fn add_marker(&mut self, style: &'a StyledNode<'a>, counters: &Counters) {
+        let marker = match style.marker {
+            Some(ref marker) => marker,
+            None => return,
+        };
+        let text = match counters.marker_text(style, marker) {
+            Some(text) => text,
+            None => return,
+        };
+        let marker_box = LayoutBox::new(BoxType::MarkerNode(marker, text));
+        if is_inside(style) {
+            self.get_inline_container().children.push(marker_box);
+        } else {
+            self.children.push(marker_box);
+        }
+    }
+
+    /// Put the outside marker of a laid out list item, if it has one, on
+    /// the baseline of its first line and just before its border box.
+    pub(super) fn layout_marker(&mut self) {
+        let border_box = self.dimensions.border_box();
+        let content = self.dimensions.content;
+        let baseline = first_baseline(self);
+        let marker = match self.children.iter_mut().find(|child| matches!(child.box_type, BoxType::MarkerNode(..))) {
+            Some(marker) => marker,
+            None => return,
+        };
+        let (style, text) = match marker.box_type {
+            BoxType::MarkerNode(style, ref text) => (style, text.clone()),
+            _ => unreachable!(),
+        };
+        let width = text_width(&text, style.font_size());
+        let (ascent, descent) = vertical_extent(style);
+        let rect = Rect {
+            x: border_box.x - width,
+            y: baseline.map_or(content.y, |baseline| baseline - ascent),
+            width,
+            height: ascent + descent,
+        };
+        marker.dimensions.content = rect;
+        marker.lines = vec![LineBox {
+            rect,
+            baseline: ascent,
+            fragments: vec![Fragment { rect, text, style }],
+        }];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use boxes::{BoxType, LayoutBox};
+    use boxes::tests::with_layout;
+
+    /// The text of each marker in the layout of `html` styled by `css`.
+    fn markers(html: &str, css: &str) -> Vec<String> {
+        fn collect(layout_box: &LayoutBox, texts: &mut Vec<String>) {
+            if let BoxType::MarkerNode(_, ref text) = layout_box.box_type {
+                texts.push(text.clone());
+            }
+            for child in &layout_box.children {
+                collect(child, texts);
+            }
+        }
+        with_layout(html, css, 800.0, |root| {
+            let mut texts = vec![];
+            collect(root, &mut texts);
+            texts
+        })
+    }
+
+    #[test]
+    fn list_item_counter() {
+        assert_eq!(markers("<ol><li>a<li>b</ol>", ""), ["1. ", "2. "]);
+        assert_eq!(markers("<ol start=4><li>a<li value=9>b<li>c</ol>", ""), ["4. ", "9. ", "10. "]);
+        assert_eq!(markers("<ul><li>a</ul>", ""), ["• "]);
+        let html = "<ol style='list-style-type: upper-roman'><li>a<li>b</ol>";
+        assert_eq!(markers(html, "li { counter-increment: list-item 2 }"), ["II. ", "IV. "]);
+    }
+
+    #[test]
+    fn marker_content() {
+        let css = "ol { counter-reset: list-item x 5 } \
+                   li::marker { content: counters(list-item, '.') ' ' counter(x, lower-alpha) }";
+        assert_eq!(markers("<ol><li>a<ol><li>b</ol></ol>", css), ["1 e", "1.1 e"]);
+    }
+
+    #[test]
+    fn invalid_values_are_ignored() {
+        for css in &["li::marker { content: counters(x) }", "li::marker { content: attr(x) }",
+                     "li::marker { content: counter(2) }", "li::marker { content: counter(x, 3) }",
+                     "li { counter-increment: 2 }", "li { counter-set: list-item 1.5 }"] {
+            assert_eq!(markers("<ol><li>a<li>b</ol>", css), ["1. ", "2. "], "{}", css);
+        }
+    }
+}
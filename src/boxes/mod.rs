@@ -0,0 +1,1092 @@
+use style::{Clear, Display, StyledNode};
+use css::{Value, Unit};
+use node::NodeType;
+
+mod flex;
+mod float;
+mod grid;
+mod inline;
+mod list;
+mod positioned;
+mod replaced;
+mod table;
+
+use self::float::FloatContext;
+use self::list::Counters;
+use self::replaced::is_replaced;
+use self::table::table_part;
+
+pub use self::inline::LineBox;
+pub use self::table::TablePart;
+
+#[derive(Default, Clone, Copy)]
+pub struct Dimensions {
+    pub content: Rect,
+    pub padding: EdgeSizes,
+    pub border: EdgeSizes,
+    pub margin: EdgeSizes,
+}
+
+impl Dimensions {
+    fn padding_box(self) -> Rect {
+        self.content.expanded_by(self.padding)
+    }
+
+    fn border_box(self) -> Rect {
+        self.padding_box().expanded_by(self.border)
+    }
+
+    fn margin_box(self) -> Rect {
+        self.border_box().expanded_by(self.margin)
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    fn expanded_by(self, edge: EdgeSizes) -> Rect {
+        Rect {
+            x: self.x - edge.left,
+            y: self.y - edge.top,
+            width: self.width + edge.left + edge.right,
+            height: self.height + edge.top + edge.bottom,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct EdgeSizes {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+/// A set of adjoining vertical margins, which collapse into a single margin:
+/// the largest positive margin plus the most negative one.
+#[derive(Default, Clone, Copy, Debug)]
+struct CollapsedMargin {
+    positive: f32,
+    negative: f32,
+}
+
+impl CollapsedMargin {
+    fn new(margin: f32) -> CollapsedMargin {
+        CollapsedMargin { positive: margin.max(0.0), negative: margin.min(0.0) }
+    }
+
+    fn adjoin(self, other: CollapsedMargin) -> CollapsedMargin {
+        CollapsedMargin {
+            positive: self.positive.max(other.positive),
+            negative: self.negative.min(other.negative),
+        }
+    }
+
+    fn resolve(self) -> f32 {
+        self.positive + self.negative
+    }
+}
+
+/// The margins at the top and bottom edges of a laid out block, including
+/// any margins of its children that collapse through its edges.
+#[derive(Default, Clone, Copy, Debug)]
+struct BlockMargins {
+    top: CollapsedMargin,
+    bottom: CollapsedMargin,
+    /// Whether the block is empty and its top and bottom margins adjoin, so
+    /// the margins around it collapse together.
+    collapses_through: bool,
+}
+
+pub enum BoxType<'a> {
+    /// A block container, block-level or an inline block.
+    BlockNode(&'a StyledNode<'a>),
+    InlineNode(&'a StyledNode<'a>),
+    /// A replaced element, block-level or atomic inline, which has no
+    /// boxes inside it.
+    ReplacedNode(&'a StyledNode<'a>),
+    /// A flex container, block-level or atomic inline, whose children are
+    /// all flex items.
+    FlexNode(&'a StyledNode<'a>),
+    /// A grid container, block-level or atomic inline, whose children are
+    /// all grid items.
+    GridNode(&'a StyledNode<'a>),
+    /// A table, block-level or atomic inline, or a part of one.
+    TableNode(TablePart, &'a StyledNode<'a>),
+    /// A table or table part made to give the table parts around it the
+    /// structure they need, holding the style of its parent for the
+    /// properties it inherits.
+    AnonymousTableNode(TablePart, &'a StyledNode<'a>),
+    /// The marker of a list item, with its text: an inline box at the start
+    /// of the item's content, or beside its first line if it is a direct
+    /// child of the item.
+    MarkerNode(&'a StyledNode<'a>, String),
+    /// A block wrapping a run of inline children, holding the style of the
+    /// block it was created in for the properties it inherits.
+    AnonymousBlock(&'a StyledNode<'a>),
+}
+
+pub struct LayoutBox<'a> {
+    pub dimensions: Dimensions,
+    pub box_type: BoxType<'a>,
+    pub children: Vec<LayoutBox<'a>>,
+    /// The line boxes of a box that contains inline content.
+    pub lines: Vec<LineBox<'a>>,
+    /// Whether this box establishes a block formatting context, so that its
+    /// margins don't collapse with its children's.
+    establishes_bfc: bool,
+}
+
+impl<'a> LayoutBox<'a> {
+    fn new(box_type: BoxType) -> LayoutBox {
+        LayoutBox {
+            box_type,
+            dimensions: Dimensions::default(),
+            children: Vec::new(),
+            lines: Vec::new(),
+            establishes_bfc: false,
+        }
+    }
+
+    /// Move this box and everything in it.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        for line in &mut self.lines {
+            line.rect.x += dx;
+            line.rect.y += dy;
+            for fragment in &mut line.fragments {
+                fragment.rect.x += dx;
+                fragment.rect.y += dy;
+            }
+        }
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    fn get_style_node(&self) -> &'a StyledNode<'a> {
+        match self.box_type {
+            BoxType::BlockNode(node) | BoxType::InlineNode(node) | BoxType::ReplacedNode(node)
+            | BoxType::FlexNode(node) | BoxType::GridNode(node) | BoxType::TableNode(_, node)
+            | BoxType::MarkerNode(node, _) => node,
+            BoxType::AnonymousBlock(_) | BoxType::AnonymousTableNode(..) => panic!("Anonymous box doesn't have a node"),
+        }
+    }
+
+    /// The style to take inherited properties from, which for an anonymous
+    /// box is the style of its parent.
+    fn inherited_style(&self) -> &'a StyledNode<'a> {
+        match self.box_type {
+            BoxType::BlockNode(node) | BoxType::InlineNode(node) | BoxType::ReplacedNode(node)
+            | BoxType::FlexNode(node) | BoxType::GridNode(node) | BoxType::TableNode(_, node)
+            | BoxType::MarkerNode(node, _) | BoxType::AnonymousBlock(node)
+            | BoxType::AnonymousTableNode(_, node) => node,
+        }
+    }
+
+    /// Lay out this box with its border box at the bottom of the containing
+    /// block's content so far, leaving its top margin to the parent, which
+    /// collapses it with the margins around it. `containing_height` is the
+    /// height of the containing block if it is definite, for resolving
+    /// percentage heights, and `floats` the floats of the block formatting
+    /// context the box is in.
+    fn layout(&mut self, containing_block: Dimensions, containing_height: Option<f32>,
+              floats: &mut FloatContext) -> BlockMargins {
+        match self.box_type {
+            BoxType::BlockNode(_) | BoxType::ReplacedNode(_) | BoxType::FlexNode(_) | BoxType::GridNode(_)
+            | BoxType::TableNode(..) => self.layout_block(containing_block, containing_height, floats),
+            BoxType::AnonymousTableNode(..) => {
+                self.layout_anonymous_table(containing_block);
+                BlockMargins::default()
+            }
+            // Inline boxes are laid out by the anonymous block containing
+            // them, and markers by their list item.
+            BoxType::InlineNode(_) | BoxType::MarkerNode(..) => BlockMargins::default(),
+            BoxType::AnonymousBlock(_) => {
+                self.layout_anonymous_block(containing_block, containing_height, floats);
+                BlockMargins { collapses_through: self.lines.is_empty(), ..BlockMargins::default() }
+            }
+        }
+    }
+
+    /// An anonymous block takes the full width of its containing block and
+    /// is as tall as the lines it contains.
+    fn layout_anonymous_block(&mut self, containing_block: Dimensions, containing_height: Option<f32>,
+                              floats: &mut FloatContext) {
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+        d.content.height = 0.0;
+        let origin = d.content;
+        self.set_inline_static_positions(origin.x, origin.y);
+        self.layout_inline_children(containing_height, floats);
+    }
+
+    /// Give the absolutely positioned boxes among this box's inline content
+    /// a static position at the start of the lines.
+    fn set_inline_static_positions(&mut self, x: f32, y: f32) {
+        for child in &mut self.children {
+            if child.is_absolutely_positioned() {
+                child.set_static_position(x, y);
+            } else if let BoxType::InlineNode(_) = child.box_type {
+                child.set_inline_static_positions(x, y);
+            }
+        }
+    }
+
+    /// Whether this box is out of flow, positioned against its containing
+    /// block by `layout_positioned` after the flow has been laid out.
+    fn is_absolutely_positioned(&self) -> bool {
+        match self.box_type {
+            BoxType::BlockNode(node) | BoxType::ReplacedNode(node) | BoxType::FlexNode(node)
+            | BoxType::GridNode(node) | BoxType::TableNode(_, node) => node.position().is_absolute(),
+            _ => false,
+        }
+    }
+
+    /// Record where the top left of an absolutely positioned box's margin
+    /// box would be if it were in the flow. It is kept in the content origin
+    /// until the box is positioned.
+    fn set_static_position(&mut self, x: f32, y: f32) {
+        self.dimensions.content.x = x;
+        self.dimensions.content.y = y;
+    }
+
+    fn layout_block(&mut self, containing_block: Dimensions, containing_height: Option<f32>,
+                    floats: &mut FloatContext) -> BlockMargins {
+        self.calculate_block_width(containing_block);
+        self.calculate_block_position(containing_block);
+        let height = self.definite_height(containing_height);
+        let margins = self.layout_contents(height, floats);
+        self.calculate_block_height(containing_height);
+        margins
+    }
+
+    /// Lay out what is inside this box once its width and position are
+    /// known: block children, flex items, grid items or the parts of a
+    /// table, or the height of a replaced element.
+    fn layout_contents(&mut self, definite_height: Option<f32>, floats: &mut FloatContext) -> BlockMargins {
+        match self.box_type {
+            BoxType::ReplacedNode(_) => self.layout_replaced(),
+            BoxType::FlexNode(_) => self.layout_flex_items(definite_height),
+            BoxType::GridNode(_) => self.layout_grid_items(definite_height),
+            BoxType::TableNode(TablePart::Table, _) | BoxType::AnonymousTableNode(TablePart::Table, _) => {
+                self.layout_table(definite_height)
+            }
+            _ => {
+                let margins = self.layout_block_children(definite_height, floats);
+                self.layout_marker();
+                margins
+            }
+        }
+    }
+
+    fn calculate_block_width(&mut self, containing_block: Dimensions) {
+        let style = self.get_style_node();
+        let auto = Value::Keyword("auto".to_string());
+        let zero = Value::Length(0.0, Unit::Px);
+
+        let containing_width = containing_block.content.width;
+
+        let margin_left = resolve_percentage(style.lookup("margin-left", &zero), containing_width);
+        let margin_right = resolve_percentage(style.lookup("margin-right", &zero), containing_width);
+
+        let border_left = style.lookup("border-left-width", &zero).to_px();
+        let border_right = style.lookup("border-right-width", &zero).to_px();
+
+        let padding_left = style.lookup("padding-left", &zero).to_px_of(containing_width);
+        let padding_right = style.lookup("padding-right", &zero).to_px_of(containing_width);
+
+        let edges = border_left + border_right + padding_left + padding_right;
+        let width = match style.value("width") {
+            Some(width @ Value::Length(..)) | Some(width @ Value::Percentage(_)) => {
+                Value::Length(self.content_size(width.to_px_of(containing_width), edges), Unit::Px)
+            }
+            _ if is_replaced(style) => Value::Length(self.replaced_width(Some(containing_width)), Unit::Px),
+            _ => auto,
+        };
+        // A table is as wide as its columns need, within the space there is
+        // if no width is given.
+        let width = match self.box_type {
+            BoxType::TableNode(TablePart::Table, _) if !self.is_fixed_layout() => {
+                let (min, _) = self.content_intrinsic_widths();
+                match width {
+                    Value::Length(width, _) => Value::Length(width.max(min), Unit::Px),
+                    _ => {
+                        let available = containing_width - margin_left.to_px() - margin_right.to_px() - edges;
+                        Value::Length(self.shrink_to_fit(available), Unit::Px)
+                    }
+                }
+            }
+            _ => width,
+        };
+        let solve = |width: &Value| {
+            solve_block_width(containing_width, width, &margin_left, &margin_right, edges)
+        };
+
+        // A width over the maximum is solved again as the maximum, and then
+        // one under the minimum as the minimum, which wins if they conflict.
+        let (mut width, mut margin_left, mut margin_right) = solve(&width);
+        if let Some(max_width) = self.size_limit("max-width", edges, Some(containing_width)) {
+            if width > max_width {
+                let (w, l, r) = solve(&Value::Length(max_width, Unit::Px));
+                width = w;
+                margin_left = l;
+                margin_right = r;
+            }
+        }
+        let min_width = self.size_limit("min-width", edges, Some(containing_width)).unwrap_or(0.0);
+        if width < min_width {
+            let (w, l, r) = solve(&Value::Length(min_width, Unit::Px));
+            width = w;
+            margin_left = l;
+            margin_right = r;
+        }
+
+        let d = &mut self.dimensions;
+        d.content.width = width;
+
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
+
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
+
+        d.border.left = border_left;
+        d.border.right = border_right;
+    }
+
+    /// Lay out a float or an atomic inline at the bottom of the containing
+    /// block's content so far. An `auto` width shrinks to fit the content,
+    /// and `auto` margins are zero.
+    fn layout_shrink_to_fit(&mut self, containing_block: Dimensions, containing_height: Option<f32>) {
+        let style = self.get_style_node();
+        let containing_width = containing_block.content.width;
+        let zero = Value::Length(0.0, Unit::Px);
+        let margin = |name: &str| style.lookup(name, &zero).to_px_of(containing_width);
+
+        // This sets the horizontal borders and padding; the width and the
+        // margins are worked out again below.
+        self.calculate_block_width(containing_block);
+        let d = self.dimensions;
+        let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+        let (margin_left, margin_right) = (margin("margin-left"), margin("margin-right"));
+        let width = match self.size_limit("width", edges, Some(containing_width)) {
+            Some(width) => width,
+            None if is_replaced(style) => self.replaced_width(Some(containing_width)),
+            None => self.shrink_to_fit(containing_width - margin_left - margin_right - edges),
+        };
+        let width = self.clamp_width(width, edges, containing_width);
+        {
+            let d = &mut self.dimensions;
+            d.content.width = width;
+            d.margin.left = margin_left;
+            d.margin.right = margin_right;
+        }
+        self.calculate_block_position(containing_block);
+
+        let height = self.definite_height(containing_height);
+        self.layout_contents(height, &mut FloatContext::default());
+        self.calculate_block_height(containing_height);
+    }
+
+    /// Whether `width` and `height` give the size of the border box rather
+    /// than the content box.
+    fn is_border_box(&self) -> bool {
+        matches!(self.get_style_node().value("box-sizing"), Some(Value::Keyword(ref s)) if s == "border-box")
+    }
+
+    /// The content size for a specified `width` or `height`, given the total
+    /// border and padding in that direction.
+    fn content_size(&self, size: f32, edges: f32) -> f32 {
+        if self.is_border_box() { (size - edges).max(0.0) } else { size }
+    }
+
+    /// The content size for a `width`, `height`, `min-*` or `max-*`
+    /// property, or `None` if it is `auto` or `none`, or a percentage of an
+    /// indefinite `basis`.
+    fn size_limit(&self, name: &str, edges: f32, basis: Option<f32>) -> Option<f32> {
+        let style = match self.box_type {
+            BoxType::AnonymousBlock(_) | BoxType::AnonymousTableNode(..) => return None,
+            _ => self.get_style_node(),
+        };
+        match (style.value(name), basis) {
+            (Some(Value::Length(size, Unit::Px)), _) => Some(self.content_size(size, edges)),
+            (Some(Value::Percentage(p)), Some(basis)) => Some(self.content_size(p * basis / 100.0, edges)),
+            // A unitless zero is a length.
+            (Some(Value::Number(0.0)), _) => Some(self.content_size(0.0, edges)),
+            _ => None,
+        }
+    }
+
+    fn calculate_block_position(&mut self, containing_block: Dimensions) {
+        let style = self.get_style_node();
+        let d = &mut self.dimensions;
+
+        let zero = Value::Length(0.0, Unit::Px);
+
+        // Vertical margins and padding are percentages of the width too.
+        let width = containing_block.content.width;
+
+        d.margin.top = style.lookup("margin-top", &zero).to_px_of(width);
+        d.margin.bottom = style.lookup("margin-bottom", &zero).to_px_of(width);
+
+        d.border.top = style.lookup("border-top-width", &zero).to_px();
+        d.border.bottom = style.lookup("border-bottom-width", &zero).to_px();
+
+        d.padding.top = style.lookup("padding-top", &zero).to_px_of(width);
+        d.padding.bottom = style.lookup("padding-bottom", &zero).to_px_of(width);
+
+        d.content.x = containing_block.content.x +
+                        d.margin.left + d.border.left + d.padding.left;
+        d.content.y = containing_block.content.height + containing_block.content.y +
+                        d.border.top + d.padding.top;
+    }
+
+    /// Set the content height from `height`, or leave the height of the
+    /// content if it is `auto`, then clamp it to `min-height` and
+    /// `max-height`. A percentage height of an indefinite containing block
+    /// behaves as `auto`.
+    fn calculate_block_height(&mut self, containing_height: Option<f32>) {
+        let height = self.vertical_size("height", containing_height)
+            .unwrap_or(self.dimensions.content.height);
+        self.dimensions.content.height = self.clamp_height(height, containing_height);
+    }
+
+    /// The content height if it doesn't depend on the content, in which case
+    /// percentage heights of children resolve against it.
+    fn definite_height(&self, containing_height: Option<f32>) -> Option<f32> {
+        self.vertical_size("height", containing_height)
+            .map(|height| self.clamp_height(height, containing_height))
+    }
+
+    fn clamp_height(&self, mut height: f32, containing_height: Option<f32>) -> f32 {
+        if let Some(max_height) = self.vertical_size("max-height", containing_height) {
+            height = height.min(max_height);
+        }
+        if let Some(min_height) = self.vertical_size("min-height", containing_height) {
+            height = height.max(min_height);
+        }
+        height
+    }
+
+    fn vertical_size(&self, name: &str, containing_height: Option<f32>) -> Option<f32> {
+        let d = self.dimensions;
+        let edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+        self.size_limit(name, edges, containing_height)
+    }
+
+    /// Stack the children vertically, collapsing adjoining margins as CSS 2.1
+    /// describes: between siblings, through empty blocks, and with this
+    /// block's own top and bottom margins when no border or padding
+    /// separates them from its first and last child. Floats are placed in
+    /// `floats`, unless this block is a formatting context root, which keeps
+    /// its own floats and grows to contain them.
+    fn layout_block_children(&mut self, definite_height: Option<f32>, floats: &mut FloatContext) -> BlockMargins {
+        let mut own_floats = FloatContext::default();
+        let floats = if self.establishes_bfc { &mut own_floats } else { floats };
+        // The bottom margin of the last child only adjoins this block's if
+        // the height comes from the content.
+        let auto_height = definite_height.is_none()
+            && self.vertical_size("min-height", None).is_none_or(|min| min == 0.0);
+        let d = &mut self.dimensions;
+        let collapse_top = !self.establishes_bfc && d.border.top == 0.0 && d.padding.top == 0.0;
+        let collapse_bottom = !self.establishes_bfc && auto_height
+            && d.border.bottom == 0.0 && d.padding.bottom == 0.0;
+
+        let mut top = CollapsedMargin::new(d.margin.top);
+        // Whether no child content has come yet, so the children's top
+        // margins still adjoin this block's.
+        let mut at_top = collapse_top;
+        // The margins that adjoin the next child's top margin.
+        let mut pending = CollapsedMargin::default();
+        for child in &mut self.children {
+            let cursor = d.content.y + d.content.height;
+            if let BoxType::MarkerNode(..) = child.box_type {
+                continue;
+            }
+            if child.is_absolutely_positioned() {
+                let offset = if at_top { 0.0 } else { pending.resolve() };
+                child.set_static_position(d.content.x, cursor + offset);
+                continue;
+            }
+            if child.is_floated() {
+                let offset = if at_top { 0.0 } else { pending.resolve() };
+                child.layout_shrink_to_fit(*d, definite_height);
+                child.place_float(floats, d.content, cursor + offset);
+                continue;
+            }
+
+            // The child is laid out where its collapsed margins are expected
+            // to put it, so its content finds the floats where they are.
+            let predicted = if at_top { 0.0 } else { pending.adjoin(child.leading_margin(d.content.width)).resolve() };
+            let mut containing_block = *d;
+            containing_block.content.height += predicted;
+
+            // Clearance puts the border box below the floats, and keeps the
+            // top margin from collapsing with anything before it.
+            let clear = match child.box_type {
+                BoxType::BlockNode(node) | BoxType::ReplacedNode(node) | BoxType::FlexNode(node)
+                | BoxType::GridNode(node) | BoxType::TableNode(_, node) => node.clear(),
+                _ => Clear::None,
+            };
+            let clearance = floats.clearance(clear).filter(|&bottom| bottom > cursor + predicted);
+            if let Some(bottom) = clearance {
+                containing_block.content.height = bottom - d.content.y;
+            }
+
+            // A formatting context root doesn't overlap the floats around it.
+            if child.establishes_bfc {
+                let y = containing_block.content.y + containing_block.content.height;
+                let room = floats.available(d.content, y, 0.0);
+                containing_block.content.x = room.x;
+                containing_block.content.width = room.width;
+            }
+
+            let start = floats.len();
+            let margins = child.layout(containing_block, definite_height, floats);
+            if let Some(bottom) = clearance {
+                d.content.height = bottom - d.content.y + child.dimensions.border_box().height;
+                pending = margins.bottom;
+                at_top = false;
+                continue;
+            }
+            if margins.collapses_through {
+                if at_top {
+                    top = top.adjoin(margins.top);
+                } else {
+                    pending = pending.adjoin(margins.top);
+                }
+                continue;
+            }
+            let offset = if at_top {
+                top = top.adjoin(margins.top);
+                0.0
+            } else {
+                pending.adjoin(margins.top).resolve()
+            };
+            child.translate(0.0, offset - predicted);
+            floats.translate_from(start, offset - predicted);
+            d.content.height += offset + child.dimensions.border_box().height;
+            pending = margins.bottom;
+            at_top = false;
+        }
+
+        let bottom = CollapsedMargin::new(d.margin.bottom);
+        if at_top && collapse_bottom {
+            BlockMargins { top: top.adjoin(bottom), bottom: top.adjoin(bottom), collapses_through: true }
+        } else if collapse_bottom {
+            BlockMargins { top, bottom: pending.adjoin(bottom), collapses_through: false }
+        } else {
+            d.content.height += pending.resolve();
+            if self.establishes_bfc {
+                if let Some(bottom) = floats.bottom() {
+                    d.content.height = d.content.height.max(bottom - d.content.y);
+                }
+            }
+            BlockMargins { top, bottom, collapses_through: false }
+        }
+    }
+
+    /// The margin at the top of this box with the margins of its first
+    /// children that collapse into it, worked out from the styles so that
+    /// the box can be laid out where it will end up. Percentages are taken
+    /// of `containing_width` all the way down.
+    fn leading_margin(&self, containing_width: f32) -> CollapsedMargin {
+        let style = match self.box_type {
+            BoxType::BlockNode(node) | BoxType::ReplacedNode(node) | BoxType::FlexNode(node)
+            | BoxType::GridNode(node) | BoxType::TableNode(TablePart::Table, node) => node,
+            _ => return CollapsedMargin::default(),
+        };
+        let zero = Value::Length(0.0, Unit::Px);
+        let margin = CollapsedMargin::new(style.lookup("margin-top", &zero).to_px_of(containing_width));
+        let separated = self.establishes_bfc
+            || style.lookup("border-top-width", &zero).to_px() != 0.0
+            || style.lookup("padding-top", &zero).to_px_of(containing_width) != 0.0;
+        if separated {
+            return margin;
+        }
+        let first = self.children.iter().find(|child| {
+            !child.is_absolutely_positioned() && !child.is_floated()
+                && !matches!(child.box_type, BoxType::MarkerNode(..))
+                && !matches!(child.box_type, BoxType::AnonymousBlock(_) if !child.has_inline_content())
+        });
+        match first {
+            Some(child) if !matches!(child.box_type,
+                BoxType::BlockNode(node) | BoxType::ReplacedNode(node) | BoxType::FlexNode(node)
+                | BoxType::GridNode(node) | BoxType::TableNode(_, node) if node.clear() != Clear::None) => {
+                margin.adjoin(child.leading_margin(containing_width))
+            }
+            _ => margin,
+        }
+    }
+
+    /// The min-content and max-content widths of this box's margin box: its
+    /// width with every line break taken, and with none but forced ones.
+    fn intrinsic_widths(&self) -> (f32, f32) {
+        let style = match self.box_type {
+            BoxType::BlockNode(node) | BoxType::ReplacedNode(node) | BoxType::FlexNode(node)
+            | BoxType::GridNode(node) | BoxType::TableNode(_, node) => node,
+            BoxType::InlineNode(_) | BoxType::MarkerNode(..) => return (0.0, 0.0),
+            BoxType::AnonymousBlock(_) | BoxType::AnonymousTableNode(..) => return self.content_intrinsic_widths(),
+        };
+        let zero = Value::Length(0.0, Unit::Px);
+        let edge = |side: &str| {
+            style.lookup(&format!("border-{}-width", side), &zero).to_px()
+                + style.lookup(&format!("padding-{}", side), &zero).to_px()
+        };
+        let edges = edge("left") + edge("right");
+        let margins = style.lookup("margin-left", &zero).to_px()
+            + style.lookup("margin-right", &zero).to_px();
+
+        let (mut min, mut max) = match self.size_limit("width", edges, None) {
+            Some(width) => (width, width),
+            None => self.content_intrinsic_widths(),
+        };
+        if let Some(max_width) = self.size_limit("max-width", edges, None) {
+            min = min.min(max_width);
+            max = max.min(max_width);
+        }
+        if let Some(min_width) = self.size_limit("min-width", edges, None) {
+            min = min.max(min_width);
+            max = max.max(min_width);
+        }
+        (min + edges + margins, max + edges + margins)
+    }
+
+    /// The min-content and max-content widths of this box's content.
+    fn content_intrinsic_widths(&self) -> (f32, f32) {
+        match self.box_type {
+            BoxType::AnonymousBlock(_) => self.inline_intrinsic_widths(),
+            BoxType::ReplacedNode(_) => {
+                let width = self.replaced_width(None);
+                (width, width)
+            }
+            BoxType::FlexNode(_) => self.flex_intrinsic_widths(),
+            BoxType::GridNode(_) => self.grid_intrinsic_widths(),
+            BoxType::TableNode(TablePart::Table, _) | BoxType::AnonymousTableNode(TablePart::Table, _) => {
+                self.table_intrinsic_widths()
+            }
+            _ => self.children.iter()
+                .filter(|child| !child.is_absolutely_positioned())
+                .map(LayoutBox::intrinsic_widths)
+                .fold((0.0, 0.0), |(min, max), (child_min, child_max)| {
+                    (f32::max(min, child_min), f32::max(max, child_max))
+                }),
+        }
+    }
+
+    /// The shrink-to-fit content width in `available` space: the
+    /// max-content width if it fits, the min-content width if even that
+    /// doesn't, and otherwise all the space there is.
+    fn shrink_to_fit(&self, available: f32) -> f32 {
+        let (min, max) = self.content_intrinsic_widths();
+        available.max(min).min(max)
+    }
+
+    /// Clamp a content width to `min-width` and `max-width`, the minimum
+    /// winning if they conflict.
+    fn clamp_width(&self, mut width: f32, edges: f32, containing_width: f32) -> f32 {
+        if let Some(max_width) = self.size_limit("max-width", edges, Some(containing_width)) {
+            width = width.min(max_width);
+        }
+        if let Some(min_width) = self.size_limit("min-width", edges, Some(containing_width)) {
+            width = width.max(min_width);
+        }
+        width
+    }
+
+    fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
+        match self.box_type {
+            BoxType::InlineNode(_) | BoxType::MarkerNode(..) | BoxType::AnonymousBlock(_) => self,
+            BoxType::BlockNode(node) | BoxType::ReplacedNode(node) | BoxType::FlexNode(node)
+            | BoxType::GridNode(node) | BoxType::TableNode(_, node) | BoxType::AnonymousTableNode(_, node) => {
+                match self.children.last() {
+                    Some(&LayoutBox { box_type: BoxType::AnonymousBlock(_), .. }) => {},
+                    _ => self.children.push(LayoutBox::new(BoxType::AnonymousBlock(node)))
+                }
+                self.children.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Turn a percentage into a length in pixels, leaving other values alone.
+fn resolve_percentage(value: Value, basis: f32) -> Value {
+    match value {
+        Value::Percentage(p) => Value::Length(p * basis / 100.0, Unit::Px),
+        value => value,
+    }
+}
+
+/// Solve the horizontal constraint of CSS 2.1 section 10.3.3 for a block in
+/// normal flow, returning its used content width and left and right margins.
+/// `edges` is the total horizontal border and padding.
+fn solve_block_width(containing_width: f32, width: &Value, margin_left: &Value,
+                     margin_right: &Value, edges: f32) -> (f32, f32, f32) {
+    let auto = Value::Keyword("auto".to_string());
+    let zero = Value::Length(0.0, Unit::Px);
+    let mut width = width.clone();
+    let mut margin_left = margin_left.clone();
+    let mut margin_right = margin_right.clone();
+
+    let total = margin_left.to_px() + margin_right.to_px() + edges + width.to_px();
+
+    if width != auto && total > containing_width {
+        if margin_left == auto {
+            margin_left = zero.clone();
+        }
+        if margin_right == auto {
+            margin_right = zero.clone();
+        }
+    }
+
+    let underflow = containing_width - total;
+
+    match (width == auto, margin_left == auto, margin_right == auto) {
+        (false, false, false) => {
+            margin_right = Value::Length(margin_right.to_px() + underflow, Unit::Px);
+        }
+        (false, false, true) => { margin_right = Value::Length(underflow, Unit::Px); }
+        (false, true, false) => { margin_left = Value::Length(underflow, Unit::Px); }
+        (false, true, true) => {
+            margin_left = Value::Length(underflow / 2.0, Unit::Px);
+            margin_right = Value::Length(underflow / 2.0, Unit::Px);
+        }
+        (true, _, _) => {
+            if margin_left == auto { margin_left = Value::Length(0.0, Unit::Px); }
+            if margin_right == auto { margin_right = Value::Length(0.0, Unit::Px); }
+
+            if underflow >= 0.0 {
+                width = Value::Length(underflow, Unit::Px);
+            } else {
+                width = Value::Length(0.0, Unit::Px);
+                margin_right = Value::Length(margin_right.to_px() + underflow, Unit::Px);
+            }
+        }
+    }
+
+    (width.to_px(), margin_left.to_px(), margin_right.to_px())
+}
+
+/// Build and lay out the box tree for a styled document. `containing_block`
+/// is the viewport, which percentage heights on the root element and
+/// absolutely positioned boxes without a positioned ancestor are relative
+/// to; otherwise heights are determined by the content.
+pub fn layout_tree<'a>(node: &'a StyledNode<'a>, containing_block: Dimensions) -> LayoutBox<'a> {
+    layout_tree_scrolled(node, containing_block, 0.0, 0.0)
+}
+
+/// Like `layout_tree`, but with the page scrolled so the viewport's top
+/// left is at `(scroll_x, scroll_y)`, which moves fixed and sticky boxes.
+pub fn layout_tree_scrolled<'a>(node: &'a StyledNode<'a>, mut containing_block: Dimensions,
+                                scroll_x: f32, scroll_y: f32) -> LayoutBox<'a> {
+    let initial_containing_block = containing_block.content;
+    let viewport = Rect {
+        x: initial_containing_block.x + scroll_x,
+        y: initial_containing_block.y + scroll_y,
+        ..initial_containing_block
+    };
+
+    // The root element's margins don't collapse with its children's.
+    let mut root_box = build_layout_tree(node);
+    root_box.establishes_bfc = true;
+    if root_box.is_absolutely_positioned() {
+        root_box.set_static_position(initial_containing_block.x, initial_containing_block.y);
+    } else {
+        let offset = root_box.leading_margin(initial_containing_block.width).resolve();
+        containing_block.content.height = offset;
+        let margins = root_box.layout(containing_block, Some(initial_containing_block.height),
+                                      &mut FloatContext::default());
+        root_box.translate(0.0, margins.top.resolve() - offset);
+    }
+    root_box.layout_positioned(initial_containing_block, viewport);
+    root_box
+}
+
+pub fn build_layout_tree<'a>(styled_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
+    build_box(styled_node, false, &mut Counters::default())
+}
+
+/// Build the box for `styled_node` and the boxes inside it, counting it in
+/// `counters`. An inline-level box is made block-level if `blockify` is
+/// set.
+fn build_box<'a>(styled_node: &'a StyledNode<'a>, blockify: bool, counters: &mut Counters) -> LayoutBox<'a> {
+    // The counters an element makes are in scope for the elements after it
+    // as well as its descendants, so those its children make go with them.
+    counters.update(styled_node);
+    let scope = counters.enter();
+    let root = build_box_in_scope(styled_node, blockify, counters);
+    counters.leave(scope);
+    root
+}
+
+fn build_box_in_scope<'a>(styled_node: &'a StyledNode<'a>, blockify: bool, counters: &mut Counters) -> LayoutBox<'a> {
+    // Floats and absolutely positioned boxes are always block-level.
+    let mut root = LayoutBox::new(match styled_node.display() {
+        Display::None => panic!("Root node has display: none."),
+        _ if is_replaced(styled_node) => BoxType::ReplacedNode(styled_node),
+        Display::Block | Display::InlineBlock | Display::ListItem => BoxType::BlockNode(styled_node),
+        Display::Flex | Display::InlineFlex => BoxType::FlexNode(styled_node),
+        Display::Grid | Display::InlineGrid => BoxType::GridNode(styled_node),
+        Display::Inline if blockify || styled_node.is_out_of_flow() => BoxType::BlockNode(styled_node),
+        Display::Inline => BoxType::InlineNode(styled_node),
+        Display::Table | Display::InlineTable => BoxType::TableNode(TablePart::Table, styled_node),
+        // Table parts away from a table are laid out as blocks.
+        _ if blockify || styled_node.is_out_of_flow() => BoxType::BlockNode(styled_node),
+        display => BoxType::TableNode(table_part(display).unwrap(), styled_node),
+    });
+    match root.box_type {
+        BoxType::BlockNode(_) => root.establishes_bfc = styled_node.establishes_bfc(),
+        // Like a formatting context root, a replaced element doesn't
+        // overlap floats.
+        BoxType::ReplacedNode(_) => {
+            root.establishes_bfc = true;
+            return root;
+        }
+        BoxType::FlexNode(_) | BoxType::GridNode(_) => {
+            root.establishes_bfc = true;
+            build_items(&mut root, styled_node, counters);
+            return root;
+        }
+        BoxType::TableNode(TablePart::Cell, _) | BoxType::TableNode(TablePart::Caption, _) => {
+            root.establishes_bfc = true;
+        }
+        BoxType::TableNode(TablePart::Column, _) => return root,
+        BoxType::TableNode(part, _) => {
+            root.establishes_bfc = part == TablePart::Table;
+            build_table_children(&mut root, styled_node, counters);
+            return root;
+        }
+        _ => {}
+    }
+    if styled_node.display() == Display::ListItem {
+        root.add_marker(styled_node, counters);
+    }
+
+    for child in &styled_node.children {
+        match child.display() {
+            Display::None => {} // Skip nodes with display none
+            // Out-of-flow boxes go where they are in the source, without
+            // breaking up a run of inline content.
+            _ if child.is_out_of_flow() => {
+                let container = match root.children.last_mut() {
+                    Some(last) if matches!(last.box_type, BoxType::AnonymousBlock(_)) => last,
+                    _ => &mut root,
+                };
+                container.children.push(build_box(child, false, counters));
+            }
+            Display::Block | Display::ListItem | Display::Flex | Display::Grid | Display::Table => {
+                root.children.push(build_box(child, false, counters))
+            }
+            // White space between table parts doesn't end their table.
+            Display::Inline if is_whitespace(child) && matches!(root.children.last(),
+                Some(last) if matches!(last.box_type, BoxType::AnonymousTableNode(..))) => {}
+            Display::Inline | Display::InlineBlock | Display::InlineFlex | Display::InlineGrid
+            | Display::InlineTable => {
+                root.get_inline_container().children.push(build_box(child, false, counters))
+            }
+            _ => root.anonymous_table().children.push(build_box(child, false, counters)),
+        }
+    }
+    root.fix_anonymous_tables();
+    root
+}
+
+/// The children of a table, row group, row or column group, leaving out
+/// the white space between them, wrapped in any anonymous table parts
+/// they need.
+fn build_table_children<'a>(root: &mut LayoutBox<'a>, styled_node: &'a StyledNode<'a>, counters: &mut Counters) {
+    for child in &styled_node.children {
+        match child.display() {
+            Display::None => {}
+            _ if is_whitespace(child) => {}
+            _ => root.children.push(build_box(child, false, counters)),
+        }
+    }
+    root.fix_table_children();
+}
+
+/// Whether a node is text that is only white space.
+fn is_whitespace(styled_node: &StyledNode) -> bool {
+    match styled_node.node.node_type {
+        NodeType::Text(ref text) => text.chars().all(char::is_whitespace),
+        _ => false,
+    }
+}
+
+/// Every child of a flex or grid container is an item, and a formatting
+/// context root: elements are made block-level, and each run of text is
+/// wrapped in an anonymous item.
+fn build_items<'a>(root: &mut LayoutBox<'a>, styled_node: &'a StyledNode<'a>, counters: &mut Counters) {
+    for child in &styled_node.children {
+        match (child.display(), &child.node.node_type) {
+            (Display::None, _) => {}
+            (_, &NodeType::Text(_)) => root.get_inline_container().children.push(build_box(child, false, counters)),
+            _ => {
+                let mut item = build_box(child, true, counters);
+                item.establishes_bfc = true;
+                root.children.push(item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use html;
+    use node::NodeType;
+    use parser;
+    use style;
+    use super::*;
+
+    /// Lay out `html` in a viewport `width` wide, with the user-agent
+    /// stylesheet and `css`, and look at the root box with `inspect`.
+    pub fn with_layout<T, F: FnOnce(&LayoutBox) -> T>(html: &str, css: &str, width: f32, inspect: F) -> T {
+        let root = html::parse(html);
+        let stylesheets = [style::user_agent_stylesheet(), parser::parse_css(css.to_string())];
+        let styled = style::style_tree(&root, &stylesheets);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = width;
+        viewport.content.height = 600.0;
+        inspect(&layout_tree(&styled, viewport))
+    }
+
+    /// The content rects of the boxes of elements with an id, by id.
+    pub fn layout(html: &str, css: &str, width: f32) -> HashMap<String, Rect> {
+        with_layout(html, css, width, |root| {
+            let mut rects = HashMap::new();
+            collect_rects(root, &mut rects);
+            rects
+        })
+    }
+
+    pub fn collect_rects(layout_box: &LayoutBox, rects: &mut HashMap<String, Rect>) {
+        match layout_box.box_type {
+            BoxType::AnonymousBlock(_) | BoxType::AnonymousTableNode(..) | BoxType::InlineNode(_)
+            | BoxType::MarkerNode(..) => {}
+            _ => if let NodeType::Element(ref elem) = layout_box.get_style_node().node.node_type {
+                if let Some(id) = elem.id() {
+                    rects.insert(id.clone(), layout_box.dimensions.content);
+                }
+            },
+        }
+        for child in &layout_box.children {
+            collect_rects(child, rects);
+        }
+    }
+
+    /// The vertical positions of the boxes with the ids `ids`.
+    fn tops(html: &str, css: &str, ids: &[&str]) -> Vec<f32> {
+        let rects = layout(html, css, 400.0);
+        ids.iter().map(|id| rects[*id].y).collect()
+    }
+
+    const CSS: &str = "body { margin: 0 } div { height: 10px }";
+
+    #[test]
+    fn sibling_margins_collapse() {
+        let html = "<div id=a style='margin-bottom: 20px'></div><div id=b style='margin-top: 30px'></div>";
+        assert_eq!(tops(html, CSS, &["a", "b"]), [0.0, 40.0]);
+        let html = "<div id=a style='margin-bottom: 20px'></div><div id=b style='margin-top: -5px'></div>";
+        assert_eq!(tops(html, CSS, &["a", "b"]), [0.0, 25.0]);
+    }
+
+    #[test]
+    fn parent_and_child_margins_collapse() {
+        let html = "<section id=p style='margin-top: 10px'><div id=c style='margin-top: 20px'></div></section>";
+        assert_eq!(tops(html, CSS, &["p", "c"]), [20.0, 20.0]);
+        // A border keeps them apart, as does a new block formatting context.
+        let html = "<section id=p style='margin-top: 10px; border-top: 1px solid'>\
+                    <div id=c style='margin-top: 20px'></div></section>";
+        assert_eq!(tops(html, CSS, &["p", "c"]), [11.0, 31.0]);
+        let html = "<section id=p style='margin-top: 10px; overflow: hidden'>\
+                    <div id=c style='margin-top: 20px'></div></section>";
+        assert_eq!(tops(html, CSS, &["p", "c"]), [10.0, 30.0]);
+        // The last child's bottom margin collapses with its parent's.
+        let html = "<section style='margin-bottom: 10px'><div id=c style='margin-bottom: 20px'></div></section>\
+                    <div id=n></div>";
+        assert_eq!(tops(html, CSS, &["c", "n"]), [0.0, 30.0]);
+    }
+
+    #[test]
+    fn margins_collapse_through_empty_blocks() {
+        let html = "<div id=a></div><p style='margin: 10px 0'></p><div id=b style='margin-top: 15px'></div>";
+        assert_eq!(tops(html, CSS, &["a", "b"]), [0.0, 25.0]);
+    }
+
+    /// The content width and height of the box with the id `id`.
+    fn size(html: &str, css: &str, id: &str) -> (f32, f32) {
+        let rect = layout(html, css, 400.0)[id];
+        (rect.width, rect.height)
+    }
+
+    #[test]
+    fn min_and_max_widths() {
+        let css = "body { margin: 0 } div { height: 10px }";
+        assert_eq!(size("<div id=a style='width: 300px; max-width: 200px'>", css, "a").0, 200.0);
+        assert_eq!(size("<div id=a style='max-width: 50%'>", css, "a").0, 200.0);
+        assert_eq!(size("<div id=a style='width: 50px; min-width: 25%'>", css, "a").0, 100.0);
+        // The minimum wins over the maximum.
+        assert_eq!(size("<div id=a style='max-width: 100px; min-width: 150px'>", css, "a").0, 150.0);
+        // A clamped width is solved again, so auto margins still center it.
+        let rects = layout("<div id=a style='max-width: 100px; margin: 0 auto'>", css, 400.0);
+        assert_eq!((rects["a"].x, rects["a"].width), (150.0, 100.0));
+    }
+
+    #[test]
+    fn min_and_max_heights() {
+        let css = "body { margin: 0 }";
+        assert_eq!(size("<div id=a style='height: 100px; max-height: 50px'>", css, "a").1, 50.0);
+        assert_eq!(size("<div id=a style='height: 10px; min-height: 30px'>", css, "a").1, 30.0);
+        assert_eq!(size("<div id=a style='min-height: 80px; max-height: 50px'>", css, "a").1, 80.0);
+        // Content taller than the maximum overflows.
+        let html = "<div id=a style='max-height: 20px'><p id=b style='height: 50px; margin: 0'></p></div>";
+        assert_eq!(size(html, css, "a").1, 20.0);
+    }
+
+    #[test]
+    fn box_sizing() {
+        let css = "body { margin: 0 } div { width: 100px; height: 50px; padding: 10px; border: 5px solid }";
+        assert_eq!(size("<div id=a>", css, "a"), (100.0, 50.0));
+        // Border-box sizes include the padding and border.
+        assert_eq!(size("<div id=a style='box-sizing: border-box'>", css, "a"), (70.0, 20.0));
+        let html = "<div id=a style='box-sizing: border-box; max-width: 60px; min-height: 80px'>";
+        assert_eq!(size(html, css, "a"), (30.0, 50.0));
+        // The content can't be narrower than nothing.
+        assert_eq!(size("<div id=a style='box-sizing: border-box; width: 20px'>", css, "a").0, 0.0);
+    }
+
+    #[test]
+    fn percentage_heights() {
+        let css = "body { margin: 0 } p { margin: 0; height: 10px }";
+        let html = "<div style='height: 200px'><div id=a style='height: 25%'></div></div>";
+        assert_eq!(size(html, css, "a").1, 50.0);
+        // Against a definite percentage height, down from the viewport.
+        let css_full = "html, body { height: 100% } body { margin: 0 }";
+        let html = "<div style='height: 50%'><div id=a style='height: 50%'></div></div>";
+        assert_eq!(size(html, css_full, "a").1, 150.0);
+        // A parent with a definite maximum is still indefinite.
+        let html = "<div style='max-height: 200px'><div id=a style='height: 50%'><p></p></div></div>";
+        assert_eq!(size(html, css, "a").1, 10.0);
+    }
+
+    #[test]
+    fn percentage_heights_of_indefinite_parents_are_auto() {
+        let css = "body { margin: 0 } p { margin: 0; height: 10px }";
+        let html = "<div id=a style='height: 50%'><p></p><p></p></div>";
+        assert_eq!(size(html, css, "a").1, 20.0);
+        let html = "<div><div id=a style='height: 50%; min-height: 5%'><p></p></div></div>";
+        assert_eq!(size(html, css, "a").1, 10.0);
+    }
+}
@@ -0,0 +1,302 @@
+use css::{Value, Unit};
+use style::{Position, StyledNode};
+use super::{BoxType, LayoutBox, Rect};
+use super::float::FloatContext;
+
+/// An inset property (`top`, `right`, `bottom` or `left`) in pixels, with
+/// percentages of `basis`, or `None` if it is `auto`.
+fn inset(style: &StyledNode, name: &str, basis: f32) -> Option<f32> {
+    match style.value(name) {
+        Some(value @ Value::Length(..)) | Some(value @ Value::Percentage(_)) => Some(value.to_px_of(basis)),
+        Some(Value::Number(0.0)) => Some(0.0),
+        _ => None,
+    }
+}
+
+/// How far a relatively positioned box moves from its place in the flow.
+/// `left` wins over `right` and `top` over `bottom`.
+pub(super) fn relative_offset(style: &StyledNode, containing_block: Rect) -> (f32, f32) {
+    let dx = match (inset(style, "left", containing_block.width), inset(style, "right", containing_block.width)) {
+        (Some(left), _) => left,
+        (None, Some(right)) => -right,
+        (None, None) => 0.0,
+    };
+    let dy = match (inset(style, "top", containing_block.height), inset(style, "bottom", containing_block.height)) {
+        (Some(top), _) => top,
+        (None, Some(bottom)) => -bottom,
+        (None, None) => 0.0,
+    };
+    (dx, dy)
+}
+
+/// Solve the constraint for an absolutely positioned box along one axis,
+/// from CSS 2.1 sections 10.3.7 and 10.6.4:
+///
+/// start + margin-start + edges + size + margin-end + end = containing size
+///
+/// `insets` and `margins` are the start and end values, `None` if `auto`.
+/// `size` is the content size, already resolved, and `edges` the border and
+/// padding. Returns the used start inset and margins. Without either inset
+/// the box stays at its static position; if the equation is over-constrained
+/// the end inset is ignored.
+fn solve_axis(containing: f32, static_start: f32, insets: (Option<f32>, Option<f32>), size: f32,
+              margins: (Option<f32>, Option<f32>), edges: f32) -> (f32, f32, f32) {
+    let (margin_start, margin_end) = margins;
+    match insets {
+        (None, None) => (static_start, margin_start.unwrap_or(0.0), margin_end.unwrap_or(0.0)),
+        (Some(start), None) => (start, margin_start.unwrap_or(0.0), margin_end.unwrap_or(0.0)),
+        (None, Some(end)) => {
+            let (margin_start, margin_end) = (margin_start.unwrap_or(0.0), margin_end.unwrap_or(0.0));
+            (containing - end - margin_end - size - edges - margin_start, margin_start, margin_end)
+        }
+        (Some(start), Some(end)) => {
+            let free = containing - start - end - size - edges;
+            let (margin_start, margin_end) = match (margin_start, margin_end) {
+                (None, None) if free < 0.0 => (0.0, free),
+                (None, None) => (free / 2.0, free / 2.0),
+                (None, Some(margin_end)) => (free - margin_end, margin_end),
+                (Some(margin_start), None) => (margin_start, free - margin_start),
+                (Some(margin_start), Some(margin_end)) => (margin_start, margin_end),
+            };
+            (start, margin_start, margin_end)
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Place the positioned boxes in this subtree once normal flow has been
+    /// laid out: absolutely positioned boxes against `containing_block`,
+    /// the padding box of their nearest positioned ancestor, fixed boxes
+    /// against the `viewport`, and relatively positioned and sticky boxes
+    /// offset from where the flow put them.
+    pub(super) fn layout_positioned(&mut self, containing_block: Rect, viewport: Rect) {
+        let content = self.dimensions.content;
+        let containing_block = match self.box_type {
+            BoxType::BlockNode(node) | BoxType::FlexNode(node) | BoxType::GridNode(node)
+            | BoxType::TableNode(_, node) if node.position() != Position::Static => {
+                self.dimensions.padding_box()
+            }
+            _ => containing_block,
+        };
+        for child in &mut self.children {
+            if let BoxType::BlockNode(style) | BoxType::ReplacedNode(style) | BoxType::FlexNode(style)
+                | BoxType::GridNode(style) | BoxType::TableNode(_, style) = child.box_type {
+                match style.position() {
+                    Position::Absolute => child.layout_absolute(containing_block),
+                    Position::Fixed => child.layout_absolute(viewport),
+                    Position::Relative => {
+                        let (dx, dy) = relative_offset(style, content);
+                        child.translate(dx, dy);
+                    }
+                    Position::Sticky => child.apply_sticky_offset(content, viewport),
+                    Position::Static => {}
+                }
+            }
+            child.layout_positioned(containing_block, viewport);
+        }
+    }
+
+    /// Lay out an absolutely positioned box, whose static position has been
+    /// recorded by `set_static_position`. An `auto` width shrinks to fit the
+    /// content and an `auto` height is the height of the content, unless
+    /// both insets are given, in which case the box stretches between them.
+    fn layout_absolute(&mut self, containing_block: Rect) {
+        let style = self.get_style_node();
+        let zero = Value::Length(0.0, Unit::Px);
+        let (width, height) = (containing_block.width, containing_block.height);
+        let static_left = self.dimensions.content.x - containing_block.x;
+        let static_top = self.dimensions.content.y - containing_block.y;
+        let margin = |name: &str| match style.lookup(name, &zero) {
+            Value::Keyword(ref keyword) if keyword == "auto" => None,
+            value => Some(value.to_px_of(width)),
+        };
+
+        {
+            let d = &mut self.dimensions;
+            d.border.left = style.lookup("border-left-width", &zero).to_px();
+            d.border.right = style.lookup("border-right-width", &zero).to_px();
+            d.border.top = style.lookup("border-top-width", &zero).to_px();
+            d.border.bottom = style.lookup("border-bottom-width", &zero).to_px();
+            d.padding.left = style.lookup("padding-left", &zero).to_px_of(width);
+            d.padding.right = style.lookup("padding-right", &zero).to_px_of(width);
+            d.padding.top = style.lookup("padding-top", &zero).to_px_of(width);
+            d.padding.bottom = style.lookup("padding-bottom", &zero).to_px_of(width);
+        }
+        let d = self.dimensions;
+        let horizontal_edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+        let vertical_edges = d.border.top + d.border.bottom + d.padding.top + d.padding.bottom;
+
+        let left = inset(style, "left", width);
+        let right = inset(style, "right", width);
+        let (margin_left, margin_right) = (margin("margin-left"), margin("margin-right"));
+        let content_width = match self.size_limit("width", horizontal_edges, Some(width)) {
+            Some(content_width) => content_width,
+            None => {
+                let available = width - left.unwrap_or(0.0) - right.unwrap_or(0.0)
+                    - margin_left.unwrap_or(0.0) - margin_right.unwrap_or(0.0) - horizontal_edges;
+                if left.is_some() && right.is_some() {
+                    available.max(0.0)
+                } else {
+                    self.shrink_to_fit(available)
+                }
+            }
+        };
+        let content_width = self.clamp_width(content_width, horizontal_edges, width);
+        let (left, margin_left, margin_right) = solve_axis(
+            width, static_left, (left, right), content_width, (margin_left, margin_right), horizontal_edges);
+
+        let top = inset(style, "top", height);
+        let bottom = inset(style, "bottom", height);
+        let (margin_top, margin_bottom) = (margin("margin-top"), margin("margin-bottom"));
+        let definite_height = match self.size_limit("height", vertical_edges, Some(height)) {
+            Some(content_height) => Some(content_height),
+            None => match (top, bottom) {
+                (Some(top), Some(bottom)) => Some((height - top - bottom - margin_top.unwrap_or(0.0)
+                    - margin_bottom.unwrap_or(0.0) - vertical_edges).max(0.0)),
+                _ => None,
+            },
+        }.map(|content_height| self.clamp_height(content_height, Some(height)));
+
+        {
+            let d = &mut self.dimensions;
+            d.content.width = content_width;
+            d.margin.left = margin_left;
+            d.margin.right = margin_right;
+            d.content.x = containing_block.x + left + margin_left + d.border.left + d.padding.left;
+            d.content.y = containing_block.y;
+            d.content.height = 0.0;
+        }
+        self.establishes_bfc = true;
+        self.layout_contents(definite_height, &mut FloatContext::default());
+
+        let content_height = definite_height
+            .unwrap_or_else(|| self.clamp_height(self.dimensions.content.height, Some(height)));
+        let (top, margin_top, margin_bottom) = solve_axis(
+            height, static_top, (top, bottom), content_height, (margin_top, margin_bottom), vertical_edges);
+        let d = &mut self.dimensions;
+        d.content.height = content_height;
+        d.margin.top = margin_top;
+        d.margin.bottom = margin_bottom;
+        let y = containing_block.y + top + margin_top + d.border.top + d.padding.top;
+        let dy = y - d.content.y;
+        self.translate(0.0, dy);
+    }
+
+    /// Move a sticky box, laid out in the flow, as little as needed to keep
+    /// it within its insets from the edges of the `viewport`, without leaving
+    /// its `containing_block`.
+    fn apply_sticky_offset(&mut self, containing_block: Rect, viewport: Rect) {
+        let style = self.get_style_node();
+        let border_box = self.dimensions.border_box();
+        let margin_box = self.dimensions.margin_box();
+
+        let mut dy = 0.0;
+        if let Some(bottom) = inset(style, "bottom", viewport.height) {
+            let overflow = border_box.y + border_box.height - (viewport.y + viewport.height - bottom);
+            if overflow > 0.0 {
+                dy = -overflow.min(margin_box.y - containing_block.y).max(0.0);
+            }
+        }
+        if let Some(top) = inset(style, "top", viewport.height) {
+            let underflow = viewport.y + top - border_box.y;
+            if underflow > 0.0 {
+                let room = containing_block.y + containing_block.height - (margin_box.y + margin_box.height);
+                dy = underflow.min(room).max(0.0);
+            }
+        }
+
+        let mut dx = 0.0;
+        if let Some(right) = inset(style, "right", viewport.width) {
+            let overflow = border_box.x + border_box.width - (viewport.x + viewport.width - right);
+            if overflow > 0.0 {
+                dx = -overflow.min(margin_box.x - containing_block.x).max(0.0);
+            }
+        }
+        if let Some(left) = inset(style, "left", viewport.width) {
+            let underflow = viewport.x + left - border_box.x;
+            if underflow > 0.0 {
+                let room = containing_block.x + containing_block.width - (margin_box.x + margin_box.width);
+                dx = underflow.min(room).max(0.0);
+            }
+        }
+        self.translate(dx, dy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use boxes::{layout_tree_scrolled, Dimensions};
+    use boxes::tests::{collect_rects, layout};
+    use html;
+    use parser;
+    use style;
+
+    const CSS: &str = "body { margin: 0 } div { height: 10px }";
+
+    fn position(html: &str, id: &str) -> (f32, f32) {
+        let rect = layout(html, CSS, 400.0)[id];
+        (rect.x, rect.y)
+    }
+
+    #[test]
+    fn relative_offsets() {
+        let html = "<div id=a style='position: relative; top: 10px; left: 20px'></div><div id=b></div>";
+        assert_eq!(position(html, "a"), (20.0, 10.0));
+        // The box keeps its place in the flow.
+        assert_eq!(position(html, "b"), (0.0, 10.0));
+        assert_eq!(position("<div id=a style='position: relative; bottom: 5px; right: 5px'>", "a"), (-5.0, -5.0));
+        // `left` wins over `right` and `top` over `bottom`, and percentages
+        // are of the containing block.
+        let html = "<div id=a style='position: relative; left: 10%; right: 5px; top: 1px; bottom: 5px'>";
+        assert_eq!(position(html, "a"), (40.0, 1.0));
+    }
+
+    #[test]
+    fn absolute_against_the_nearest_positioned_ancestor() {
+        let html = "<div></div><section style='position: relative; margin-left: 30px; padding: 10px; height: 100px'>\
+                    <div><div id=a style='position: absolute; top: 5px; left: 5px; width: 20px'></div></div>\
+                    </section>";
+        assert_eq!(position(html, "a"), (35.0, 15.0));
+        // Without one, the initial containing block, with insets from its
+        // bottom right.
+        let html = "<section><div id=a style='position: absolute; right: 0; bottom: 0; width: 20px'></div></section>";
+        assert_eq!(position(html, "a"), (380.0, 590.0));
+        // Without insets, the box stays where it would be in the flow.
+        let html = "<div></div><div id=a style='position: absolute; margin-left: 5px'></div><div id=b></div>";
+        assert_eq!(position(html, "a"), (5.0, 10.0));
+        assert_eq!(position(html, "b"), (0.0, 10.0));
+    }
+
+    #[test]
+    fn absolute_sizes() {
+        let html = "<div style='position: relative; width: 200px; height: 100px'>\
+                    <p id=a style='position: absolute; left: 10px; right: 30px; top: 0; bottom: 20px; margin: 0'>\
+                    </p></div>";
+        let rect = layout(html, CSS, 400.0)["a"];
+        assert_eq!((rect.width, rect.height), (160.0, 80.0));
+        // Auto margins center a box between its insets.
+        let html = "<div style='position: relative; width: 200px'>\
+                    <div id=a style='position: absolute; left: 0; right: 0; width: 100px; margin: 0 auto'></div></div>";
+        assert_eq!(position(html, "a").0, 50.0);
+    }
+
+    #[test]
+    fn fixed_against_the_viewport() {
+        let html = "<div style='position: relative; margin: 50px'>\
+                    <div id=a style='position: fixed; top: 10px; left: 10px'></div></div>";
+        assert_eq!(position(html, "a"), (10.0, 10.0));
+
+        // Scrolling moves it with the viewport.
+        let root = html::parse(html);
+        let stylesheets = [style::user_agent_stylesheet(), parser::parse_css(CSS.to_string())];
+        let styled = style::style_tree(&root, &stylesheets);
+        let mut viewport = Dimensions::default();
+        viewport.content.width = 400.0;
+        viewport.content.height = 600.0;
+        let mut rects = HashMap::new();
+        collect_rects(&layout_tree_scrolled(&styled, viewport, 0.0, 100.0), &mut rects);
+        assert_eq!((rects["a"].x, rects["a"].y), (10.0, 110.0));
+    }
+}
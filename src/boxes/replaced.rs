@@ -0,0 +1,152 @@
+use css::{Value, Unit};
+use node::NodeType;
+use style::StyledNode;
+use super::{BlockMargins, CollapsedMargin, LayoutBox};
+
+/// The HTML elements whose content comes from outside the document.
+const REPLACED_ELEMENTS: [&str; 6] = ["img", "video", "canvas", "iframe", "embed", "object"];
+
+/// The size of replaced content that has no natural width or height.
+const DEFAULT_WIDTH: f32 = 300.0;
+const DEFAULT_HEIGHT: f32 = 150.0;
+
+/// Whether an element is replaced: laid out as a box of some size with
+/// nothing inside it.
+pub(super) fn is_replaced(style: &StyledNode) -> bool {
+    match style.node.node_type {
+        NodeType::Element(ref element) => element.is_html() && REPLACED_ELEMENTS.contains(&&*element.tag_name),
+        _ => false,
+    }
+}
+
+/// The natural width, height and aspect ratio of a replaced element. There
+/// are no images to load, so only a canvas has a natural size, that of its
+/// bitmap, from its `width` and `height` attributes; for the other elements
+/// they are presentational hints for the `width` and `height` properties.
+/// The attributes give the aspect ratio, and `aspect-ratio` gives one
+/// without both of them.
+fn natural_size(style: &StyledNode) -> (Option<f32>, Option<f32>, Option<f32>) {
+    let attribute = |name: &str| match style.node.node_type {
+        NodeType::Element(ref element) => element.attribute(name)
+            .and_then(|value| value.trim().trim_end_matches("px").parse::<f32>().ok())
+            .filter(|&value| value >= 0.0),
+        _ => None,
+    };
+    let (width, height) = (attribute("width"), attribute("height"));
+    let natural = match (width, height) {
+        (Some(width), Some(height)) if width > 0.0 && height > 0.0 => Some(width / height),
+        _ => None,
+    };
+
+    // `aspect-ratio: auto <ratio>` only applies without a natural ratio.
+    let values = match style.value("aspect-ratio") {
+        Some(Value::List(values)) => values,
+        Some(value) => vec![value],
+        None => vec![],
+    };
+    let numbers: Vec<f32> = values.iter().filter_map(|value| match *value {
+        Value::Number(n) => Some(n),
+        _ => None,
+    }).collect();
+    let specified = match numbers[..] {
+        [ratio] => Some(ratio),
+        [width, height] if height > 0.0 => Some(width / height),
+        _ => None,
+    }.filter(|&ratio| ratio > 0.0);
+    let auto = values.contains(&Value::Keyword("auto".to_string()));
+    let ratio = if auto { natural.or(specified) } else { specified.or(natural) };
+    match style.node.node_type {
+        NodeType::Element(ref element) if element.tag_name == "canvas" => (width, height, ratio),
+        _ => (None, None, ratio),
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// The content width of a replaced element with `width: auto`: from
+    /// the height if that is given, and otherwise the natural width, scaled
+    /// to keep the aspect ratio within the `min-*` and `max-*` limits.
+    /// Percentages are of `containing_width`, or ignored if it is `None`.
+    pub(super) fn replaced_width(&self, containing_width: Option<f32>) -> f32 {
+        let style = self.get_style_node();
+        let (width, height, ratio) = natural_size(style);
+        let zero = Value::Length(0.0, Unit::Px);
+        let basis = containing_width.unwrap_or(0.0);
+        let edges = |first: &str, second: &str| {
+            style.lookup(&format!("border-{}-width", first), &zero).to_px()
+                + style.lookup(&format!("border-{}-width", second), &zero).to_px()
+                + style.lookup(&format!("padding-{}", first), &zero).to_px_of(basis)
+                + style.lookup(&format!("padding-{}", second), &zero).to_px_of(basis)
+        };
+        let (horizontal, vertical) = (edges("left", "right"), edges("top", "bottom"));
+        let clamp_width = |mut width: f32| {
+            if let Some(max_width) = self.size_limit("max-width", horizontal, containing_width) {
+                width = width.min(max_width);
+            }
+            width.max(self.size_limit("min-width", horizontal, containing_width).unwrap_or(0.0))
+        };
+        let clamp_height = |mut height: f32| {
+            if let Some(max_height) = self.size_limit("max-height", vertical, None) {
+                height = height.min(max_height);
+            }
+            height.max(self.size_limit("min-height", vertical, None).unwrap_or(0.0))
+        };
+
+        let ratio = match ratio {
+            Some(ratio) => ratio,
+            None => return width.unwrap_or(DEFAULT_WIDTH),
+        };
+        if let Some(height) = self.size_limit("height", vertical, None) {
+            return clamp_height(height) * ratio;
+        }
+        let width = clamp_width(width.or(height.map(|height| height * ratio)).unwrap_or(DEFAULT_WIDTH));
+        clamp_width(clamp_height(width / ratio) * ratio)
+    }
+
+    /// Lay out a replaced element whose width is known, giving it the
+    /// height that keeps its aspect ratio if the height is `auto`.
+    pub(super) fn layout_replaced(&mut self) -> BlockMargins {
+        let (_, height, ratio) = natural_size(self.get_style_node());
+        let d = &mut self.dimensions;
+        d.content.height = match ratio {
+            Some(ratio) => d.content.width / ratio,
+            None => height.unwrap_or(DEFAULT_HEIGHT),
+        };
+        BlockMargins {
+            top: CollapsedMargin::new(d.margin.top),
+            bottom: CollapsedMargin::new(d.margin.bottom),
+            collapses_through: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use boxes::tests::layout;
+
+    fn size(html: &str) -> (f32, f32) {
+        let rect = layout(html, "body { margin: 0 }", 400.0)["a"];
+        (rect.width, rect.height)
+    }
+
+    #[test]
+    fn size_attributes_are_hints() {
+        assert_eq!(size("<img id=a width=20 height=10>"), (20.0, 10.0));
+        assert_eq!(size("<img id=a width=20px height=10.5>"), (20.0, 10.5));
+        assert_eq!(size("<img id=a width=50% height=10>"), (200.0, 10.0));
+        // Any author style wins over them.
+        assert_eq!(size("<img id=a width=20 height=10 style='width: 100px'>"), (100.0, 10.0));
+        // They still give the aspect ratio for an `auto` size.
+        assert_eq!(size("<img id=a width=20 height=10 style='width: 40px; height: auto'>"), (40.0, 20.0));
+        assert_eq!(size("<img id=a width=200 height=100 style='max-width: 100px; height: auto'>"), (100.0, 50.0));
+    }
+
+    #[test]
+    fn default_and_natural_sizes() {
+        assert_eq!(size("<img id=a>"), (300.0, 150.0));
+        assert_eq!(size("<video id=a width=20></video>"), (20.0, 150.0));
+        assert_eq!(size("<img id=a style='height: 50px; aspect-ratio: 2'>"), (100.0, 50.0));
+        // A canvas's attributes are the natural size of its bitmap.
+        assert_eq!(size("<canvas id=a></canvas>"), (300.0, 150.0));
+        assert_eq!(size("<canvas id=a width=40 height=20 style='width: 80px'></canvas>"), (80.0, 40.0));
+    }
+}
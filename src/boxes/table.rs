@@ -0,0 +1,925 @@
+use std::mem;
+use css::{Value, Unit};
+use node::NodeType;
+use style::{Display, StyledNode};
+use super::{BlockMargins, BoxType, CollapsedMargin, Dimensions, EdgeSizes, LayoutBox, Rect};
+use super::flex::first_baseline;
+use super::float::FloatContext;
+
+/// The part a box plays in a table.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TablePart {
+    Table,
+    RowGroup,
+    Row,
+    Cell,
+    ColumnGroup,
+    Column,
+    Caption,
+}
+
+/// The table part a `display` value makes, if any.
+pub(super) fn table_part(display: Display) -> Option<TablePart> {
+    match display {
+        Display::Table | Display::InlineTable => Some(TablePart::Table),
+        Display::TableRowGroup | Display::TableHeaderGroup | Display::TableFooterGroup => Some(TablePart::RowGroup),
+        Display::TableRow => Some(TablePart::Row),
+        Display::TableCell => Some(TablePart::Cell),
+        Display::TableColumnGroup => Some(TablePart::ColumnGroup),
+        Display::TableColumn => Some(TablePart::Column),
+        Display::TableCaption => Some(TablePart::Caption),
+        _ => None,
+    }
+}
+
+/// A non-negative integer attribute like `colspan`, or `default` if it is
+/// missing or not a number.
+fn span_attribute(style: &StyledNode, name: &str, default: usize) -> usize {
+    match style.node.node_type {
+        NodeType::Element(ref element) => element.attribute(name)
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(default),
+        _ => default,
+    }
+}
+
+/// The widths of the borders and the padding set by a style, with
+/// percentages of `width`.
+fn style_edges(style: &StyledNode, width: f32) -> (EdgeSizes, EdgeSizes) {
+    let zero = Value::Length(0.0, Unit::Px);
+    let border = |side: &str| style.lookup(&format!("border-{}-width", side), &zero).to_px();
+    let padding = |side: &str| style.lookup(&format!("padding-{}", side), &zero).to_px_of(width);
+    (EdgeSizes { left: border("left"), right: border("right"), top: border("top"), bottom: border("bottom") },
+     EdgeSizes { left: padding("left"), right: padding("right"), top: padding("top"), bottom: padding("bottom") })
+}
+
+/// A cell and the slots of the table grid it covers.
+struct Cell {
+    /// The indices of the children leading from the table to the cell's
+    /// box: through its row group if it has one, its row, and the cell.
+    path: Vec<usize>,
+    row: usize,
+    column: usize,
+    rows: usize,
+    columns: usize,
+    /// The used borders and padding, which with collapsing borders are
+    /// half of the borders shared with the cells around.
+    border: EdgeSizes,
+    padding: EdgeSizes,
+}
+
+impl Cell {
+    fn horizontal_edges(&self) -> f32 {
+        self.border.left + self.border.right + self.padding.left + self.padding.right
+    }
+
+    fn vertical_edges(&self) -> f32 {
+        self.border.top + self.border.bottom + self.padding.top + self.padding.bottom
+    }
+}
+
+/// The rows, columns and cells of a table, as in CSS 2.1 section 17.5.
+struct TableGrid {
+    /// The paths from the table to each row's box, in the order they are
+    /// displayed: header groups first and footer groups last.
+    rows: Vec<Vec<usize>>,
+    cells: Vec<Cell>,
+    columns: usize,
+    /// The widths set on columns by column and column group boxes.
+    column_widths: Vec<Option<f32>>,
+    /// The `border-spacing` across and down.
+    spacing: (f32, f32),
+    /// The table's used borders and padding.
+    border: EdgeSizes,
+    padding: EdgeSizes,
+}
+
+impl TableGrid {
+    /// The total width of the columns from `start` spanning `count`, with
+    /// the spacing between them.
+    fn span_width(&self, widths: &[f32], start: usize, count: usize) -> f32 {
+        widths[start..start + count].iter().sum::<f32>() + self.spacing.0 * (count - 1) as f32
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    pub(super) fn table_part(&self) -> Option<TablePart> {
+        match self.box_type {
+            BoxType::TableNode(part, _) | BoxType::AnonymousTableNode(part, _) => Some(part),
+            _ => None,
+        }
+    }
+
+    fn descendant(&self, path: &[usize]) -> &LayoutBox<'a> {
+        path.iter().fold(self, |parent, &i| &parent.children[i])
+    }
+
+    fn descendant_mut(&mut self, path: &[usize]) -> &mut LayoutBox<'a> {
+        path.iter().fold(self, |parent, &i| &mut parent.children[i])
+    }
+
+    /// Add a child to an anonymous cell: inline-level boxes go in an
+    /// anonymous block, and table parts in an anonymous table.
+    fn push_cell_child(&mut self, child: LayoutBox<'a>) {
+        let inline = match child.box_type {
+            BoxType::InlineNode(_) => true,
+            BoxType::AnonymousBlock(_) | BoxType::AnonymousTableNode(..) => false,
+            _ => matches!(child.get_style_node().display(),
+                Display::Inline | Display::InlineBlock | Display::InlineFlex | Display::InlineGrid
+                | Display::InlineTable),
+        };
+        if inline {
+            self.get_inline_container().children.push(child);
+        } else if child.table_part().is_some_and(|part| part != TablePart::Table) {
+            self.anonymous_table().children.push(child);
+        } else {
+            self.children.push(child);
+        }
+    }
+
+    /// The anonymous table at the end of this block container's children,
+    /// made if there isn't one, to hold table parts found outside a table.
+    pub(super) fn anonymous_table(&mut self) -> &mut LayoutBox<'a> {
+        match self.children.last() {
+            Some(&LayoutBox { box_type: BoxType::AnonymousTableNode(TablePart::Table, _), .. }) => {}
+            _ => {
+                let mut table = LayoutBox::new(BoxType::AnonymousTableNode(TablePart::Table, self.inherited_style()));
+                table.establishes_bfc = true;
+                self.children.push(table);
+            }
+        }
+        self.children.last_mut().unwrap()
+    }
+
+    /// Give the anonymous tables among this box's children the structure
+    /// they need.
+    pub(super) fn fix_anonymous_tables(&mut self) {
+        for child in &mut self.children {
+            if let BoxType::AnonymousTableNode(TablePart::Table, _) = child.box_type {
+                child.fix_table_children();
+            }
+        }
+    }
+
+    /// Wrap the children of a table, row group or row in the anonymous
+    /// table parts CSS 2.1 section 17.2.1 calls for: a table holds
+    /// captions, columns, row groups and rows, a row group holds rows and a
+    /// row holds cells, and anything else is wrapped in an anonymous row or
+    /// cell.
+    pub(super) fn fix_table_children(&mut self) {
+        let (part, style) = match self.box_type {
+            BoxType::TableNode(part, style) | BoxType::AnonymousTableNode(part, style) => (part, style),
+            _ => return,
+        };
+        let wrapper = match part {
+            TablePart::Table | TablePart::RowGroup => TablePart::Row,
+            TablePart::Row => TablePart::Cell,
+            _ => return self.fix_anonymous_tables(),
+        };
+        for child in mem::take(&mut self.children) {
+            let fits = matches!((part, child.table_part()),
+                (TablePart::Table, Some(TablePart::Caption)) | (TablePart::Table, Some(TablePart::Column))
+                | (TablePart::Table, Some(TablePart::ColumnGroup)) | (TablePart::Table, Some(TablePart::RowGroup))
+                | (TablePart::Table, Some(TablePart::Row))
+                | (TablePart::RowGroup, Some(TablePart::Row)) | (TablePart::Row, Some(TablePart::Cell)));
+            if fits {
+                self.children.push(child);
+                continue;
+            }
+            match self.children.last() {
+                Some(last) if matches!(last.box_type, BoxType::AnonymousTableNode(p, _) if p == wrapper) => {}
+                _ => {
+                    let mut anonymous = LayoutBox::new(BoxType::AnonymousTableNode(wrapper, style));
+                    anonymous.establishes_bfc = wrapper == TablePart::Cell;
+                    self.children.push(anonymous);
+                }
+            }
+            let anonymous = self.children.last_mut().unwrap();
+            if wrapper == TablePart::Cell {
+                anonymous.push_cell_child(child);
+            } else {
+                anonymous.children.push(child);
+            }
+        }
+        for child in &mut self.children {
+            child.fix_table_children();
+        }
+    }
+
+    /// Lay out an anonymous table at the bottom of the containing block's
+    /// content so far, as wide as its content needs.
+    pub(super) fn layout_anonymous_table(&mut self, containing_block: Dimensions) {
+        let width = self.shrink_to_fit(containing_block.content.width);
+        {
+            let d = &mut self.dimensions;
+            d.content.x = containing_block.content.x;
+            d.content.y = containing_block.content.y + containing_block.content.height;
+            d.content.width = width;
+            d.content.height = 0.0;
+        }
+        self.layout_table(None);
+    }
+
+    /// The style of a table, which for an anonymous table is `None`.
+    fn table_style(&self) -> Option<&'a StyledNode<'a>> {
+        match self.box_type {
+            BoxType::TableNode(_, style) => Some(style),
+            _ => None,
+        }
+    }
+
+    /// Find the rows and columns of this table and the cells in them, with
+    /// the borders and padding worked out. Percentage padding is of `width`.
+    fn table_grid(&self, width: f32) -> TableGrid {
+        let style = self.table_style();
+        let keyword = |name: &str| match style.and_then(|style| style.value(name)) {
+            Some(Value::Keyword(keyword)) => keyword,
+            _ => String::new(),
+        };
+        let spacing = match style.and_then(|style| style.value("border-spacing")) {
+            Some(Value::List(ref values)) if values.len() == 2 => (values[0].to_px(), values[1].to_px()),
+            Some(value) => (value.to_px(), value.to_px()),
+            None => (0.0, 0.0),
+        };
+        let collapse = keyword("border-collapse") == "collapse";
+        let (border, padding) = match style {
+            Some(style) => style_edges(style, width),
+            None => (EdgeSizes::default(), EdgeSizes::default()),
+        };
+
+        // Header groups go first and footer groups last, whatever their
+        // place in the source. Each run of rows outside a group acts as a
+        // group of its own, which a `rowspan` can't reach beyond.
+        let group_order = |child: &LayoutBox| match child.box_type {
+            BoxType::TableNode(TablePart::RowGroup, style) => match style.display() {
+                Display::TableHeaderGroup => 0,
+                Display::TableFooterGroup => 2,
+                _ => 1,
+            },
+            _ => 1,
+        };
+        let mut order: Vec<usize> = (0..self.children.len()).collect();
+        order.sort_by_key(|&i| group_order(&self.children[i]));
+        let mut rows = vec![];
+        let mut group_ends = vec![];
+        let mut loose_rows = None;
+        for i in order {
+            let child = &self.children[i];
+            match child.table_part() {
+                Some(TablePart::Row) => {
+                    loose_rows.get_or_insert(rows.len());
+                    rows.push(vec![i]);
+                    continue;
+                }
+                Some(TablePart::RowGroup) => {
+                    if let Some(start) = loose_rows.take() {
+                        group_ends.resize(start, 0);
+                        group_ends.resize(rows.len(), rows.len());
+                    }
+                    for (j, row) in child.children.iter().enumerate() {
+                        if row.table_part() == Some(TablePart::Row) {
+                            rows.push(vec![i, j]);
+                        }
+                    }
+                    group_ends.resize(rows.len(), rows.len());
+                }
+                _ => {}
+            }
+        }
+        group_ends.resize(rows.len(), rows.len());
+
+        // Place the cells in the first free slots of their rows, each taking
+        // its `colspan` and `rowspan`.
+        let mut occupied: Vec<Vec<bool>> = vec![vec![]; rows.len()];
+        let mut cells = vec![];
+        let mut columns = 0;
+        for (r, path) in rows.iter().enumerate() {
+            let mut column = 0;
+            for (k, cell) in self.descendant(path).children.iter().enumerate() {
+                let style = match cell.box_type {
+                    BoxType::TableNode(TablePart::Cell, style) => Some(style),
+                    BoxType::AnonymousTableNode(TablePart::Cell, _) => None,
+                    _ => continue,
+                };
+                while occupied[r].get(column).cloned().unwrap_or(false) {
+                    column += 1;
+                }
+                let column_span = style.map_or(1, |style| span_attribute(style, "colspan", 1).clamp(1, 1000));
+                let row_span = match style.map_or(1, |style| span_attribute(style, "rowspan", 1)) {
+                    0 => group_ends[r] - r,
+                    span => span.min(group_ends[r] - r),
+                };
+                for slots in &mut occupied[r..r + row_span] {
+                    if slots.len() < column + column_span {
+                        slots.resize(column + column_span, false);
+                    }
+                    for slot in &mut slots[column..column + column_span] {
+                        *slot = true;
+                    }
+                }
+                let (border, padding) = match style {
+                    Some(style) => style_edges(style, width),
+                    None => (EdgeSizes::default(), EdgeSizes::default()),
+                };
+                let mut cell_path = path.clone();
+                cell_path.push(k);
+                cells.push(Cell { path: cell_path, row: r, column, rows: row_span, columns: column_span, border, padding });
+                column += column_span;
+                columns = columns.max(column);
+            }
+        }
+
+        // Columns take widths from column boxes, and from column groups
+        // without any.
+        let mut column_widths = vec![];
+        for child in &self.children {
+            let mut add = |column: &LayoutBox, style: &StyledNode| {
+                let width = column.size_limit("width", 0.0, None);
+                for _ in 0..span_attribute(style, "span", 1).max(1) {
+                    column_widths.push(width);
+                }
+            };
+            match child.box_type {
+                BoxType::TableNode(TablePart::Column, style) => add(child, style),
+                BoxType::TableNode(TablePart::ColumnGroup, style) => {
+                    let mut any = false;
+                    for column in &child.children {
+                        if let BoxType::TableNode(TablePart::Column, style) = column.box_type {
+                            add(column, style);
+                            any = true;
+                        }
+                    }
+                    if !any {
+                        add(child, style);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let columns = columns.max(column_widths.len());
+        column_widths.resize(columns, None);
+
+        let mut grid = TableGrid { rows, cells, columns, column_widths, spacing, border, padding };
+        if collapse {
+            grid.collapse_borders();
+        }
+        grid
+    }
+}
+
+impl TableGrid {
+    /// Resolve the borders in the collapsing border model of CSS 2.1
+    /// section 17.6.2: the border between two cells, or a cell and the
+    /// edge of the table, is the widest of theirs, and each side takes half
+    /// of it. There is no spacing, and the table has no padding.
+    fn collapse_borders(&mut self) {
+        let rows = self.rows.len();
+        let mut slots = vec![None; rows * self.columns];
+        for (i, cell) in self.cells.iter().enumerate() {
+            for r in cell.row..cell.row + cell.rows {
+                for c in cell.column..cell.column + cell.columns {
+                    slots[r * self.columns + c] = Some(i);
+                }
+            }
+        }
+        let own: Vec<EdgeSizes> = self.cells.iter().map(|cell| cell.border).collect();
+        let (table, columns) = (self.border, self.columns);
+        let neighbours = |r: usize, c: usize, side: fn(&EdgeSizes) -> f32| {
+            slots[r * columns + c].map_or(0.0, |i| side(&own[i]))
+        };
+        let mut edge = EdgeSizes::default();
+        for (i, cell) in self.cells.iter_mut().enumerate() {
+            let (row_range, column_range) = (cell.row..cell.row + cell.rows, cell.column..cell.column + cell.columns);
+            let last_column = cell.column + cell.columns;
+            let last_row = cell.row + cell.rows;
+            let left = if cell.column == 0 {
+                own[i].left.max(table.left)
+            } else {
+                row_range.clone().map(|r| neighbours(r, cell.column - 1, |e| e.right)).fold(own[i].left, f32::max)
+            };
+            let right = if last_column == columns {
+                own[i].right.max(table.right)
+            } else {
+                row_range.clone().map(|r| neighbours(r, last_column, |e| e.left)).fold(own[i].right, f32::max)
+            };
+            let top = if cell.row == 0 {
+                own[i].top.max(table.top)
+            } else {
+                column_range.clone().map(|c| neighbours(cell.row - 1, c, |e| e.bottom)).fold(own[i].top, f32::max)
+            };
+            let bottom = if last_row == rows {
+                own[i].bottom.max(table.bottom)
+            } else {
+                column_range.clone().map(|c| neighbours(last_row, c, |e| e.top)).fold(own[i].bottom, f32::max)
+            };
+            if cell.column == 0 {
+                edge.left = edge.left.max(left);
+            }
+            if last_column == columns {
+                edge.right = edge.right.max(right);
+            }
+            if cell.row == 0 {
+                edge.top = edge.top.max(top);
+            }
+            if last_row == rows {
+                edge.bottom = edge.bottom.max(bottom);
+            }
+            cell.border = EdgeSizes { left: left / 2.0, right: right / 2.0, top: top / 2.0, bottom: bottom / 2.0 };
+        }
+        self.border = EdgeSizes {
+            left: table.left.max(edge.left) / 2.0,
+            right: table.right.max(edge.right) / 2.0,
+            top: table.top.max(edge.top) / 2.0,
+            bottom: table.bottom.max(edge.bottom) / 2.0,
+        };
+        self.padding = EdgeSizes::default();
+        self.spacing = (0.0, 0.0);
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// The min-content and max-content widths of each column, from the
+    /// cells in it, whether a width was given for it, and the largest
+    /// percentage width given for it, which is of the table's width. A cell
+    /// spanning several columns widens them in proportion to their
+    /// max-content widths where they are too narrow for it together.
+    fn column_intrinsic_widths(&self, grid: &TableGrid) -> (Vec<f32>, Vec<f32>, Vec<bool>, Vec<Option<f32>>) {
+        let mut min = vec![0.0f32; grid.columns];
+        let mut max = vec![0.0f32; grid.columns];
+        let mut fixed = vec![false; grid.columns];
+        let mut percent: Vec<Option<f32>> = vec![None; grid.columns];
+        for (c, width) in grid.column_widths.iter().enumerate() {
+            if let Some(width) = *width {
+                max[c] = width;
+                fixed[c] = true;
+            }
+        }
+        let mut spanning = vec![];
+        for cell in &grid.cells {
+            let cell_box = self.descendant(&cell.path);
+            let edges = cell.horizontal_edges();
+            let (content_min, content_max) = cell_box.content_intrinsic_widths();
+            let (cell_min, cell_max, cell_fixed) = match cell_box.size_limit("width", edges, None) {
+                Some(width) => (content_min + edges, width.max(content_min) + edges, true),
+                None => (content_min + edges, content_max + edges, false),
+            };
+            if cell.columns == 1 {
+                min[cell.column] = min[cell.column].max(cell_min);
+                max[cell.column] = max[cell.column].max(cell_max);
+                fixed[cell.column] |= cell_fixed;
+                // Anonymous cells have no width of their own.
+                if let BoxType::TableNode(TablePart::Cell, style) = cell_box.box_type {
+                    if let Some(Value::Percentage(p)) = style.value("width") {
+                        percent[cell.column] = Some(percent[cell.column].map_or(p, |q| q.max(p)));
+                    }
+                }
+            } else {
+                spanning.push((cell.column, cell.columns, cell_min, cell_max));
+            }
+        }
+        for c in 0..grid.columns {
+            max[c] = max[c].max(min[c]);
+        }
+        spanning.sort_by_key(|span| span.1);
+        for (start, count, cell_min, cell_max) in spanning {
+            let range = start..start + count;
+            let spacing = grid.spacing.0 * (count - 1) as f32;
+            let total: f32 = max[range.clone()].iter().sum();
+            let shares: Vec<f32> = range.clone()
+                .map(|c| if total > 0.0 { max[c] / total } else { 1.0 / count as f32 })
+                .collect();
+            let extra = cell_min - spacing - min[range.clone()].iter().sum::<f32>();
+            if extra > 0.0 {
+                for (c, share) in range.clone().zip(&shares) {
+                    min[c] += extra * share;
+                }
+            }
+            let extra = cell_max - spacing - max[range.clone()].iter().sum::<f32>();
+            if extra > 0.0 {
+                for (c, share) in range.clone().zip(&shares) {
+                    max[c] += extra * share;
+                }
+            }
+            for c in range {
+                max[c] = max[c].max(min[c]);
+            }
+        }
+        (min, max, fixed, percent)
+    }
+
+    /// Whether this table uses the fixed table layout algorithm, which
+    /// needs a width to have been given.
+    pub(super) fn is_fixed_layout(&self) -> bool {
+        match self.table_style() {
+            Some(style) => matches!(style.value("table-layout"), Some(Value::Keyword(ref k)) if k == "fixed")
+                && matches!(style.value("width"), Some(Value::Length(..)) | Some(Value::Percentage(_))),
+            None => false,
+        }
+    }
+
+    /// The widths of the columns in `available` space, not counting the
+    /// spacing. In the fixed layout of CSS 2.1 section 17.5.2.1 they come
+    /// from the column boxes and the cells of the first row, and the columns
+    /// without a width share what is left. Otherwise, as browsers do, the
+    /// columns given a percentage width take that much of `available`, as
+    /// far as the min-content widths of the others leave room for it, and
+    /// the others share the rest.
+    fn column_widths(&self, grid: &TableGrid, available: f32) -> Vec<f32> {
+        if self.is_fixed_layout() {
+            let mut widths = grid.column_widths.clone();
+            for cell in grid.cells.iter().filter(|cell| cell.row == 0) {
+                let range = cell.column..cell.column + cell.columns;
+                if widths[range.clone()].iter().any(Option::is_some) {
+                    continue;
+                }
+                let edges = cell.horizontal_edges();
+                if let Some(width) = self.descendant(&cell.path).size_limit("width", edges, Some(available)) {
+                    let share = (width + edges - grid.spacing.0 * (cell.columns - 1) as f32) / cell.columns as f32;
+                    for c in range {
+                        widths[c] = Some(share.max(0.0));
+                    }
+                }
+            }
+            let given: f32 = widths.iter().filter_map(|&width| width).sum();
+            let rest = widths.iter().filter(|width| width.is_none()).count();
+            let share = if rest > 0 { (available - given).max(0.0) / rest as f32 } else { 0.0 };
+            return widths.into_iter().map(|width| width.unwrap_or(share)).collect();
+        }
+
+        let (min, max, fixed, percent) = self.column_intrinsic_widths(grid);
+        let mut widths = min.clone();
+        // Percentages past 100% in all are dropped.
+        let mut percent_left = 100.0f32;
+        for c in 0..grid.columns {
+            if let Some(p) = percent[c] {
+                let p = p.min(percent_left);
+                percent_left -= p;
+                widths[c] = min[c].max(p * available / 100.0);
+            }
+        }
+        let (percentages, auto): (Vec<usize>, Vec<usize>) = (0..grid.columns).partition(|&c| percent[c].is_some());
+        let percent_min: f32 = percentages.iter().map(|&c| min[c]).sum();
+        let percent_total: f32 = percentages.iter().map(|&c| widths[c]).sum();
+        let room = available - auto.iter().map(|&c| min[c]).sum::<f32>();
+        if percent_total > room && percent_total > percent_min {
+            let ratio = ((room - percent_min) / (percent_total - percent_min)).max(0.0);
+            for &c in &percentages {
+                widths[c] = min[c] + (widths[c] - min[c]) * ratio;
+            }
+        }
+        let percent_total: f32 = percentages.iter().map(|&c| widths[c]).sum();
+        if auto.is_empty() {
+            let extra = available - percent_total;
+            if extra > 0.0 && percent_total > 0.0 {
+                for &c in &percentages {
+                    widths[c] += extra * widths[c] / percent_total;
+                }
+            }
+        } else {
+            share_columns(&auto, &min, &max, &fixed, available - percent_total, &mut widths);
+        }
+        widths
+    }
+
+    /// The `vertical-align` of a cell, which it takes from its row and row
+    /// group if it doesn't have its own, as the user-agent stylesheet of
+    /// HTML has cells inherit it.
+    fn cell_vertical_align(&self, path: &[usize]) -> String {
+        for depth in (1..=path.len()).rev() {
+            if let BoxType::TableNode(_, style) = self.descendant(&path[..depth]).box_type {
+                if let Some(Value::Keyword(keyword)) = style.value("vertical-align") {
+                    return keyword;
+                }
+            }
+        }
+        "middle".to_string()
+    }
+
+    /// Lay out the captions, rows and cells of a table whose width and
+    /// position are known, and set the content height to fit them. Columns
+    /// are sized by `column_widths` and rows to fit the tallest cell in
+    /// them; a cell spanning rows taller than them together makes the last
+    /// one taller. Rows grow evenly to fill a `definite_height`. Captions go
+    /// inside the table box, above the rows or below them with
+    /// `caption-side: bottom`.
+    pub(super) fn layout_table(&mut self, definite_height: Option<f32>) -> BlockMargins {
+        let grid = self.table_grid(self.dimensions.content.width);
+        {
+            // Collapsing borders change the table's own borders and padding,
+            // which its margin box stays put around.
+            let d = &mut self.dimensions;
+            let (left, top) = (d.border.left + d.padding.left, d.border.top + d.padding.top);
+            let right = d.border.right + d.padding.right;
+            d.border = grid.border;
+            d.padding = grid.padding;
+            d.content.x -= left - d.border.left - d.padding.left;
+            d.content.y -= top - d.border.top - d.padding.top;
+            d.content.width += left + right - d.border.left - d.padding.left - d.border.right - d.padding.right;
+        }
+        let content = self.dimensions.content;
+        let (spacing_x, spacing_y) = grid.spacing;
+        let outer_spacing = spacing_x * (grid.columns + 1) as f32;
+        let widths = self.column_widths(&grid, content.width - outer_spacing);
+        let columns_width = widths.iter().sum::<f32>() + outer_spacing;
+        self.dimensions.content.width = content.width.max(columns_width);
+        let content = self.dimensions.content;
+
+        // Lay out the cells at their widths to find the heights of the rows.
+        let mut heights: Vec<f32> = grid.rows.iter()
+            .map(|path| self.descendant(path).size_limit("height", 0.0, None).unwrap_or(0.0))
+            .collect();
+        let mut ascents = vec![0.0f32; grid.rows.len()];
+        let mut descents = vec![0.0f32; grid.rows.len()];
+        let mut measured = vec![];
+        for cell in &grid.cells {
+            let width = (grid.span_width(&widths, cell.column, cell.columns) - cell.horizontal_edges()).max(0.0);
+            let baseline_aligned = self.cell_vertical_align(&cell.path) == "baseline";
+            let cell_box = self.descendant_mut(&cell.path);
+            {
+                let d = &mut cell_box.dimensions;
+                d.margin = EdgeSizes::default();
+                d.border = cell.border;
+                d.padding = cell.padding;
+            }
+            cell_box.layout_item(width, None);
+            let content_height = cell_box.dimensions.content.height;
+            let height = cell_box.size_limit("height", cell.vertical_edges(), None)
+                .map_or(content_height, |height| height.max(content_height)) + cell.vertical_edges();
+            let border_box = cell_box.dimensions.border_box();
+            let baseline = first_baseline(cell_box)
+                .unwrap_or(border_box.y + border_box.height - cell.border.bottom - cell.padding.bottom) - border_box.y;
+            measured.push((content_height, baseline));
+            if cell.rows == 1 {
+                heights[cell.row] = heights[cell.row].max(height);
+                if baseline_aligned {
+                    ascents[cell.row] = ascents[cell.row].max(baseline);
+                    descents[cell.row] = descents[cell.row].max(height - baseline);
+                }
+            }
+        }
+        for r in 0..heights.len() {
+            heights[r] = heights[r].max(ascents[r] + descents[r]);
+        }
+        for cell in grid.cells.iter().filter(|cell| cell.rows > 1) {
+            let cell_box = self.descendant(&cell.path);
+            let height = cell_box.dimensions.content.height + cell.vertical_edges();
+            let spanned = heights[cell.row..cell.row + cell.rows].iter().sum::<f32>() + spacing_y * (cell.rows - 1) as f32;
+            if height > spanned {
+                heights[cell.row + cell.rows - 1] += height - spanned;
+            }
+        }
+
+        // Captions are laid out as blocks across the table.
+        let mut captions = vec![];
+        for (i, child) in self.children.iter().enumerate() {
+            if let BoxType::TableNode(TablePart::Caption, style) = child.box_type {
+                let bottom = matches!(style.value("caption-side"), Some(Value::Keyword(ref k)) if k == "bottom");
+                captions.push((i, bottom));
+            }
+        }
+        let layout_caption = |table: &mut LayoutBox<'a>, i: usize, y: f32| {
+            let containing_block = Dimensions {
+                content: Rect { x: content.x, y, width: content.width, height: 0.0 },
+                ..Default::default()
+            };
+            let caption = &mut table.children[i];
+            let margins = caption.layout(containing_block, None, &mut FloatContext::default());
+            caption.translate(0.0, margins.top.resolve());
+            margins.top.resolve() + caption.dimensions.border_box().height + margins.bottom.resolve()
+        };
+        let mut top_captions = 0.0;
+        for &(i, _) in captions.iter().filter(|caption| !caption.1) {
+            top_captions += layout_caption(self, i, content.y + top_captions);
+        }
+
+        let rows_height = |heights: &[f32]| {
+            if heights.is_empty() { 0.0 } else { heights.iter().sum::<f32>() + spacing_y * (heights.len() + 1) as f32 }
+        };
+        if let Some(definite_height) = definite_height {
+            let extra = definite_height - top_captions - rows_height(&heights);
+            if extra > 0.0 && !heights.is_empty() {
+                let share = extra / heights.len() as f32;
+                for height in &mut heights {
+                    *height += share;
+                }
+            }
+        }
+        let mut x = content.x + spacing_x;
+        let column_xs: Vec<f32> = widths.iter().map(|width| {
+            let start = x;
+            x += width + spacing_x;
+            start
+        }).collect();
+        let mut y = content.y + top_captions + spacing_y;
+        let row_ys: Vec<f32> = heights.iter().map(|height| {
+            let start = y;
+            y += height + spacing_y;
+            start
+        }).collect();
+
+        // Put each cell in place at the height of its rows, with its content
+        // aligned within it.
+        let mut row_ascents = vec![0.0f32; grid.rows.len()];
+        for (cell, &(_, baseline)) in grid.cells.iter().zip(&measured) {
+            if cell.rows == 1 && self.cell_vertical_align(&cell.path) == "baseline" {
+                row_ascents[cell.row] = row_ascents[cell.row].max(baseline);
+            }
+        }
+        for (cell, &(content_height, baseline)) in grid.cells.iter().zip(&measured) {
+            let width = (grid.span_width(&widths, cell.column, cell.columns) - cell.horizontal_edges()).max(0.0);
+            let height = heights[cell.row..cell.row + cell.rows].iter().sum::<f32>()
+                + spacing_y * (cell.rows - 1) as f32 - cell.vertical_edges();
+            let free = (height - content_height).max(0.0);
+            let offset = match &*self.cell_vertical_align(&cell.path) {
+                "top" => 0.0,
+                "bottom" => free,
+                "baseline" => (row_ascents[cell.row] - baseline).max(0.0).min(free),
+                _ => free / 2.0,
+            };
+            let cell_box = self.descendant_mut(&cell.path);
+            cell_box.layout_item(width, Some(height.max(0.0)));
+            for child in &mut cell_box.children {
+                child.translate(0.0, offset);
+            }
+            cell_box.translate(column_xs[cell.column], row_ys[cell.row]);
+        }
+
+        // Rows, row groups, columns and column groups cover the slots they
+        // take up.
+        let grid_width = columns_width - 2.0 * spacing_x;
+        let grid_top = content.y + top_captions + spacing_y;
+        let grid_height = (rows_height(&heights) - 2.0 * spacing_y).max(0.0);
+        for (r, path) in grid.rows.iter().enumerate() {
+            let rect = Rect { x: content.x + spacing_x, y: row_ys[r], width: grid_width, height: heights[r] };
+            self.descendant_mut(path).dimensions.content = rect;
+            if path.len() == 2 {
+                let group = &mut self.children[path[0]].dimensions.content;
+                if group.height == 0.0 && group.width == 0.0 {
+                    *group = rect;
+                } else {
+                    group.height = rect.y + rect.height - group.y;
+                }
+            }
+        }
+        let mut column = 0;
+        for child in &mut self.children {
+            let (part, style) = match child.box_type {
+                BoxType::TableNode(part, style) => (part, style),
+                _ => continue,
+            };
+            let cover = |column_box: &mut LayoutBox, style: &StyledNode, column: &mut usize| {
+                let span = span_attribute(style, "span", 1).max(1).min(grid.columns.saturating_sub(*column));
+                if span > 0 {
+                    column_box.dimensions.content = Rect {
+                        x: column_xs[*column],
+                        y: grid_top,
+                        width: grid.span_width(&widths, *column, span),
+                        height: grid_height,
+                    };
+                }
+                *column += span;
+            };
+            match part {
+                TablePart::Column => cover(child, style, &mut column),
+                TablePart::ColumnGroup => {
+                    let start = column;
+                    for column_box in &mut child.children {
+                        if let BoxType::TableNode(TablePart::Column, style) = column_box.box_type {
+                            cover(column_box, style, &mut column);
+                        }
+                    }
+                    if column == start {
+                        cover(child, style, &mut column);
+                    } else if start < grid.columns {
+                        let end = column.min(grid.columns);
+                        child.dimensions.content = Rect {
+                            x: column_xs[start],
+                            y: grid_top,
+                            width: grid.span_width(&widths, start, end - start),
+                            height: grid_height,
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut bottom_captions = 0.0;
+        let below = content.y + top_captions + rows_height(&heights);
+        for &(i, _) in captions.iter().filter(|caption| caption.1) {
+            bottom_captions += layout_caption(self, i, below + bottom_captions);
+        }
+
+        let d = &mut self.dimensions;
+        d.content.height = top_captions + rows_height(&heights) + bottom_captions;
+        BlockMargins {
+            top: CollapsedMargin::new(d.margin.top),
+            bottom: CollapsedMargin::new(d.margin.bottom),
+            collapses_through: false,
+        }
+    }
+
+    /// The min-content and max-content widths of a table's content: its
+    /// columns at their min-content and max-content widths, with the
+    /// spacing, and no narrower than its captions.
+    pub(super) fn table_intrinsic_widths(&self) -> (f32, f32) {
+        let grid = self.table_grid(0.0);
+        let (min, max, _, _) = self.column_intrinsic_widths(&grid);
+        let outer_spacing = grid.spacing.0 * (grid.columns + 1) as f32;
+        let (mut min, mut max) = (min.iter().sum::<f32>() + outer_spacing, max.iter().sum::<f32>() + outer_spacing);
+        for child in &self.children {
+            if child.table_part() == Some(TablePart::Caption) {
+                let (caption_min, _) = child.intrinsic_widths();
+                min = min.max(caption_min);
+                max = max.max(caption_min);
+            }
+        }
+        (min, max)
+    }
+}
+
+/// Share `available` space between `columns`, setting their `widths`. They
+/// grow from their min-content towards their max-content widths in
+/// proportion to the difference, and beyond that the columns not given a
+/// width share the rest in proportion to their max-content widths.
+fn share_columns(columns: &[usize], min: &[f32], max: &[f32], fixed: &[bool], available: f32, widths: &mut [f32]) {
+    let total_min: f32 = columns.iter().map(|&c| min[c]).sum();
+    let total_max: f32 = columns.iter().map(|&c| max[c]).sum();
+    if available <= total_min {
+        for &c in columns {
+            widths[c] = min[c];
+        }
+        return;
+    }
+    if available < total_max {
+        let ratio = (available - total_min) / (total_max - total_min);
+        for &c in columns {
+            widths[c] = min[c] + (max[c] - min[c]) * ratio;
+        }
+        return;
+    }
+    let extra = available - total_max;
+    let growing: Vec<usize> = match columns.iter().cloned().filter(|&c| !fixed[c]).collect::<Vec<_>>() {
+        ref auto if auto.is_empty() => columns.to_vec(),
+        auto => auto,
+    };
+    let total: f32 = growing.iter().map(|&c| max[c]).sum();
+    for &c in columns {
+        widths[c] = max[c];
+    }
+    for &c in &growing {
+        widths[c] += if total > 0.0 { extra * max[c] / total } else { extra / growing.len() as f32 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use boxes::tests::layout;
+
+    const CSS: &str = "body { margin: 0 } table { border-spacing: 0 } td { padding: 0 }";
+
+    /// The x positions and widths of the cells with the ids `ids`.
+    fn columns(html: &str, ids: &[&str]) -> Vec<(f32, f32)> {
+        let rects = layout(html, CSS, 800.0);
+        ids.iter().map(|id| (rects[*id].x, rects[*id].width)).collect()
+    }
+
+    #[test]
+    fn percentage_columns() {
+        let html = "<table style='width: 400px'><tr><td id=a style='width: 25%'>a<td id=b>b</table>";
+        assert_eq!(columns(html, &["a", "b"]), [(0.0, 100.0), (100.0, 300.0)]);
+        // With only percentage columns, they grow in proportion.
+        let html = "<table style='width: 400px'><tr><td id=a style='width: 10%'>a\
+                    <td id=b style='width: 30%'>b</table>";
+        assert_eq!(columns(html, &["a", "b"]), [(0.0, 100.0), (100.0, 300.0)]);
+    }
+
+    #[test]
+    fn auto_columns() {
+        // Columns given a width keep it, and the others share the rest.
+        let html = "<table style='width: 400px'><tr><td id=a style='width: 100px'><td id=b><td id=c></table>";
+        assert_eq!(columns(html, &["a", "b", "c"]), [(0.0, 100.0), (100.0, 150.0), (250.0, 150.0)]);
+        // An auto-width table is as wide as its widest cells.
+        let html = "<table><tr><td id=a style='width: 30px'><td id=b style='width: 50px'></table>";
+        assert_eq!(columns(html, &["a", "b"]), [(0.0, 30.0), (30.0, 50.0)]);
+    }
+
+    #[test]
+    fn fixed_layout() {
+        let html = "<table style='width: 300px; table-layout: fixed'><tr><td id=a style='width: 50px'>\
+                    <td id=b><td id=c><tr><td style='width: 200px'><td><td></table>";
+        assert_eq!(columns(html, &["a", "b", "c"]), [(0.0, 50.0), (50.0, 125.0), (175.0, 125.0)]);
+    }
+
+    #[test]
+    fn anonymous_cells() {
+        // Text in an element displayed as a table goes in an anonymous cell.
+        for html in &["<div id=t style='display: table'>x</div>", "<p><span id=t style='display: table'>x</span>"] {
+            let rects = layout(html, CSS, 800.0);
+            assert_eq!(rects["t"].width, 8.0, "{}", html);
+        }
+        let html = "<div style='display: table; width: 200px'><div id=a style='display: table-cell; width: 25%'>a</div>\
+                    x</div>";
+        assert_eq!(columns(html, &["a"]), [(0.0, 50.0)]);
+    }
+}
@@ -1,15 +1,24 @@
-use node::ElementData;
+use node::{ElementData, Namespace};
 
 pub struct Stylesheet {
-    rules: Vec<Rule>,
+    pub rules: Vec<Rule>,
+    pub origin: Origin,
 }
 
 impl Stylesheet {
     pub fn new() -> Stylesheet {
-        Stylesheet { rules: vec![] }
+        Stylesheet { rules: vec![], origin: Origin::Author }
     }
 }
 
+/// Where a stylesheet came from. Declarations from a later origin win over
+/// those from an earlier one regardless of specificity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    UserAgent,
+    Author,
+}
+
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
@@ -23,52 +32,121 @@ impl Rule {
 
 pub enum Selector {
     Simple(SimpleSelector),
+    /// A simple selector for an element that also has to be related, by the
+    /// combinator, to an element matching the selector on the left.
+    Combined(Box<Selector>, Combinator, SimpleSelector),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Combinator {
+    /// Whitespace: `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
 }
 
 pub type Specificity = (usize, usize, usize);
 impl Selector {
     pub fn specificity(&self) -> Specificity {
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Combined(ref left, _, ref right) => {
+                let (a1, b1, c1) = left.specificity();
+                let (a2, b2, c2) = right.specificity();
+                (a1 + a2, b1 + b2, c1 + c2)
+            }
+        }
+    }
 
-        (a, b, c)
+    /// The pseudo-element the selector is for, or `None` if it is for the
+    /// element itself.
+    pub fn pseudo_element(&self) -> Option<&str> {
+        match *self {
+            Selector::Simple(ref simple) | Selector::Combined(_, _, ref simple) => simple.pseudo_element.as_deref(),
+        }
     }
 }
 
 pub struct SimpleSelector {
+    /// The namespace the element has to be in, or `None` for any.
+    pub namespace: Option<Namespace>,
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    /// The pseudo-element of the matched element that the selector is for,
+    /// like `marker` in `li::marker`.
+    pub pseudo_element: Option<String>,
+}
+
+impl SimpleSelector {
+    fn specificity(&self) -> Specificity {
+        let a = self.id.iter().count();
+        let b = self.class.len();
+        let c = self.tag_name.iter().count() + self.pseudo_element.iter().count();
+
+        (a, b, c)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    /// Whether the declaration is `!important`, which puts it ahead of the
+    /// normal declarations of every origin.
+    pub important: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    /// A percentage, of a size that depends on the property.
+    Percentage(f32),
+    Number(f32),
     Color(Color),
+    /// A quoted string.
+    String(String),
+    /// A function, like `repeat(3, 1fr)`, with its comma-separated arguments.
+    Function(String, Vec<Value>),
+    /// Several components separated by whitespace, like `100px 1fr`.
+    List(Vec<Value>),
+    /// The `/` between the parts of shorthands like `grid-row: 1 / 3`.
+    Slash,
+    /// The `,` between the items of a list like `font-family: "x", serif`.
+    Comma,
     // insert more values here
 }
 
 impl Value {
+    /// The length in pixels, or zero for anything that isn't an absolute
+    /// length. Relative units are resolved to pixels during styling.
     pub fn to_px(&self) -> f32 {
         match *self {
             Value::Length(f, Unit::Px) => f,
             _ => 0.0,
         }
     }
+
+    /// Like `to_px`, but resolving a percentage against `basis`.
+    pub fn to_px_of(&self, basis: f32) -> f32 {
+        match *self {
+            Value::Percentage(p) => p * basis / 100.0,
+            _ => self.to_px(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
+    Em,
+    /// A fraction of the free space in a grid container.
+    Fr,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -81,14 +159,72 @@ pub struct Color {
 
 
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+/// An element as selector matching sees it: its own data, and the elements
+/// that combinators relate it to.
+pub trait Element<'a>: Copy {
+    fn data(self) -> &'a ElementData;
+    fn parent_element(self) -> Option<Self>;
+    fn previous_element_sibling(self) -> Option<Self>;
+}
+
+/// Whether `elem` matches `selector`. A selector for a pseudo-element
+/// matches no element.
+pub fn matches<'a, E: Element<'a>>(elem: E, selector: &Selector) -> bool {
+    selector.pseudo_element().is_none() && matches_subject(elem, selector)
+}
+
+/// Whether `selector` is for the pseudo-element `name` of `elem`.
+pub fn matches_pseudo_element<'a, E: Element<'a>>(elem: E, selector: &Selector, name: &str) -> bool {
+    selector.pseudo_element() == Some(name) && matches_subject(elem, selector)
+}
+
+/// Whether `elem` matches `selector`, leaving out any pseudo-element.
+fn matches_subject<'a, E: Element<'a>>(elem: E, selector: &Selector) -> bool {
     match *selector {
-        Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector)
+        Selector::Simple(ref simple_selector) => matches_simple_selector(elem.data(), simple_selector),
+        Selector::Combined(ref left, combinator, ref right) => {
+            if !matches_simple_selector(elem.data(), right) {
+                return false;
+            }
+            match combinator {
+                Combinator::Child => elem.parent_element().is_some_and(|parent| matches(parent, left)),
+                Combinator::Descendant => {
+                    let mut ancestor = elem.parent_element();
+                    while let Some(elem) = ancestor {
+                        if matches(elem, left) {
+                            return true;
+                        }
+                        ancestor = elem.parent_element();
+                    }
+                    false
+                }
+                Combinator::NextSibling => {
+                    elem.previous_element_sibling().is_some_and(|sibling| matches(sibling, left))
+                }
+                Combinator::SubsequentSibling => {
+                    let mut sibling = elem.previous_element_sibling();
+                    while let Some(elem) = sibling {
+                        if matches(elem, left) {
+                            return true;
+                        }
+                        sibling = elem.previous_element_sibling();
+                    }
+                    false
+                }
+            }
+        }
     }
 }
 
 fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
-    if selector.tag_name.iter().any(|name| elem.tag_name != *name) {
+    if selector.namespace.iter().any(|namespace| elem.namespace != *namespace) {
+        return false;
+    }
+
+    // HTML element names are matched case-insensitively, others exactly.
+    if selector.tag_name.iter().any(|name| {
+        if elem.is_html() { !elem.tag_name.eq_ignore_ascii_case(name) } else { elem.tag_name != *name }
+    }) {
         return false;
     }
 
@@ -107,13 +243,19 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
 
 pub type MatchedRule<'a> = (Specificity, &'a Rule);
 
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
+fn match_rule<'a, 'b, E: Element<'b>>(elem: E, rule: &'a Rule, pseudo_element: Option<&str>) -> Option<MatchedRule<'a>> {
     rule.selectors.iter()
-        .find(|selector| matches(elem, *selector))
+        .find(|selector| match pseudo_element {
+            Some(name) => matches_pseudo_element(elem, selector, name),
+            None => matches(elem, selector),
+        })
         .map(|selector| (selector.specificity(), rule))
 }
 
-pub fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet.rules.iter().filter_map(|rule| match_rule(elem, rule)).collect()
+/// The rules that apply to `elem`, or to its pseudo-element if
+/// `pseudo_element` is given.
+pub fn matching_rules<'a, 'b, E: Element<'b>>(elem: E, stylesheet: &'a Stylesheet,
+                                             pseudo_element: Option<&str>) -> Vec<MatchedRule<'a>> {
+    stylesheet.rules.iter().filter_map(|rule| match_rule(elem, rule, pseudo_element)).collect()
 }
 
@@ -0,0 +1,822 @@
+//! An arena-based document tree, with links to parents and siblings.
+//!
+//! `node::Node` owns its children, which is convenient for building trees
+//! but leaves no way back up. Here every node lives in one `Document` and is
+//! referred to by a `NodeId`, so it can be navigated in any direction. Trees
+//! built with `node::elem` and `node::text` convert to and from this form.
+
+use std::fmt;
+
+use css::{self, Selector};
+use node::{Node, NodeType, ElementData, AttrMap, QuirksMode};
+use parser;
+
+/// A handle to a node in a `Document`. It is only meaningful for the
+/// document that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+struct NodeData {
+    node_type: NodeType,
+    parent: Option<NodeId>,
+    first_child: Option<NodeId>,
+    last_child: Option<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+}
+
+pub struct Document {
+    nodes: Vec<NodeData>,
+}
+
+impl Document {
+    /// An empty document.
+    pub fn new() -> Document {
+        Document::with_quirks_mode(QuirksMode::NoQuirks)
+    }
+
+    fn with_quirks_mode(mode: QuirksMode) -> Document {
+        let mut document = Document { nodes: vec![] };
+        document.create_node(NodeType::Document(mode));
+        document
+    }
+
+    /// Build a document from a `Node` tree. A document node becomes the
+    /// root; any other node is added as the root's only child.
+    pub fn from_node(node: &Node) -> Document {
+        let mut document = match node.node_type {
+            NodeType::Document(mode) => Document::with_quirks_mode(mode),
+            _ => Document::new(),
+        };
+        let root = document.root();
+        match node.node_type {
+            NodeType::Document(_) => {
+                for child in &node.children {
+                    document.append_tree(root, child);
+                }
+            }
+            _ => document.append_tree(root, node),
+        }
+        document
+    }
+
+    fn append_tree(&mut self, parent: NodeId, node: &Node) {
+        let id = self.create_node(node.node_type.clone());
+        self.append(parent, id);
+        for child in &node.children {
+            self.append_tree(id, child);
+        }
+    }
+
+    /// Copy a node and its descendants out into a `Node` tree.
+    pub fn to_node(&self, id: NodeId) -> Node {
+        Node {
+            children: self.children(id).map(|child| self.to_node(child)).collect(),
+            node_type: self.node_type(id).clone(),
+        }
+    }
+
+    fn create_node(&mut self, node_type: NodeType) -> NodeId {
+        self.nodes.push(NodeData {
+            node_type,
+            parent: None,
+            first_child: None,
+            last_child: None,
+            previous_sibling: None,
+            next_sibling: None,
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Link a node with no parent in as the last child of `parent`.
+    fn append(&mut self, parent: NodeId, child: NodeId) {
+        self.link(parent, child, None);
+    }
+
+    /// Link a node with no parent in as a child of `parent`, before
+    /// `reference` or at the end.
+    fn link(&mut self, parent: NodeId, child: NodeId, reference: Option<NodeId>) {
+        let previous = match reference {
+            Some(reference) => self.nodes[reference.0].previous_sibling,
+            None => self.nodes[parent.0].last_child,
+        };
+        {
+            let data = &mut self.nodes[child.0];
+            data.parent = Some(parent);
+            data.previous_sibling = previous;
+            data.next_sibling = reference;
+        }
+        match previous {
+            Some(previous) => self.nodes[previous.0].next_sibling = Some(child),
+            None => self.nodes[parent.0].first_child = Some(child),
+        }
+        match reference {
+            Some(reference) => self.nodes[reference.0].previous_sibling = Some(child),
+            None => self.nodes[parent.0].last_child = Some(child),
+        }
+    }
+
+    /// Unlink a node from its parent and siblings. It keeps its children.
+    fn detach(&mut self, id: NodeId) {
+        let (parent, previous, next) = {
+            let data = &mut self.nodes[id.0];
+            let links = (data.parent.take(), data.previous_sibling.take(), data.next_sibling.take());
+            match links.0 {
+                Some(parent) => (parent, links.1, links.2),
+                None => return,
+            }
+        };
+        match previous {
+            Some(previous) => self.nodes[previous.0].next_sibling = next,
+            None => self.nodes[parent.0].first_child = next,
+        }
+        match next {
+            Some(next) => self.nodes[next.0].previous_sibling = previous,
+            None => self.nodes[parent.0].last_child = previous,
+        }
+    }
+
+    /// Check that `child` can be inserted into `parent`, as the DOM's
+    /// pre-insertion validity checks do.
+    fn check_can_insert(&self, parent: NodeId, child: NodeId) -> Result<(), MutationError> {
+        if child == self.root() {
+            Err(MutationError::InsertingDocument)
+        } else if child == parent || self.ancestors(parent).any(|id| id == child) {
+            Err(MutationError::InsertingAncestor)
+        } else if !matches!(self.nodes[parent.0].node_type, NodeType::Element(_) | NodeType::Document(_)) {
+            Err(MutationError::CantHaveChildren)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_is_child(&self, parent: NodeId, child: NodeId) -> Result<(), MutationError> {
+        if self.parent(child) == Some(parent) { Ok(()) } else { Err(MutationError::NotAChild) }
+    }
+
+    // Mutation
+    //
+    // Nodes are never freed: a removed node stays in the arena, detached,
+    // and can be inserted again. A mutation that fails changes nothing.
+
+    /// Create an element that isn't in the tree yet.
+    pub fn create_element(&mut self, tag_name: &str, attributes: AttrMap) -> NodeId {
+        self.create_node(NodeType::Element(ElementData::new(tag_name.to_string(), attributes)))
+    }
+
+    /// Create a text node that isn't in the tree yet.
+    pub fn create_text(&mut self, text: &str) -> NodeId {
+        self.create_node(NodeType::Text(text.to_string()))
+    }
+
+    /// Create a comment that isn't in the tree yet.
+    pub fn create_comment(&mut self, text: &str) -> NodeId {
+        self.create_node(NodeType::Comment(text.to_string()))
+    }
+
+    /// Add `child` as the last child of `parent`, first removing it from
+    /// wherever it is.
+    ///
+    /// Fails if `child` is the document, `parent` or one of its ancestors,
+    /// or if `parent` can't have children.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) -> Result<(), MutationError> {
+        self.insert_before(parent, child, None)
+    }
+
+    /// Add `child` to `parent` before `reference`, or at the end if
+    /// `reference` is `None`, first removing it from wherever it is.
+    ///
+    /// Fails like `append_child`, or if `reference` isn't a child of
+    /// `parent`.
+    pub fn insert_before(&mut self, parent: NodeId, child: NodeId, reference: Option<NodeId>)
+                         -> Result<(), MutationError> {
+        self.check_can_insert(parent, child)?;
+        if let Some(reference) = reference {
+            self.check_is_child(parent, reference)?;
+        }
+        // Inserting a node before itself leaves it where it is.
+        let reference = if reference == Some(child) { self.next_sibling(child) } else { reference };
+        self.detach(child);
+        self.link(parent, child, reference);
+        Ok(())
+    }
+
+    /// Remove `child` from `parent`. Fails if it isn't a child of `parent`.
+    pub fn remove_child(&mut self, parent: NodeId, child: NodeId) -> Result<(), MutationError> {
+        self.check_is_child(parent, child)?;
+        self.detach(child);
+        Ok(())
+    }
+
+    /// Remove a node from its parent, if it has one.
+    pub fn remove(&mut self, id: NodeId) {
+        self.detach(id);
+    }
+
+    /// Put `new_child` in the place of `old_child` among the children of
+    /// `parent`. Fails if `old_child` isn't a child of `parent` or
+    /// `new_child` can't be inserted there.
+    pub fn replace_child(&mut self, parent: NodeId, new_child: NodeId, old_child: NodeId)
+                         -> Result<(), MutationError> {
+        self.check_is_child(parent, old_child)?;
+        if new_child == old_child {
+            return Ok(());
+        }
+        self.check_can_insert(parent, new_child)?;
+        let mut reference = self.next_sibling(old_child);
+        if reference == Some(new_child) {
+            reference = self.next_sibling(new_child);
+        }
+        self.detach(old_child);
+        self.detach(new_child);
+        self.link(parent, new_child, reference);
+        Ok(())
+    }
+
+    /// The element data of a node for modification, if it is an element.
+    pub fn element_mut(&mut self, id: NodeId) -> Option<&mut ElementData> {
+        match self.nodes[id.0].node_type {
+            NodeType::Element(ref mut elem) => Some(elem),
+            _ => None,
+        }
+    }
+
+    /// Set an attribute on an element. Fails if the node isn't an element.
+    pub fn set_attribute(&mut self, id: NodeId, name: &str, value: &str) -> Result<(), MutationError> {
+        self.element_mut(id).ok_or(MutationError::NotAnElement)?.set_attribute(name, value);
+        Ok(())
+    }
+
+    /// Remove an attribute from an element, returning its old value. Fails
+    /// if the node isn't an element.
+    pub fn remove_attribute(&mut self, id: NodeId, name: &str) -> Result<Option<String>, MutationError> {
+        Ok(self.element_mut(id).ok_or(MutationError::NotAnElement)?.remove_attribute(name))
+    }
+
+    /// Replace the contents of a node with text. Elements and the document
+    /// lose all their children, gaining a single text node unless `text` is
+    /// empty; text and comment nodes have their data replaced.
+    pub fn set_text_content(&mut self, id: NodeId, text: &str) {
+        match self.nodes[id.0].node_type {
+            NodeType::Text(ref mut data) | NodeType::Comment(ref mut data) => {
+                *data = text.to_string();
+                return;
+            }
+            NodeType::Doctype(_) => return,
+            _ => {}
+        }
+        while let Some(child) = self.first_child(id) {
+            self.detach(child);
+        }
+        if !text.is_empty() {
+            let child = self.create_text(text);
+            self.append(id, child);
+        }
+    }
+
+    /// Copy a node, and all its descendants if `deep` is set. The copy isn't
+    /// in the tree.
+    pub fn clone_node(&mut self, id: NodeId, deep: bool) -> NodeId {
+        let copy = self.create_node(self.nodes[id.0].node_type.clone());
+        if deep {
+            let children: Vec<NodeId> = self.children(id).collect();
+            for child in children {
+                let child_copy = self.clone_node(child, true);
+                self.append(copy, child_copy);
+            }
+        }
+        copy
+    }
+
+    /// The document node.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// The document element, normally `html`.
+    pub fn document_element(&self) -> Option<NodeId> {
+        self.children(self.root()).find(|&id| self.element(id).is_some())
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        match *self.node_type(self.root()) {
+            NodeType::Document(mode) => mode,
+            _ => QuirksMode::NoQuirks,
+        }
+    }
+
+    pub fn node_type(&self, id: NodeId) -> &NodeType {
+        &self.nodes[id.0].node_type
+    }
+
+    /// The element data of a node, if it is an element.
+    pub fn element(&self, id: NodeId) -> Option<&ElementData> {
+        match *self.node_type(id) {
+            NodeType::Element(ref elem) => Some(elem),
+            _ => None,
+        }
+    }
+
+    /// The tag name of a node, if it is an element.
+    pub fn tag_name(&self, id: NodeId) -> Option<&str> {
+        self.element(id).map(|elem| &*elem.tag_name)
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].first_child
+    }
+
+    pub fn last_child(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].last_child
+    }
+
+    pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].previous_sibling
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].next_sibling
+    }
+
+    /// The parent of a node if it is an element.
+    pub fn parent_element(&self, id: NodeId) -> Option<NodeId> {
+        self.parent(id).filter(|&parent| self.element(parent).is_some())
+    }
+
+    pub fn previous_element_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let mut sibling = self.previous_sibling(id);
+        while let Some(node) = sibling {
+            if self.element(node).is_some() {
+                return Some(node);
+            }
+            sibling = self.previous_sibling(node);
+        }
+        None
+    }
+
+    pub fn next_element_sibling(&self, id: NodeId) -> Option<NodeId> {
+        let mut sibling = self.next_sibling(id);
+        while let Some(node) = sibling {
+            if self.element(node).is_some() {
+                return Some(node);
+            }
+            sibling = self.next_sibling(node);
+        }
+        None
+    }
+
+    /// The children of a node, first to last.
+    pub fn children(&self, id: NodeId) -> Children<'_> {
+        Children { document: self, next: self.first_child(id) }
+    }
+
+    /// The ancestors of a node, starting with its parent.
+    pub fn ancestors(&self, id: NodeId) -> Ancestors<'_> {
+        Ancestors { document: self, next: self.parent(id) }
+    }
+
+    /// A node and its descendants in tree order.
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_> {
+        Descendants { document: self, root: id, next: Some(id) }
+    }
+
+    /// The concatenated text of a node's text descendants.
+    pub fn text_content(&self, id: NodeId) -> String {
+        self.descendants(id)
+            .filter_map(|node| match *self.node_type(node) {
+                NodeType::Text(ref text) => Some(&**text),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A selector list that can't be parsed, or uses selectors that aren't
+/// supported: the DOM's `SyntaxError`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectorError {
+    pub selectors: String,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a valid selector", self.selectors)
+    }
+}
+
+/// A mutation that would give a tree the DOM doesn't allow, or that names a
+/// node that isn't where it should be: the DOM's `HierarchyRequestError`,
+/// `NotFoundError` and `InvalidNodeTypeError`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MutationError {
+    /// The document node can't be inserted anywhere.
+    InsertingDocument,
+    /// A node can't be inserted into itself or its descendants.
+    InsertingAncestor,
+    /// Only elements and the document can have children.
+    CantHaveChildren,
+    /// A node given as a child of a parent isn't one.
+    NotAChild,
+    /// Only elements have attributes.
+    NotAnElement,
+}
+
+impl fmt::Display for MutationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            MutationError::InsertingDocument => "the document node can't be inserted",
+            MutationError::InsertingAncestor => "a node can't be inserted into itself or its descendants",
+            MutationError::CantHaveChildren => "only elements and the document can have children",
+            MutationError::NotAChild => "node isn't a child of parent",
+            MutationError::NotAnElement => "node isn't an element",
+        })
+    }
+}
+
+fn parse_selectors(selectors: &str) -> Result<Vec<Selector>, SelectorError> {
+    parser::parse_selector_list(selectors.to_string())
+        .ok_or_else(|| SelectorError { selectors: selectors.to_string() })
+}
+
+// Selector queries
+//
+// These take a comma-separated list of selectors in the syntax stylesheets
+// use, and fail with a `SelectorError` if it is malformed.
+impl Document {
+    /// The first element among the descendants of `scope`, in document
+    /// order, that matches `selectors`.
+    pub fn query_selector(&self, scope: NodeId, selectors: &str) -> Result<Option<NodeId>, SelectorError> {
+        let selectors = parse_selectors(selectors)?;
+        Ok(self.descendants(scope).skip(1).find(|&id| self.matches_any(id, &selectors)))
+    }
+
+    /// All the elements among the descendants of `scope` that match
+    /// `selectors`, in document order.
+    pub fn query_selector_all(&self, scope: NodeId, selectors: &str) -> Result<Vec<NodeId>, SelectorError> {
+        let selectors = parse_selectors(selectors)?;
+        Ok(self.descendants(scope).skip(1).filter(|&id| self.matches_any(id, &selectors)).collect())
+    }
+
+    /// Whether a node is an element matching `selectors`.
+    pub fn matches(&self, id: NodeId, selectors: &str) -> Result<bool, SelectorError> {
+        let selectors = parse_selectors(selectors)?;
+        Ok(self.matches_any(id, &selectors))
+    }
+
+    /// The nearest element, starting with `id` itself and going up through
+    /// its ancestors, that matches `selectors`.
+    pub fn closest(&self, id: NodeId, selectors: &str) -> Result<Option<NodeId>, SelectorError> {
+        let selectors = parse_selectors(selectors)?;
+        Ok(Some(id).into_iter().chain(self.ancestors(id)).find(|&id| self.matches_any(id, &selectors)))
+    }
+
+    fn matches_any(&self, id: NodeId, selectors: &[Selector]) -> bool {
+        match self.element(id) {
+            Some(_) => {
+                let elem = DocumentElement { document: self, id };
+                selectors.iter().any(|selector| css::matches(elem, selector))
+            }
+            None => false,
+        }
+    }
+}
+
+/// An element of a `Document`, for selector matching.
+#[derive(Clone, Copy)]
+struct DocumentElement<'a> {
+    document: &'a Document,
+    id: NodeId,
+}
+
+impl<'a> css::Element<'a> for DocumentElement<'a> {
+    fn data(self) -> &'a ElementData {
+        self.document.element(self.id).expect("node isn't an element")
+    }
+
+    fn parent_element(self) -> Option<Self> {
+        self.document.parent_element(self.id).map(|id| DocumentElement { id, ..self })
+    }
+
+    fn previous_element_sibling(self) -> Option<Self> {
+        self.document.previous_element_sibling(self.id).map(|id| DocumentElement { id, ..self })
+    }
+}
+
+impl Default for Document {
+    fn default() -> Document {
+        Document::new()
+    }
+}
+
+impl<'a> From<&'a Node> for Document {
+    fn from(node: &'a Node) -> Document {
+        Document::from_node(node)
+    }
+}
+
+pub struct Children<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        self.next = self.document.next_sibling(id);
+        Some(id)
+    }
+}
+
+pub struct Ancestors<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        self.next = self.document.parent(id);
+        Some(id)
+    }
+}
+
+pub struct Descendants<'a> {
+    document: &'a Document,
+    root: NodeId,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        let document = self.document;
+        self.next = document.first_child(id).or_else(|| {
+            // Climb until there's a next sibling, without leaving the subtree.
+            let mut node = id;
+            loop {
+                if node == self.root {
+                    return None;
+                }
+                if let Some(sibling) = document.next_sibling(node) {
+                    return Some(sibling);
+                }
+                node = document.parent(node)?;
+            }
+        });
+        Some(id)
+    }
+}
+
+/// Build a document from a tree made with the `node` builders, such as
+/// `document(vec![elem(...)])`.
+pub fn document(children: Vec<Node>) -> Document {
+    Document::from_node(&Node {
+        children,
+        node_type: NodeType::Document(QuirksMode::NoQuirks),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use html;
+    use node::{self, AttrMap, Node};
+    use super::*;
+
+    fn elem(name: &str, children: Vec<Node>) -> Node {
+        node::elem(name.to_string(), AttrMap::new(), children)
+    }
+
+    fn text(data: &str) -> Node {
+        node::text(data.to_string())
+    }
+
+    /// `<div><p>a</p>b<!--c--><span><i></i></span></div>`
+    fn tree() -> Node {
+        elem("div", vec![
+            elem("p", vec![text("a")]),
+            text("b"),
+            node::comment("c".to_string()),
+            elem("span", vec![elem("i", vec![])]),
+        ])
+    }
+
+    fn names(doc: &Document, ids: &[NodeId]) -> Vec<String> {
+        ids.iter().map(|&id| match *doc.node_type(id) {
+            NodeType::Element(ref elem) => elem.tag_name.clone(),
+            NodeType::Text(ref text) => format!("'{}'", text),
+            NodeType::Comment(ref text) => format!("<!--{}-->", text),
+            _ => "#document".to_string(),
+        }).collect()
+    }
+
+    #[test]
+    fn parent_and_sibling_links() {
+        let doc = Document::from_node(&tree());
+        let div = doc.first_child(doc.root()).unwrap();
+        assert_eq!(doc.parent(div), Some(doc.root()));
+        assert_eq!(doc.document_element(), Some(div));
+        let children: Vec<NodeId> = doc.children(div).collect();
+        assert_eq!(names(&doc, &children), ["p", "'b'", "<!--c-->", "span"]);
+        assert!(children.iter().all(|&child| doc.parent(child) == Some(div)));
+        for pair in children.windows(2) {
+            assert_eq!(doc.next_sibling(pair[0]), Some(pair[1]));
+            assert_eq!(doc.previous_sibling(pair[1]), Some(pair[0]));
+        }
+        assert_eq!(doc.previous_sibling(children[0]), None);
+        assert_eq!(doc.next_sibling(children[3]), None);
+        assert_eq!(doc.last_child(div), Some(children[3]));
+        assert_eq!(doc.next_element_sibling(children[0]), Some(children[3]));
+        assert_eq!(doc.previous_element_sibling(children[3]), Some(children[0]));
+        assert_eq!(doc.parent_element(div), None);
+    }
+
+    #[test]
+    fn descendants_in_document_order() {
+        let doc = Document::from_node(&tree());
+        let div = doc.first_child(doc.root()).unwrap();
+        let all: Vec<NodeId> = doc.descendants(div).collect();
+        assert_eq!(names(&doc, &all), ["div", "p", "'a'", "'b'", "<!--c-->", "span", "i"]);
+        let span = doc.last_child(div).unwrap();
+        let inner: Vec<NodeId> = doc.descendants(span).collect();
+        assert_eq!(names(&doc, &inner), ["span", "i"]);
+        let i = doc.first_child(span).unwrap();
+        let ancestors: Vec<NodeId> = doc.ancestors(i).collect();
+        assert_eq!(names(&doc, &ancestors), ["span", "div", "#document"]);
+        assert_eq!(doc.text_content(div), "ab");
+    }
+
+    #[test]
+    fn node_trees_round_trip() {
+        let tree = tree();
+        let doc = Document::from_node(&tree);
+        assert!(doc.to_node(doc.first_child(doc.root()).unwrap()) == tree);
+        let document = html::parse_document("<!DOCTYPE html><p class=a>x<br>y");
+        let doc = Document::from_node(&document);
+        assert!(doc.to_node(doc.root()) == document);
+    }
+
+    /// The document from `tree()`, with its div and that div's children.
+    fn tree_document() -> (Document, NodeId, Vec<NodeId>) {
+        let doc = Document::from_node(&tree());
+        let div = doc.first_child(doc.root()).unwrap();
+        let children = doc.children(div).collect();
+        (doc, div, children)
+    }
+
+    fn child_names(doc: &Document, id: NodeId) -> Vec<String> {
+        names(doc, &doc.children(id).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn insert_before() {
+        let (mut doc, div, children) = tree_document();
+        let em = doc.create_element("em", AttrMap::new());
+        doc.insert_before(div, em, Some(children[1])).unwrap();
+        assert_eq!(child_names(&doc, div), ["p", "em", "'b'", "<!--c-->", "span"]);
+        assert_eq!(doc.parent(em), Some(div));
+
+        // An attached node moves, and inserting a node before itself keeps it
+        // in place.
+        doc.insert_before(div, children[3], Some(children[0])).unwrap();
+        doc.insert_before(div, em, Some(em)).unwrap();
+        assert_eq!(child_names(&doc, div), ["span", "p", "em", "'b'", "<!--c-->"]);
+        doc.append_child(div, children[3]).unwrap();
+        assert_eq!(child_names(&doc, div), ["p", "em", "'b'", "<!--c-->", "span"]);
+        assert_eq!(doc.last_child(div), Some(children[3]));
+    }
+
+    #[test]
+    fn remove_and_replace_child() {
+        let (mut doc, div, children) = tree_document();
+        doc.remove_child(div, children[2]).unwrap();
+        assert_eq!(child_names(&doc, div), ["p", "'b'", "span"]);
+        assert_eq!(doc.parent(children[2]), None);
+
+        let em = doc.create_element("em", AttrMap::new());
+        doc.replace_child(div, em, children[1]).unwrap();
+        assert_eq!(child_names(&doc, div), ["p", "em", "span"]);
+        assert_eq!(doc.parent(children[1]), None);
+
+        // Replacing with the old child's next sibling.
+        doc.replace_child(div, children[3], em).unwrap();
+        assert_eq!(child_names(&doc, div), ["p", "span"]);
+        doc.replace_child(div, children[0], children[0]).unwrap();
+        assert_eq!(child_names(&doc, div), ["p", "span"]);
+    }
+
+    #[test]
+    fn invalid_mutations() {
+        let (mut doc, div, children) = tree_document();
+        let root = doc.root();
+        let i = doc.first_child(children[3]).unwrap();
+        let em = doc.create_element("em", AttrMap::new());
+        assert_eq!(doc.append_child(div, root), Err(MutationError::InsertingDocument));
+        assert_eq!(doc.append_child(div, div), Err(MutationError::InsertingAncestor));
+        assert_eq!(doc.append_child(i, div), Err(MutationError::InsertingAncestor));
+        assert_eq!(doc.append_child(children[1], em), Err(MutationError::CantHaveChildren));
+        assert_eq!(doc.insert_before(div, em, Some(i)), Err(MutationError::NotAChild));
+        assert_eq!(doc.remove_child(div, i), Err(MutationError::NotAChild));
+        assert_eq!(doc.replace_child(div, em, i), Err(MutationError::NotAChild));
+        assert_eq!(doc.replace_child(children[3], div, i), Err(MutationError::InsertingAncestor));
+        assert_eq!(doc.set_attribute(children[1], "id", "x"), Err(MutationError::NotAnElement));
+        assert_eq!(doc.remove_attribute(children[2], "id"), Err(MutationError::NotAnElement));
+        assert_eq!(MutationError::NotAChild.to_string(), "node isn't a child of parent");
+
+        // Nothing changed.
+        assert!(doc.to_node(div) == tree());
+        assert_eq!(doc.parent(em), None);
+    }
+
+    #[test]
+    fn set_text_content() {
+        let (mut doc, div, children) = tree_document();
+        doc.set_text_content(children[1], "x");
+        doc.set_text_content(children[2], "y");
+        assert_eq!(child_names(&doc, div), ["p", "'x'", "<!--y-->", "span"]);
+        doc.set_text_content(children[3], "z");
+        assert_eq!(child_names(&doc, children[3]), ["'z'"]);
+        doc.set_text_content(div, "");
+        assert_eq!(doc.first_child(div), None);
+        assert_eq!(doc.parent(children[0]), None);
+        doc.set_text_content(div, "w");
+        assert_eq!(doc.text_content(div), "w");
+    }
+
+    #[test]
+    fn clone_node() {
+        let (mut doc, div, _) = tree_document();
+        doc.set_attribute(div, "class", "a b").unwrap();
+        let shallow = doc.clone_node(div, false);
+        assert_eq!(doc.parent(shallow), None);
+        assert_eq!(doc.first_child(shallow), None);
+        assert_eq!(doc.element(shallow).unwrap().attribute("class"), Some("a b"));
+
+        let deep = doc.clone_node(div, true);
+        assert!(doc.to_node(deep) == doc.to_node(div));
+        let copies: Vec<NodeId> = doc.descendants(deep).collect();
+        let originals: Vec<NodeId> = doc.descendants(div).collect();
+        assert!(copies.iter().all(|copy| !originals.contains(copy)));
+        doc.set_text_content(deep, "x");
+        assert_eq!(doc.text_content(div), "ab");
+    }
+
+    #[test]
+    fn attributes_update_id_and_class() {
+        let (mut doc, div, _) = tree_document();
+        doc.set_attribute(div, "id", "a").unwrap();
+        doc.set_attribute(div, "class", " b  c ").unwrap();
+        {
+            let elem = doc.element(div).unwrap();
+            assert_eq!(elem.id().map(|id| &**id), Some("a"));
+            let mut classes: Vec<&String> = elem.classes().iter().collect();
+            classes.sort();
+            assert_eq!(classes, ["b", "c"]);
+        }
+        assert_eq!(doc.query_selector(doc.root(), "#a.c").unwrap(), Some(div));
+
+        doc.set_attribute(div, "id", "d").unwrap();
+        assert_eq!(doc.remove_attribute(div, "class").unwrap(), Some(" b  c ".to_string()));
+        assert_eq!(doc.remove_attribute(div, "class").unwrap(), None);
+        let elem = doc.element(div).unwrap();
+        assert_eq!(elem.id().map(|id| &**id), Some("d"));
+        assert!(elem.classes().is_empty());
+        assert_eq!(doc.query_selector(doc.root(), "#a").unwrap(), None);
+    }
+
+    #[test]
+    fn selector_queries() {
+        let doc = html::parse_dom("<p class=a><a id=b>y</a></p><p><a>z</a></p>");
+        let root = doc.root();
+        assert_eq!(doc.query_selector_all(root, "p a").map(|found| found.len()), Ok(2));
+        assert_eq!(doc.query_selector_all(root, "p.a > a, #b").map(|found| found.len()), Ok(1));
+        let link = doc.query_selector(root, "#b").unwrap().unwrap();
+        assert_eq!(doc.text_content(link), "y");
+        assert_eq!(doc.matches(link, ".a > a"), Ok(true));
+        let p = doc.closest(link, "p").unwrap().unwrap();
+        assert_eq!(doc.tag_name(p), Some("p"));
+        assert_eq!(doc.closest(link, "table"), Ok(None));
+    }
+
+    #[test]
+    fn invalid_selectors_are_errors() {
+        let doc = html::parse_dom("<p>");
+        let root = doc.root();
+        for selectors in &["p..a", "a,", "", "svg|rect", "p:"] {
+            let error = doc.query_selector_all(root, selectors).unwrap_err();
+            assert_eq!(error.to_string(), format!("'{}' is not a valid selector", selectors));
+            assert!(doc.query_selector(root, selectors).is_err());
+            assert!(doc.matches(root, selectors).is_err());
+            assert!(doc.closest(root, selectors).is_err());
+        }
+    }
+}
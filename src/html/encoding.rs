@@ -0,0 +1,455 @@
+//! Character encodings for HTML documents: detecting the encoding of a byte
+//! stream and decoding it, following the Encoding standard.
+
+use std::char;
+use std::str;
+
+use super::jis0208::JIS0208;
+
+/// The encodings the parser can decode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Also used for ISO-8859-1 and ASCII, which the Encoding standard treats
+    /// as windows-1252.
+    Windows1252,
+    ShiftJis,
+}
+
+/// The characters that windows-1252 puts at bytes 0x80 to 0x9F. Bytes with no
+/// character of their own decode to the C1 control character.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+impl Encoding {
+    /// Look up an encoding by one of its labels, such as `latin1` or `sjis`,
+    /// ignoring case and surrounding white space.
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        let label = label.trim_matches(is_whitespace).to_ascii_lowercase();
+        match &*label {
+            "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8"
+                | "x-unicode20utf8" => Some(Encoding::Utf8),
+            "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff" | "utf-16"
+                | "utf-16le" => Some(Encoding::Utf16Le),
+            "unicodefffe" | "utf-16be" => Some(Encoding::Utf16Be),
+            "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819"
+                | "iso-8859-1" | "iso-ir-100" | "iso8859-1" | "iso88591" | "iso_8859-1"
+                | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii" | "windows-1252"
+                | "x-cp1252" => Some(Encoding::Windows1252),
+            "csshiftjis" | "ms932" | "ms_kanji" | "shift-jis" | "shift_jis" | "sjis"
+                | "windows-31j" | "x-sjis" => Some(Encoding::ShiftJis),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1252 => "windows-1252",
+            Encoding::ShiftJis => "Shift_JIS",
+        }
+    }
+}
+
+/// The number of bytes `prescan` looks at.
+pub const PRESCAN_LENGTH: usize = 1024;
+
+/// Find the encoding given by a byte order mark, returning it with the length
+/// of the mark.
+pub fn sniff_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        Some((Encoding::Utf8, 3))
+    } else if bytes.starts_with(b"\xFE\xFF") {
+        Some((Encoding::Utf16Be, 2))
+    } else if bytes.starts_with(b"\xFF\xFE") {
+        Some((Encoding::Utf16Le, 2))
+    } else {
+        None
+    }
+}
+
+/// Look for a `<meta charset>` or `<meta http-equiv=content-type>` in the
+/// start of a document, following the prescan algorithm of the HTML
+/// standard. Comments are skipped, as are the attributes of other tags.
+pub fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let rest = &bytes[pos..];
+        if rest.starts_with(b"<!--") {
+            pos = match find(bytes, pos + 2, b"-->") {
+                Some(end) => end + 3,
+                None => return None,
+            };
+        } else if starts_with_ignore_case(rest, b"<meta")
+                && rest.get(5).is_some_and(|&b| is_whitespace_byte(b) || b == b'/') {
+            pos += 5;
+            if let Some(encoding) = prescan_meta(bytes, &mut pos) {
+                return Some(encoding);
+            }
+        } else if rest.starts_with(b"<") && rest.get(1).is_some_and(u8::is_ascii_alphabetic)
+                || rest.starts_with(b"</") && rest.get(2).is_some_and(u8::is_ascii_alphabetic) {
+            while pos < bytes.len() && !is_whitespace_byte(bytes[pos]) && bytes[pos] != b'>' {
+                pos += 1;
+            }
+            while get_attribute(bytes, &mut pos).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            pos = match find(bytes, pos, b">") {
+                Some(end) => end + 1,
+                None => return None,
+            };
+        } else {
+            pos += 1;
+        }
+    }
+    None
+}
+
+/// Read the attributes of a `meta` tag, returning the encoding it declares.
+fn prescan_meta(bytes: &[u8], pos: &mut usize) -> Option<Encoding> {
+    let mut seen = vec![];
+    let mut got_pragma = false;
+    // Whether the charset came from a `content` attribute, which only counts
+    // alongside `http-equiv=content-type`. `None` until a charset is found.
+    let mut need_pragma = None;
+    let mut charset = None;
+    while let Some((name, value)) = get_attribute(bytes, pos) {
+        if seen.contains(&name) {
+            continue;
+        }
+        match &*name {
+            "http-equiv" if value == "content-type" => got_pragma = true,
+            "content" if charset.is_none() => {
+                if let Some(label) = charset_from_content(&value) {
+                    charset = Encoding::for_label(&label);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        seen.push(name);
+    }
+    match need_pragma {
+        None => None,
+        Some(true) if !got_pragma => None,
+        // A document that can be prescanned as ASCII can't be UTF-16.
+        _ => charset.map(|encoding| match encoding {
+            Encoding::Utf16Le | Encoding::Utf16Be => Encoding::Utf8,
+            encoding => encoding,
+        }),
+    }
+}
+
+/// Read one attribute of a tag, lowercased, as the prescan does. Returns
+/// `None` at the end of the tag.
+fn get_attribute(bytes: &[u8], pos: &mut usize) -> Option<(String, String)> {
+    while *pos < bytes.len() && (is_whitespace_byte(bytes[*pos]) || bytes[*pos] == b'/') {
+        *pos += 1;
+    }
+    if *pos >= bytes.len() || bytes[*pos] == b'>' {
+        return None;
+    }
+    let mut name = String::new();
+    loop {
+        let b = *bytes.get(*pos)?;
+        if b == b'=' && !name.is_empty() {
+            *pos += 1;
+            break;
+        }
+        if is_whitespace_byte(b) {
+            while *pos < bytes.len() && is_whitespace_byte(bytes[*pos]) {
+                *pos += 1;
+            }
+            if bytes.get(*pos) != Some(&b'=') {
+                return Some((name, String::new()));
+            }
+            *pos += 1;
+            break;
+        }
+        if b == b'/' || b == b'>' {
+            return Some((name, String::new()));
+        }
+        name.push(b.to_ascii_lowercase() as char);
+        *pos += 1;
+    }
+
+    while *pos < bytes.len() && is_whitespace_byte(bytes[*pos]) {
+        *pos += 1;
+    }
+    let mut value = String::new();
+    match *bytes.get(*pos)? {
+        quote @ b'"' | quote @ b'\'' => {
+            *pos += 1;
+            loop {
+                let b = *bytes.get(*pos)?;
+                *pos += 1;
+                if b == quote {
+                    return Some((name, value));
+                }
+                value.push(b.to_ascii_lowercase() as char);
+            }
+        }
+        b'>' => Some((name, value)),
+        _ => {
+            while let Some(&b) = bytes.get(*pos) {
+                if is_whitespace_byte(b) || b == b'>' {
+                    break;
+                }
+                value.push(b.to_ascii_lowercase() as char);
+                *pos += 1;
+            }
+            Some((name, value))
+        }
+    }
+}
+
+/// Extract the encoding label from a `content` attribute such as
+/// `text/html; charset=utf-8`.
+pub fn charset_from_content(content: &str) -> Option<String> {
+    let mut rest = content;
+    loop {
+        let start = rest.to_ascii_lowercase().find("charset")?;
+        rest = rest[start + "charset".len()..].trim_start_matches(is_whitespace);
+        if let Some(value) = rest.strip_prefix('=') {
+            rest = value.trim_start_matches(is_whitespace);
+            break;
+        }
+    }
+    match rest.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            let value = &rest[1..];
+            value.find(quote).map(|end| value[..end].to_string())
+        }
+        Some(_) => {
+            let end = rest.find(|c| is_whitespace(c) || c == ';').unwrap_or(rest.len());
+            Some(rest[..end].to_string())
+        }
+        None => None,
+    }
+}
+
+/// Decodes a byte stream that arrives in chunks, which can split characters.
+/// Malformed input is replaced with U+FFFD.
+pub struct Decoder {
+    encoding: Encoding,
+    /// Bytes of a character cut off at the end of the last chunk.
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new(encoding: Encoding) -> Decoder {
+        Decoder { encoding, pending: vec![] }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Decode the next chunk of input, appending it to `out`.
+    pub fn decode(&mut self, bytes: &[u8], out: &mut String) {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+        let consumed = match self.encoding {
+            Encoding::Utf8 => decode_utf8(&input, out),
+            Encoding::Utf16Le => decode_utf16(&input, false, out),
+            Encoding::Utf16Be => decode_utf16(&input, true, out),
+            Encoding::Windows1252 => decode_windows_1252(&input, out),
+            Encoding::ShiftJis => decode_shift_jis(&input, out),
+        };
+        self.pending = input[consumed..].to_vec();
+    }
+
+    /// End the input. A character left incomplete is replaced with U+FFFD.
+    pub fn finish(&mut self, out: &mut String) {
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            out.push('\u{FFFD}');
+        }
+    }
+}
+
+/// Each of these decodes as much of `bytes` as it can, returning how many
+/// bytes it used. The rest is the start of an incomplete character.
+fn decode_utf8(bytes: &[u8], out: &mut String) -> usize {
+    let mut pos = 0;
+    loop {
+        match str::from_utf8(&bytes[pos..]) {
+            Ok(valid) => {
+                out.push_str(valid);
+                return bytes.len();
+            }
+            Err(e) => {
+                let valid_end = pos + e.valid_up_to();
+                out.push_str(str::from_utf8(&bytes[pos..valid_end]).unwrap());
+                match e.error_len() {
+                    Some(len) => {
+                        out.push('\u{FFFD}');
+                        pos = valid_end + len;
+                    }
+                    None => return valid_end,
+                }
+            }
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool, out: &mut String) -> usize {
+    let units: Vec<u16> = bytes.chunks_exact(2)
+        .map(|pair| if big_endian {
+            u16::from_be_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_le_bytes([pair[0], pair[1]])
+        })
+        .collect();
+    // Hold back a lead surrogate at the end, whose trail may be in the next
+    // chunk.
+    let mut len = units.len();
+    if len > 0 && (0xD800..0xDC00).contains(&units[len - 1]) {
+        len -= 1;
+    }
+    out.extend(char::decode_utf16(units[..len].iter().cloned())
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)));
+    len * 2
+}
+
+fn decode_windows_1252(bytes: &[u8], out: &mut String) -> usize {
+    out.extend(bytes.iter().map(|&b| match b {
+        0x80..=0x9F => WINDOWS_1252_HIGH[b as usize - 0x80],
+        b => b as char,
+    }));
+    bytes.len()
+}
+
+fn decode_shift_jis(bytes: &[u8], out: &mut String) -> usize {
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let lead = bytes[pos];
+        match lead {
+            0x00..=0x80 => out.push(lead as char),
+            0xA1..=0xDF => out.push(char::from_u32(0xFF61 + lead as u32 - 0xA1).unwrap()),
+            0x81..=0x9F | 0xE0..=0xFC => {
+                let trail = match bytes.get(pos + 1) {
+                    Some(&trail) => trail,
+                    None => return pos,
+                };
+                pos += 1;
+                let c = shift_jis_pair(lead, trail);
+                out.push(c.unwrap_or('\u{FFFD}'));
+                // An ASCII byte after an invalid lead starts a new character.
+                if c.is_none() && trail < 0x80 {
+                    pos -= 1;
+                }
+            }
+            _ => out.push('\u{FFFD}'),
+        }
+        pos += 1;
+    }
+    pos
+}
+
+fn shift_jis_pair(lead: u8, trail: u8) -> Option<char> {
+    let lead_offset = if lead < 0xA0 { 0x81 } else { 0xC1 };
+    let trail_offset = match trail {
+        0x40..=0x7E => 0x40,
+        0x80..=0xFC => 0x41,
+        _ => return None,
+    };
+    let pointer = (lead - lead_offset) as usize * 188 + (trail - trail_offset) as usize;
+    // These pointers are the user-defined area, mapped to private use.
+    if (8836..=10715).contains(&pointer) {
+        return char::from_u32(0xE000 + pointer as u32 - 8836);
+    }
+    match JIS0208.get(pointer) {
+        Some(&0) | None => None,
+        Some(&code) => char::from_u32(code as u32),
+    }
+}
+
+fn find(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes[from..].windows(needle.len()).position(|w| w == needle).map(|i| from + i)
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\x0C' | '\r')
+}
+
+fn is_whitespace_byte(b: u8) -> bool {
+    is_whitespace(b as char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode `chunks` one after another, then finish.
+    fn decode(encoding: Encoding, chunks: &[&[u8]]) -> String {
+        let mut decoder = Decoder::new(encoding);
+        let mut out = String::new();
+        for chunk in chunks {
+            decoder.decode(chunk, &mut out);
+        }
+        decoder.finish(&mut out);
+        out
+    }
+
+    #[test]
+    fn utf16() {
+        assert_eq!(decode(Encoding::Utf16Le, &[b"a\0\xAC\x20"]), "a€");
+        assert_eq!(decode(Encoding::Utf16Be, &[b"\0a\x20\xAC"]), "a€");
+        // A surrogate pair split between chunks.
+        assert_eq!(decode(Encoding::Utf16Le, &[b"\x3D", b"\xD8\x00", b"\xDE"]), "\u{1F600}");
+        assert_eq!(decode(Encoding::Utf16Le, &[b"\x3D\xD8a\0"]), "\u{FFFD}a");
+        assert_eq!(decode(Encoding::Utf16Le, &[b"a\0b"]), "a\u{FFFD}");
+    }
+
+    #[test]
+    fn windows_1252() {
+        assert_eq!(decode(Encoding::Windows1252, &[b"a\x80\x93x\x94\xE9"]), "a€“x”é");
+        assert_eq!(decode(Encoding::Windows1252, &[b"\x81\x9D"]), "\u{81}\u{9D}");
+    }
+
+    #[test]
+    fn shift_jis() {
+        assert_eq!(decode(Encoding::ShiftJis, &[b"\x93\xFA\x96\x7B"]), "日本");
+        assert_eq!(decode(Encoding::ShiftJis, &[b"\x82", b"\xA0a\xB1"]), "あaｱ");
+        // A trail byte that isn't one is read again on its own.
+        assert_eq!(decode(Encoding::ShiftJis, &[b"\x82 "]), "\u{FFFD} ");
+        assert_eq!(decode(Encoding::ShiftJis, &[b"\x82"]), "\u{FFFD}");
+    }
+
+    #[test]
+    fn bom() {
+        assert_eq!(sniff_bom(b"\xEF\xBB\xBFa"), Some((Encoding::Utf8, 3)));
+        assert_eq!(sniff_bom(b"\xFF\xFEa\0"), Some((Encoding::Utf16Le, 2)));
+        assert_eq!(sniff_bom(b"\xFE\xFF\0a"), Some((Encoding::Utf16Be, 2)));
+        assert_eq!(sniff_bom(b"<html>"), None);
+    }
+
+    #[test]
+    fn meta_prescan() {
+        assert_eq!(prescan(b"<meta charset=shift_jis>"), Some(Encoding::ShiftJis));
+        assert_eq!(prescan(b"<!-- <meta charset=utf-16> --><META CHARSET='latin1'>"), Some(Encoding::Windows1252));
+        assert_eq!(prescan(b"<meta http-equiv=Content-Type content='text/html; charset=sjis'>"),
+                   Some(Encoding::ShiftJis));
+        assert_eq!(prescan(b"<meta content='text/html; charset=sjis'>"), None);
+        assert_eq!(prescan(b"<p title='<meta charset=sjis>'>"), None);
+        // A document can't claim to be UTF-16 once it is being read as bytes.
+        assert_eq!(prescan(b"<meta charset=utf-16le>"), Some(Encoding::Utf8));
+    }
+}
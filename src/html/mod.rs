@@ -0,0 +1,255 @@
+use std::io::{self, Read};
+use std::mem;
+
+use dom::Document;
+use node::Node;
+
+pub mod encoding;
+pub mod entities;
+mod jis0208;
+pub mod serialize;
+mod tokenizer;
+mod tree_builder;
+
+use self::encoding::{Decoder, Encoding};
+use self::tokenizer::{Token, Tokenizer};
+use self::tree_builder::TreeBuilder;
+
+/// Parse an HTML document following the HTML standard's parsing algorithm.
+/// Malformed markup is recovered from the same way browsers do, so this
+/// never fails. Returns the `html` element.
+pub fn parse(input: &str) -> Node {
+    let mut parser = Parser::new();
+    parser.feed_str(input);
+    parser.end().into_node()
+}
+
+/// Like `parse`, but returns the document node, whose children include the
+/// DOCTYPE and any comments outside the `html` element, and whose node type
+/// records the quirks mode.
+pub fn parse_document(input: &str) -> Node {
+    let mut parser = Parser::new();
+    parser.feed_str(input);
+    parser.finish()
+}
+
+/// Like `parse_document`, but returns an arena-based `Document` that can be
+/// navigated through parent and sibling links.
+pub fn parse_dom(input: &str) -> Document {
+    Document::from_node(&parse_document(input))
+}
+
+/// Parse HTML from bytes, detecting their encoding. Returns the document
+/// node.
+pub fn parse_bytes(input: &[u8]) -> Node {
+    let mut parser = Parser::new();
+    parser.feed(input);
+    parser.finish()
+}
+
+/// Parse HTML read from `reader`, detecting its encoding, and return the
+/// document node. The input is parsed as it is read rather than collected
+/// first.
+pub fn parse_reader<R: Read>(mut reader: R) -> io::Result<Node> {
+    let mut parser = Parser::new();
+    let mut buf = [0; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(parser.finish()),
+            Ok(len) => parser.feed(&buf[..len]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// An incremental HTML parser. Input is pushed in chunks as it arrives and
+/// parsed straight away, so only the unconsumed end of the input is kept in
+/// memory, and `document` can be called to work on the partial tree before
+/// the rest has been read.
+///
+/// The encoding of byte input is taken from a byte order mark, then from the
+/// encoding given to `with_encoding`, such as one from a `Content-Type`
+/// header, then from a `<meta charset>` in the first 1024 bytes, and is
+/// UTF-8 otherwise.
+pub struct Parser {
+    tokenizer: Tokenizer,
+    builder: TreeBuilder,
+    /// The encoding to use if there is no byte order mark.
+    encoding: Option<Encoding>,
+    /// The decoder, once the encoding has been determined.
+    decoder: Option<Decoder>,
+    /// Input held back until there is enough to determine the encoding.
+    sniff_buffer: Vec<u8>,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser {
+            tokenizer: Tokenizer::new(),
+            builder: TreeBuilder::new(),
+            encoding: None,
+            decoder: None,
+            sniff_buffer: vec![],
+        }
+    }
+
+    /// A parser for input known to be in `encoding`, unless it starts with a
+    /// byte order mark.
+    pub fn with_encoding(encoding: Encoding) -> Parser {
+        Parser { encoding: Some(encoding), ..Parser::new() }
+    }
+
+    /// The encoding of the input, once enough of it has been fed to tell.
+    pub fn encoding(&self) -> Option<Encoding> {
+        self.decoder.as_ref().map(Decoder::encoding)
+    }
+
+    /// Parse a chunk of input. Chunks can split characters; malformed input
+    /// is replaced with U+FFFD.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut text = String::new();
+        match self.decoder {
+            Some(ref mut decoder) => decoder.decode(bytes, &mut text),
+            None => {
+                self.sniff_buffer.extend_from_slice(bytes);
+                let needed = if self.encoding.is_some() { 3 } else { encoding::PRESCAN_LENGTH };
+                if self.sniff_buffer.len() < needed {
+                    return;
+                }
+                self.start_decoding(&mut text);
+            }
+        }
+        self.feed_str(&text);
+    }
+
+    /// Pick the encoding from the buffered start of the input and decode it.
+    fn start_decoding(&mut self, out: &mut String) {
+        let input = mem::take(&mut self.sniff_buffer);
+        let (encoding, bom_len) = match encoding::sniff_bom(&input) {
+            Some(bom) => bom,
+            None => {
+                let encoding = self.encoding
+                    .or_else(|| encoding::prescan(&input))
+                    .unwrap_or(Encoding::Utf8);
+                (encoding, 0)
+            }
+        };
+        let mut decoder = Decoder::new(encoding);
+        decoder.decode(&input[bom_len..], out);
+        self.decoder = Some(decoder);
+    }
+
+    /// Parse a chunk of input that has already been decoded.
+    pub fn feed_str(&mut self, input: &str) {
+        self.tokenizer.feed(input);
+        self.run();
+    }
+
+    /// A copy of the document parsed so far. Elements that are still open
+    /// have only the children seen up to now.
+    pub fn document(&self) -> Node {
+        self.builder.document()
+    }
+
+    /// Parse the end of the input, returning the document node.
+    pub fn finish(self) -> Node {
+        self.end().into_document()
+    }
+
+    fn end(mut self) -> TreeBuilder {
+        let mut text = String::new();
+        if self.decoder.is_none() && !self.sniff_buffer.is_empty() {
+            self.start_decoding(&mut text);
+        }
+        if let Some(ref mut decoder) = self.decoder {
+            decoder.finish(&mut text);
+        }
+        self.tokenizer.feed(&text);
+        self.tokenizer.finish();
+        self.run();
+        self.builder
+    }
+
+    /// Pass tokens to the tree builder until the tokenizer needs more input.
+    fn run(&mut self) {
+        while let Some(token) = self.tokenizer.next_token() {
+            let eof = token == Token::Eof;
+            self.builder.process_token(token);
+            if let Some(state) = self.builder.take_tokenizer_state() {
+                self.tokenizer.set_state(state);
+            }
+            self.tokenizer.set_cdata_allowed(self.builder.in_foreign_content());
+            if eof {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use self::serialize::serialize_children;
+
+    /// A reader that hands out its input a few bytes at a time.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.0.len().min(buf.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    const MARKUP: &str = "<!DOCTYPE html><title>é &amp; ü</title><p class=a>日本<b>x</p><table><td>y</table>";
+
+    #[test]
+    fn chunks_parse_like_the_whole_input() {
+        let whole = serialize_children(&parse_document(MARKUP));
+        let mut parser = Parser::new();
+        for chunk in MARKUP.split_inclusive(['<', ';']) {
+            parser.feed_str(chunk);
+        }
+        assert_eq!(serialize_children(&parser.finish()), whole);
+        assert_eq!(serialize_children(&parse_reader(Trickle(MARKUP.as_bytes())).unwrap()), whole);
+    }
+
+    #[test]
+    fn partial_documents() {
+        let mut parser = Parser::new();
+        parser.feed_str("<p>a<b>b");
+        assert_eq!(serialize_children(&parser.document()), "<html><head></head><body><p>a<b>b</b></p></body></html>");
+        parser.feed_str("</b>c");
+        assert_eq!(serialize_children(&parser.finish()), "<html><head></head><body><p>a<b>b</b>c</p></body></html>");
+    }
+
+    #[test]
+    fn parse_bytes_in_meta_encoding() {
+        let mut parser = Parser::new();
+        parser.feed(b"<meta charset=shift_jis><p>\x93\xFA");
+        parser.feed(b"\x96\x7B</p>");
+        let html = serialize_children(&parser.finish());
+        assert!(html.ends_with("<p>日本</p></body></html>"), "{}", html);
+    }
+
+    #[test]
+    fn parse_bytes_with_bom() {
+        let html = serialize_children(&parse_bytes(b"\xFF\xFE<\0p\0>\0\xAC\x20"));
+        assert!(html.ends_with("<p>€</p></body></html>"), "{}", html);
+        // A byte order mark overrides the encoding given.
+        let mut parser = Parser::with_encoding(Encoding::Windows1252);
+        parser.feed(b"\xEF\xBB\xBF\xC3\xA9");
+        assert!(serialize_children(&parser.finish()).ends_with("<body>é</body></html>"));
+    }
+
+    #[test]
+    fn parse_bytes_in_given_encoding() {
+        let mut parser = Parser::with_encoding(Encoding::Windows1252);
+        parser.feed(b"<meta charset=utf-8>\x93a\x94");
+        assert_eq!(parser.encoding(), Some(Encoding::Windows1252));
+        assert!(serialize_children(&parser.finish()).ends_with("<body>“a”</body></html>"));
+    }
+}
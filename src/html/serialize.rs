@@ -0,0 +1,297 @@
+use node::{Node, NodeType, ElementData};
+
+/// Elements that have no end tag and never have children.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img",
+    "input", "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose text content is written without escaping.
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "iframe", "noembed", "noframes", "plaintext", "script", "style", "xmp",
+];
+
+/// Elements whose contents the pretty printer leaves exactly as they are.
+const PREFORMATTED_ELEMENTS: &[&str] = &["listing", "pre", "textarea"];
+
+/// Whether `elem` is an HTML element named in `names`. MathML and SVG
+/// elements are never void, raw text or preformatted, even if they share a
+/// name with one that is, such as SVG's `style`.
+fn is_html_one_of(elem: &ElementData, names: &[&str]) -> bool {
+    elem.is_html() && names.contains(&&*elem.tag_name)
+}
+
+/// Serialize a node and its descendants to HTML. A document node is written
+/// as its children.
+///
+/// This follows the HTML fragment serialization algorithm, so parsing the
+/// output gives back the same tree. Attributes are written in name order,
+/// since the DOM doesn't keep their source order.
+pub fn serialize(node: &Node) -> String {
+    let mut out = String::new();
+    match node.node_type {
+        NodeType::Document(_) => serialize_children_to(node, &mut out),
+        _ => serialize_node(node, None, &mut out),
+    }
+    out
+}
+
+/// Serialize the children of a node, like the `innerHTML` getter.
+pub fn serialize_children(node: &Node) -> String {
+    let mut out = String::new();
+    serialize_children_to(node, &mut out);
+    out
+}
+
+/// Serialize a node with one node per line, indenting each element's
+/// children by two spaces and leaving out whitespace-only text. Text inside
+/// `<pre>`, `<textarea>` and raw text elements is kept as it is.
+///
+/// This is for reading rather than round-tripping: reparsing the output can
+/// give a tree with different white space.
+pub fn pretty_print(node: &Node) -> String {
+    let mut out = String::new();
+    match node.node_type {
+        NodeType::Document(_) => {
+            for child in &node.children {
+                pretty_print_node(child, None, 0, &mut out);
+            }
+        }
+        _ => pretty_print_node(node, None, 0, &mut out),
+    }
+    out
+}
+
+fn serialize_children_to(node: &Node, out: &mut String) {
+    let parent = match node.node_type {
+        NodeType::Element(ref elem) => Some(elem),
+        _ => None,
+    };
+    for child in &node.children {
+        serialize_node(child, parent, out);
+    }
+}
+
+fn serialize_node(node: &Node, parent: Option<&ElementData>, out: &mut String) {
+    match node.node_type {
+        NodeType::Element(ref elem) => {
+            write_start_tag(elem, out);
+            if is_void(elem) {
+                return;
+            }
+            // The parser drops a newline right after these start tags, so
+            // a leading newline in the text needs another in front of it.
+            if is_html_one_of(elem, PREFORMATTED_ELEMENTS) && starts_with_newline(node) {
+                out.push('\n');
+            }
+            serialize_children_to(node, out);
+            write_end_tag(elem, out);
+        }
+        NodeType::Text(ref text) => write_text(text, parent, out),
+        NodeType::Comment(ref text) => {
+            out.push_str("<!--");
+            out.push_str(text);
+            out.push_str("-->");
+        }
+        NodeType::Doctype(ref doctype) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(&doctype.name);
+            out.push('>');
+        }
+        NodeType::ProcessingInstruction(ref pi) => {
+            out.push_str("<?");
+            out.push_str(&pi.target);
+            out.push(' ');
+            out.push_str(&pi.data);
+            out.push('>');
+        }
+        NodeType::Document(_) => serialize_children_to(node, out),
+    }
+}
+
+fn pretty_print_node(node: &Node, parent: Option<&ElementData>, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    match node.node_type {
+        NodeType::Element(ref elem) => {
+            out.push_str(&indent);
+            write_start_tag(elem, out);
+            if is_void(elem) {
+                out.push('\n');
+                return;
+            }
+            let preformatted = is_html_one_of(elem, PREFORMATTED_ELEMENTS)
+                || is_html_one_of(elem, RAW_TEXT_ELEMENTS);
+            let single_text = node.children.len() == 1 && is_text(&node.children[0]);
+            if preformatted || single_text || !has_visible_children(node) {
+                if preformatted && starts_with_newline(node) {
+                    out.push('\n');
+                }
+                if preformatted {
+                    serialize_children_to(node, out);
+                } else if single_text {
+                    if let NodeType::Text(ref text) = node.children[0].node_type {
+                        write_text(trim(text), Some(elem), out);
+                    }
+                }
+            } else {
+                out.push('\n');
+                for child in &node.children {
+                    pretty_print_node(child, Some(elem), depth + 1, out);
+                }
+                out.push_str(&indent);
+            }
+            write_end_tag(elem, out);
+            out.push('\n');
+        }
+        NodeType::Text(ref text) => {
+            let text = trim(text);
+            if !text.is_empty() {
+                out.push_str(&indent);
+                write_text(text, parent, out);
+                out.push('\n');
+            }
+        }
+        _ => {
+            out.push_str(&indent);
+            serialize_node(node, parent, out);
+            out.push('\n');
+        }
+    }
+}
+
+fn write_start_tag(elem: &ElementData, out: &mut String) {
+    out.push('<');
+    out.push_str(&elem.tag_name);
+    let mut attributes: Vec<_> = elem.attributes().iter().collect();
+    attributes.sort();
+    for (name, value) in attributes {
+        out.push(' ');
+        out.push_str(name);
+        out.push_str("=\"");
+        escape(value, true, out);
+        out.push('"');
+    }
+    out.push('>');
+}
+
+fn write_end_tag(elem: &ElementData, out: &mut String) {
+    out.push_str("</");
+    out.push_str(&elem.tag_name);
+    out.push('>');
+}
+
+fn write_text(text: &str, parent: Option<&ElementData>, out: &mut String) {
+    match parent {
+        Some(elem) if is_html_one_of(elem, RAW_TEXT_ELEMENTS) => out.push_str(text),
+        _ => escape(text, false, out),
+    }
+}
+
+/// Escape text for use as element content, or as a double-quoted attribute
+/// value if `attribute` is set.
+fn escape(text: &str, attribute: bool, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '\u{A0}' => out.push_str("&nbsp;"),
+            '"' if attribute => out.push_str("&quot;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn is_void(elem: &ElementData) -> bool {
+    is_html_one_of(elem, VOID_ELEMENTS)
+}
+
+fn is_text(node: &Node) -> bool {
+    matches!(node.node_type, NodeType::Text(_))
+}
+
+fn starts_with_newline(node: &Node) -> bool {
+    match node.children.first() {
+        Some(&Node { node_type: NodeType::Text(ref text), .. }) => text.starts_with('\n'),
+        _ => false,
+    }
+}
+
+/// Whether a node has any children other than whitespace-only text.
+fn has_visible_children(node: &Node) -> bool {
+    node.children.iter().any(|child| match child.node_type {
+        NodeType::Text(ref text) => !trim(text).is_empty(),
+        _ => true,
+    })
+}
+
+/// Strip leading and trailing ASCII white space, but not other white space
+/// such as no-break spaces.
+fn trim(text: &str) -> &str {
+    text.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\x0C' | '\r'))
+}
+
+#[cfg(test)]
+mod tests {
+    use html::{parse, parse_document};
+    use super::*;
+
+    /// `html` parsed and written back, from inside the `body`.
+    fn round_trip(html: &str) -> String {
+        let html = serialize(&parse(html));
+        html["<html><head></head><body>".len()..html.len() - "</body></html>".len()].to_string()
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(round_trip("a &amp; &lt;b&gt; \"c\"&nbsp;"), "a &amp; &lt;b&gt; \"c\"&nbsp;");
+        assert_eq!(round_trip("<p title='a \"b\" &amp; <c>&nbsp;'>"),
+                   "<p title=\"a &quot;b&quot; &amp; &lt;c&gt;&nbsp;\"></p>");
+    }
+
+    #[test]
+    fn void_elements() {
+        assert_eq!(round_trip("a<br>b<img src=x><input></input>c<hr/>"), "a<br>b<img src=\"x\"><input>c<hr>");
+        // An SVG element named like a void one has an end tag.
+        assert_eq!(round_trip("<svg><source/></svg>"), "<svg><source></source></svg>");
+    }
+
+    #[test]
+    fn raw_text_elements() {
+        assert_eq!(round_trip("<body><script>if (a < b && c) {}</script><style>a > b {}</style>"),
+                   "<script>if (a < b && c) {}</script><style>a > b {}</style>");
+        assert_eq!(round_trip("<svg><style>a > b</style></svg>"), "<svg><style>a &gt; b</style></svg>");
+    }
+
+    #[test]
+    fn leading_newline() {
+        // The first newline after the start tag is dropped by the parser, so
+        // one that is kept is written after another.
+        assert_eq!(round_trip("<pre>\na</pre><textarea>\n\nb</textarea>"), "<pre>a</pre><textarea>\n\nb</textarea>");
+        let html = "<pre>\n\na</pre>";
+        assert_eq!(round_trip(html), "<pre>\n\na</pre>");
+        assert_eq!(round_trip(&round_trip(html)), round_trip(html));
+    }
+
+    #[test]
+    fn attributes_are_quoted_in_name_order() {
+        assert_eq!(round_trip("<a title=x href=y data-b=2 data-a=1 hidden>"),
+                   "<a data-a=\"1\" data-b=\"2\" hidden=\"\" href=\"y\" title=\"x\"></a>");
+    }
+
+    #[test]
+    fn documents() {
+        let document = parse_document("<!DOCTYPE html><!--c--><title>t</title><p>x");
+        assert_eq!(serialize(&document), "<!DOCTYPE html><!--c--><html><head><title>t</title></head>\
+                                          <body><p>x</p></body></html>");
+        let body = &parse("<p>a<b>b</b></p>").children[1];
+        assert_eq!(serialize_children(body), "<p>a<b>b</b></p>");
+    }
+
+    #[test]
+    fn pretty_printing() {
+        let html = pretty_print(&parse("<ul>\n  <li>a</li> <li><b>b</b> c</li></ul><pre>\n x\n</pre>"));
+        assert_eq!(html, "<html>\n  <head></head>\n  <body>\n    <ul>\n      <li>a</li>\n      <li>\n        <b>b</b>\n\
+                          \x20       c\n      </li>\n    </ul>\n    <pre> x\n</pre>\n  </body>\n</html>\n");
+    }
+}
@@ -0,0 +1,1342 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use super::entities::{ENTITIES, LONGEST_ENTITY_NAME};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Tag {
+    pub name: String,
+    pub attributes: Vec<Attribute>,
+    pub self_closing: bool,
+}
+
+impl Tag {
+    pub fn new(name: &str) -> Tag {
+        Tag { name: name.to_string(), ..Tag::default() }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|a| a.name == name).map(|a| &*a.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Doctype {
+    pub name: Option<String>,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    pub force_quirks: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Doctype(Doctype),
+    StartTag(Tag),
+    EndTag(Tag),
+    Character(char),
+    Comment(String),
+    Eof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Data,
+    Rcdata,
+    Rawtext,
+    ScriptData,
+    Plaintext,
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    RcdataLessThanSign,
+    RcdataEndTagOpen,
+    RcdataEndTagName,
+    RawtextLessThanSign,
+    RawtextEndTagOpen,
+    RawtextEndTagName,
+    ScriptDataLessThanSign,
+    ScriptDataEndTagOpen,
+    ScriptDataEndTagName,
+    ScriptDataEscapeStart,
+    ScriptDataEscapeStartDash,
+    ScriptDataEscaped,
+    ScriptDataEscapedDash,
+    ScriptDataEscapedDashDash,
+    ScriptDataEscapedLessThanSign,
+    ScriptDataEscapedEndTagOpen,
+    ScriptDataEscapedEndTagName,
+    ScriptDataDoubleEscapeStart,
+    ScriptDataDoubleEscaped,
+    ScriptDataDoubleEscapedDash,
+    ScriptDataDoubleEscapedDashDash,
+    ScriptDataDoubleEscapedLessThanSign,
+    ScriptDataDoubleEscapeEnd,
+    BeforeAttributeName,
+    AttributeName,
+    AfterAttributeName,
+    BeforeAttributeValue,
+    AttributeValueDoubleQuoted,
+    AttributeValueSingleQuoted,
+    AttributeValueUnquoted,
+    AfterAttributeValueQuoted,
+    SelfClosingStartTag,
+    BogusComment,
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentLessThanSign,
+    CommentLessThanSignBang,
+    CommentLessThanSignBangDash,
+    CommentLessThanSignBangDashDash,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypePublicKeyword,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierDoubleQuoted,
+    DoctypePublicIdentifierSingleQuoted,
+    AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
+    AfterDoctypeSystemKeyword,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierDoubleQuoted,
+    DoctypeSystemIdentifierSingleQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    CharacterReference,
+    NamedCharacterReference,
+    AmbiguousAmpersand,
+    NumericCharacterReference,
+    HexadecimalCharacterReferenceStart,
+    DecimalCharacterReferenceStart,
+    HexadecimalCharacterReference,
+    DecimalCharacterReference,
+    NumericCharacterReferenceEnd,
+    CdataSection,
+    CdataSectionBracket,
+    CdataSectionEnd,
+}
+
+/// Replacements for numeric references to C1 control characters, which are
+/// interpreted as windows-1252 for compatibility.
+const C1_REPLACEMENTS: &[(u32, char)] = &[
+    (0x80, '\u{20AC}'), (0x82, '\u{201A}'), (0x83, '\u{0192}'), (0x84, '\u{201E}'),
+    (0x85, '\u{2026}'), (0x86, '\u{2020}'), (0x87, '\u{2021}'), (0x88, '\u{02C6}'),
+    (0x89, '\u{2030}'), (0x8A, '\u{0160}'), (0x8B, '\u{2039}'), (0x8C, '\u{0152}'),
+    (0x8E, '\u{017D}'), (0x91, '\u{2018}'), (0x92, '\u{2019}'), (0x93, '\u{201C}'),
+    (0x94, '\u{201D}'), (0x95, '\u{2022}'), (0x96, '\u{2013}'), (0x97, '\u{2014}'),
+    (0x98, '\u{02DC}'), (0x99, '\u{2122}'), (0x9A, '\u{0161}'), (0x9B, '\u{203A}'),
+    (0x9C, '\u{0153}'), (0x9E, '\u{017E}'), (0x9F, '\u{0178}'),
+];
+
+enum Input {
+    Char(char),
+    Eof,
+    /// The end of the buffered input was reached before `finish` was called.
+    Wait,
+}
+
+/// An HTML tokenizer implementing the state machine from the "Tokenization"
+/// section of the HTML standard.
+///
+/// Input is pushed with `feed` and tokens are pulled with `next_token`, which
+/// returns `None` when it needs more input. Once `finish` has been called the
+/// remaining input is tokenized and terminated by `Token::Eof`.
+pub struct Tokenizer {
+    input: String,
+    /// The byte offset of the next character of `input`.
+    pos: usize,
+    finished: bool,
+    last_was_cr: bool,
+    state: State,
+    tokens: VecDeque<Token>,
+    emitted_eof: bool,
+    tag: Tag,
+    tag_is_end: bool,
+    attribute: Option<Attribute>,
+    comment: String,
+    doctype: Doctype,
+    return_state: State,
+    temp_buffer: String,
+    char_ref_code: u32,
+    /// The name of the last start tag emitted, for recognizing the end tag
+    /// that closes a raw text element.
+    last_start_tag: String,
+    /// Whether `<![CDATA[` starts a CDATA section, as it does in foreign
+    /// content, rather than a bogus comment.
+    cdata_allowed: bool,
+}
+
+impl Tokenizer {
+    pub fn new() -> Tokenizer {
+        Tokenizer {
+            input: String::new(),
+            pos: 0,
+            finished: false,
+            last_was_cr: false,
+            state: State::Data,
+            tokens: VecDeque::new(),
+            emitted_eof: false,
+            tag: Tag::default(),
+            tag_is_end: false,
+            attribute: None,
+            comment: String::new(),
+            doctype: Doctype::default(),
+            return_state: State::Data,
+            temp_buffer: String::new(),
+            char_ref_code: 0,
+            last_start_tag: String::new(),
+            cdata_allowed: false,
+        }
+    }
+
+    /// Append input, normalizing newlines as the input stream preprocessor
+    /// requires. Input that has already been tokenized is dropped first.
+    pub fn feed(&mut self, input: &str) {
+        self.input.drain(..self.pos);
+        self.pos = 0;
+        for c in input.chars() {
+            match c {
+                '\r' => self.input.push('\n'),
+                '\n' if self.last_was_cr => {}
+                c => self.input.push(c),
+            }
+            self.last_was_cr = c == '\r';
+        }
+    }
+
+    /// Switch to a new state. The tree builder does this after the start tag
+    /// of an element whose contents are text, such as `<script>`.
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// Set whether CDATA sections are recognized. The tree builder allows
+    /// them while the current node is a MathML or SVG element.
+    pub fn set_cdata_allowed(&mut self, allowed: bool) {
+        self.cdata_allowed = allowed;
+    }
+
+    /// Mark the end of the input.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    pub fn next_token(&mut self) -> Option<Token> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(token);
+            }
+            if self.emitted_eof || !self.step() {
+                return None;
+            }
+        }
+    }
+
+    fn consume(&mut self) -> Input {
+        match self.input[self.pos..].chars().next() {
+            Some(c) => {
+                self.pos += c.len_utf8();
+                Input::Char(c)
+            }
+            None if self.finished => Input::Eof,
+            None => Input::Wait,
+        }
+    }
+
+    /// Whether the unconsumed input starts with `s`, compared ASCII
+    /// case-insensitively. `None` means there isn't enough input to tell yet.
+    fn lookahead(&self, s: &str) -> Option<bool> {
+        // `s` is ASCII, so it can be compared byte by byte.
+        let available = &self.input.as_bytes()[self.pos..];
+        let prefix_matches = available.iter().zip(s.as_bytes()).all(|(a, b)| a.eq_ignore_ascii_case(b));
+        if available.len() < s.len() && !self.finished {
+            return if prefix_matches { None } else { Some(false) };
+        }
+        Some(available.len() >= s.len() && prefix_matches)
+    }
+
+    fn reconsume_in(&mut self, c: Option<char>, state: State) {
+        if let Some(c) = c {
+            self.pos -= c.len_utf8();
+        }
+        self.state = state;
+    }
+
+    fn emit(&mut self, token: Token) {
+        if token == Token::Eof {
+            self.emitted_eof = true;
+        }
+        self.tokens.push_back(token);
+    }
+
+    fn emit_chars(&mut self, s: &str) {
+        for c in s.chars() {
+            self.emit(Token::Character(c));
+        }
+    }
+
+    fn start_tag(&mut self, is_end: bool) {
+        self.tag = Tag::default();
+        self.tag_is_end = is_end;
+    }
+
+    fn start_attribute(&mut self) {
+        self.finish_attribute();
+        self.attribute = Some(Attribute { name: String::new(), value: String::new() });
+    }
+
+    /// Add the attribute being built to the current tag. Duplicates are
+    /// dropped; the first occurrence wins.
+    fn finish_attribute(&mut self) {
+        if let Some(attribute) = self.attribute.take() {
+            if self.tag.attribute(&attribute.name).is_none() {
+                self.tag.attributes.push(attribute);
+            }
+        }
+    }
+
+    fn emit_tag(&mut self) {
+        self.finish_attribute();
+        let tag = mem::take(&mut self.tag);
+        if self.tag_is_end {
+            self.emit(Token::EndTag(tag));
+        } else {
+            self.last_start_tag = tag.name.clone();
+            self.emit(Token::StartTag(tag));
+        }
+    }
+
+    /// Whether the end tag being built would close the current raw text
+    /// element.
+    fn is_appropriate_end_tag(&self) -> bool {
+        self.tag_is_end && self.tag.name == self.last_start_tag
+    }
+
+    /// The "less-than sign" state of RCDATA and RAWTEXT text.
+    fn text_less_than_sign(&mut self, c: Option<char>, text_state: State, end_tag_open: State) {
+        if c == Some('/') {
+            self.temp_buffer.clear();
+            self.state = end_tag_open;
+        } else {
+            self.emit(Token::Character('<'));
+            self.reconsume_in(c, text_state);
+        }
+    }
+
+    /// The "end tag open" state of RCDATA, RAWTEXT and script data text.
+    fn text_end_tag_open(&mut self, c: Option<char>, text_state: State, end_tag_name: State) {
+        match c {
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.start_tag(true);
+                self.reconsume_in(Some(c), end_tag_name);
+            }
+            _ => {
+                self.emit_chars("</");
+                self.reconsume_in(c, text_state);
+            }
+        }
+    }
+
+    /// The "end tag name" state of RCDATA, RAWTEXT and script data text. An
+    /// end tag that doesn't close the element is emitted as text.
+    fn text_end_tag_name(&mut self, c: Option<char>, text_state: State) {
+        match c {
+            Some('\t') | Some('\n') | Some('\x0C') | Some(' ') if self.is_appropriate_end_tag() => {
+                self.state = State::BeforeAttributeName;
+            }
+            Some('/') if self.is_appropriate_end_tag() => self.state = State::SelfClosingStartTag,
+            Some('>') if self.is_appropriate_end_tag() => {
+                self.state = State::Data;
+                self.emit_tag();
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.tag.name.push(c.to_ascii_lowercase());
+                self.temp_buffer.push(c);
+            }
+            _ => {
+                self.emit_chars("</");
+                let buffer = mem::take(&mut self.temp_buffer);
+                self.emit_chars(&buffer);
+                self.reconsume_in(c, text_state);
+            }
+        }
+    }
+
+    /// The states that look for a `script` tag name to enter or leave double
+    /// escaped script data.
+    fn script_data_double_escape(&mut self, c: Option<char>, text_state: State, matched: State,
+                                 unmatched: State) {
+        match c {
+            Some(c @ '\t') | Some(c @ '\n') | Some(c @ '\x0C') | Some(c @ ' ') | Some(c @ '/')
+            | Some(c @ '>') => {
+                self.state = if self.temp_buffer == "script" { matched } else { unmatched };
+                self.emit(Token::Character(c));
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.temp_buffer.push(c.to_ascii_lowercase());
+                self.emit(Token::Character(c));
+            }
+            _ => self.reconsume_in(c, text_state),
+        }
+    }
+
+    fn emit_comment(&mut self) {
+        let comment = mem::take(&mut self.comment);
+        self.emit(Token::Comment(comment));
+    }
+
+    fn begin_character_reference(&mut self, return_state: State) {
+        self.return_state = return_state;
+        self.state = State::CharacterReference;
+    }
+
+    fn in_attribute_value(&self) -> bool {
+        matches!(self.return_state, State::AttributeValueDoubleQuoted
+                 | State::AttributeValueSingleQuoted | State::AttributeValueUnquoted)
+    }
+
+    /// Output the text of a character reference to wherever the reference
+    /// appeared: an attribute value or the document text.
+    fn flush_temp_buffer(&mut self) {
+        let buffer = mem::take(&mut self.temp_buffer);
+        if self.in_attribute_value() {
+            self.attribute.as_mut().unwrap().value.push_str(&buffer);
+        } else {
+            self.emit_chars(&buffer);
+        }
+    }
+
+    fn emit_doctype(&mut self) {
+        let doctype = mem::take(&mut self.doctype);
+        self.emit(Token::Doctype(doctype));
+    }
+
+    /// Emit the current DOCTYPE with its force-quirks flag set, as is done
+    /// for most malformed DOCTYPEs.
+    fn emit_quirky_doctype(&mut self) {
+        self.doctype.force_quirks = true;
+        self.emit_doctype();
+    }
+
+    fn doctype_name(&mut self) -> &mut String {
+        self.doctype.name.get_or_insert_with(String::new)
+    }
+
+    fn doctype_public_id(&mut self) -> &mut String {
+        self.doctype.public_id.get_or_insert_with(String::new)
+    }
+
+    fn doctype_system_id(&mut self) -> &mut String {
+        self.doctype.system_id.get_or_insert_with(String::new)
+    }
+
+    /// Run one transition of the state machine. Returns false if more input
+    /// is needed to make progress.
+    fn step(&mut self) -> bool {
+        if self.state == State::MarkupDeclarationOpen {
+            return self.markup_declaration_open();
+        }
+        if self.state == State::NamedCharacterReference {
+            return self.named_character_reference();
+        }
+        let c = match self.consume() {
+            Input::Char(c) => Some(c),
+            Input::Eof => None,
+            Input::Wait => return false,
+        };
+        match self.state {
+            State::Data => match c {
+                Some('&') => self.begin_character_reference(State::Data),
+                Some('<') => self.state = State::TagOpen,
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::Rcdata => match c {
+                Some('&') => self.begin_character_reference(State::Rcdata),
+                Some('<') => self.state = State::RcdataLessThanSign,
+                Some('\0') => self.emit(Token::Character('\u{FFFD}')),
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::Rawtext | State::ScriptData => match c {
+                Some('<') if self.state == State::Rawtext => self.state = State::RawtextLessThanSign,
+                Some('<') => self.state = State::ScriptDataLessThanSign,
+                Some('\0') => self.emit(Token::Character('\u{FFFD}')),
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::Plaintext => match c {
+                Some('\0') => self.emit(Token::Character('\u{FFFD}')),
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::TagOpen => match c {
+                Some('!') => self.state = State::MarkupDeclarationOpen,
+                Some('?') => {
+                    self.comment.clear();
+                    self.reconsume_in(c, State::BogusComment);
+                }
+                Some('/') => self.state = State::EndTagOpen,
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.start_tag(false);
+                    self.reconsume_in(Some(c), State::TagName);
+                }
+                _ => {
+                    self.emit(Token::Character('<'));
+                    self.reconsume_in(c, State::Data);
+                }
+            },
+            State::EndTagOpen => match c {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.start_tag(true);
+                    self.reconsume_in(Some(c), State::TagName);
+                }
+                Some('>') => self.state = State::Data,
+                None => {
+                    self.emit_chars("</");
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.comment.clear();
+                    self.reconsume_in(Some(c), State::BogusComment);
+                }
+            },
+            State::TagName => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::BeforeAttributeName;
+                }
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                Some('\0') => self.tag.name.push('\u{FFFD}'),
+                Some(c) => self.tag.name.push(c.to_ascii_lowercase()),
+                None => self.emit(Token::Eof),
+            },
+            State::RcdataLessThanSign => {
+                self.text_less_than_sign(c, State::Rcdata, State::RcdataEndTagOpen);
+            }
+            State::RcdataEndTagOpen => {
+                self.text_end_tag_open(c, State::Rcdata, State::RcdataEndTagName);
+            }
+            State::RcdataEndTagName => self.text_end_tag_name(c, State::Rcdata),
+            State::RawtextLessThanSign => {
+                self.text_less_than_sign(c, State::Rawtext, State::RawtextEndTagOpen);
+            }
+            State::RawtextEndTagOpen => {
+                self.text_end_tag_open(c, State::Rawtext, State::RawtextEndTagName);
+            }
+            State::RawtextEndTagName => self.text_end_tag_name(c, State::Rawtext),
+            State::ScriptDataLessThanSign => match c {
+                Some('/') => {
+                    self.temp_buffer.clear();
+                    self.state = State::ScriptDataEndTagOpen;
+                }
+                Some('!') => {
+                    self.state = State::ScriptDataEscapeStart;
+                    self.emit_chars("<!");
+                }
+                _ => {
+                    self.emit(Token::Character('<'));
+                    self.reconsume_in(c, State::ScriptData);
+                }
+            },
+            State::ScriptDataEndTagOpen => {
+                self.text_end_tag_open(c, State::ScriptData, State::ScriptDataEndTagName);
+            }
+            State::ScriptDataEndTagName => self.text_end_tag_name(c, State::ScriptData),
+            State::ScriptDataEscapeStart | State::ScriptDataEscapeStartDash => match c {
+                Some('-') => {
+                    self.state = if self.state == State::ScriptDataEscapeStart {
+                        State::ScriptDataEscapeStartDash
+                    } else {
+                        State::ScriptDataEscapedDashDash
+                    };
+                    self.emit(Token::Character('-'));
+                }
+                _ => self.reconsume_in(c, State::ScriptData),
+            },
+            State::ScriptDataEscaped | State::ScriptDataEscapedDash | State::ScriptDataEscapedDashDash => {
+                match c {
+                    Some('-') => {
+                        self.state = match self.state {
+                            State::ScriptDataEscaped => State::ScriptDataEscapedDash,
+                            _ => State::ScriptDataEscapedDashDash,
+                        };
+                        self.emit(Token::Character('-'));
+                    }
+                    Some('<') => self.state = State::ScriptDataEscapedLessThanSign,
+                    Some('>') if self.state == State::ScriptDataEscapedDashDash => {
+                        self.state = State::ScriptData;
+                        self.emit(Token::Character('>'));
+                    }
+                    Some(c) => {
+                        self.state = State::ScriptDataEscaped;
+                        self.emit(Token::Character(if c == '\0' { '\u{FFFD}' } else { c }));
+                    }
+                    None => self.emit(Token::Eof),
+                }
+            }
+            State::ScriptDataEscapedLessThanSign => match c {
+                Some('/') => {
+                    self.temp_buffer.clear();
+                    self.state = State::ScriptDataEscapedEndTagOpen;
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.temp_buffer.clear();
+                    self.emit(Token::Character('<'));
+                    self.reconsume_in(Some(c), State::ScriptDataDoubleEscapeStart);
+                }
+                _ => {
+                    self.emit(Token::Character('<'));
+                    self.reconsume_in(c, State::ScriptDataEscaped);
+                }
+            },
+            State::ScriptDataEscapedEndTagOpen => {
+                self.text_end_tag_open(c, State::ScriptDataEscaped, State::ScriptDataEscapedEndTagName);
+            }
+            State::ScriptDataEscapedEndTagName => self.text_end_tag_name(c, State::ScriptDataEscaped),
+            State::ScriptDataDoubleEscapeStart => {
+                self.script_data_double_escape(c, State::ScriptDataEscaped,
+                                               State::ScriptDataDoubleEscaped, State::ScriptDataEscaped);
+            }
+            State::ScriptDataDoubleEscaped | State::ScriptDataDoubleEscapedDash
+            | State::ScriptDataDoubleEscapedDashDash => match c {
+                Some('-') => {
+                    self.state = match self.state {
+                        State::ScriptDataDoubleEscaped => State::ScriptDataDoubleEscapedDash,
+                        _ => State::ScriptDataDoubleEscapedDashDash,
+                    };
+                    self.emit(Token::Character('-'));
+                }
+                Some('<') => {
+                    self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                    self.emit(Token::Character('<'));
+                }
+                Some('>') if self.state == State::ScriptDataDoubleEscapedDashDash => {
+                    self.state = State::ScriptData;
+                    self.emit(Token::Character('>'));
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit(Token::Character(if c == '\0' { '\u{FFFD}' } else { c }));
+                }
+                None => self.emit(Token::Eof),
+            },
+            State::ScriptDataDoubleEscapedLessThanSign => match c {
+                Some('/') => {
+                    self.temp_buffer.clear();
+                    self.state = State::ScriptDataDoubleEscapeEnd;
+                    self.emit(Token::Character('/'));
+                }
+                _ => self.reconsume_in(c, State::ScriptDataDoubleEscaped),
+            },
+            State::ScriptDataDoubleEscapeEnd => {
+                self.script_data_double_escape(c, State::ScriptDataDoubleEscaped,
+                                               State::ScriptDataEscaped, State::ScriptDataDoubleEscaped);
+            }
+            State::BeforeAttributeName => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {}
+                Some('/') | Some('>') | None => self.reconsume_in(c, State::AfterAttributeName),
+                Some('=') => {
+                    self.start_attribute();
+                    self.attribute.as_mut().unwrap().name.push('=');
+                    self.state = State::AttributeName;
+                }
+                Some(c) => {
+                    self.start_attribute();
+                    self.reconsume_in(Some(c), State::AttributeName);
+                }
+            },
+            State::AttributeName => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') | Some('/') | Some('>') | None => {
+                    self.reconsume_in(c, State::AfterAttributeName);
+                }
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some('\0') => self.attribute.as_mut().unwrap().name.push('\u{FFFD}'),
+                Some(c) => self.attribute.as_mut().unwrap().name.push(c.to_ascii_lowercase()),
+            },
+            State::AfterAttributeName => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {}
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                Some(c) => {
+                    self.start_attribute();
+                    self.reconsume_in(Some(c), State::AttributeName);
+                }
+                None => self.emit(Token::Eof),
+            },
+            State::BeforeAttributeValue => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {}
+                Some('"') => self.state = State::AttributeValueDoubleQuoted,
+                Some('\'') => self.state = State::AttributeValueSingleQuoted,
+                // A missing value; the attribute is left empty.
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                _ => self.reconsume_in(c, State::AttributeValueUnquoted),
+            },
+            State::AttributeValueDoubleQuoted | State::AttributeValueSingleQuoted => {
+                let quote = if self.state == State::AttributeValueDoubleQuoted { '"' } else { '\'' };
+                match c {
+                    Some(c) if c == quote => self.state = State::AfterAttributeValueQuoted,
+                    Some('&') => {
+                        let state = self.state;
+                        self.begin_character_reference(state);
+                    }
+                    Some('\0') => self.attribute.as_mut().unwrap().value.push('\u{FFFD}'),
+                    Some(c) => self.attribute.as_mut().unwrap().value.push(c),
+                    None => self.emit(Token::Eof),
+                }
+            }
+            State::AttributeValueUnquoted => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::BeforeAttributeName;
+                }
+                Some('&') => self.begin_character_reference(State::AttributeValueUnquoted),
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                Some('\0') => self.attribute.as_mut().unwrap().value.push('\u{FFFD}'),
+                Some(c) => self.attribute.as_mut().unwrap().value.push(c),
+                None => self.emit(Token::Eof),
+            },
+            State::AfterAttributeValueQuoted => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::BeforeAttributeName;
+                }
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                None => self.emit(Token::Eof),
+                Some(c) => self.reconsume_in(Some(c), State::BeforeAttributeName),
+            },
+            State::SelfClosingStartTag => match c {
+                Some('>') => {
+                    self.tag.self_closing = true;
+                    self.state = State::Data;
+                    self.emit_tag();
+                }
+                None => self.emit(Token::Eof),
+                Some(c) => self.reconsume_in(Some(c), State::BeforeAttributeName),
+            },
+            State::BogusComment => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                Some('\0') => self.comment.push('\u{FFFD}'),
+                Some(c) => self.comment.push(c),
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::MarkupDeclarationOpen | State::NamedCharacterReference => unreachable!(),
+            State::CharacterReference => {
+                self.temp_buffer = "&".to_string();
+                match c {
+                    Some(c) if c.is_ascii_alphanumeric() => {
+                        self.reconsume_in(Some(c), State::NamedCharacterReference);
+                    }
+                    Some('#') => {
+                        self.temp_buffer.push('#');
+                        self.state = State::NumericCharacterReference;
+                    }
+                    _ => {
+                        self.flush_temp_buffer();
+                        let state = self.return_state;
+                        self.reconsume_in(c, state);
+                    }
+                }
+            }
+            State::AmbiguousAmpersand => match c {
+                Some(c) if c.is_ascii_alphanumeric() => {
+                    if self.in_attribute_value() {
+                        self.attribute.as_mut().unwrap().value.push(c);
+                    } else {
+                        self.emit(Token::Character(c));
+                    }
+                }
+                _ => {
+                    let state = self.return_state;
+                    self.reconsume_in(c, state);
+                }
+            },
+            State::NumericCharacterReference => {
+                self.char_ref_code = 0;
+                match c {
+                    Some(c) if c == 'x' || c == 'X' => {
+                        self.temp_buffer.push(c);
+                        self.state = State::HexadecimalCharacterReferenceStart;
+                    }
+                    _ => self.reconsume_in(c, State::DecimalCharacterReferenceStart),
+                }
+            }
+            State::HexadecimalCharacterReferenceStart | State::DecimalCharacterReferenceStart => {
+                let hex = self.state == State::HexadecimalCharacterReferenceStart;
+                match c {
+                    Some(c) if hex && c.is_ascii_hexdigit() => {
+                        self.reconsume_in(Some(c), State::HexadecimalCharacterReference);
+                    }
+                    Some(c) if !hex && c.is_ascii_digit() => {
+                        self.reconsume_in(Some(c), State::DecimalCharacterReference);
+                    }
+                    _ => {
+                        // Not a reference after all, e.g. "&#x;".
+                        self.flush_temp_buffer();
+                        let state = self.return_state;
+                        self.reconsume_in(c, state);
+                    }
+                }
+            }
+            State::HexadecimalCharacterReference | State::DecimalCharacterReference => {
+                let radix = if self.state == State::HexadecimalCharacterReference { 16 } else { 10 };
+                match c.and_then(|c| c.to_digit(radix)) {
+                    Some(digit) => {
+                        // Saturate well above the largest code point so that
+                        // huge references don't overflow.
+                        self.char_ref_code = (self.char_ref_code * radix + digit).min(0x110000);
+                    }
+                    None if c == Some(';') => self.state = State::NumericCharacterReferenceEnd,
+                    None => self.reconsume_in(c, State::NumericCharacterReferenceEnd),
+                }
+            }
+            State::NumericCharacterReferenceEnd => {
+                self.reconsume_in(c, State::NumericCharacterReferenceEnd);
+                let code = self.char_ref_code;
+                let c = match C1_REPLACEMENTS.iter().find(|&&(from, _)| from == code) {
+                    Some(&(_, to)) => to,
+                    None if code == 0 => '\u{FFFD}',
+                    None => ::std::char::from_u32(code).unwrap_or('\u{FFFD}'),
+                };
+                self.temp_buffer = c.to_string();
+                self.flush_temp_buffer();
+                self.state = self.return_state;
+            }
+            State::CdataSection => match c {
+                Some(']') => self.state = State::CdataSectionBracket,
+                Some(c) => self.emit(Token::Character(c)),
+                None => self.emit(Token::Eof),
+            },
+            State::CdataSectionBracket => match c {
+                Some(']') => self.state = State::CdataSectionEnd,
+                _ => {
+                    self.emit(Token::Character(']'));
+                    self.reconsume_in(c, State::CdataSection);
+                }
+            },
+            State::CdataSectionEnd => match c {
+                Some(']') => self.emit(Token::Character(']')),
+                Some('>') => self.state = State::Data,
+                _ => {
+                    self.emit_chars("]]");
+                    self.reconsume_in(c, State::CdataSection);
+                }
+            },
+            State::CommentStart => match c {
+                Some('-') => self.state = State::CommentStartDash,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                _ => self.reconsume_in(c, State::Comment),
+            },
+            State::CommentStartDash => match c {
+                Some('-') => self.state = State::CommentEnd,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.comment.push('-');
+                    self.reconsume_in(Some(c), State::Comment);
+                }
+            },
+            State::Comment => match c {
+                Some('<') => {
+                    self.comment.push('<');
+                    self.state = State::CommentLessThanSign;
+                }
+                Some('-') => self.state = State::CommentEndDash,
+                Some('\0') => self.comment.push('\u{FFFD}'),
+                Some(c) => self.comment.push(c),
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::CommentLessThanSign => match c {
+                Some('!') => {
+                    self.comment.push('!');
+                    self.state = State::CommentLessThanSignBang;
+                }
+                Some('<') => self.comment.push('<'),
+                _ => self.reconsume_in(c, State::Comment),
+            },
+            State::CommentLessThanSignBang => match c {
+                Some('-') => self.state = State::CommentLessThanSignBangDash,
+                _ => self.reconsume_in(c, State::Comment),
+            },
+            State::CommentLessThanSignBangDash => match c {
+                Some('-') => self.state = State::CommentLessThanSignBangDashDash,
+                _ => self.reconsume_in(c, State::CommentEndDash),
+            },
+            State::CommentLessThanSignBangDashDash => self.reconsume_in(c, State::CommentEnd),
+            State::CommentEndDash => match c {
+                Some('-') => self.state = State::CommentEnd,
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.comment.push('-');
+                    self.reconsume_in(Some(c), State::Comment);
+                }
+            },
+            State::CommentEnd => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                Some('!') => self.state = State::CommentEndBang,
+                Some('-') => self.comment.push('-'),
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.comment.push_str("--");
+                    self.reconsume_in(Some(c), State::Comment);
+                }
+            },
+            State::CommentEndBang => match c {
+                Some('-') => {
+                    self.comment.push_str("--!");
+                    self.state = State::CommentEndDash;
+                }
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                None => {
+                    self.emit_comment();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.comment.push_str("--!");
+                    self.reconsume_in(Some(c), State::Comment);
+                }
+            },
+            State::Doctype => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::BeforeDoctypeName;
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => self.reconsume_in(Some(c), State::BeforeDoctypeName),
+            },
+            State::BeforeDoctypeName => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_quirky_doctype();
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.doctype_name();
+                    self.reconsume_in(Some(c), State::DoctypeName);
+                }
+            },
+            State::DoctypeName => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::AfterDoctypeName;
+                }
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some('\0') => self.doctype_name().push('\u{FFFD}'),
+                Some(c) => self.doctype_name().push(c.to_ascii_lowercase()),
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+            },
+            State::AfterDoctypeName => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(c) => {
+                    self.pos -= c.len_utf8();
+                    let keyword = match (self.lookahead("PUBLIC"), self.lookahead("SYSTEM")) {
+                        (None, _) | (_, None) => return false,
+                        (Some(true), _) => Some(State::AfterDoctypePublicKeyword),
+                        (_, Some(true)) => Some(State::AfterDoctypeSystemKeyword),
+                        _ => None,
+                    };
+                    match keyword {
+                        Some(state) => {
+                            self.pos += "PUBLIC".len();
+                            self.state = state;
+                        }
+                        None => {
+                            self.doctype.force_quirks = true;
+                            self.state = State::BogusDoctype;
+                        }
+                    }
+                }
+            },
+            State::AfterDoctypePublicKeyword | State::BeforeDoctypePublicIdentifier => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::BeforeDoctypePublicIdentifier;
+                }
+                Some('"') => {
+                    self.doctype_public_id().clear();
+                    self.state = State::DoctypePublicIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.doctype_public_id().clear();
+                    self.state = State::DoctypePublicIdentifierSingleQuoted;
+                }
+                _ => self.bogus_doctype(c),
+            },
+            State::DoctypePublicIdentifierDoubleQuoted
+            | State::DoctypePublicIdentifierSingleQuoted => {
+                let quote = if self.state == State::DoctypePublicIdentifierDoubleQuoted { '"' } else { '\'' };
+                match c {
+                    Some(c) if c == quote => self.state = State::AfterDoctypePublicIdentifier,
+                    Some('\0') => self.doctype_public_id().push('\u{FFFD}'),
+                    Some('>') | None => self.bogus_doctype(c),
+                    Some(c) => self.doctype_public_id().push(c),
+                }
+            }
+            State::AfterDoctypePublicIdentifier
+            | State::BetweenDoctypePublicAndSystemIdentifiers => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::BetweenDoctypePublicAndSystemIdentifiers;
+                }
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some('"') => {
+                    self.doctype_system_id().clear();
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.doctype_system_id().clear();
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                _ => self.bogus_doctype(c),
+            },
+            State::AfterDoctypeSystemKeyword | State::BeforeDoctypeSystemIdentifier => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {
+                    self.state = State::BeforeDoctypeSystemIdentifier;
+                }
+                Some('"') => {
+                    self.doctype_system_id().clear();
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.doctype_system_id().clear();
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                _ => self.bogus_doctype(c),
+            },
+            State::DoctypeSystemIdentifierDoubleQuoted
+            | State::DoctypeSystemIdentifierSingleQuoted => {
+                let quote = if self.state == State::DoctypeSystemIdentifierDoubleQuoted { '"' } else { '\'' };
+                match c {
+                    Some(c) if c == quote => self.state = State::AfterDoctypeSystemIdentifier,
+                    Some('\0') => self.doctype_system_id().push('\u{FFFD}'),
+                    Some('>') | None => self.bogus_doctype(c),
+                    Some(c) => self.doctype_system_id().push(c),
+                }
+            }
+            State::AfterDoctypeSystemIdentifier => match c {
+                Some('\t') | Some('\n') | Some('\x0C') | Some(' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                None => {
+                    self.emit_quirky_doctype();
+                    self.emit(Token::Eof);
+                }
+                // Unlike the other malformed cases this doesn't force quirks.
+                Some(_) => self.state = State::BogusDoctype,
+            },
+            State::BogusDoctype => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                None => {
+                    self.emit_doctype();
+                    self.emit(Token::Eof);
+                }
+                Some(_) => {}
+            },
+        }
+        true
+    }
+
+    /// The shared error handling of the DOCTYPE identifier states: a `>` or
+    /// the end of input ends the DOCTYPE, anything else skips to its end. In
+    /// every case the document is forced into quirks mode.
+    fn bogus_doctype(&mut self, c: Option<char>) {
+        match c {
+            Some('>') => {
+                self.state = State::Data;
+                self.emit_quirky_doctype();
+            }
+            None => {
+                self.emit_quirky_doctype();
+                self.emit(Token::Eof);
+            }
+            Some(c) => {
+                self.doctype.force_quirks = true;
+                self.reconsume_in(Some(c), State::BogusDoctype);
+            }
+        }
+    }
+
+    /// Handle the named character reference state, which consumes the
+    /// longest run of characters that names a character reference.
+    fn named_character_reference(&mut self) -> bool {
+        let available = &self.input[self.pos..];
+        let run: String = available.chars()
+            .take(LONGEST_ENTITY_NAME)
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let mut candidate = run.clone();
+        match available[run.len()..].chars().next() {
+            Some(';') => candidate.push(';'),
+            Some(_) => {}
+            None if run.len() < LONGEST_ENTITY_NAME && !self.finished => return false,
+            None => {}
+        }
+
+        let found = (1..candidate.len() + 1).rev()
+            .filter_map(|len| {
+                ENTITIES.binary_search_by(|&(name, _)| name.cmp(&candidate[..len]))
+                    .ok()
+                    .map(|i| ENTITIES[i])
+            })
+            .next();
+        let (name, value) = match found {
+            Some(entity) => entity,
+            None => {
+                self.flush_temp_buffer();
+                self.state = State::AmbiguousAmpersand;
+                return true;
+            }
+        };
+
+        self.pos += name.len();
+        let next = self.input[self.pos..].chars().next();
+        let historical = !name.ends_with(';') && self.in_attribute_value()
+            && next.is_some_and(|c| c == '=' || c.is_ascii_alphanumeric());
+        if historical {
+            // For compatibility, "&copy=" in an attribute value isn't decoded.
+            self.temp_buffer.push_str(name);
+        } else {
+            self.temp_buffer = value.to_string();
+        }
+        self.flush_temp_buffer();
+        self.state = self.return_state;
+        true
+    }
+
+    /// Handle the markup declaration open state, which looks ahead for `--`,
+    /// `DOCTYPE` or `[CDATA[` rather than consuming a single character.
+    fn markup_declaration_open(&mut self) -> bool {
+        let (comment, doctype, cdata) = match (self.lookahead("--"), self.lookahead("DOCTYPE"),
+                                               self.lookahead("[CDATA[")) {
+            (Some(comment), Some(doctype), Some(cdata)) => (comment, doctype, cdata),
+            _ => return false,
+        };
+        // The CDATA keyword is case-sensitive.
+        let cdata = cdata && self.input[self.pos..].starts_with("[CDATA[");
+        self.comment.clear();
+        if comment {
+            self.pos += 2;
+            self.state = State::CommentStart;
+        } else if doctype {
+            self.pos += 7;
+            self.doctype = Doctype::default();
+            self.state = State::Doctype;
+        } else if cdata {
+            // CDATA sections are only allowed in foreign content; in HTML they
+            // are bogus comments.
+            self.pos += 7;
+            if self.cdata_allowed {
+                self.state = State::CdataSection;
+            } else {
+                self.comment.push_str("[CDATA[");
+                self.state = State::BogusComment;
+            }
+        } else {
+            self.state = State::BogusComment;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tokens of `chunks` fed one after another, written back as markup,
+    /// with the characters of each run of text together. Elements whose
+    /// contents aren't markup switch the tokenizer's state as the tree
+    /// builder would.
+    fn tokens(chunks: &[&str]) -> Vec<String> {
+        let mut tokenizer = Tokenizer::new();
+        let mut tokens: Vec<(bool, String)> = vec![];
+        for (i, chunk) in chunks.iter().enumerate() {
+            tokenizer.feed(chunk);
+            if i == chunks.len() - 1 {
+                tokenizer.finish();
+            }
+            while let Some(token) = tokenizer.next_token() {
+                let text = match token {
+                    Token::Character(c) => {
+                        match tokens.last_mut() {
+                            Some(&mut (true, ref mut text)) => text.push(c),
+                            _ => tokens.push((true, c.to_string())),
+                        }
+                        continue;
+                    }
+                    Token::StartTag(ref tag) => {
+                        match &*tag.name {
+                            "title" | "textarea" => tokenizer.set_state(State::Rcdata),
+                            "style" => tokenizer.set_state(State::Rawtext),
+                            "script" => tokenizer.set_state(State::ScriptData),
+                            "plaintext" => tokenizer.set_state(State::Plaintext),
+                            _ => {}
+                        }
+                        let attributes: String = tag.attributes.iter()
+                            .map(|attribute| format!(" {}=\"{}\"", attribute.name, attribute.value))
+                            .collect();
+                        format!("<{}{}{}>", tag.name, attributes, if tag.self_closing { "/" } else { "" })
+                    }
+                    Token::EndTag(ref tag) => format!("</{}>", tag.name),
+                    Token::Comment(ref text) => format!("<!--{}-->", text),
+                    Token::Doctype(ref doctype) => format!("<!DOCTYPE {}>", doctype.name.as_deref().unwrap_or("")),
+                    Token::Eof => continue,
+                };
+                tokens.push((false, text));
+            }
+        }
+        tokens.into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn named_references() {
+        assert_eq!(tokens(&["a &amp; &lt;b&gt; &notin; &NotEqualTilde; &bogus; &"]),
+                   ["a & <b> ∉ \u{2242}\u{338} &bogus; &"]);
+        // Legacy names work without the semicolon, taking the longest match.
+        assert_eq!(tokens(&["&amp &ampx &notit; &copy2"]), ["& &x ¬it; ©2"]);
+    }
+
+    #[test]
+    fn references_in_attributes() {
+        assert_eq!(tokens(&["<a href='?a=1&amp;b=2&copy=3&ampx&amp'>"]), ["<a href=\"?a=1&b=2&copy=3&ampx&\">"]);
+        assert_eq!(tokens(&["<a title=&lt;&gt;>"]), ["<a title=\"<>\">"]);
+    }
+
+    #[test]
+    fn numeric_references() {
+        assert_eq!(tokens(&["&#65;&#x42;&#X43;&#x1F600;"]), ["ABC\u{1F600}"]);
+        // Zero, surrogates and values past Unicode are replaced.
+        assert_eq!(tokens(&["&#0;&#xD800;&#x110000;&#99999999999;"]), ["\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}"]);
+        // The C1 controls are read as windows-1252, where it has a character.
+        assert_eq!(tokens(&["&#x80;&#150;&#x9F;&#x81;"]), ["€–Ÿ\u{81}"]);
+        assert_eq!(tokens(&["&#65 &#x; &#;"]), ["A &#x; &#;"]);
+    }
+
+    #[test]
+    fn attribute_forms() {
+        assert_eq!(tokens(&["<input type=checkbox checked value='a \"b\"' name=\"x 'y'\" data-x=>"]),
+                   ["<input type=\"checkbox\" checked=\"\" value=\"a \"b\"\" name=\"x 'y'\" data-x=\"\">"]);
+        assert_eq!(tokens(&["<br/><img src=a/>"]), ["<br/>", "<img src=\"a/\">"]);
+        // The first of two attributes with the same name wins.
+        assert_eq!(tokens(&["<p id=a ID=b>"]), ["<p id=\"a\">"]);
+    }
+
+    #[test]
+    fn rawtext() {
+        assert_eq!(tokens(&["<style>a<b &amp;</style>x"]), ["<style>", "a<b &amp;", "</style>", "x"]);
+        assert_eq!(tokens(&["<style></styles></style >"]), ["<style>", "</styles>", "</style>"]);
+    }
+
+    #[test]
+    fn rcdata() {
+        assert_eq!(tokens(&["<textarea>&lt;b&gt; <b></textarea>"]), ["<textarea>", "<b> <b>", "</textarea>"]);
+        assert_eq!(tokens(&["<title>a &amp b</Title>"]), ["<title>", "a & b", "</title>"]);
+    }
+
+    #[test]
+    fn script_data() {
+        assert_eq!(tokens(&["<script>if (a<b) {}</script>"]), ["<script>", "if (a<b) {}", "</script>"]);
+        // An end tag inside an escaped `<script>` doesn't end the script.
+        assert_eq!(tokens(&["<script><!--<script></script>--></script>"]),
+                   ["<script>", "<!--<script></script>-->", "</script>"]);
+        assert_eq!(tokens(&["<script><!--</script>x"]), ["<script>", "<!--", "</script>", "x"]);
+    }
+
+    #[test]
+    fn plaintext() {
+        assert_eq!(tokens(&["<plaintext>a</plaintext><b>&amp;", "</b>"]), ["<plaintext>", "a</plaintext><b>&amp;</b>"]);
+    }
+
+    #[test]
+    fn input_split_across_chunks() {
+        assert_eq!(tokens(&["<p title='日", "本'>é", "&amp;€&notin;&noti", "x"]), ["<p title=\"日本\">", "é&€∉¬ix"]);
+        assert_eq!(tokens(&["<!DOCTYPE html><!--é-", "->x"]), ["<!DOCTYPE html>", "<!--é-->", "x"]);
+        assert_eq!(tokens(&["<scr", "ipt>a</scr", "ipt>"]), ["<script>", "a", "</script>"]);
+        let markup = "<a href='x&amp;y'>&lt;b&gt;</a><!--c-->";
+        let whole = tokens(&[markup]);
+        for i in (1..markup.len()).filter(|&i| markup.is_char_boundary(i)) {
+            assert_eq!(tokens(&[&markup[..i], &markup[i..]]), whole, "split at {}", i);
+        }
+    }
+}
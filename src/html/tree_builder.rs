@@ -0,0 +1,2227 @@
+use std::collections::HashMap;
+use std::mem;
+use std::ops::Deref;
+
+use node::{Node, NodeType, ElementData, DoctypeData, AttrMap, Namespace, QuirksMode};
+use super::tokenizer::{self, Doctype, Tag, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    InTemplate,
+    AfterBody,
+    InFrameset,
+    AfterFrameset,
+    AfterAfterBody,
+    AfterAfterFrameset,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+enum FormatEntry {
+    Marker,
+    /// A formatting element and the tag it was created from, which is needed
+    /// to recreate it.
+    Element(usize, Tag),
+}
+
+struct TreeNode {
+    node_type: NodeType,
+    parent: Option<usize>,
+    children: Vec<usize>,
+}
+
+const DOCUMENT: usize = 0;
+
+/// How deep the stack of open elements can get before new nodes are put
+/// next to the current node instead of inside it, as browsers do, so that
+/// the tree stays shallow enough to walk recursively.
+const MAX_TREE_DEPTH: usize = 512;
+
+const SPECIAL: &[&str] = &[
+    "address", "applet", "area", "article", "aside", "base", "basefont", "bgsound",
+    "blockquote", "body", "br", "button", "caption", "center", "col", "colgroup", "dd",
+    "details", "dir", "div", "dl", "dt", "embed", "fieldset", "figcaption", "figure",
+    "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head",
+    "header", "hgroup", "hr", "html", "iframe", "img", "input", "keygen", "li", "link",
+    "listing", "main", "marquee", "menu", "meta", "nav", "noembed", "noframes",
+    "noscript", "object", "ol", "p", "param", "plaintext", "pre", "script", "search",
+    "section", "select", "source", "style", "summary", "table", "tbody", "td",
+    "template", "textarea", "tfoot", "th", "thead", "title", "tr", "track", "ul", "wbr",
+    "xmp",
+];
+
+/// MathML and SVG elements that are special, and that bound the default
+/// scopes.
+const SPECIAL_MATHML: &[&str] = &["mi", "mo", "mn", "ms", "mtext", "annotation-xml"];
+const SPECIAL_SVG: &[&str] = &["foreignObject", "desc", "title"];
+
+/// Start tags that break out of foreign content back into HTML.
+const FOREIGN_BREAKOUT: &[&str] = &[
+    "b", "big", "blockquote", "body", "br", "center", "code", "dd", "div", "dl", "dt",
+    "em", "embed", "h1", "h2", "h3", "h4", "h5", "h6", "head", "hr", "i", "img", "li",
+    "listing", "menu", "meta", "nobr", "ol", "p", "pre", "ruby", "s", "small", "span",
+    "strong", "strike", "sub", "sup", "table", "tt", "u", "ul", "var",
+];
+
+/// The mixed-case names of SVG elements, by their lowercased name as it
+/// comes from the tokenizer.
+const SVG_TAG_NAMES: &[(&str, &str)] = &[
+    ("altglyph", "altGlyph"), ("altglyphdef", "altGlyphDef"), ("altglyphitem", "altGlyphItem"),
+    ("animatecolor", "animateColor"), ("animatemotion", "animateMotion"),
+    ("animatetransform", "animateTransform"), ("clippath", "clipPath"), ("feblend", "feBlend"),
+    ("fecolormatrix", "feColorMatrix"), ("fecomponenttransfer", "feComponentTransfer"),
+    ("fecomposite", "feComposite"), ("feconvolvematrix", "feConvolveMatrix"),
+    ("fediffuselighting", "feDiffuseLighting"), ("fedisplacementmap", "feDisplacementMap"),
+    ("fedistantlight", "feDistantLight"), ("fedropshadow", "feDropShadow"),
+    ("feflood", "feFlood"), ("fefunca", "feFuncA"), ("fefuncb", "feFuncB"),
+    ("fefuncg", "feFuncG"), ("fefuncr", "feFuncR"), ("fegaussianblur", "feGaussianBlur"),
+    ("feimage", "feImage"), ("femerge", "feMerge"), ("femergenode", "feMergeNode"),
+    ("femorphology", "feMorphology"), ("feoffset", "feOffset"),
+    ("fepointlight", "fePointLight"), ("fespecularlighting", "feSpecularLighting"),
+    ("fespotlight", "feSpotLight"), ("fetile", "feTile"), ("feturbulence", "feTurbulence"),
+    ("foreignobject", "foreignObject"), ("glyphref", "glyphRef"),
+    ("lineargradient", "linearGradient"), ("radialgradient", "radialGradient"),
+    ("textpath", "textPath"),
+];
+
+/// The mixed-case names of SVG attributes, by their lowercased name.
+const SVG_ATTRIBUTES: &[(&str, &str)] = &[
+    ("attributename", "attributeName"), ("attributetype", "attributeType"),
+    ("basefrequency", "baseFrequency"), ("baseprofile", "baseProfile"),
+    ("calcmode", "calcMode"), ("clippathunits", "clipPathUnits"),
+    ("diffuseconstant", "diffuseConstant"), ("edgemode", "edgeMode"),
+    ("filterunits", "filterUnits"), ("glyphref", "glyphRef"),
+    ("gradienttransform", "gradientTransform"), ("gradientunits", "gradientUnits"),
+    ("kernelmatrix", "kernelMatrix"), ("kernelunitlength", "kernelUnitLength"),
+    ("keypoints", "keyPoints"), ("keysplines", "keySplines"), ("keytimes", "keyTimes"),
+    ("lengthadjust", "lengthAdjust"), ("limitingconeangle", "limitingConeAngle"),
+    ("markerheight", "markerHeight"), ("markerunits", "markerUnits"),
+    ("markerwidth", "markerWidth"), ("maskcontentunits", "maskContentUnits"),
+    ("maskunits", "maskUnits"), ("numoctaves", "numOctaves"), ("pathlength", "pathLength"),
+    ("patterncontentunits", "patternContentUnits"), ("patterntransform", "patternTransform"),
+    ("patternunits", "patternUnits"), ("pointsatx", "pointsAtX"), ("pointsaty", "pointsAtY"),
+    ("pointsatz", "pointsAtZ"), ("preservealpha", "preserveAlpha"),
+    ("preserveaspectratio", "preserveAspectRatio"), ("primitiveunits", "primitiveUnits"),
+    ("refx", "refX"), ("refy", "refY"), ("repeatcount", "repeatCount"),
+    ("repeatdur", "repeatDur"), ("requiredextensions", "requiredExtensions"),
+    ("requiredfeatures", "requiredFeatures"), ("specularconstant", "specularConstant"),
+    ("specularexponent", "specularExponent"), ("spreadmethod", "spreadMethod"),
+    ("startoffset", "startOffset"), ("stddeviation", "stdDeviation"),
+    ("stitchtiles", "stitchTiles"), ("surfacescale", "surfaceScale"),
+    ("systemlanguage", "systemLanguage"), ("tablevalues", "tableValues"),
+    ("targetx", "targetX"), ("targety", "targetY"), ("textlength", "textLength"),
+    ("viewbox", "viewBox"), ("viewtarget", "viewTarget"),
+    ("xchannelselector", "xChannelSelector"), ("ychannelselector", "yChannelSelector"),
+    ("zoomandpan", "zoomAndPan"),
+];
+
+/// Attributes of foreign elements that are put in a namespace.
+const FOREIGN_ATTRIBUTES: &[(&str, Namespace)] = &[
+    ("xlink:actuate", Namespace::XLink), ("xlink:arcrole", Namespace::XLink),
+    ("xlink:href", Namespace::XLink), ("xlink:role", Namespace::XLink),
+    ("xlink:show", Namespace::XLink), ("xlink:title", Namespace::XLink),
+    ("xlink:type", Namespace::XLink), ("xml:lang", Namespace::Xml),
+    ("xml:space", Namespace::Xml), ("xmlns", Namespace::Xmlns),
+    ("xmlns:xlink", Namespace::Xmlns),
+];
+
+fn lookup<'a>(table: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    table.iter().find(|&&(from, _)| from == name).map(|&(_, to)| to)
+}
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+const FORMATTING: &[&str] = &[
+    "a", "b", "big", "code", "em", "font", "i", "nobr", "s", "small", "strike", "strong",
+    "tt", "u",
+];
+
+const IMPLIED_END_TAGS: &[&str] = &[
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+const IMPLIED_END_TAGS_THOROUGH: &[&str] = &[
+    "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt",
+    "rtc", "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+/// Public identifier prefixes of DOCTYPEs that trigger quirks mode.
+const QUIRKY_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+/// Choose the document's mode from its DOCTYPE, as described in the
+/// "initial" insertion mode.
+/// The stack of open elements. It keeps count of the HTML elements on it by
+/// name, so that looking for an element that isn't open doesn't walk the
+/// whole stack.
+struct OpenElements {
+    ids: Vec<usize>,
+    names: Vec<String>,
+    counts: HashMap<String, usize>,
+}
+
+impl OpenElements {
+    fn new() -> OpenElements {
+        OpenElements { ids: vec![], names: vec![], counts: HashMap::new() }
+    }
+
+    /// Whether an HTML element called `name` is open.
+    fn has_named(&self, name: &str) -> bool {
+        self.counts.get(name).is_some_and(|&count| count > 0)
+    }
+
+    /// Push `id`, an HTML element called `name` or, with the empty name,
+    /// any other element.
+    fn push(&mut self, id: usize, name: String) {
+        *self.counts.entry(name.clone()).or_insert(0) += 1;
+        self.ids.push(id);
+        self.names.push(name);
+    }
+
+    fn insert(&mut self, index: usize, id: usize, name: String) {
+        *self.counts.entry(name.clone()).or_insert(0) += 1;
+        self.ids.insert(index, id);
+        self.names.insert(index, name);
+    }
+
+    fn replace(&mut self, index: usize, id: usize, name: String) {
+        self.remove(index);
+        self.insert(index, id, name);
+    }
+
+    fn remove(&mut self, index: usize) -> usize {
+        let name = self.names.remove(index);
+        if let Some(count) = self.counts.get_mut(&name) {
+            *count -= 1;
+        }
+        self.ids.remove(index)
+    }
+
+    fn pop(&mut self) -> Option<usize> {
+        if self.ids.is_empty() {
+            None
+        } else {
+            Some(self.remove(self.ids.len() - 1))
+        }
+    }
+
+    fn truncate(&mut self, len: usize) {
+        while self.ids.len() > len {
+            self.pop();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl Deref for OpenElements {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.ids
+    }
+}
+
+fn quirks_mode(doctype: &Doctype) -> QuirksMode {
+    let public_id = doctype.public_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let system_id = doctype.system_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let public = public_id.as_deref().unwrap_or("");
+    let system = system_id.as_deref().unwrap_or("");
+    let html4_frameset_or_transitional = public.starts_with("-//w3c//dtd html 4.01 frameset//")
+        || public.starts_with("-//w3c//dtd html 4.01 transitional//");
+
+    if doctype.force_quirks
+            || doctype.name.as_deref() != Some("html")
+            || public == "-//w3o//dtd w3 html strict 3.0//en//"
+            || public == "-/w3c/dtd html 4.0 transitional/en"
+            || public == "html"
+            || system == "http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd"
+            || QUIRKY_PUBLIC_ID_PREFIXES.iter().any(|prefix| public.starts_with(prefix))
+            || (system_id.is_none() && html4_frameset_or_transitional) {
+        QuirksMode::Quirks
+    } else if public.starts_with("-//w3c//dtd xhtml 1.0 frameset//")
+            || public.starts_with("-//w3c//dtd xhtml 1.0 transitional//")
+            || (system_id.is_some() && html4_frameset_or_transitional) {
+        QuirksMode::LimitedQuirks
+    } else {
+        QuirksMode::NoQuirks
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+/// The tree construction stage of the HTML parser. Tokens are processed
+/// according to the current insertion mode, building a document in an arena
+/// that is converted to a `Node` tree once parsing is done.
+pub struct TreeBuilder {
+    nodes: Vec<TreeNode>,
+    mode: InsertionMode,
+    original_mode: InsertionMode,
+    open_elements: OpenElements,
+    active_formatting: Vec<FormatEntry>,
+    /// The modes to go back to inside each open `template`, innermost last.
+    template_modes: Vec<InsertionMode>,
+    head: Option<usize>,
+    form: Option<usize>,
+    frameset_ok: bool,
+    foster_parenting: bool,
+    ignore_lf: bool,
+    pending_table_chars: String,
+    quirks_mode: QuirksMode,
+    stopped: bool,
+    /// A tokenizer state switch requested by the last token, for elements
+    /// whose contents aren't markup.
+    tokenizer_state: Option<tokenizer::State>,
+}
+
+impl TreeBuilder {
+    pub fn new() -> TreeBuilder {
+        TreeBuilder {
+            nodes: vec![TreeNode {
+                node_type: NodeType::Document(QuirksMode::NoQuirks),
+                parent: None,
+                children: vec![],
+            }],
+            mode: InsertionMode::Initial,
+            original_mode: InsertionMode::Initial,
+            open_elements: OpenElements::new(),
+            active_formatting: vec![],
+            template_modes: vec![],
+            head: None,
+            form: None,
+            frameset_ok: true,
+            foster_parenting: false,
+            ignore_lf: false,
+            pending_table_chars: String::new(),
+            quirks_mode: QuirksMode::NoQuirks,
+            stopped: false,
+            tokenizer_state: None,
+        }
+    }
+
+    /// Convert the finished document to a `Node` tree rooted at the `html`
+    /// element.
+    pub fn into_node(self) -> Node {
+        let root = self.nodes[DOCUMENT].children.iter()
+            .cloned()
+            .find(|&id| self.is_element(id))
+            .expect("the tree builder always creates an html element");
+        self.to_node(root)
+    }
+
+    /// Convert the finished document to a `Node` tree rooted at the document
+    /// node, which also holds the DOCTYPE and any comments outside `html`.
+    pub fn into_document(self) -> Node {
+        self.document()
+    }
+
+    /// Copy the document built so far to a `Node` tree, without finishing.
+    pub fn document(&self) -> Node {
+        self.to_node(DOCUMENT)
+    }
+
+    fn to_node(&self, id: usize) -> Node {
+        let node = &self.nodes[id];
+        Node {
+            children: node.children.iter().map(|&child| self.to_node(child)).collect(),
+            node_type: node.node_type.clone(),
+        }
+    }
+
+    /// The state the tokenizer should switch to before producing the next
+    /// token, if the last token asked for one.
+    pub fn take_tokenizer_state(&mut self) -> Option<tokenizer::State> {
+        self.tokenizer_state.take()
+    }
+
+    pub fn process_token(&mut self, token: Token) {
+        if self.stopped {
+            return;
+        }
+        if self.ignore_lf {
+            self.ignore_lf = false;
+            if token == Token::Character('\n') {
+                return;
+            }
+        }
+        if self.uses_foreign_content_rules(&token) {
+            self.foreign_content(token);
+        } else {
+            let mode = self.mode;
+            self.process(mode, token);
+        }
+    }
+
+    /// The tree construction dispatcher: whether a token is handled by the
+    /// rules for foreign content rather than the current insertion mode.
+    fn uses_foreign_content_rules(&self, token: &Token) -> bool {
+        let current = match self.open_elements.last() {
+            Some(&current) => current,
+            None => return false,
+        };
+        if self.is_html(current) {
+            return false;
+        }
+        match *token {
+            Token::Eof => false,
+            Token::StartTag(ref tag) if self.is_mathml_text_integration_point(current) => {
+                tag.name == "mglyph" || tag.name == "malignmark"
+            }
+            Token::Character(_) if self.is_mathml_text_integration_point(current) => false,
+            Token::StartTag(ref tag) if tag.name == "svg"
+                && self.is_foreign(current, Namespace::MathMl, &["annotation-xml"]) => false,
+            Token::StartTag(_) | Token::Character(_) => !self.is_html_integration_point(current),
+            _ => true,
+        }
+    }
+
+    // Tree inspection
+
+    fn is_element(&self, id: usize) -> bool {
+        matches!(self.nodes[id].node_type, NodeType::Element(_))
+    }
+
+    fn element(&self, id: usize) -> Option<&ElementData> {
+        match self.nodes[id].node_type {
+            NodeType::Element(ref elem) => Some(elem),
+            _ => None,
+        }
+    }
+
+    /// The tag name of an HTML element, or the empty string for anything
+    /// else, so that name checks only ever match HTML elements.
+    fn name(&self, id: usize) -> &str {
+        match self.element(id) {
+            Some(elem) if elem.is_html() => &elem.tag_name,
+            _ => "",
+        }
+    }
+
+    fn is(&self, id: usize, name: &str) -> bool {
+        self.name(id) == name
+    }
+
+    fn is_one_of(&self, id: usize, names: &[&str]) -> bool {
+        names.contains(&self.name(id))
+    }
+
+    fn is_html(&self, id: usize) -> bool {
+        self.element(id).is_some_and(|elem| elem.is_html())
+    }
+
+    fn is_foreign(&self, id: usize, namespace: Namespace, names: &[&str]) -> bool {
+        self.element(id).is_some_and(|elem| elem.namespace == namespace
+                                     && names.contains(&&*elem.tag_name))
+    }
+
+    fn is_special(&self, id: usize) -> bool {
+        self.is_one_of(id, SPECIAL)
+            || self.is_foreign(id, Namespace::MathMl, SPECIAL_MATHML)
+            || self.is_foreign(id, Namespace::Svg, SPECIAL_SVG)
+    }
+
+    fn is_mathml_text_integration_point(&self, id: usize) -> bool {
+        self.is_foreign(id, Namespace::MathMl, &["mi", "mo", "mn", "ms", "mtext"])
+    }
+
+    fn is_html_integration_point(&self, id: usize) -> bool {
+        if self.is_foreign(id, Namespace::MathMl, &["annotation-xml"]) {
+            let encoding = self.element(id).and_then(|elem| elem.attribute("encoding"));
+            return encoding.is_some_and(|encoding| {
+                encoding.eq_ignore_ascii_case("text/html")
+                    || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+            });
+        }
+        self.is_foreign(id, Namespace::Svg, SPECIAL_SVG)
+    }
+
+    /// Whether the current node is a MathML or SVG element, in which case
+    /// tokens may be handled by the rules for foreign content and the
+    /// tokenizer recognizes CDATA sections.
+    pub fn in_foreign_content(&self) -> bool {
+        self.open_elements.last().is_some_and(|&id| !self.is_html(id))
+    }
+
+    fn current_node(&self) -> usize {
+        *self.open_elements.last().expect("stack of open elements is empty")
+    }
+
+    fn current_is(&self, name: &str) -> bool {
+        self.open_elements.last().is_some_and(|&id| self.is(id, name))
+    }
+
+    fn is_open(&self, id: usize) -> bool {
+        self.open_elements.contains(&id)
+    }
+
+    fn has_in_scope_where<F>(&self, scope: Scope, test: F) -> bool
+            where F: Fn(usize) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if test(id) {
+                return true;
+            }
+            let boundary = match scope {
+                Scope::Select => !self.is_one_of(id, &["optgroup", "option"]),
+                Scope::Table => self.is_one_of(id, &["html", "table", "template"]),
+                _ => {
+                    self.is_one_of(id, &["applet", "caption", "html", "table", "td", "th",
+                                         "marquee", "object", "template"])
+                        || self.is_foreign(id, Namespace::MathMl, SPECIAL_MATHML)
+                        || self.is_foreign(id, Namespace::Svg, SPECIAL_SVG)
+                        || (scope == Scope::ListItem && self.is_one_of(id, &["ol", "ul"]))
+                        || (scope == Scope::Button && self.is(id, "button"))
+                }
+            };
+            if boundary {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn has_in_scope(&self, name: &str, scope: Scope) -> bool {
+        self.open_elements.has_named(name) && self.has_in_scope_where(scope, |id| self.is(id, name))
+    }
+
+    fn has_element_in_scope(&self, target: usize) -> bool {
+        self.has_in_scope_where(Scope::Default, |id| id == target)
+    }
+
+    // Tree mutation
+
+    fn new_node(&mut self, node_type: NodeType) -> usize {
+        self.nodes.push(TreeNode { node_type, parent: None, children: vec![] });
+        self.nodes.len() - 1
+    }
+
+    fn create_element(&mut self, tag: &Tag) -> usize {
+        let mut attributes = AttrMap::new();
+        for attribute in &tag.attributes {
+            attributes.insert(attribute.name.clone(), attribute.value.clone());
+        }
+        self.new_node(NodeType::Element(ElementData::new(tag.name.clone(), attributes)))
+    }
+
+    /// Create a MathML or SVG element, fixing up the case of SVG names and
+    /// putting `xlink:` and `xml:` attributes in their namespaces.
+    fn create_foreign_element(&mut self, tag: &Tag, namespace: Namespace) -> usize {
+        let name = match namespace {
+            Namespace::Svg => lookup(SVG_TAG_NAMES, &tag.name).unwrap_or(&tag.name),
+            _ => &tag.name,
+        };
+        let mut elem = ElementData::new_ns(namespace.clone(), name.to_string(), AttrMap::new());
+        for attribute in &tag.attributes {
+            let name = match namespace {
+                Namespace::Svg => lookup(SVG_ATTRIBUTES, &attribute.name),
+                Namespace::MathMl if attribute.name == "definitionurl" => Some("definitionURL"),
+                _ => None,
+            };
+            let name = name.unwrap_or(&attribute.name);
+            match FOREIGN_ATTRIBUTES.iter().find(|&&(foreign, _)| foreign == name) {
+                Some((_, ns)) => elem.set_attribute_ns(ns.clone(), name, &attribute.value),
+                None => elem.set_attribute(name, &attribute.value),
+            }
+        }
+        self.new_node(NodeType::Element(elem))
+    }
+
+    fn detach(&mut self, id: usize) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|&child| child != id);
+        }
+    }
+
+    /// Insert `id` into `parent`, before the child at `index` or at the end.
+    fn insert_at(&mut self, parent: usize, index: Option<usize>, id: usize) {
+        self.detach(id);
+        self.nodes[id].parent = Some(parent);
+        let children = &mut self.nodes[parent].children;
+        match index {
+            Some(index) => children.insert(index, id),
+            None => children.push(id),
+        }
+    }
+
+    fn append(&mut self, parent: usize, id: usize) {
+        self.insert_at(parent, None, id);
+    }
+
+    /// The appropriate place for inserting a node, as a parent and an
+    /// optional index to insert before. Handles foster parenting of content
+    /// misplaced in tables.
+    fn insertion_place(&self, override_target: Option<usize>) -> (usize, Option<usize>) {
+        let target = override_target.unwrap_or_else(|| self.current_node());
+        if self.foster_parenting && self.is_one_of(target, &["table", "tbody", "tfoot", "thead", "tr"]) {
+            let last_table = self.open_elements.iter().rposition(|&id| self.is(id, "table"));
+            match last_table {
+                Some(position) => {
+                    let table = self.open_elements[position];
+                    if let Some(parent) = self.nodes[table].parent {
+                        let index = self.nodes[parent].children.iter().position(|&c| c == table);
+                        return (parent, index);
+                    }
+                    (self.open_elements[position - 1], None)
+                }
+                None => (self.open_elements[0], None),
+            }
+        } else if self.open_elements.len() > MAX_TREE_DEPTH && override_target.is_none() {
+            (self.nodes[target].parent.unwrap_or(target), None)
+        } else {
+            (target, None)
+        }
+    }
+
+    fn insert_element(&mut self, tag: &Tag) -> usize {
+        let (parent, index) = self.insertion_place(None);
+        let id = self.create_element(tag);
+        self.insert_at(parent, index, id);
+        self.push_open(id);
+        id
+    }
+
+    fn insert_foreign_element(&mut self, tag: &Tag, namespace: Namespace) -> usize {
+        let (parent, index) = self.insertion_place(None);
+        let id = self.create_foreign_element(tag, namespace);
+        self.insert_at(parent, index, id);
+        self.push_open(id);
+        if tag.self_closing {
+            self.pop();
+        }
+        id
+    }
+
+    fn insert_html_element(&mut self, name: &str) -> usize {
+        self.insert_element(&Tag::new(name))
+    }
+
+    fn insert_character(&mut self, c: char) {
+        let (parent, index) = self.insertion_place(None);
+        if parent == DOCUMENT {
+            return;
+        }
+        let previous = match index {
+            Some(0) => None,
+            Some(index) => Some(self.nodes[parent].children[index - 1]),
+            None => self.nodes[parent].children.last().cloned(),
+        };
+        if let Some(previous) = previous {
+            if let NodeType::Text(ref mut text) = self.nodes[previous].node_type {
+                text.push(c);
+                return;
+            }
+        }
+        let id = self.new_node(NodeType::Text(c.to_string()));
+        self.insert_at(parent, index, id);
+    }
+
+    /// Insert a comment as the last child of `parent`, or at the appropriate
+    /// place for inserting a node if no parent is given.
+    fn insert_comment(&mut self, text: String, parent: Option<usize>) {
+        let (parent, index) = match parent {
+            Some(parent) => (parent, None),
+            None => self.insertion_place(None),
+        };
+        let id = self.new_node(NodeType::Comment(text));
+        self.insert_at(parent, index, id);
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.quirks_mode = mode;
+        self.nodes[DOCUMENT].node_type = NodeType::Document(mode);
+    }
+
+    /// Copy attributes from a misplaced `<html>` or `<body>` tag onto the
+    /// existing element, without overwriting any it already has.
+    fn merge_attributes(&mut self, id: usize, tag: &Tag) {
+        if let NodeType::Element(ref mut elem) = self.nodes[id].node_type {
+            for attribute in &tag.attributes {
+                if elem.attribute(&attribute.name).is_none() {
+                    elem.set_attribute(&attribute.name, &attribute.value);
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> usize {
+        self.open_elements.pop().expect("stack of open elements is empty")
+    }
+
+    fn pop_until(&mut self, name: &str) {
+        while let Some(id) = self.open_elements.pop() {
+            if self.is(id, name) {
+                break;
+            }
+        }
+    }
+
+    fn pop_until_one_of(&mut self, names: &[&str]) {
+        while let Some(id) = self.open_elements.pop() {
+            if self.is_one_of(id, names) {
+                break;
+            }
+        }
+    }
+
+    fn push_open(&mut self, id: usize) {
+        let name = self.name(id).to_string();
+        self.open_elements.push(id, name);
+    }
+
+    fn remove_from_stack(&mut self, target: usize) {
+        if let Some(index) = self.open_elements.iter().position(|&id| id == target) {
+            self.open_elements.remove(index);
+        }
+    }
+
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while let Some(&id) = self.open_elements.last() {
+            if !self.is_one_of(id, IMPLIED_END_TAGS) || except.is_some_and(|name| self.is(id, name)) {
+                break;
+            }
+            self.pop();
+        }
+    }
+
+    fn generate_all_implied_end_tags(&mut self) {
+        while let Some(&id) = self.open_elements.last() {
+            if !self.is_one_of(id, IMPLIED_END_TAGS_THOROUGH) {
+                break;
+            }
+            self.pop();
+        }
+    }
+
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        self.pop_until("p");
+    }
+
+    fn close_p_if_in_button_scope(&mut self) {
+        if self.has_in_scope("p", Scope::Button) {
+            self.close_p_element();
+        }
+    }
+
+    // The list of active formatting elements
+
+    fn formatting_position(&self, target: usize) -> Option<usize> {
+        self.active_formatting.iter().position(|entry| match *entry {
+            FormatEntry::Element(id, _) => id == target,
+            FormatEntry::Marker => false,
+        })
+    }
+
+    fn remove_formatting(&mut self, target: usize) {
+        if let Some(position) = self.formatting_position(target) {
+            self.active_formatting.remove(position);
+        }
+    }
+
+    /// The last formatting element after the last marker with the given name.
+    fn formatting_element_named(&self, name: &str) -> Option<usize> {
+        for entry in self.active_formatting.iter().rev() {
+            match *entry {
+                FormatEntry::Marker => return None,
+                FormatEntry::Element(id, _) if self.is(id, name) => return Some(id),
+                FormatEntry::Element(..) => {}
+            }
+        }
+        None
+    }
+
+    fn push_formatting(&mut self, id: usize, tag: Tag) {
+        // The "Noah's Ark" clause: at most three identical entries after the
+        // last marker.
+        let mut matching = vec![];
+        for (i, entry) in self.active_formatting.iter().enumerate().rev() {
+            match *entry {
+                FormatEntry::Marker => break,
+                FormatEntry::Element(_, ref other) => {
+                    let mut a = other.attributes.clone();
+                    let mut b = tag.attributes.clone();
+                    a.sort_by(|x, y| x.name.cmp(&y.name));
+                    b.sort_by(|x, y| x.name.cmp(&y.name));
+                    if other.name == tag.name && a == b {
+                        matching.push(i);
+                    }
+                }
+            }
+        }
+        if matching.len() >= 3 {
+            self.active_formatting.remove(*matching.last().unwrap());
+        }
+        self.active_formatting.push(FormatEntry::Element(id, tag));
+    }
+
+    fn clear_formatting_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting.pop() {
+            if let FormatEntry::Marker = entry {
+                break;
+            }
+        }
+    }
+
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let needs_reopening = |builder: &TreeBuilder, i: usize| match builder.active_formatting[i] {
+            FormatEntry::Marker => false,
+            FormatEntry::Element(id, _) => !builder.is_open(id),
+        };
+        let len = self.active_formatting.len();
+        if len == 0 || !needs_reopening(self, len - 1) {
+            return;
+        }
+        let mut first = len - 1;
+        while first > 0 && needs_reopening(self, first - 1) {
+            first -= 1;
+        }
+        for i in first..len {
+            let tag = match self.active_formatting[i] {
+                FormatEntry::Element(_, ref tag) => tag.clone(),
+                FormatEntry::Marker => unreachable!(),
+            };
+            let id = self.insert_element(&tag);
+            self.active_formatting[i] = FormatEntry::Element(id, tag);
+        }
+    }
+
+    /// The adoption agency algorithm, which repairs misnested formatting
+    /// elements such as `<b><i></b></i>`. Returns false if the end tag should
+    /// instead be handled like any other end tag.
+    fn adoption_agency(&mut self, subject: &str) -> bool {
+        let current = self.current_node();
+        if self.is(current, subject) && self.formatting_position(current).is_none() {
+            self.pop();
+            return true;
+        }
+
+        for _ in 0..8 {
+            let formatting_element = match self.formatting_element_named(subject) {
+                Some(id) => id,
+                None => return false,
+            };
+            let stack_index = match self.open_elements.iter().position(|&id| id == formatting_element) {
+                Some(index) => index,
+                None => {
+                    self.remove_formatting(formatting_element);
+                    return true;
+                }
+            };
+            if !self.has_element_in_scope(formatting_element) {
+                return true;
+            }
+
+            let furthest_block = self.open_elements[stack_index + 1..].iter()
+                .cloned()
+                .find(|&id| self.is_special(id));
+            let furthest_block = match furthest_block {
+                Some(id) => id,
+                None => {
+                    self.open_elements.truncate(stack_index);
+                    self.remove_formatting(formatting_element);
+                    return true;
+                }
+            };
+
+            let common_ancestor = self.open_elements[stack_index - 1];
+            let mut bookmark = self.formatting_position(formatting_element).unwrap();
+            let mut node_index = self.open_elements.iter().position(|&id| id == furthest_block).unwrap();
+            let mut last_node = furthest_block;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.open_elements[node_index];
+                if node == formatting_element {
+                    break;
+                }
+                let mut position = self.formatting_position(node);
+                if inner_loop_counter > 3 {
+                    if let Some(p) = position {
+                        self.active_formatting.remove(p);
+                        if p < bookmark {
+                            bookmark -= 1;
+                        }
+                        position = None;
+                    }
+                }
+                let position = match position {
+                    Some(p) => p,
+                    None => {
+                        self.open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+
+                let tag = match self.active_formatting[position] {
+                    FormatEntry::Element(_, ref tag) => tag.clone(),
+                    FormatEntry::Marker => unreachable!(),
+                };
+                let new_node = self.create_element(&tag);
+                self.active_formatting[position] = FormatEntry::Element(new_node, tag);
+                let name = self.name(new_node).to_string();
+                self.open_elements.replace(node_index, new_node, name);
+                if last_node == furthest_block {
+                    bookmark = position + 1;
+                }
+                self.append(new_node, last_node);
+                last_node = new_node;
+            }
+
+            let (parent, index) = self.insertion_place(Some(common_ancestor));
+            self.insert_at(parent, index, last_node);
+
+            let position = self.formatting_position(formatting_element).unwrap();
+            let tag = match self.active_formatting[position] {
+                FormatEntry::Element(_, ref tag) => tag.clone(),
+                FormatEntry::Marker => unreachable!(),
+            };
+            let new_element = self.create_element(&tag);
+            let children = mem::take(&mut self.nodes[furthest_block].children);
+            for child in children {
+                self.nodes[child].parent = None;
+                self.append(new_element, child);
+            }
+            self.append(furthest_block, new_element);
+
+            self.active_formatting.remove(position);
+            if position < bookmark {
+                bookmark -= 1;
+            }
+            self.active_formatting.insert(bookmark, FormatEntry::Element(new_element, tag));
+
+            self.remove_from_stack(formatting_element);
+            let furthest_index = self.open_elements.iter().position(|&id| id == furthest_block).unwrap();
+            let name = self.name(new_element).to_string();
+            self.open_elements.insert(furthest_index + 1, new_element, name);
+        }
+        true
+    }
+
+    fn reset_insertion_mode(&mut self) {
+        for (i, &id) in self.open_elements.iter().enumerate().rev() {
+            let last = i == 0;
+            let mode = match self.name(id) {
+                "template" => *self.template_modes.last().unwrap_or(&InsertionMode::InBody),
+                "select" => {
+                    let in_table = self.open_elements[..i].iter().rev()
+                        .take_while(|&&ancestor| !self.is(ancestor, "template"))
+                        .any(|&ancestor| self.is(ancestor, "table"));
+                    if in_table { InsertionMode::InSelectInTable } else { InsertionMode::InSelect }
+                }
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "frameset" => InsertionMode::InFrameset,
+                "html" => {
+                    if self.head.is_none() { InsertionMode::BeforeHead } else { InsertionMode::AfterHead }
+                }
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+            self.mode = mode;
+            return;
+        }
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// Parse an element whose contents are text, such as `<title>` or
+    /// `<style>`, by switching the tokenizer to `state` until its end tag.
+    fn parse_text_element(&mut self, tag: &Tag, state: tokenizer::State) {
+        self.insert_element(tag);
+        self.tokenizer_state = Some(state);
+        self.original_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    fn stop_parsing(&mut self) {
+        self.open_elements.clear();
+        self.stopped = true;
+    }
+
+    // Insertion modes
+
+    fn process(&mut self, mode: InsertionMode, token: Token) {
+        match mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::InTable => self.in_table(token),
+            InsertionMode::InTableText => self.in_table_text(token),
+            InsertionMode::InCaption => self.in_caption(token),
+            InsertionMode::InColumnGroup => self.in_column_group(token),
+            InsertionMode::InTableBody => self.in_table_body(token),
+            InsertionMode::InRow => self.in_row(token),
+            InsertionMode::InCell => self.in_cell(token),
+            InsertionMode::InSelect => self.in_select(token),
+            InsertionMode::InSelectInTable => self.in_select_in_table(token),
+            InsertionMode::InTemplate => self.in_template(token),
+            InsertionMode::AfterBody => self.after_body(token),
+            InsertionMode::InFrameset => self.in_frameset(token),
+            InsertionMode::AfterFrameset => self.after_frameset(token),
+            InsertionMode::AfterAfterBody => self.after_after_body(token),
+            InsertionMode::AfterAfterFrameset => self.after_after_frameset(token),
+        }
+    }
+
+    fn reprocess_in(&mut self, mode: InsertionMode, token: Token) {
+        self.mode = mode;
+        self.process(mode, token);
+    }
+
+    fn initial(&mut self, token: Token) {
+        match token {
+            Token::Character(c) if is_whitespace(c) => {}
+            Token::Comment(text) => self.insert_comment(text, Some(DOCUMENT)),
+            Token::Doctype(doctype) => {
+                let id = self.new_node(NodeType::Doctype(DoctypeData {
+                    name: doctype.name.clone().unwrap_or_default(),
+                    public_id: doctype.public_id.clone().unwrap_or_default(),
+                    system_id: doctype.system_id.clone().unwrap_or_default(),
+                }));
+                self.append(DOCUMENT, id);
+                self.set_quirks_mode(quirks_mode(&doctype));
+                self.mode = InsertionMode::BeforeHtml;
+            }
+            token => {
+                self.set_quirks_mode(QuirksMode::Quirks);
+                self.reprocess_in(InsertionMode::BeforeHtml, token);
+            }
+        }
+    }
+
+    fn before_html(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Comment(text) => self.insert_comment(text, Some(DOCUMENT)),
+            Token::Character(c) if is_whitespace(c) => {}
+            Token::StartTag(ref tag) if tag.name == "html" => {
+                let id = self.create_element(tag);
+                self.append(DOCUMENT, id);
+                self.push_open(id);
+                self.mode = InsertionMode::BeforeHead;
+            }
+            Token::EndTag(ref tag) if !["head", "body", "html", "br"].contains(&&*tag.name) => {}
+            token => {
+                let id = self.create_element(&Tag::new("html"));
+                self.append(DOCUMENT, id);
+                self.push_open(id);
+                self.reprocess_in(InsertionMode::BeforeHead, token);
+            }
+        }
+    }
+
+    fn before_head(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(c) if is_whitespace(c) => {}
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::StartTag(ref tag) if tag.name == "head" => {
+                self.head = Some(self.insert_element(tag));
+                self.mode = InsertionMode::InHead;
+            }
+            Token::EndTag(ref tag) if !["head", "body", "html", "br"].contains(&&*tag.name) => {}
+            token => {
+                self.head = Some(self.insert_html_element("head"));
+                self.reprocess_in(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    fn in_head(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) => match &*tag.name {
+                "html" => self.in_body(token.clone()),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    self.insert_element(tag);
+                    self.pop();
+                }
+                "title" => self.parse_text_element(tag, tokenizer::State::Rcdata),
+                "noframes" | "style" => self.parse_text_element(tag, tokenizer::State::Rawtext),
+                "script" => self.parse_text_element(tag, tokenizer::State::ScriptData),
+                "noscript" => {
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InHeadNoscript;
+                }
+                "template" => {
+                    self.insert_element(tag);
+                    self.active_formatting.push(FormatEntry::Marker);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InTemplate;
+                    self.template_modes.push(InsertionMode::InTemplate);
+                }
+                "head" => {}
+                _ => self.in_head_anything_else(token.clone()),
+            },
+            Token::EndTag(ref tag) => match &*tag.name {
+                "head" => {
+                    self.pop();
+                    self.mode = InsertionMode::AfterHead;
+                }
+                "template" if self.open_elements.iter().any(|&id| self.is(id, "template")) => {
+                    self.generate_all_implied_end_tags();
+                    self.pop_until("template");
+                    self.clear_formatting_to_last_marker();
+                    self.template_modes.pop();
+                    self.reset_insertion_mode();
+                }
+                "body" | "html" | "br" => self.in_head_anything_else(token.clone()),
+                _ => {}
+            },
+            token => self.in_head_anything_else(token),
+        }
+    }
+
+    fn in_head_anything_else(&mut self, token: Token) {
+        self.pop();
+        self.reprocess_in(InsertionMode::AfterHead, token);
+    }
+
+    fn in_head_noscript(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::EndTag(ref tag) if tag.name == "noscript" => {
+                self.pop();
+                self.mode = InsertionMode::InHead;
+            }
+            Token::Character(c) if is_whitespace(c) => self.in_head(token),
+            Token::Comment(_) => self.in_head(token),
+            Token::StartTag(ref tag)
+                if ["basefont", "bgsound", "link", "meta", "noframes", "style"].contains(&&*tag.name) => {
+                self.in_head(token.clone())
+            }
+            Token::StartTag(ref tag) if tag.name == "head" || tag.name == "noscript" => {}
+            Token::EndTag(ref tag) if tag.name != "br" => {}
+            token => {
+                self.pop();
+                self.reprocess_in(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    fn after_head(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) => match &*tag.name {
+                "html" => self.in_body(token.clone()),
+                "body" => {
+                    self.insert_element(tag);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InBody;
+                }
+                "frameset" => {
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InFrameset;
+                }
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                | "style" | "template" | "title" => {
+                    let head = self.head.expect("head element pointer is set");
+                    self.push_open(head);
+                    self.in_head(token.clone());
+                    self.remove_from_stack(head);
+                }
+                "head" => {}
+                _ => self.after_head_anything_else(token.clone()),
+            },
+            Token::EndTag(ref tag) => match &*tag.name {
+                "template" => self.in_head(token.clone()),
+                "body" | "html" | "br" => self.after_head_anything_else(token.clone()),
+                _ => {}
+            },
+            token => self.after_head_anything_else(token),
+        }
+    }
+
+    fn after_head_anything_else(&mut self, token: Token) {
+        self.insert_html_element("body");
+        self.reprocess_in(InsertionMode::InBody, token);
+    }
+
+    fn in_body(&mut self, token: Token) {
+        match token {
+            Token::Character('\0') => {}
+            Token::Character(c) => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_character(c);
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+            }
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::Doctype(_) => {}
+            Token::StartTag(tag) => self.in_body_start_tag(tag),
+            Token::EndTag(tag) => self.in_body_end_tag(tag),
+            Token::Eof if !self.template_modes.is_empty() => self.in_template(Token::Eof),
+            Token::Eof => self.stop_parsing(),
+        }
+    }
+
+    fn in_body_start_tag(&mut self, tag: Tag) {
+        match &*tag.name {
+            "html" => {
+                if !self.open_elements.iter().any(|&id| self.is(id, "template")) {
+                    let html = self.open_elements[0];
+                    self.merge_attributes(html, &tag);
+                }
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+            | "style" | "template" | "title" => self.in_head(Token::StartTag(tag)),
+            "body" => {
+                if self.open_elements.len() > 1 && self.is(self.open_elements[1], "body") {
+                    self.frameset_ok = false;
+                    let body = self.open_elements[1];
+                    self.merge_attributes(body, &tag);
+                }
+            }
+            "frameset" => {
+                if self.open_elements.len() > 1 && self.is(self.open_elements[1], "body")
+                        && self.frameset_ok {
+                    let body = self.open_elements[1];
+                    self.detach(body);
+                    self.open_elements.truncate(1);
+                    self.insert_element(&tag);
+                    self.mode = InsertionMode::InFrameset;
+                }
+            }
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog"
+            | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer"
+            | "header" | "hgroup" | "main" | "menu" | "nav" | "ol" | "p" | "search"
+            | "section" | "summary" | "ul" => {
+                self.close_p_if_in_button_scope();
+                self.insert_element(&tag);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_if_in_button_scope();
+                if self.is_one_of(self.current_node(), HEADINGS) {
+                    self.pop();
+                }
+                self.insert_element(&tag);
+            }
+            "pre" | "listing" => {
+                self.close_p_if_in_button_scope();
+                self.insert_element(&tag);
+                self.ignore_lf = true;
+                self.frameset_ok = false;
+            }
+            "form" => {
+                if self.form.is_none() {
+                    self.close_p_if_in_button_scope();
+                    self.form = Some(self.insert_element(&tag));
+                }
+            }
+            "li" | "dd" | "dt" => {
+                self.frameset_ok = false;
+                let closes: &[&str] = if tag.name == "li" { &["li"] } else { &["dd", "dt"] };
+                for i in (0..self.open_elements.len()).rev() {
+                    let id = self.open_elements[i];
+                    if self.is_one_of(id, closes) {
+                        let name = self.name(id).to_string();
+                        self.generate_implied_end_tags(Some(&name));
+                        self.pop_until(&name);
+                        break;
+                    }
+                    if self.is_special(id) && !self.is_one_of(id, &["address", "div", "p"]) {
+                        break;
+                    }
+                }
+                self.close_p_if_in_button_scope();
+                self.insert_element(&tag);
+            }
+            "plaintext" => {
+                self.close_p_if_in_button_scope();
+                self.insert_element(&tag);
+                self.tokenizer_state = Some(tokenizer::State::Plaintext);
+            }
+            "button" => {
+                if self.has_in_scope("button", Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until("button");
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(&tag);
+                self.frameset_ok = false;
+            }
+            "a" => {
+                if let Some(a) = self.formatting_element_named("a") {
+                    self.adoption_agency("a");
+                    self.remove_formatting(a);
+                    self.remove_from_stack(a);
+                }
+                self.reconstruct_active_formatting_elements();
+                let id = self.insert_element(&tag);
+                self.push_formatting(id, tag);
+            }
+            "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong"
+            | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let id = self.insert_element(&tag);
+                self.push_formatting(id, tag);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_in_scope("nobr", Scope::Default) {
+                    self.adoption_agency("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let id = self.insert_element(&tag);
+                self.push_formatting(id, tag);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(&tag);
+                self.active_formatting.push(FormatEntry::Marker);
+                self.frameset_ok = false;
+            }
+            "table" => {
+                if self.quirks_mode != QuirksMode::Quirks {
+                    self.close_p_if_in_button_scope();
+                }
+                self.insert_element(&tag);
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(&tag);
+                self.pop();
+                self.frameset_ok = false;
+            }
+            "input" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(&tag);
+                self.pop();
+                if !tag.attribute("type").is_some_and(|t| t.eq_ignore_ascii_case("hidden")) {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => {
+                self.insert_element(&tag);
+                self.pop();
+            }
+            "hr" => {
+                self.close_p_if_in_button_scope();
+                self.insert_element(&tag);
+                self.pop();
+                self.frameset_ok = false;
+            }
+            "image" => {
+                let tag = Tag { name: "img".to_string(), ..tag };
+                self.in_body_start_tag(tag);
+            }
+            "textarea" => {
+                self.ignore_lf = true;
+                self.frameset_ok = false;
+                self.parse_text_element(&tag, tokenizer::State::Rcdata);
+            }
+            "xmp" => {
+                self.close_p_if_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.frameset_ok = false;
+                self.parse_text_element(&tag, tokenizer::State::Rawtext);
+            }
+            "iframe" => {
+                self.frameset_ok = false;
+                self.parse_text_element(&tag, tokenizer::State::Rawtext);
+            }
+            "noembed" => self.parse_text_element(&tag, tokenizer::State::Rawtext),
+            "math" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_foreign_element(&tag, Namespace::MathMl);
+            }
+            "svg" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_foreign_element(&tag, Namespace::Svg);
+            }
+            "select" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(&tag);
+                self.frameset_ok = false;
+                self.mode = match self.mode {
+                    InsertionMode::InTable | InsertionMode::InCaption | InsertionMode::InTableBody
+                    | InsertionMode::InRow | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                    _ => InsertionMode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.current_is("option") {
+                    self.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(&tag);
+            }
+            "rb" | "rtc" => {
+                if self.has_in_scope("ruby", Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                }
+                self.insert_element(&tag);
+            }
+            "rp" | "rt" => {
+                if self.has_in_scope("ruby", Scope::Default) {
+                    self.generate_implied_end_tags(Some("rtc"));
+                }
+                self.insert_element(&tag);
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot"
+            | "th" | "thead" | "tr" => {}
+            _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_element(&tag);
+            }
+        }
+    }
+
+    fn in_body_end_tag(&mut self, tag: Tag) {
+        match &*tag.name {
+            "template" => self.in_head(Token::EndTag(tag)),
+            "body" => {
+                if self.has_in_scope("body", Scope::Default) {
+                    self.mode = InsertionMode::AfterBody;
+                }
+            }
+            "html" => {
+                if self.has_in_scope("body", Scope::Default) {
+                    self.reprocess_in(InsertionMode::AfterBody, Token::EndTag(tag));
+                }
+            }
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details"
+            | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure"
+            | "footer" | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol"
+            | "pre" | "search" | "section" | "summary" | "ul" => {
+                if self.has_in_scope(&tag.name, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&tag.name);
+                }
+            }
+            "form" => {
+                if let Some(form) = self.form.take() {
+                    if self.has_element_in_scope(form) {
+                        self.generate_implied_end_tags(None);
+                        self.remove_from_stack(form);
+                    }
+                }
+            }
+            "p" => {
+                if !self.has_in_scope("p", Scope::Button) {
+                    self.insert_html_element("p");
+                }
+                self.close_p_element();
+            }
+            "li" => {
+                if self.has_in_scope("li", Scope::ListItem) {
+                    self.generate_implied_end_tags(Some("li"));
+                    self.pop_until("li");
+                }
+            }
+            "dd" | "dt" => {
+                if self.has_in_scope(&tag.name, Scope::Default) {
+                    self.generate_implied_end_tags(Some(&tag.name));
+                    self.pop_until(&tag.name);
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if self.has_in_scope_where(Scope::Default, |id| self.is_one_of(id, HEADINGS)) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until_one_of(HEADINGS);
+                }
+            }
+            name if FORMATTING.contains(&name) => {
+                if !self.adoption_agency(name) {
+                    self.in_body_any_other_end_tag(&tag);
+                }
+            }
+            "applet" | "marquee" | "object" => {
+                if self.has_in_scope(&tag.name, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&tag.name);
+                    self.clear_formatting_to_last_marker();
+                }
+            }
+            "br" => self.in_body_start_tag(Tag::new("br")),
+            _ => self.in_body_any_other_end_tag(&tag),
+        }
+    }
+
+    fn in_body_any_other_end_tag(&mut self, tag: &Tag) {
+        for i in (0..self.open_elements.len()).rev() {
+            let id = self.open_elements[i];
+            if self.is(id, &tag.name) {
+                self.generate_implied_end_tags(Some(&tag.name));
+                self.open_elements.truncate(i);
+                return;
+            }
+            if self.is_special(id) {
+                return;
+            }
+        }
+    }
+
+    fn text(&mut self, token: Token) {
+        match token {
+            Token::Character(c) => self.insert_character(c),
+            Token::Eof => {
+                self.pop();
+                let mode = self.original_mode;
+                self.reprocess_in(mode, token);
+            }
+            Token::EndTag(_) => {
+                self.pop();
+                self.mode = self.original_mode;
+            }
+            _ => {}
+        }
+    }
+
+    /// The rules for parsing tokens in foreign content, used inside MathML
+    /// and SVG elements.
+    fn foreign_content(&mut self, token: Token) {
+        match token {
+            Token::Character('\0') => self.insert_character('\u{FFFD}'),
+            Token::Character(c) => {
+                if !is_whitespace(c) {
+                    self.frameset_ok = false;
+                }
+                self.insert_character(c);
+            }
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::Doctype(_) => {}
+            Token::StartTag(ref tag) if FOREIGN_BREAKOUT.contains(&&*tag.name)
+                || (tag.name == "font" && ["color", "face", "size"].iter()
+                    .any(|&name| tag.attribute(name).is_some())) => {
+                self.pop_foreign_content();
+                let mode = self.mode;
+                self.process(mode, token.clone());
+            }
+            Token::EndTag(ref tag) if tag.name == "br" || tag.name == "p" => {
+                self.pop_foreign_content();
+                let mode = self.mode;
+                self.process(mode, token.clone());
+            }
+            Token::StartTag(tag) => {
+                let namespace = self.element(self.current_node())
+                    .map(|elem| elem.namespace.clone())
+                    .unwrap_or(Namespace::Html);
+                self.insert_foreign_element(&tag, namespace);
+            }
+            Token::EndTag(tag) => {
+                let mut i = self.open_elements.len() - 1;
+                loop {
+                    if i == 0 {
+                        return;
+                    }
+                    let id = self.open_elements[i];
+                    let matches = self.element(id)
+                        .is_some_and(|elem| elem.tag_name.to_ascii_lowercase() == tag.name);
+                    if matches {
+                        self.open_elements.truncate(i);
+                        return;
+                    }
+                    i -= 1;
+                    if self.is_html(self.open_elements[i]) {
+                        let mode = self.mode;
+                        self.process(mode, Token::EndTag(tag));
+                        return;
+                    }
+                }
+            }
+            Token::Eof => unreachable!("end of file is never handled as foreign content"),
+        }
+    }
+
+    /// Pop foreign elements until the current node is an HTML element or an
+    /// integration point.
+    fn pop_foreign_content(&mut self) {
+        loop {
+            let current = self.current_node();
+            if self.is_html(current) || self.is_mathml_text_integration_point(current)
+                    || self.is_html_integration_point(current) {
+                break;
+            }
+            self.pop();
+        }
+    }
+
+    fn clear_stack_back_to(&mut self, names: &[&str]) {
+        while !self.is_one_of(self.current_node(), names) {
+            self.pop();
+        }
+    }
+
+    fn clear_stack_back_to_table_context(&mut self) {
+        self.clear_stack_back_to(&["table", "template", "html"]);
+    }
+
+    fn clear_stack_back_to_table_body_context(&mut self) {
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+    }
+
+    fn clear_stack_back_to_table_row_context(&mut self) {
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+    }
+
+    fn in_table(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(_)
+                if self.is_one_of(self.current_node(), &["table", "tbody", "template", "tfoot", "thead", "tr"]) => {
+                self.pending_table_chars.clear();
+                self.original_mode = self.mode;
+                self.reprocess_in(InsertionMode::InTableText, token);
+            }
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) => match &*tag.name {
+                "caption" => {
+                    self.clear_stack_back_to_table_context();
+                    self.active_formatting.push(FormatEntry::Marker);
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to_table_context();
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_stack_back_to_table_context();
+                    self.insert_html_element("colgroup");
+                    self.reprocess_in(InsertionMode::InColumnGroup, token.clone());
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to_table_context();
+                    self.insert_element(tag);
+                    self.mode = InsertionMode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_stack_back_to_table_context();
+                    self.insert_html_element("tbody");
+                    self.reprocess_in(InsertionMode::InTableBody, token.clone());
+                }
+                "table" => {
+                    if self.has_in_scope("table", Scope::Table) {
+                        self.pop_until("table");
+                        self.reset_insertion_mode();
+                        self.process_token(token.clone());
+                    }
+                }
+                "style" | "script" | "template" => self.in_head(token.clone()),
+                "input" if tag.attribute("type").is_some_and(|t| t.eq_ignore_ascii_case("hidden")) => {
+                    self.insert_element(tag);
+                    self.pop();
+                }
+                "form" => {
+                    if self.form.is_none() && !self.open_elements.iter().any(|&id| self.is(id, "template")) {
+                        self.form = Some(self.insert_element(tag));
+                        self.pop();
+                    }
+                }
+                _ => self.in_table_anything_else(token.clone()),
+            },
+            Token::EndTag(ref tag) => match &*tag.name {
+                "table" => {
+                    if self.has_in_scope("table", Scope::Table) {
+                        self.pop_until("table");
+                        self.reset_insertion_mode();
+                    }
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => {}
+                "template" => self.in_head(token.clone()),
+                _ => self.in_table_anything_else(token.clone()),
+            },
+            Token::Eof => self.in_body(token),
+            token => self.in_table_anything_else(token),
+        }
+    }
+
+    fn in_table_anything_else(&mut self, token: Token) {
+        self.foster_parenting = true;
+        self.in_body(token);
+        self.foster_parenting = false;
+    }
+
+    fn in_table_text(&mut self, token: Token) {
+        match token {
+            Token::Character('\0') => {}
+            Token::Character(c) => self.pending_table_chars.push(c),
+            token => {
+                let chars = mem::take(&mut self.pending_table_chars);
+                if chars.chars().any(|c| !is_whitespace(c)) {
+                    for c in chars.chars() {
+                        self.in_table_anything_else(Token::Character(c));
+                    }
+                } else {
+                    for c in chars.chars() {
+                        self.insert_character(c);
+                    }
+                }
+                let mode = self.original_mode;
+                self.reprocess_in(mode, token);
+            }
+        }
+    }
+
+    fn close_caption(&mut self) -> bool {
+        if !self.has_in_scope("caption", Scope::Table) {
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.pop_until("caption");
+        self.clear_formatting_to_last_marker();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    fn in_caption(&mut self, token: Token) {
+        match token {
+            Token::EndTag(ref tag) if tag.name == "caption" => {
+                self.close_caption();
+            }
+            Token::StartTag(ref tag)
+                if ["caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr"].contains(&&*tag.name) => {
+                if self.close_caption() {
+                    self.process_token(token.clone());
+                }
+            }
+            Token::EndTag(ref tag) if tag.name == "table" => {
+                if self.close_caption() {
+                    self.process_token(token.clone());
+                }
+            }
+            Token::EndTag(ref tag)
+                if ["body", "col", "colgroup", "html", "tbody", "td", "tfoot", "th", "thead", "tr"].contains(&&*tag.name) => {}
+            token => self.in_body(token),
+        }
+    }
+
+    fn in_column_group(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::StartTag(ref tag) if tag.name == "col" => {
+                self.insert_element(tag);
+                self.pop();
+            }
+            Token::EndTag(ref tag) if tag.name == "colgroup" => {
+                if self.current_is("colgroup") {
+                    self.pop();
+                    self.mode = InsertionMode::InTable;
+                }
+            }
+            Token::EndTag(ref tag) if tag.name == "col" => {}
+            Token::StartTag(ref tag) if tag.name == "template" => self.in_head(token.clone()),
+            Token::EndTag(ref tag) if tag.name == "template" => self.in_head(token.clone()),
+            Token::Eof => self.in_body(token),
+            token => {
+                if self.current_is("colgroup") {
+                    self.pop();
+                    self.reprocess_in(InsertionMode::InTable, token);
+                }
+            }
+        }
+    }
+
+    fn in_table_body(&mut self, token: Token) {
+        match token {
+            Token::StartTag(ref tag) if tag.name == "tr" => {
+                self.clear_stack_back_to_table_body_context();
+                self.insert_element(tag);
+                self.mode = InsertionMode::InRow;
+            }
+            Token::StartTag(ref tag) if tag.name == "th" || tag.name == "td" => {
+                self.clear_stack_back_to_table_body_context();
+                self.insert_html_element("tr");
+                self.reprocess_in(InsertionMode::InRow, token.clone());
+            }
+            Token::EndTag(ref tag) if ["tbody", "tfoot", "thead"].contains(&&*tag.name) => {
+                if self.has_in_scope(&tag.name, Scope::Table) {
+                    self.clear_stack_back_to_table_body_context();
+                    self.pop();
+                    self.mode = InsertionMode::InTable;
+                }
+            }
+            Token::StartTag(ref tag)
+                if ["caption", "col", "colgroup", "tbody", "tfoot", "thead"].contains(&&*tag.name) => {
+                self.close_table_body(token.clone());
+            }
+            Token::EndTag(ref tag) if tag.name == "table" => self.close_table_body(token.clone()),
+            Token::EndTag(ref tag)
+                if ["body", "caption", "col", "colgroup", "html", "td", "th", "tr"].contains(&&*tag.name) => {}
+            token => self.in_table(token),
+        }
+    }
+
+    fn close_table_body(&mut self, token: Token) {
+        let in_scope = self.has_in_scope_where(Scope::Table, |id| {
+            self.is_one_of(id, &["tbody", "thead", "tfoot"])
+        });
+        if in_scope {
+            self.clear_stack_back_to_table_body_context();
+            self.pop();
+            self.reprocess_in(InsertionMode::InTable, token);
+        }
+    }
+
+    fn close_row(&mut self) -> bool {
+        if !self.has_in_scope("tr", Scope::Table) {
+            return false;
+        }
+        self.clear_stack_back_to_table_row_context();
+        self.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    fn in_row(&mut self, token: Token) {
+        match token {
+            Token::StartTag(ref tag) if tag.name == "th" || tag.name == "td" => {
+                self.clear_stack_back_to_table_row_context();
+                self.insert_element(tag);
+                self.mode = InsertionMode::InCell;
+                self.active_formatting.push(FormatEntry::Marker);
+            }
+            Token::EndTag(ref tag) if tag.name == "tr" => {
+                self.close_row();
+            }
+            Token::StartTag(ref tag)
+                if ["caption", "col", "colgroup", "tbody", "tfoot", "thead", "tr"].contains(&&*tag.name) => {
+                if self.close_row() {
+                    self.process_token(token.clone());
+                }
+            }
+            Token::EndTag(ref tag) if tag.name == "table" => {
+                if self.close_row() {
+                    self.process_token(token.clone());
+                }
+            }
+            Token::EndTag(ref tag) if ["tbody", "tfoot", "thead"].contains(&&*tag.name) => {
+                if self.has_in_scope(&tag.name, Scope::Table) && self.close_row() {
+                    self.process_token(token.clone());
+                }
+            }
+            Token::EndTag(ref tag)
+                if ["body", "caption", "col", "colgroup", "html", "td", "th"].contains(&&*tag.name) => {}
+            token => self.in_table(token),
+        }
+    }
+
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        self.pop_until_one_of(&["td", "th"]);
+        self.clear_formatting_to_last_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    fn in_cell(&mut self, token: Token) {
+        match token {
+            Token::EndTag(ref tag) if tag.name == "td" || tag.name == "th" => {
+                if self.has_in_scope(&tag.name, Scope::Table) {
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&tag.name);
+                    self.clear_formatting_to_last_marker();
+                    self.mode = InsertionMode::InRow;
+                }
+            }
+            Token::StartTag(ref tag)
+                if ["caption", "col", "colgroup", "tbody", "td", "tfoot", "th", "thead", "tr"].contains(&&*tag.name) => {
+                if self.has_in_scope("td", Scope::Table) || self.has_in_scope("th", Scope::Table) {
+                    self.close_cell();
+                    self.process_token(token.clone());
+                }
+            }
+            Token::EndTag(ref tag)
+                if ["body", "caption", "col", "colgroup", "html"].contains(&&*tag.name) => {}
+            Token::EndTag(ref tag)
+                if ["table", "tbody", "tfoot", "thead", "tr"].contains(&&*tag.name) => {
+                if self.has_in_scope(&tag.name, Scope::Table) {
+                    self.close_cell();
+                    self.process_token(token.clone());
+                }
+            }
+            token => self.in_body(token),
+        }
+    }
+
+    fn in_select(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character('\0') => {}
+            Token::Character(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) => match &*tag.name {
+                "html" => self.in_body(token.clone()),
+                "option" => {
+                    if self.current_is("option") {
+                        self.pop();
+                    }
+                    self.insert_element(tag);
+                }
+                "optgroup" => {
+                    if self.current_is("option") {
+                        self.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.pop();
+                    }
+                    self.insert_element(tag);
+                }
+                "hr" => {
+                    if self.current_is("option") {
+                        self.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.pop();
+                    }
+                    self.insert_element(tag);
+                    self.pop();
+                }
+                "select" if self.has_in_scope("select", Scope::Select) => {
+                    self.pop_until("select");
+                    self.reset_insertion_mode();
+                }
+                "input" | "keygen" | "textarea" if self.has_in_scope("select", Scope::Select) => {
+                    self.pop_until("select");
+                    self.reset_insertion_mode();
+                    self.process_token(token.clone());
+                }
+                "script" | "template" => self.in_head(token.clone()),
+                _ => {}
+            },
+            Token::EndTag(ref tag) => match &*tag.name {
+                "optgroup" => {
+                    let len = self.open_elements.len();
+                    if self.current_is("option") && len > 1
+                            && self.is(self.open_elements[len - 2], "optgroup") {
+                        self.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.pop();
+                    }
+                }
+                "option" if self.current_is("option") => {
+                    self.pop();
+                }
+                "select" if self.has_in_scope("select", Scope::Select) => {
+                    self.pop_until("select");
+                    self.reset_insertion_mode();
+                }
+                "template" => self.in_head(token.clone()),
+                _ => {}
+            },
+            Token::Eof => self.in_body(token),
+        }
+    }
+
+    fn in_select_in_table(&mut self, token: Token) {
+        const TABLE_TAGS: &[&str] = &["caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th"];
+        match token {
+            Token::StartTag(ref tag) if TABLE_TAGS.contains(&&*tag.name) => {
+                self.pop_until("select");
+                self.reset_insertion_mode();
+                self.process_token(token.clone());
+            }
+            Token::EndTag(ref tag) if TABLE_TAGS.contains(&&*tag.name) => {
+                if self.has_in_scope(&tag.name, Scope::Table) {
+                    self.pop_until("select");
+                    self.reset_insertion_mode();
+                    self.process_token(token.clone());
+                }
+            }
+            token => self.in_select(token),
+        }
+    }
+
+    /// The contents of a `template`, which switch to the mode of the context
+    /// their first tag needs, so that table parts aren't dropped.
+    fn in_template(&mut self, token: Token) {
+        match token {
+            Token::Character(_) | Token::Comment(_) | Token::Doctype(_) => self.in_body(token),
+            Token::StartTag(ref tag) => {
+                let mode = match &*tag.name {
+                    "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+                    | "template" | "title" => return self.in_head(token.clone()),
+                    "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => InsertionMode::InTable,
+                    "col" => InsertionMode::InColumnGroup,
+                    "tr" => InsertionMode::InTableBody,
+                    "td" | "th" => InsertionMode::InRow,
+                    _ => InsertionMode::InBody,
+                };
+                self.template_modes.pop();
+                self.template_modes.push(mode);
+                self.reprocess_in(mode, token.clone());
+            }
+            Token::EndTag(ref tag) if tag.name == "template" => self.in_head(token.clone()),
+            Token::EndTag(_) => {}
+            Token::Eof => {
+                if !self.open_elements.iter().any(|&id| self.is(id, "template")) {
+                    return self.stop_parsing();
+                }
+                self.pop_until("template");
+                self.clear_formatting_to_last_marker();
+                self.template_modes.pop();
+                self.reset_insertion_mode();
+                let mode = self.mode;
+                self.process(mode, Token::Eof);
+            }
+        }
+    }
+
+    fn after_body(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::Comment(text) => {
+                let html = self.open_elements[0];
+                self.insert_comment(text, Some(html));
+            }
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::EndTag(ref tag) if tag.name == "html" => self.mode = InsertionMode::AfterAfterBody,
+            Token::Eof => self.stop_parsing(),
+            token => self.reprocess_in(InsertionMode::InBody, token),
+        }
+    }
+
+    fn in_frameset(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) => match &*tag.name {
+                "html" => self.in_body(token.clone()),
+                "frameset" => {
+                    self.insert_element(tag);
+                }
+                "frame" => {
+                    self.insert_element(tag);
+                    self.pop();
+                }
+                "noframes" => self.in_head(token.clone()),
+                _ => {}
+            },
+            Token::EndTag(ref tag) if tag.name == "frameset" && !self.current_is("html") => {
+                self.pop();
+                if !self.current_is("frameset") {
+                    self.mode = InsertionMode::AfterFrameset;
+                }
+            }
+            Token::Eof => self.stop_parsing(),
+            _ => {}
+        }
+    }
+
+    fn after_frameset(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => {}
+            Token::Character(c) if is_whitespace(c) => self.insert_character(c),
+            Token::Comment(text) => self.insert_comment(text, None),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::EndTag(ref tag) if tag.name == "html" => {
+                self.mode = InsertionMode::AfterAfterFrameset;
+            }
+            Token::StartTag(ref tag) if tag.name == "noframes" => self.in_head(token.clone()),
+            Token::Eof => self.stop_parsing(),
+            _ => {}
+        }
+    }
+
+    fn after_after_body(&mut self, token: Token) {
+        match token {
+            Token::Comment(text) => self.insert_comment(text, Some(DOCUMENT)),
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::Eof => self.stop_parsing(),
+            token => self.reprocess_in(InsertionMode::InBody, token),
+        }
+    }
+
+    fn after_after_frameset(&mut self, token: Token) {
+        match token {
+            Token::Comment(text) => self.insert_comment(text, Some(DOCUMENT)),
+            Token::Character(c) if is_whitespace(c) => self.in_body(token),
+            Token::StartTag(ref tag) if tag.name == "html" => self.in_body(token.clone()),
+            Token::StartTag(ref tag) if tag.name == "noframes" => self.in_head(token.clone()),
+            Token::Eof => self.stop_parsing(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use html::parse;
+    use html::serialize::serialize;
+    use node::{Namespace, Node, NodeType};
+    use super::MAX_TREE_DEPTH;
+
+    /// The markup of the body of `html` as parsed.
+    fn body(html: &str) -> String {
+        let html = serialize(&parse(html));
+        html["<html><head></head><body>".len()..html.len() - "</body></html>".len()].to_string()
+    }
+
+    /// The namespaces of the elements called `name` in `node`, in tree order.
+    fn namespaces(node: &Node, name: &str, found: &mut Vec<Namespace>) {
+        if let NodeType::Element(ref elem) = node.node_type {
+            if elem.tag_name == name {
+                found.push(elem.namespace.clone());
+            }
+        }
+        for child in &node.children {
+            namespaces(child, name, found);
+        }
+    }
+
+    #[test]
+    fn adoption_agency() {
+        assert_eq!(body("<p>1<b>2<i>3</b>4</i>5</p>"), "<p>1<b>2<i>3</i></b><i>4</i>5</p>");
+        assert_eq!(body("<a>1<p>2</a>3</p>"), "<a>1</a><p><a>2</a>3</p>");
+        assert_eq!(body("<b>1<p>2</b>3</p>"), "<b>1</b><p><b>2</b>3</p>");
+    }
+
+    #[test]
+    fn foster_parenting() {
+        assert_eq!(body("<table>a<tr><td>b</td></tr>c</table>"),
+                   "ac<table><tbody><tr><td>b</td></tr></tbody></table>");
+        assert_eq!(body("<table><tr><td>x</td></tr><div>y</div></table>"),
+                   "<div>y</div><table><tbody><tr><td>x</td></tr></tbody></table>");
+    }
+
+    #[test]
+    fn foreign_content() {
+        let html = "<svg viewbox='0 0 1 1'><foreignobject><p>x</p></foreignobject><clippath/></svg>";
+        assert_eq!(body(html), "<svg viewBox=\"0 0 1 1\"><foreignObject><p>x</p></foreignObject>\
+                                <clipPath></clipPath></svg>");
+        let node = parse(html);
+        let mut found = vec![];
+        namespaces(&node, "p", &mut found);
+        namespaces(&node, "clipPath", &mut found);
+        assert_eq!(found, [Namespace::Html, Namespace::Svg]);
+
+        assert_eq!(body("<svg><p>x</svg>"), "<svg></svg><p>x</p>");
+        assert_eq!(body("<svg><![CDATA[a<b]]></svg>"), "<svg>a&lt;b</svg>");
+        assert_eq!(body("<math><mi>x</mi><mtext><b>y</b></mtext></math>"),
+                   "<math><mi>x</mi><mtext><b>y</b></mtext></math>");
+    }
+
+    #[test]
+    fn template_contents() {
+        assert_eq!(body("<body><template><td>x</td></template>"), "<template><td>x</td></template>");
+        assert_eq!(body("<body><template><tr><td>x</template>y"), "<template><tr><td>x</td></tr></template>y");
+        // Only column content can follow a `col`.
+        assert_eq!(body("<body><template><col><caption>c</caption></template>"), "<template><col></template>");
+        assert_eq!(body("<body><template><p>a<td>b</template>"), "<template><p>ab</p></template>");
+        assert_eq!(body("<table><template><tr></template></table>"),
+                   "<table><template><tr></tr></template></table>");
+        assert_eq!(body("<body><template><div><template><td>x</template></div></template>"),
+                   "<template><div><template><td>x</td></template></div></template>");
+    }
+
+    #[test]
+    fn deep_nesting() {
+        fn depth(node: &Node) -> usize {
+            1 + node.children.iter().map(depth).max().unwrap_or(0)
+        }
+        let node = parse(&"<div>".repeat(MAX_TREE_DEPTH * 4));
+        assert!(depth(&node) <= MAX_TREE_DEPTH + 2);
+        assert_eq!(body("<div><div></div>x</div>"), "<div><div></div>x</div>");
+    }
+}
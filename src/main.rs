@@ -1,8 +1,12 @@
 #![allow(dead_code)]
 
 mod node;
+mod dom;
+mod html;
+mod xml;
 mod parser;
 mod css;
+mod shorthands;
 mod style;
 mod boxes;
 
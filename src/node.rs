@@ -1,31 +1,188 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 
+#[derive(Clone, PartialEq)]
 pub enum NodeType {
     Text(String),
     Element(ElementData),
+    Comment(String),
+    Doctype(DoctypeData),
+    ProcessingInstruction(ProcessingInstructionData),
+    Document(QuirksMode),
 }
 
+#[derive(Clone, PartialEq)]
+pub struct DoctypeData {
+    pub name: String,
+    pub public_id: String,
+    pub system_id: String,
+}
+
+/// A processing instruction, `<?target data?>`. Only XML documents have them.
+#[derive(Clone, PartialEq)]
+pub struct ProcessingInstructionData {
+    pub target: String,
+    pub data: String,
+}
+
+/// The rendering mode of a document, chosen by the parser from its DOCTYPE.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+/// The namespace of an element or attribute.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    Html,
+    Svg,
+    MathMl,
+    XLink,
+    Xml,
+    Xmlns,
+    /// Any other namespace, by its URL. The empty URL stands for no
+    /// namespace.
+    Other(String),
+}
+
+impl Namespace {
+    pub fn from_url(url: &str) -> Namespace {
+        match url {
+            "http://www.w3.org/1999/xhtml" => Namespace::Html,
+            "http://www.w3.org/2000/svg" => Namespace::Svg,
+            "http://www.w3.org/1998/Math/MathML" => Namespace::MathMl,
+            "http://www.w3.org/1999/xlink" => Namespace::XLink,
+            "http://www.w3.org/XML/1998/namespace" => Namespace::Xml,
+            "http://www.w3.org/2000/xmlns/" => Namespace::Xmlns,
+            url => Namespace::Other(url.to_string()),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        match *self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::XLink => "http://www.w3.org/1999/xlink",
+            Namespace::Xml => "http://www.w3.org/XML/1998/namespace",
+            Namespace::Xmlns => "http://www.w3.org/2000/xmlns/",
+            Namespace::Other(ref url) => url,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
 pub struct ElementData {
+    /// The local name. It is lowercase for HTML elements, but keeps its case
+    /// in other namespaces, as in SVG's `foreignObject`.
     pub tag_name: String,
-    pub attributes: AttrMap,
+    pub namespace: Namespace,
+    /// Attributes by qualified name, such as `xlink:href`.
+    attributes: AttrMap,
+    /// The namespaces of the attributes that have one.
+    attribute_namespaces: HashMap<String, Namespace>,
+    /// The `id` attribute and the split up `class` attribute, cached for
+    /// selector matching and kept up to date by `set_attribute` and
+    /// `remove_attribute`.
+    id: Option<String>,
+    classes: HashSet<String>,
 }
 
 impl ElementData {
-    pub fn id(&self) -> Option<&String> {
-        self.attributes.get("id")
+    /// An HTML element.
+    pub fn new(tag_name: String, attributes: AttrMap) -> ElementData {
+        ElementData::new_ns(Namespace::Html, tag_name, attributes)
     }
 
-    pub fn classes(&self) -> HashSet<&str> {
-        match self.attributes.get("class") {
-            Some(classlist) => classlist.split(' ').collect(),
-            None => HashSet::new(),
+    /// An element in the given namespace. The attributes are in no namespace.
+    pub fn new_ns(namespace: Namespace, tag_name: String, attributes: AttrMap) -> ElementData {
+        let mut elem = ElementData {
+            tag_name,
+            namespace,
+            attributes,
+            attribute_namespaces: HashMap::new(),
+            id: None,
+            classes: HashSet::new(),
+        };
+        elem.update_cache("id");
+        elem.update_cache("class");
+        elem
+    }
+
+    pub fn attributes(&self) -> &AttrMap {
+        &self.attributes
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|value| &**value)
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.namespace == Namespace::Html
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.attributes.insert(name.to_string(), value.to_string());
+        self.attribute_namespaces.remove(name);
+        self.update_cache(name);
+    }
+
+    /// Set an attribute in a namespace. `name` is the qualified name, with
+    /// any prefix.
+    pub fn set_attribute_ns(&mut self, namespace: Namespace, name: &str, value: &str) {
+        self.attributes.insert(name.to_string(), value.to_string());
+        self.attribute_namespaces.insert(name.to_string(), namespace);
+        self.update_cache(name);
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let value = self.attributes.remove(name);
+        self.attribute_namespaces.remove(name);
+        self.update_cache(name);
+        value
+    }
+
+    /// The namespace of an attribute, by qualified name.
+    pub fn attribute_namespace(&self, name: &str) -> Option<&Namespace> {
+        self.attribute_namespaces.get(name)
+    }
+
+    /// Look up an attribute by namespace and local name.
+    pub fn attribute_ns(&self, namespace: &Namespace, local_name: &str) -> Option<&str> {
+        self.attribute_namespaces.iter()
+            .find(|&(name, ns)| {
+                ns == namespace && name.rsplit(':').next() == Some(local_name)
+            })
+            .and_then(|(name, _)| self.attribute(name))
+    }
+
+    fn update_cache(&mut self, name: &str) {
+        match name {
+            "id" => self.id = self.attributes.get("id").cloned(),
+            "class" => {
+                self.classes = match self.attributes.get("class") {
+                    Some(classlist) => classlist.split_ascii_whitespace().map(String::from).collect(),
+                    None => HashSet::new(),
+                };
+            }
+            _ => {}
         }
     }
+
+    pub fn id(&self) -> Option<&String> {
+        self.id.as_ref()
+    }
+
+    pub fn classes(&self) -> &HashSet<String> {
+        &self.classes
+    }
 }
 
 pub type AttrMap = HashMap<String, String>;
 
+#[derive(PartialEq)]
 pub struct Node {
     pub children: Vec<Node>,
     pub node_type: NodeType,
@@ -35,13 +192,14 @@ pub fn text(data: String) -> Node {
     Node { children : vec![], node_type: NodeType::Text(data) }
 }
 
+pub fn comment(data: String) -> Node {
+    Node { children: vec![], node_type: NodeType::Comment(data) }
+}
+
 pub fn elem(name: String, attrs: AttrMap, children: Vec<Node>) -> Node {
     Node {
         children,
-        node_type: NodeType::Element(ElementData {
-            tag_name: name,
-            attributes: attrs,
-        }),
+        node_type: NodeType::Element(ElementData::new(name, attrs)),
     }
 }
 
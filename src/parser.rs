@@ -1,244 +1,547 @@
-use node::{self, Node};
+use html;
+use xml;
+use node::Node;
+use std::collections::HashMap;
+use std::io::{self, Read};
+
 use css::{SimpleSelector, self};
+use node::Namespace;
 
+/// A CSS parser. Invalid CSS is dropped the way browsers drop it: a
+/// declaration up to the next `;` or `}`, and a rule or at-rule through the
+/// end of its block. The parsing methods return `None` for input they
+/// don't accept.
 pub struct Parser {
     pos: usize,
     input: String,
+    /// Namespace prefixes declared by `@namespace` rules.
+    namespaces: HashMap<String, Namespace>,
+    default_namespace: Option<Namespace>,
 }
 
 impl Parser {
     pub fn new(input: String) -> Parser {
         Parser {
             pos: 0,
-            input: input,
+            input,
+            namespaces: HashMap::new(),
+            default_namespace: None,
         }
     }
 
-    fn parse_simple_selector(&mut self) -> SimpleSelector {
+    fn parse_simple_selector(&mut self) -> Option<SimpleSelector> {
         let mut selector = SimpleSelector {
+            namespace: None,
             tag_name: None,
             id: None,
             class: vec![],
+            pseudo_element: None,
         };
 
+        // The selector matches elements in the default namespace unless its
+        // type selector has a prefix: `ns|` for a declared namespace, `*|`
+        // for any, or `|` for none.
+        let mut namespace = self.default_namespace.clone();
+        let start = self.pos;
+        let prefix = if self.starts_with("*|") {
+            self.consume_char();
+            Some(None)
+        } else if self.starts_with("|") {
+            Some(Some(Namespace::Other(String::new())))
+        } else {
+            let name = self.parse_identifier();
+            if self.starts_with("|") {
+                Some(Some(self.namespaces.get(&name)?.clone()))
+            } else {
+                self.pos = start;
+                None
+            }
+        };
+        let has_prefix = prefix.is_some();
+        if let Some(prefix) = prefix {
+            self.consume_char();
+            namespace = prefix;
+        }
+
+        let start = self.pos;
         while !self.eof() {
             match self.next_char() {
                 '#' => {
                     self.consume_char();
-                    selector.id = Some(self.parse_identifier());
+                    selector.id = Some(self.parse_name()?);
                 }
                 '.' => {
                     self.consume_char();
-                    selector.class.push(self.parse_identifier());
+                    selector.class.push(self.parse_name()?);
                 }
                 '*' => {
                     // universal selector
                     self.consume_char();
                 }
+                ':' if self.starts_with("::") => {
+                    self.pos += 2;
+                    selector.pseudo_element = Some(self.parse_name()?.to_ascii_lowercase());
+                }
                 c if valid_identifier_char(c) => {
                     selector.tag_name = Some(self.parse_identifier());
                 }
                 _ => break,
             }
         }
-        selector
+        // Anything else, like an attribute selector or a pseudo-class, is
+        // not supported and makes the selector invalid.
+        if self.pos == start && !has_prefix {
+            return None;
+        }
+        if !self.eof() && !self.next_char().is_whitespace()
+            && !['>', '+', '~', ',', '{', '/'].contains(&self.next_char()) {
+            return None;
+        }
+        selector.namespace = namespace;
+        Some(selector)
     }
 
     fn parse_rules(&mut self) -> Vec<css::Rule> {
         let mut rules = vec![];
         loop {
-            self.consume_whitespace();
+            self.consume_css_whitespace();
             if self.eof() { break }
-            rules.push(self.parse_rule());
+            let start = self.pos;
+            if self.starts_with("@") {
+                if self.parse_at_rule().is_none() {
+                    self.pos = start;
+                    self.skip_at_rule();
+                }
+                continue;
+            }
+            match self.parse_rule() {
+                Some(rule) => rules.push(rule),
+                None => {
+                    self.pos = start;
+                    self.skip_until(&['{']);
+                    self.skip_block();
+                }
+            }
         }
         rules
     }
 
-    fn parse_rule(&mut self) -> css::Rule {
-        css::Rule {
-            selectors: self.parse_selectors(),
-            declarations: self.parse_declarations(),
+    /// Parse an at-rule. Only `@namespace` is supported.
+    fn parse_at_rule(&mut self) -> Option<()> {
+        self.consume_char();
+        let name = self.parse_identifier();
+        if !name.eq_ignore_ascii_case("namespace") {
+            return None;
+        }
+        self.consume_css_whitespace();
+        let prefix = match self.next_char_or_eof()? {
+            '"' | '\'' => None,
+            _ if self.starts_with("url(") => None,
+            _ => Some(self.parse_name()?),
+        };
+        self.consume_css_whitespace();
+        let namespace = Namespace::from_url(&self.parse_url()?);
+        self.consume_css_whitespace();
+        if !self.eof() && self.consume_char() != ';' {
+            return None;
+        }
+        match prefix {
+            Some(prefix) => { self.namespaces.insert(prefix, namespace); }
+            None => self.default_namespace = Some(namespace),
+        }
+        Some(())
+    }
+
+    /// Skip an at-rule that isn't supported or isn't valid: up to its `;`,
+    /// or through the end of its block.
+    fn skip_at_rule(&mut self) {
+        self.skip_until(&[';', '{']);
+        if self.starts_with(";") {
+            self.consume_char();
+        } else {
+            self.skip_block();
+        }
+    }
+
+    /// Parse a `url(...)` or a quoted string.
+    fn parse_url(&mut self) -> Option<String> {
+        if self.starts_with("url(") {
+            self.pos += 4;
+            self.parse_url_contents()
+        } else {
+            self.parse_string()
+        }
+    }
+
+    /// Parse what comes after the `url(` of a URL, through the `)`.
+    fn parse_url_contents(&mut self) -> Option<String> {
+        self.consume_css_whitespace();
+        let url = match self.next_char_or_eof()? {
+            '"' | '\'' => self.parse_string()?,
+            _ => self.consume_while(|c| c != ')' && !c.is_whitespace()),
+        };
+        self.consume_css_whitespace();
+        if self.next_char_or_eof() != Some(')') {
+            return None;
+        }
+        self.consume_char();
+        Some(url)
+    }
+
+    /// Parse a quoted string, with backslash escapes. A string may end
+    /// with the input, but a newline in it makes it invalid.
+    fn parse_string(&mut self) -> Option<String> {
+        let quote = self.consume_char();
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let mut s = String::new();
+        while !self.eof() {
+            match self.consume_char() {
+                c if c == quote => return Some(s),
+                '\n' => return None,
+                '\\' => {
+                    if let Some(c) = self.parse_escape() {
+                        s.push(c);
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+        Some(s)
+    }
+
+    /// Parse what follows a backslash: up to six hex digits and a space
+    /// for a code point, or a character that stands for itself. An escaped
+    /// newline stands for nothing.
+    fn parse_escape(&mut self) -> Option<char> {
+        let hex = self.consume_while_max(6, |c| c.is_ascii_hexdigit());
+        if hex.is_empty() {
+            return match self.consume_char_or_eof()? {
+                '\n' => None,
+                c => Some(c),
+            };
+        }
+        if self.starts_with(" ") {
+            self.consume_char();
         }
+        let code = u32::from_str_radix(&hex, 16).unwrap_or(0);
+        Some(std::char::from_u32(code).filter(|&c| c != '\0').unwrap_or('\u{FFFD}'))
     }
 
-    fn parse_selectors(&mut self) -> Vec<css::Selector> {
+    fn parse_rule(&mut self) -> Option<css::Rule> {
+        Some(css::Rule {
+            selectors: self.parse_selectors()?,
+            declarations: self.parse_declarations()?,
+        })
+    }
+
+    /// Parse a simple selector followed by any number of combinators, each
+    /// with another simple selector.
+    fn parse_selector(&mut self) -> Option<css::Selector> {
+        let mut selector = css::Selector::Simple(self.parse_simple_selector()?);
+        loop {
+            let start = self.pos;
+            self.consume_css_whitespace();
+            if self.eof() { break }
+            let combinator = match self.next_char() {
+                '>' => css::Combinator::Child,
+                '+' => css::Combinator::NextSibling,
+                '~' => css::Combinator::SubsequentSibling,
+                ',' | '{' => break,
+                _ if self.pos > start => css::Combinator::Descendant,
+                _ => return None,
+            };
+            if combinator != css::Combinator::Descendant {
+                self.consume_char();
+                self.consume_css_whitespace();
+            }
+            let right = self.parse_simple_selector()?;
+            selector = css::Selector::Combined(Box::new(selector), combinator, right);
+        }
+        Some(selector)
+    }
+
+    fn parse_selectors(&mut self) -> Option<Vec<css::Selector>> {
         let mut selectors = vec![];
         loop {
-            selectors.push(css::Selector::Simple(self.parse_simple_selector()));
-            self.consume_whitespace();
+            selectors.push(self.parse_selector()?);
+            self.consume_css_whitespace();
+            if self.eof() { break }
             match self.next_char() {
                 ',' => {
                     self.consume_char();
-                    self.consume_whitespace();
+                    self.consume_css_whitespace();
                 }
                 '{' => break,
-                c => panic!("Unexpected character {} in selector list", c),
+                _ => return None,
             }
         }
-        selectors.sort_by(|a,b| b.specificity().cmp(&a.specificity()));
-        selectors
+        selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+        Some(selectors)
     }
 
-    fn parse_declarations(&mut self) -> Vec<css::Declaration> {
-        assert_eq!(self.consume_char(), '{');
+    /// Parse a declaration block. The end of the input closes it.
+    fn parse_declarations(&mut self) -> Option<Vec<css::Declaration>> {
+        if self.consume_char_or_eof()? != '{' {
+            return None;
+        }
+        let declarations = self.parse_declaration_list();
+        if !self.eof() {
+            self.consume_char();
+        }
+        Some(declarations)
+    }
+
+    /// Parse declarations up to a `}` or the end of the input, dropping
+    /// the invalid ones.
+    fn parse_declaration_list(&mut self) -> Vec<css::Declaration> {
         let mut declarations = vec![];
         loop {
-            self.consume_whitespace();
-            if self.next_char() == '}' {
+            self.consume_css_whitespace();
+            if self.eof() || self.next_char() == '}' { break }
+            if self.next_char() == ';' {
                 self.consume_char();
-                break;
+                continue;
+            }
+            match self.parse_declaration() {
+                Some(declaration) => declarations.push(declaration),
+                None => self.skip_until(&[';', '}']),
             }
-            declarations.push(self.parse_declaration());
         }
         declarations
     }
 
-    fn parse_declaration(&mut self) -> css::Declaration {
-        let property_name = self.parse_identifier();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ':');
-        self.consume_whitespace();
-        let value = self.parse_value();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ';');
+    fn parse_declaration(&mut self) -> Option<css::Declaration> {
+        let property_name = self.parse_name()?;
+        self.consume_css_whitespace();
+        if self.consume_char_or_eof()? != ':' {
+            return None;
+        }
+        let value = self.parse_value(false)?;
+        self.consume_css_whitespace();
+        let important = self.starts_with("!");
+        if important {
+            self.consume_char();
+            self.consume_css_whitespace();
+            if !self.parse_identifier().eq_ignore_ascii_case("important") {
+                return None;
+            }
+            self.consume_css_whitespace();
+        }
+        // The semicolon is optional after the last declaration in a block.
+        match self.next_char_or_eof() {
+            None | Some('}') => {}
+            Some(';') => { self.consume_char(); }
+            Some(_) => return None,
+        }
 
-        css::Declaration {
-            name: property_name,
-            value: value,
+        Some(css::Declaration {
+            name: property_name.to_ascii_lowercase(),
+            value,
+            important,
+        })
+    }
+
+    /// A value of one or more components separated by whitespace, ending at
+    /// the end of the declaration, or of a function argument if
+    /// `in_function` is set. Outside functions, commas separate the items
+    /// of a list.
+    fn parse_value(&mut self, in_function: bool) -> Option<css::Value> {
+        let mut components = vec![];
+        loop {
+            self.consume_css_whitespace();
+            match self.next_char_or_eof() {
+                None | Some(';') | Some('}') | Some(')') | Some('!') => break,
+                Some(',') if in_function => break,
+                Some(',') => {
+                    self.consume_char();
+                    components.push(css::Value::Comma);
+                }
+                Some(_) => components.push(self.parse_component()?),
+            }
+        }
+        match components.len() {
+            0 => None,
+            1 => components.pop(),
+            _ => Some(css::Value::List(components)),
         }
     }
 
-    fn parse_value(&mut self) -> css::Value {
+    fn parse_component(&mut self) -> Option<css::Value> {
         match self.next_char() {
-            c if c.is_digit(10) => self.parse_length(),
+            c if c.is_ascii_digit() || c == '.' => self.parse_length(),
+            '-' if self.input[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit() || c == '.') => {
+                self.parse_length()
+            }
             '#' => self.parse_color(),
-            _ => css::Value::Keyword(self.parse_identifier()),
+            '"' | '\'' => self.parse_string().map(css::Value::String),
+            '/' => {
+                self.consume_char();
+                Some(css::Value::Slash)
+            }
+            _ => {
+                let name = self.parse_name()?;
+                if self.eof() || self.next_char() != '(' {
+                    return Some(css::Value::Keyword(name));
+                }
+                self.consume_char();
+                if name.eq_ignore_ascii_case("url") {
+                    let url = self.parse_url_contents()?;
+                    return Some(css::Value::Function(name, vec![css::Value::String(url)]));
+                }
+                let mut arguments = vec![];
+                loop {
+                    arguments.push(self.parse_value(true)?);
+                    match self.consume_char_or_eof()? {
+                        ',' => {}
+                        ')' => break,
+                        _ => return None,
+                    }
+                }
+                Some(css::Value::Function(name, arguments))
+            }
         }
     }
 
-    fn parse_length(&mut self) -> css::Value {
-        css::Value::Length(self.parse_float(), self.parse_unit())
-    }
-
-    fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| c.is_digit(10) || c == '.');
-        s.parse().unwrap()
+    fn parse_length(&mut self) -> Option<css::Value> {
+        let number = self.parse_float()?;
+        if self.starts_with("%") {
+            self.consume_char();
+            return Some(css::Value::Percentage(number));
+        }
+        if self.eof() || !valid_identifier_char(self.next_char()) {
+            return Some(css::Value::Number(number));
+        }
+        self.parse_unit(number)
     }
 
-    fn parse_unit(&mut self) -> css::Unit {
-        match &*self.parse_identifier().to_lowercase() {
-            "px" => css::Unit::Px,
-            _ => panic!("Unrecognized unit"),
+    fn parse_float(&mut self) -> Option<f32> {
+        let negative = self.starts_with("-");
+        if negative {
+            self.consume_char();
         }
+        let s = self.consume_while(|c| c.is_ascii_digit() || c == '.');
+        let number: f32 = s.parse().ok()?;
+        Some(if negative { -number } else { number })
     }
 
-    fn parse_color(&mut self) -> css::Value {
-        assert_eq!(self.consume_char(), '#');
-        css::Value::Color(css::Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
-        })
+    /// The length `number` in the unit that follows. Absolute units are
+    /// converted to pixels; units that aren't supported make the value
+    /// invalid.
+    fn parse_unit(&mut self, number: f32) -> Option<css::Value> {
+        let (number, unit) = match &*self.parse_identifier().to_lowercase() {
+            "px" => (number, css::Unit::Px),
+            "em" => (number, css::Unit::Em),
+            "fr" => (number, css::Unit::Fr),
+            "pt" => (number * 4.0 / 3.0, css::Unit::Px),
+            "pc" => (number * 16.0, css::Unit::Px),
+            "in" => (number * 96.0, css::Unit::Px),
+            "cm" => (number * 96.0 / 2.54, css::Unit::Px),
+            "mm" => (number * 96.0 / 25.4, css::Unit::Px),
+            "q" => (number * 96.0 / 101.6, css::Unit::Px),
+            _ => return None,
+        };
+        Some(css::Value::Length(number, unit))
     }
 
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos+2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+    /// Parse a hex color of three, four, six or eight digits.
+    fn parse_color(&mut self) -> Option<css::Value> {
+        self.consume_char();
+        let digits = self.parse_identifier();
+        let hex = |i: usize, len: usize| u8::from_str_radix(&digits[i * len..(i + 1) * len], 16).ok();
+        let (len, count) = match digits.len() {
+            3 | 4 => (1, digits.len()),
+            6 | 8 => (2, digits.len() / 2),
+            _ => return None,
+        };
+        if !digits.is_ascii() {
+            return None;
+        }
+        let mut channels = [255; 4];
+        for (i, channel) in channels.iter_mut().enumerate().take(count) {
+            // A single digit stands for itself twice, as in `#f00`.
+            *channel = hex(i, len)? * if len == 1 { 17 } else { 1 };
+        }
+        let [r, g, b, a] = channels;
+        Some(css::Value::Color(css::Color { r, g, b, a }))
     }
 
     fn parse_identifier(&mut self) -> String {
         self.consume_while(valid_identifier_char)
     }
 
-    fn parse_node(&mut self) -> Node {
-        match self.next_char() {
-            '<' => self.parse_element(),
-            _ => self.parse_text(),
-        }
-    }
-
-    fn parse_element(&mut self) -> Node {
-        assert_eq!(self.consume_char(), '<');
-        let tag_name = self.parse_tag_name();
-        let attrs = self.parse_attrs();
-        assert_eq!(self.consume_char(), '>');
-        
-        let children = self.parse_nodes();
-
-        assert_eq!(self.consume_char(), '<');
-        assert_eq!(self.consume_char(), '/');
-        assert_eq!(self.parse_tag_name(), tag_name);
-        assert_eq!(self.consume_char(), '>');
-
-        node::elem(tag_name, attrs, children)
-    }
-
-    fn parse_attr(&mut self) -> (String, String) {
-        let name = self.parse_tag_name();
-        assert_eq!(self.consume_char(), '=');
-        let value = self.parse_attr_value();
-        (name, value)
+    /// An identifier that isn't empty.
+    fn parse_name(&mut self) -> Option<String> {
+        Some(self.parse_identifier()).filter(|name| !name.is_empty())
     }
 
-    fn parse_attr_value(&mut self) -> String {
-        let open_quote = self.consume_char();
-        assert!(open_quote == '"' || open_quote == '\'');
-        let value = self.consume_while(|c| c != open_quote);
-        assert_eq!(self.consume_char(), open_quote);
-        value
-    }
-
-    fn parse_attrs(&mut self) -> node::AttrMap {
-        let mut attrs = node::AttrMap::new();
-        loop {
-            self.consume_whitespace();
-            if self.next_char() == '>' {
-                break;
+    /// Skip input up to the first of `stops` outside any brackets, strings
+    /// and comments, leaving it unconsumed, or to the end of the input.
+    fn skip_until(&mut self, stops: &[char]) {
+        let mut closers = vec![];
+        while !self.eof() {
+            if self.starts_with("/*") {
+                self.consume_css_whitespace();
+                continue;
             }
-            let (name, value) = self.parse_attr();
-            attrs.insert(name, value);
-        }
-        attrs
-    }
-
-    fn parse_nodes(&mut self) -> Vec<Node> {
-        let mut nodes = Vec::new();
-        loop {
-            self.consume_whitespace();
-            if self.eof() || self.starts_with("</") {
-                break;
+            let c = self.next_char();
+            if closers.is_empty() && stops.contains(&c) { break }
+            match c {
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                '\\' => { self.consume_char(); }
+                '(' => closers.push(')'),
+                '[' => closers.push(']'),
+                '{' => closers.push('}'),
+                c if closers.last() == Some(&c) => { closers.pop(); }
+                _ => {}
             }
-            nodes.push(self.parse_node());
+            self.consume_char_or_eof();
         }
-        nodes
     }
 
-    fn parse_text(&mut self) -> Node {
-        node::text(self.consume_while(|c| c != '<'))
-    }
-
-    fn parse_tag_name(&mut self) -> String {
-        self.consume_while(char::is_alphanumeric)
+    /// Skip a `{}` block that starts here, if one does.
+    fn skip_block(&mut self) {
+        if self.starts_with("{") {
+            self.consume_char();
+            self.skip_until(&['}']);
+            self.consume_char_or_eof();
+        }
     }
 
     fn next_char(&self) -> char {
         self.input[self.pos..].chars().next().unwrap()
     }
 
+    fn next_char_or_eof(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
     fn consume_char(&mut self) -> char {
         let mut iter = self.input[self.pos..].char_indices();
         let (_, cur_char) = iter.next().unwrap();
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
+        let (next_pos, _) = iter.next().unwrap_or((cur_char.len_utf8(), ' '));
         self.pos += next_pos;
         cur_char
     }
 
+    fn consume_char_or_eof(&mut self) -> Option<char> {
+        if self.eof() { None } else { Some(self.consume_char()) }
+    }
+
     fn consume_while<F>(&mut self, test: F) -> String 
             where F: Fn(char) -> bool {
+        self.consume_while_max(usize::MAX, test)
+    }
+
+    /// Like `consume_while`, but stopping after `max` characters.
+    fn consume_while_max<F>(&mut self, max: usize, test: F) -> String
+            where F: Fn(char) -> bool {
         let mut res = String::new();
-        while !self.eof() && test(self.next_char()) {
+        while res.chars().count() < max && !self.eof() && test(self.next_char()) {
             res.push(self.consume_char());
         }
         res
@@ -248,6 +551,18 @@ impl Parser {
         self.consume_while(char::is_whitespace);
     }
 
+    /// Skip whitespace and `/* ... */` comments between CSS tokens.
+    fn consume_css_whitespace(&mut self) {
+        loop {
+            self.consume_whitespace();
+            if !self.starts_with("/*") { break }
+            match self.input[self.pos + 2..].find("*/") {
+                Some(end) => self.pos += end + 4,
+                None => self.pos = self.input.len(),
+            }
+        }
+    }
+
     fn starts_with(&self, s: &str) -> bool {
         self.input[self.pos..].starts_with(s)
     }
@@ -261,12 +576,97 @@ fn valid_identifier_char(c: char) -> bool {
     c.is_alphanumeric() || c == '-' || c == '_'
 }
 
-pub fn parse(input: String) -> Node {
-    let mut nodes = Parser::new(input).parse_nodes();
-    if nodes.len() == 1 {
-        nodes.swap_remove(0)
-    } else {
-        node::elem("html".to_string(), node::AttrMap::new(), nodes)
+pub fn parse_css(source: String) -> css::Stylesheet {
+    css::Stylesheet {
+        rules: Parser::new(source).parse_rules(),
+        origin: css::Origin::Author,
     }
 }
 
+/// Parse a comma-separated selector list, as taken by `querySelector`, or
+/// return `None` if it isn't valid or isn't supported.
+pub fn parse_selector_list(source: String) -> Option<Vec<css::Selector>> {
+    let mut parser = Parser::new(source);
+    parser.consume_css_whitespace();
+    parser.parse_selectors().filter(|_| parser.eof())
+}
+
+/// Parse the contents of a `style` attribute, which is a bare declaration
+/// list without selectors or braces.
+pub fn parse_inline_style(source: String) -> Vec<css::Declaration> {
+    Parser::new(source).parse_declaration_list()
+}
+
+pub fn parse(input: String) -> Node {
+    html::parse(&input)
+}
+
+pub fn parse_document(input: String) -> Node {
+    html::parse_document(&input)
+}
+
+/// Parse HTML from a reader as it is read, returning the document node.
+pub fn parse_reader<R: Read>(reader: R) -> io::Result<Node> {
+    html::parse_reader(reader)
+}
+
+
+/// Parse an XML or XHTML document, returning the document node or the first
+/// well-formedness error.
+pub fn parse_xml(input: String) -> Result<Node, xml::Error> {
+    xml::parse(&input)
+}
+
+#[cfg(test)]
+mod tests {
+    use css::{Color, Unit, Value};
+    use super::*;
+
+    /// The selectors' count and the declarations of each rule parsed from
+    /// `source`.
+    fn rules(source: &str) -> Vec<(usize, Vec<(String, Value)>)> {
+        parse_css(source.to_string()).rules.into_iter()
+            .map(|rule| (rule.selectors.len(),
+                         rule.declarations.into_iter().map(|d| (d.name, d.value)).collect()))
+            .collect()
+    }
+
+    fn px(n: f32) -> Value {
+        Value::Length(n, Unit::Px)
+    }
+
+    #[test]
+    fn invalid_declarations_are_dropped() {
+        assert_eq!(rules("p { width: 10px; height: 5foo; color: #12345; margin: 1px }"),
+                   [(1, vec![("width".to_string(), px(10.0)), ("margin".to_string(), px(1.0))])]);
+        assert_eq!(rules("p { width: (1px; 2px); ; height: 2px; top: } q { left: 3px"),
+                   [(1, vec![("height".to_string(), px(2.0))]), (1, vec![("left".to_string(), px(3.0))])]);
+    }
+
+    #[test]
+    fn invalid_rules_are_dropped() {
+        assert_eq!(rules("p!! { width: 1px } @media print { p { width: 2px } } a, b { width: 3px }"),
+                   [(2, vec![("width".to_string(), px(3.0))])]);
+        assert_eq!(rules("@import 'x.css'; p { width: 1pt }"), [(1, vec![("width".to_string(), px(4.0 / 3.0))])]);
+    }
+
+    #[test]
+    fn values() {
+        let color = |r, g, b, a| Value::Color(Color { r, g, b, a });
+        assert_eq!(rules("p { color: #f08; background: #11223380 }"),
+                   [(1, vec![("color".to_string(), color(0xff, 0, 0x88, 0xff)),
+                             ("background".to_string(), color(0x11, 0x22, 0x33, 0x80))])]);
+        let declarations = &parse_css("p { font-family: a, b !important }".to_string()).rules[0].declarations;
+        assert!(declarations[0].important);
+        assert_eq!(declarations[0].value, Value::List(vec![Value::Keyword("a".to_string()), Value::Comma,
+                                                           Value::Keyword("b".to_string())]));
+    }
+
+    #[test]
+    fn selector_lists() {
+        assert_eq!(parse_selector_list("p > a, .b".to_string()).map(|selectors| selectors.len()), Some(2));
+        assert!(parse_selector_list("p..a".to_string()).is_none());
+        assert!(parse_selector_list("a,".to_string()).is_none());
+        assert!(parse_selector_list("".to_string()).is_none());
+    }
+}
@@ -0,0 +1,318 @@
+//! Expansion of shorthand properties into the longhands they set, so that
+//! the cascade sees every declaration of a longhand, however it was
+//! written.
+
+use css::{Value, Unit};
+
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+const BORDER_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// The longhands a declaration sets, with their values. A longhand stands
+/// for itself, and a shorthand with a value it can't take sets nothing.
+pub fn expand(name: &str, value: &Value) -> Vec<(String, Value)> {
+    let parts = components(value);
+    let longhands = match name {
+        "margin" | "padding" => sides(&parts, |side| format!("{}-{}", name, side)),
+        "border-width" | "border-style" | "border-color" => {
+            let property = &name["border-".len()..];
+            sides(&parts, |side| format!("border-{}-{}", side, property))
+        }
+        "border" => border(&parts, &SIDES),
+        "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            border(&parts, &[&name["border-".len()..]])
+        }
+        "list-style" => list_style(&parts),
+        "gap" | "grid-gap" => match parts[..] {
+            [ref gap] => Some(vec![("row-gap".to_string(), gap.clone()), ("column-gap".to_string(), gap.clone())]),
+            [ref row, ref column] => {
+                Some(vec![("row-gap".to_string(), row.clone()), ("column-gap".to_string(), column.clone())])
+            }
+            _ => None,
+        },
+        "flex" => flex(&parts),
+        "flex-flow" => flex_flow(&parts),
+        "grid-row" | "grid-column" => grid_lines(value).and_then(|lines| match lines[..] {
+            [ref start] => Some(vec![(format!("{}-start", name), start.clone()),
+                                     (format!("{}-end", name), same_area(start))]),
+            [ref start, ref end] => Some(vec![(format!("{}-start", name), start.clone()),
+                                              (format!("{}-end", name), end.clone())]),
+            _ => None,
+        }),
+        "grid-area" => grid_area(value),
+        _ => Some(vec![(name.to_string(), value.clone())]),
+    };
+    longhands.unwrap_or_default()
+}
+
+/// The components of a value separated by whitespace.
+fn components(value: &Value) -> Vec<Value> {
+    match *value {
+        Value::List(ref values) => values.clone(),
+        ref value => vec![value.clone()],
+    }
+}
+
+/// The four sides of a box from one to four values, in the order top,
+/// right, bottom, left, where a missing side takes the value of the side
+/// opposite.
+fn sides<F: Fn(&str) -> String>(parts: &[Value], name: F) -> Option<Vec<(String, Value)>> {
+    let indices = match parts.len() {
+        1 => [0, 0, 0, 0],
+        2 => [0, 1, 0, 1],
+        3 => [0, 1, 2, 1],
+        4 => [0, 1, 2, 3],
+        _ => return None,
+    };
+    if parts.iter().any(|part| matches!(*part, Value::Slash | Value::Comma)) {
+        return None;
+    }
+    Some(SIDES.iter().zip(&indices).map(|(side, &i)| (name(side), parts[i].clone())).collect())
+}
+
+/// `border` or a side's `border-*`: a width, a style and a color, in any
+/// order, each optional. A border with a style but no width is `medium`;
+/// without a style there is no border, so its width is zero.
+fn border(parts: &[Value], sides: &[&str]) -> Option<Vec<(String, Value)>> {
+    let (mut width, mut style, mut color) = (None, None, None);
+    for part in parts {
+        match *part {
+            Value::Length(..) | Value::Number(_) if width.is_none() => width = Some(part.clone()),
+            Value::Keyword(ref k) if width.is_none() && border_width_keyword(k).is_some() => {
+                width = Some(part.clone())
+            }
+            Value::Keyword(ref k) if style.is_none() && BORDER_STYLES.contains(&&**k) => style = Some(part.clone()),
+            Value::Keyword(_) | Value::Color(_) | Value::Function(..) if color.is_none() => color = Some(part.clone()),
+            _ => return None,
+        }
+    }
+    let visible = match style {
+        Some(Value::Keyword(ref k)) => k != "none" && k != "hidden",
+        _ => false,
+    };
+    let width = match width {
+        Some(width) => width,
+        None if visible => Value::Keyword("medium".to_string()),
+        None => Value::Length(0.0, Unit::Px),
+    };
+    let width = match width {
+        Value::Keyword(ref k) => Value::Length(border_width_keyword(k).unwrap(), Unit::Px),
+        width => width,
+    };
+    let style = style.unwrap_or(Value::Keyword("none".to_string()));
+    let color = color.unwrap_or(Value::Keyword("currentcolor".to_string()));
+    let mut longhands = vec![];
+    for side in sides {
+        longhands.push((format!("border-{}-width", side), width.clone()));
+        longhands.push((format!("border-{}-style", side), style.clone()));
+        longhands.push((format!("border-{}-color", side), color.clone()));
+    }
+    Some(longhands)
+}
+
+fn border_width_keyword(keyword: &str) -> Option<f32> {
+    match keyword {
+        "thin" => Some(1.0),
+        "medium" => Some(3.0),
+        "thick" => Some(5.0),
+        _ => None,
+    }
+}
+
+/// `list-style`: a position, an image and a type, in any order. A `none`
+/// that isn't needed for the image sets the type.
+fn list_style(parts: &[Value]) -> Option<Vec<(String, Value)>> {
+    let (mut position, mut image, mut style_type) = (None, None, None);
+    let mut nones = 0;
+    for part in parts {
+        match *part {
+            Value::Keyword(ref k) if k == "none" => nones += 1,
+            Value::Keyword(ref k) if position.is_none() && (k == "inside" || k == "outside") => {
+                position = Some(part.clone())
+            }
+            Value::Function(ref f, _) if image.is_none() && f.eq_ignore_ascii_case("url") => image = Some(part.clone()),
+            Value::Keyword(_) | Value::String(_) if style_type.is_none() => style_type = Some(part.clone()),
+            _ => return None,
+        }
+    }
+    let none = || Some(Value::Keyword("none".to_string()));
+    match (nones, image.is_none(), style_type.is_none()) {
+        (0, _, _) => {}
+        (1, true, true) | (2, true, true) => {
+            image = none();
+            style_type = none();
+        }
+        (1, true, false) => image = none(),
+        (1, false, true) => style_type = none(),
+        _ => return None,
+    }
+    Some(vec![
+        ("list-style-position".to_string(), position.unwrap_or(Value::Keyword("outside".to_string()))),
+        ("list-style-image".to_string(), image.unwrap_or(Value::Keyword("none".to_string()))),
+        ("list-style-type".to_string(), style_type.unwrap_or(Value::Keyword("disc".to_string()))),
+    ])
+}
+
+/// `flex`: `none`, `auto`, or a grow factor, a shrink factor and a basis,
+/// where a missing factor is 1 and a missing basis after a factor is 0.
+fn flex(parts: &[Value]) -> Option<Vec<(String, Value)>> {
+    let auto = Value::Keyword("auto".to_string());
+    let (grow, shrink, basis) = match parts {
+        [Value::Keyword(ref k)] if k == "none" => (0.0, 0.0, auto),
+        [Value::Keyword(ref k)] if k == "auto" => (1.0, 1.0, auto),
+        [Value::Keyword(ref k)] if k == "initial" => (0.0, 1.0, auto),
+        _ => {
+            let mut factors = vec![];
+            let mut basis = None;
+            for part in parts {
+                match *part {
+                    Value::Number(n) if basis.is_none() && factors.len() < 2 => factors.push(n),
+                    // A basis of zero may be written as a plain number.
+                    Value::Number(n) if n == 0.0 && basis.is_none() => basis = Some(Value::Length(0.0, Unit::Px)),
+                    Value::Length(..) | Value::Percentage(_) if basis.is_none() => basis = Some(part.clone()),
+                    Value::Keyword(ref k) if basis.is_none() && (k == "auto" || k == "content") => {
+                        basis = Some(part.clone())
+                    }
+                    _ => return None,
+                }
+            }
+            if factors.is_empty() && basis.is_none() {
+                return None;
+            }
+            let basis = match basis {
+                Some(basis) => basis,
+                None => Value::Length(0.0, Unit::Px),
+            };
+            let grow = factors.first().cloned().unwrap_or(1.0);
+            let shrink = factors.get(1).cloned().unwrap_or(1.0);
+            (grow, shrink, basis)
+        }
+    };
+    Some(vec![
+        ("flex-grow".to_string(), Value::Number(grow)),
+        ("flex-shrink".to_string(), Value::Number(shrink)),
+        ("flex-basis".to_string(), basis),
+    ])
+}
+
+/// `flex-flow`: a direction and a wrap, in either order, each optional.
+fn flex_flow(parts: &[Value]) -> Option<Vec<(String, Value)>> {
+    let (mut direction, mut wrap) = (None, None);
+    for part in parts {
+        match *part {
+            Value::Keyword(ref k) if direction.is_none() && (k.starts_with("row") || k.starts_with("column")) => {
+                direction = Some(part.clone())
+            }
+            Value::Keyword(ref k) if wrap.is_none() && (k == "wrap" || k == "nowrap" || k == "wrap-reverse") => {
+                wrap = Some(part.clone())
+            }
+            _ => return None,
+        }
+    }
+    Some(vec![
+        ("flex-direction".to_string(), direction.unwrap_or(Value::Keyword("row".to_string()))),
+        ("flex-wrap".to_string(), wrap.unwrap_or(Value::Keyword("nowrap".to_string()))),
+    ])
+}
+
+/// The grid lines of a value like `1 / span 2`, between the slashes.
+fn grid_lines(value: &Value) -> Option<Vec<Value>> {
+    let parts: Vec<Value> = components(value).split(|value| *value == Value::Slash)
+        .map(|part| if part.len() == 1 { part[0].clone() } else { Value::List(part.to_vec()) })
+        .collect();
+    if parts.iter().any(|part| *part == Value::List(vec![])) {
+        return None;
+    }
+    Some(parts)
+}
+
+/// The line a shorthand leaves out after `line`: the same area if `line`
+/// names one, and otherwise `auto`.
+fn same_area(line: &Value) -> Value {
+    match *line {
+        Value::Keyword(ref k) if k != "auto" && k != "span" => line.clone(),
+        _ => Value::Keyword("auto".to_string()),
+    }
+}
+
+/// `grid-area`: the row start, column start, row end and column end lines,
+/// where a missing one is taken from the area the one before it names.
+fn grid_area(value: &Value) -> Option<Vec<(String, Value)>> {
+    let lines = grid_lines(value)?;
+    if lines.len() > 4 {
+        return None;
+    }
+    let row_start = lines[0].clone();
+    let column_start = lines.get(1).cloned().unwrap_or_else(|| same_area(&row_start));
+    let row_end = lines.get(2).cloned().unwrap_or_else(|| same_area(&row_start));
+    let column_end = lines.get(3).cloned().unwrap_or_else(|| same_area(&column_start));
+    Some(vec![
+        ("grid-row-start".to_string(), row_start),
+        ("grid-column-start".to_string(), column_start),
+        ("grid-row-end".to_string(), row_end),
+        ("grid-column-end".to_string(), column_end),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use css::{Value, Unit};
+    use super::expand;
+
+    fn px(n: f32) -> Value {
+        Value::Length(n, Unit::Px)
+    }
+
+    fn keyword(k: &str) -> Value {
+        Value::Keyword(k.to_string())
+    }
+
+    /// The longhands `name: value` sets, by name.
+    fn longhands(name: &str, value: Value) -> Vec<(String, Value)> {
+        expand(name, &value)
+    }
+
+    #[test]
+    fn sides() {
+        let set = longhands("margin", Value::List(vec![px(1.0), px(2.0), px(3.0)]));
+        assert_eq!(set, [("margin-top".to_string(), px(1.0)), ("margin-right".to_string(), px(2.0)),
+                         ("margin-bottom".to_string(), px(3.0)), ("margin-left".to_string(), px(2.0))]);
+        assert!(longhands("padding", Value::List(vec![px(1.0); 5])).is_empty());
+    }
+
+    #[test]
+    fn border() {
+        let set = longhands("border-top", Value::List(vec![keyword("solid"), keyword("red")]));
+        assert_eq!(set, [("border-top-width".to_string(), px(3.0)), ("border-top-style".to_string(), keyword("solid")),
+                         ("border-top-color".to_string(), keyword("red"))]);
+        let set = longhands("border", keyword("none"));
+        assert_eq!(set.len(), 12);
+        assert_eq!(set[0], ("border-top-width".to_string(), px(0.0)));
+    }
+
+    #[test]
+    fn flex() {
+        let factors = |value| longhands("flex", value).into_iter().map(|(_, value)| value).collect::<Vec<_>>();
+        assert_eq!(factors(keyword("none")), [Value::Number(0.0), Value::Number(0.0), keyword("auto")]);
+        assert_eq!(factors(Value::Number(2.0)), [Value::Number(2.0), Value::Number(1.0), px(0.0)]);
+        assert_eq!(factors(Value::List(vec![Value::Number(1.0), px(10.0)])),
+                   [Value::Number(1.0), Value::Number(1.0), px(10.0)]);
+    }
+
+    #[test]
+    fn grid_area() {
+        let set = longhands("grid-area", keyword("main"));
+        assert!(set.iter().all(|(_, value)| *value == keyword("main")));
+        let set = longhands("grid-row", Value::List(vec![Value::Number(1.0), Value::Slash,
+                                                         Value::List(vec![keyword("span"), Value::Number(2.0)])]));
+        assert_eq!(set, [("grid-row-start".to_string(), Value::Number(1.0)),
+                         ("grid-row-end".to_string(), Value::List(vec![keyword("span"), Value::Number(2.0)]))]);
+    }
+
+    #[test]
+    fn longhands_stand_for_themselves() {
+        assert_eq!(longhands("width", px(1.0)), [("width".to_string(), px(1.0))]);
+    }
+}
@@ -1,20 +1,132 @@
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use css::{Value, Stylesheet, matching_rules};
+use css::{self, Value, Unit, Origin, Stylesheet, matching_rules};
 use node::{Node, NodeType, ElementData};
+use parser;
+use shorthands;
 
 pub type PropertyMap = HashMap<String, Value>;
 
+/// Properties whose value is taken from the parent when not specified.
+const INHERITED_PROPERTIES: &[&str] = &[
+    "color", "font-family", "font-size", "font-style", "font-weight",
+    "line-height", "list-style-image", "list-style-position", "list-style-type", "text-align", "visibility",
+    "white-space",
+];
+
+/// The computed `font-size` of the root element's parent, i.e. `medium`.
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// The built-in user-agent stylesheet, modeled on the rendering section of
+/// the HTML specification.
+const USER_AGENT_CSS: &str = include_str!("ua.css");
+
 pub struct StyledNode<'a> {
     pub node: &'a Node,
     pub specified_values: PropertyMap,
     pub children: Vec<StyledNode<'a>>,
+    /// The style of a list item's `::marker`, which inherits from it.
+    pub marker: Option<Box<StyledNode<'a>>>,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Display {
     Inline,
     Block,
+    InlineBlock,
+    ListItem,
+    Flex,
+    InlineFlex,
+    Grid,
+    InlineGrid,
+    Table,
+    InlineTable,
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableColumnGroup,
+    TableColumn,
+    TableCaption,
+    None,
+}
+
+/// The `white-space` property, which controls whether spaces and newlines
+/// in text are collapsed and whether lines wrap.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WhiteSpace {
+    Normal,
+    Pre,
+    Nowrap,
+    PreWrap,
+    PreLine,
+    BreakSpaces,
+}
+
+impl WhiteSpace {
+    /// Whether runs of spaces and tabs collapse to a single space.
+    pub fn collapses_spaces(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine)
+    }
+
+    /// Whether newlines force a line break rather than acting as spaces.
+    pub fn preserves_newlines(self) -> bool {
+        self != WhiteSpace::Normal && self != WhiteSpace::Nowrap
+    }
+
+    /// Whether lines may wrap at spaces.
+    pub fn wraps(self) -> bool {
+        self != WhiteSpace::Nowrap && self != WhiteSpace::Pre
+    }
+}
+
+/// The `position` property.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+impl Position {
+    /// Whether the box is taken out of normal flow and placed against its
+    /// containing block.
+    pub fn is_absolute(self) -> bool {
+        self == Position::Absolute || self == Position::Fixed
+    }
+}
+
+/// The `float` property.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Float {
     None,
+    Left,
+    Right,
+}
+
+/// The `clear` property: which floats a block must be placed below.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Clear {
+    None,
+    Left,
+    Right,
+    Both,
+}
+
+impl Clear {
+    /// Whether floats on `side` are cleared.
+    pub fn clears(self, side: Float) -> bool {
+        match side {
+            Float::Left => self == Clear::Left || self == Clear::Both,
+            Float::Right => self == Clear::Right || self == Clear::Both,
+            Float::None => false,
+        }
+    }
 }
 
 impl<'a> StyledNode<'a> {
@@ -23,9 +135,29 @@ impl<'a> StyledNode<'a> {
     }
 
     pub fn display(&self) -> Display {
+        match self.node.node_type {
+            NodeType::Element(_) | NodeType::Text(_) => {}
+            _ => return Display::None,
+        }
         match self.value("display") {
             Some(Value::Keyword(s)) => match s.as_ref() {
-                "block" => Display::Block,
+                "block" | "flow-root" => Display::Block,
+                "inline-block" => Display::InlineBlock,
+                "list-item" => Display::ListItem,
+                "flex" => Display::Flex,
+                "inline-flex" => Display::InlineFlex,
+                "grid" => Display::Grid,
+                "inline-grid" => Display::InlineGrid,
+                "table" => Display::Table,
+                "inline-table" => Display::InlineTable,
+                "table-row-group" => Display::TableRowGroup,
+                "table-header-group" => Display::TableHeaderGroup,
+                "table-footer-group" => Display::TableFooterGroup,
+                "table-row" => Display::TableRow,
+                "table-cell" => Display::TableCell,
+                "table-column-group" => Display::TableColumnGroup,
+                "table-column" => Display::TableColumn,
+                "table-caption" => Display::TableCaption,
                 "none" => Display::None,
                 _ => Display::Inline,
             },
@@ -33,32 +165,470 @@ impl<'a> StyledNode<'a> {
         }
     }
 
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name).or(self.value(fallback_name)).unwrap_or(default.clone())
+    pub fn white_space(&self) -> WhiteSpace {
+        match self.value("white-space") {
+            Some(Value::Keyword(s)) => match s.as_ref() {
+                "pre" => WhiteSpace::Pre,
+                "nowrap" => WhiteSpace::Nowrap,
+                "pre-wrap" => WhiteSpace::PreWrap,
+                "pre-line" => WhiteSpace::PreLine,
+                "break-spaces" => WhiteSpace::BreakSpaces,
+                _ => WhiteSpace::Normal,
+            },
+            _ => WhiteSpace::Normal,
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        match self.value("position") {
+            Some(Value::Keyword(s)) => match s.as_ref() {
+                "relative" => Position::Relative,
+                "absolute" => Position::Absolute,
+                "fixed" => Position::Fixed,
+                "sticky" => Position::Sticky,
+                _ => Position::Static,
+            },
+            _ => Position::Static,
+        }
+    }
+
+    /// The `float` property. Absolutely positioned boxes don't float.
+    pub fn float(&self) -> Float {
+        if self.position().is_absolute() {
+            return Float::None;
+        }
+        match self.value("float") {
+            Some(Value::Keyword(s)) => match s.as_ref() {
+                "left" => Float::Left,
+                "right" => Float::Right,
+                _ => Float::None,
+            },
+            _ => Float::None,
+        }
+    }
+
+    pub fn clear(&self) -> Clear {
+        match self.value("clear") {
+            Some(Value::Keyword(s)) => match s.as_ref() {
+                "left" => Clear::Left,
+                "right" => Clear::Right,
+                "both" => Clear::Both,
+                _ => Clear::None,
+            },
+            _ => Clear::None,
+        }
+    }
+
+    /// Whether the element's box is taken out of normal flow, by floating
+    /// or absolute positioning.
+    pub fn is_out_of_flow(&self) -> bool {
+        self.position().is_absolute() || self.float() != Float::None
+    }
+
+    /// Whether the element's box is a block formatting context root that
+    /// contains its floats and keeps its margins apart from its children's:
+    /// floats, absolutely positioned boxes, boxes with `overflow` other than
+    /// `visible`, inline blocks and `display: flow-root`.
+    pub fn establishes_bfc(&self) -> bool {
+        let keyword = |name: &str| match self.value(name) {
+            Some(Value::Keyword(s)) => s,
+            _ => String::new(),
+        };
+        self.float() != Float::None
+            || self.position().is_absolute()
+            || matches!(&*keyword("display"), "flow-root" | "inline-block")
+            || !matches!(&*keyword("overflow"), "" | "visible" | "clip")
+    }
+
+    /// The computed font size in pixels.
+    pub fn font_size(&self) -> f32 {
+        match self.value("font-size") {
+            Some(Value::Length(size, Unit::Px)) => size,
+            _ => DEFAULT_FONT_SIZE,
+        }
+    }
+
+    /// The used line height in pixels. `normal` is taken to be 1.2 times the
+    /// font size.
+    pub fn line_height(&self) -> f32 {
+        match self.value("line-height") {
+            Some(Value::Length(height, Unit::Px)) => height,
+            Some(Value::Number(factor)) => factor * self.font_size(),
+            _ => 1.2 * self.font_size(),
+        }
+    }
+
+    pub fn lookup(&self, name: &str, default: &Value) -> Value {
+        self.value(name).unwrap_or(default.clone())
     }
 }
 
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+/// An element in the tree being styled, linked to its parent so selectors
+/// with combinators can look at the elements around it.
+#[derive(Clone, Copy)]
+struct TreeElement<'a, 'b> {
+    node: &'a Node,
+    elem: &'a ElementData,
+    parent: Option<&'b TreeElement<'a, 'b>>,
+    /// The position of `node` among its parent's children.
+    index: usize,
+}
+
+impl<'a, 'b> css::Element<'a> for TreeElement<'a, 'b> {
+    fn data(self) -> &'a ElementData {
+        self.elem
+    }
+
+    fn parent_element(self) -> Option<Self> {
+        self.parent.cloned()
+    }
+
+    fn previous_element_sibling(self) -> Option<Self> {
+        let parent = self.parent?;
+        parent.node.children[..self.index].iter().enumerate().rev()
+            .find_map(|(index, node)| match node.node_type {
+                NodeType::Element(ref elem) => Some(TreeElement { node, elem, parent: self.parent, index }),
+                _ => None,
+            })
+    }
+}
+
+/// The declared values for an element, or for its pseudo-element if
+/// `pseudo_element` is given.
+fn specified_values(element: TreeElement, stylesheets: &[Stylesheet], pseudo_element: Option<&str>) -> PropertyMap {
+    let elem = element.elem;
+    let inline = match (elem.attribute("style"), pseudo_element) {
+        (Some(style), None) => parser::parse_inline_style(style.to_string()),
+        _ => vec![],
+    };
+    let hints = match pseudo_element {
+        None => presentational_hints(elem),
+        Some(_) => vec![],
+    };
+    let mut declarations: Vec<_> = hints.iter()
+        .map(|declaration| (precedence(declaration, Origin::Author, false, (0, 0, 0)), declaration))
+        .chain(stylesheets.iter().flat_map(|stylesheet| {
+            matching_rules(element, stylesheet, pseudo_element).into_iter()
+                .flat_map(move |(specificity, rule)| {
+                    rule.declarations.iter().map(move |declaration| {
+                        (precedence(declaration, stylesheet.origin, false, specificity), declaration)
+                    })
+                })
+        }))
+        .chain(inline.iter().map(|declaration| {
+            (precedence(declaration, Origin::Author, true, (0, 0, 0)), declaration)
+        }))
+        .collect();
+
+    // The sort is stable, so declarations of equal precedence keep source
+    // order and the last one wins. A shorthand counts as a declaration of
+    // each of its longhands.
+    declarations.sort_by_key(|&(precedence, _)| precedence);
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    for (_, declaration) in declarations {
+        values.extend(shorthands::expand(&declaration.name, &declaration.value));
+    }
+    values
+}
+
+/// The elements whose `width` and `height` attributes give their size.
+const SIZED_ELEMENTS: [&str; 5] = ["img", "video", "iframe", "embed", "object"];
+
+/// The declarations HTML maps an element's attributes to. They come first
+/// among the author's declarations with no specificity, so any rule wins
+/// over them.
+fn presentational_hints(elem: &ElementData) -> Vec<css::Declaration> {
+    if !elem.is_html() || !SIZED_ELEMENTS.contains(&&*elem.tag_name) {
+        return vec![];
+    }
+    ["width", "height"].iter()
+        .filter_map(|&name| {
+            let value = parse_dimension(elem.attribute(name)?)?;
+            Some(css::Declaration { name: name.to_string(), value, important: false })
+        })
+        .collect()
+}
 
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+/// Parse an attribute as HTML's dimension values: a number of pixels or a
+/// percentage, ignoring anything after it, as in `width="20px"`.
+fn parse_dimension(value: &str) -> Option<Value> {
+    let value = value.trim_start();
+    let end = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let number = value[..end].trim_end_matches('.').parse::<f32>().ok()?;
+    if value[end..].starts_with('%') {
+        Some(Value::Percentage(number))
+    } else {
+        Some(Value::Length(number, Unit::Px))
+    }
+}
+
+/// Where a declaration comes in the cascade, lowest first. Important
+/// declarations win over normal ones, and between origins the order is
+/// reversed for them. Within an origin, declarations in the style
+/// attribute win over those of every matched rule.
+fn precedence(declaration: &css::Declaration, origin: Origin, inline: bool,
+              specificity: css::Specificity) -> (bool, i8, bool, css::Specificity) {
+    let origin = if declaration.important { -(origin as i8) } else { origin as i8 };
+    (declaration.important, origin, inline, specificity)
+}
+
+/// Fill in inherited properties from the parent and resolve `em` lengths
+/// against the element's font size, so that layout only sees pixels. A
+/// percentage font size is of the parent's.
+fn compute_values(mut values: PropertyMap, parent: Option<&PropertyMap>) -> PropertyMap {
+    if let Some(parent) = parent {
+        for name in INHERITED_PROPERTIES {
+            if !values.contains_key(*name) {
+                if let Some(value) = parent.get(*name) {
+                    values.insert(name.to_string(), value.clone());
+                }
+            }
         }
     }
+
+    let parent_font_size = match parent.and_then(|p| p.get("font-size")) {
+        Some(&Value::Length(size, Unit::Px)) => size,
+        _ => DEFAULT_FONT_SIZE,
+    };
+    let font_size = match values.get("font-size") {
+        Some(&Value::Length(size, Unit::Px)) => size,
+        Some(&Value::Length(size, Unit::Em)) => size * parent_font_size,
+        Some(&Value::Percentage(p)) => p * parent_font_size / 100.0,
+        Some(Value::Keyword(keyword)) => font_size_keyword(keyword, parent_font_size),
+        _ => parent_font_size,
+    };
+    values.insert("font-size".to_string(), Value::Length(font_size, Unit::Px));
+
+    for value in values.values_mut() {
+        resolve_em(value, font_size);
+    }
     values
 }
 
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
+/// Convert the em lengths in `value`, including those within lists and
+/// function arguments, to pixels.
+fn resolve_em(value: &mut Value, font_size: f32) {
+    match *value {
+        Value::Length(size, Unit::Em) => *value = Value::Length(size * font_size, Unit::Px),
+        Value::List(ref mut values) | Value::Function(_, ref mut values) => {
+            for value in values {
+                resolve_em(value, font_size);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn font_size_keyword(keyword: &str, parent_font_size: f32) -> f32 {
+    let scale = match keyword {
+        "larger" => return parent_font_size * 1.2,
+        "smaller" => return parent_font_size / 1.2,
+        "xx-small" => 3.0 / 5.0,
+        "x-small" => 3.0 / 4.0,
+        "small" => 8.0 / 9.0,
+        "medium" => 1.0,
+        "large" => 6.0 / 5.0,
+        "x-large" => 3.0 / 2.0,
+        "xx-large" => 2.0,
+        _ => return parent_font_size,
+    };
+    DEFAULT_FONT_SIZE * scale
+}
+
+pub fn style_tree<'a>(root: &'a Node, stylesheets: &[Stylesheet]) -> StyledNode<'a> {
+    style_node(root, 0, None, stylesheets, None)
+}
+
+/// Style `node`, the child at `index` of the element `parent_element`.
+fn style_node<'a>(node: &'a Node, index: usize, parent_element: Option<&TreeElement<'a, '_>>,
+                  stylesheets: &[Stylesheet], parent: Option<&PropertyMap>) -> StyledNode<'a> {
+    let element = match node.node_type {
+        NodeType::Element(ref elem) => Some(TreeElement { node, elem, parent: parent_element, index }),
+        _ => None,
+    };
+    let specified = match element {
+        Some(element) => specified_values(element, stylesheets, None),
+        None => HashMap::new(),
+    };
+    let values = compute_values(specified, parent);
+    let marker = match element {
+        Some(element) if values.get("display") == Some(&Value::Keyword("list-item".to_string())) => {
+            Some(Box::new(StyledNode {
+                node,
+                specified_values: compute_values(specified_values(element, stylesheets, Some("marker")), Some(&values)),
+                children: vec![],
+                marker: None,
+            }))
+        }
+        _ => None,
+    };
     StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
-        },
-        children: root.children.iter().map(|child| style_tree(child, stylesheet)).collect(),
+        node,
+        children: node.children.iter().enumerate()
+            .map(|(index, child)| style_node(child, index, element.as_ref(), stylesheets, Some(&values)))
+            .collect(),
+        specified_values: values,
+        marker,
+    }
+}
+
+/// Style a document using the user-agent stylesheet followed by the
+/// stylesheets the document embeds or links to. Relative `href`s are
+/// resolved against `base_dir`.
+pub fn style_document<'a>(root: &'a Node, base_dir: &Path) -> StyledNode<'a> {
+    let mut stylesheets = vec![user_agent_stylesheet()];
+    stylesheets.extend(author_stylesheets(root, base_dir));
+    style_tree(root, &stylesheets)
+}
+
+pub fn user_agent_stylesheet() -> Stylesheet {
+    let mut stylesheet = parser::parse_css(USER_AGENT_CSS.to_string());
+    stylesheet.origin = Origin::UserAgent;
+    stylesheet
+}
+
+/// Collect the author stylesheets of a document in document order: the
+/// contents of every `<style>` element and every local file referenced by a
+/// `<link rel=stylesheet href=...>`. Links that can't be read are skipped.
+pub fn author_stylesheets(root: &Node, base_dir: &Path) -> Vec<Stylesheet> {
+    let mut stylesheets = vec![];
+    collect_stylesheets(root, base_dir, &mut stylesheets);
+    stylesheets
+}
+
+fn collect_stylesheets(node: &Node, base_dir: &Path, stylesheets: &mut Vec<Stylesheet>) {
+    if let NodeType::Element(ref elem) = node.node_type {
+        match &*elem.tag_name {
+            "style" => {
+                stylesheets.push(parser::parse_css(text_content(node)));
+                return;
+            }
+            "link" if is_stylesheet_link(elem) => {
+                let source = elem.attribute("href")
+                    .and_then(|href| local_path(href, base_dir))
+                    .and_then(|path| fs::read_to_string(path).ok());
+                if let Some(source) = source {
+                    stylesheets.push(parser::parse_css(source));
+                }
+            }
+            _ => {}
+        }
+    }
+    for child in &node.children {
+        collect_stylesheets(child, base_dir, stylesheets);
     }
 }
 
+fn is_stylesheet_link(elem: &ElementData) -> bool {
+    match elem.attribute("rel") {
+        Some(rel) => rel.split_whitespace().any(|t| t.eq_ignore_ascii_case("stylesheet")),
+        None => false,
+    }
+}
+
+/// Map an `href` to a file on disk. Only relative paths and `file://` URLs
+/// are local; anything with another scheme is ignored.
+fn local_path(href: &str, base_dir: &Path) -> Option<PathBuf> {
+    if let Some(path) = href.strip_prefix("file://") {
+        Some(PathBuf::from(path))
+    } else if href.contains("://") {
+        None
+    } else {
+        Some(base_dir.join(href))
+    }
+}
+
+fn text_content(node: &Node) -> String {
+    match node.node_type {
+        NodeType::Text(ref text) => text.clone(),
+        NodeType::Element(_) | NodeType::Document(_) => {
+            node.children.iter().map(text_content).collect()
+        }
+        NodeType::Comment(_) | NodeType::Doctype(_) | NodeType::ProcessingInstruction(_) => String::new(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use css::{Unit, Value};
+    use html;
+    use parser;
+    use super::*;
+
+    /// The style of the first element called `tag` in `html`, styled by
+    /// the user-agent stylesheet and `css`, passed to `inspect`.
+    fn with_style<T, F: FnOnce(&StyledNode) -> T>(html: &str, css: &str, tag: &str, inspect: F) -> T {
+        fn find<'a, 'b>(styled: &'b StyledNode<'a>, tag: &str) -> Option<&'b StyledNode<'a>> {
+            match styled.node.node_type {
+                NodeType::Element(ref elem) if elem.tag_name == tag => Some(styled),
+                _ => styled.children.iter().filter_map(|child| find(child, tag)).next(),
+            }
+        }
+        let root = html::parse(html);
+        let styled = style_tree(&root, &[user_agent_stylesheet(), parser::parse_css(css.to_string())]);
+        inspect(find(&styled, tag).expect("no such element"))
+    }
+
+    fn value(html: &str, css: &str, tag: &str, name: &str) -> Option<Value> {
+        with_style(html, css, tag, |styled| styled.value(name))
+    }
+
+    /// The value of `name` on the first `p` of `html` styled by `css`.
+    fn p_value(html: &str, css: &str, name: &str) -> Option<Value> {
+        value(html, css, "p", name)
+    }
+
+    #[test]
+    fn shorthands_and_longhands_cascade_in_order() {
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(p_value("<p>", "p { margin: 1px; margin-top: 2px }", "margin-top"), px(2.0));
+        assert_eq!(p_value("<p>", "p { margin-top: 2px; margin: 1px }", "margin-top"), px(1.0));
+        assert_eq!(p_value("<p>", "p { margin-top: 2px } p { margin: 1px }", "margin-top"), px(1.0));
+        assert_eq!(p_value("<p>", "p { margin-top: 2px } p { margin: 1px 2px 3px 4px 5px }", "margin-top"), px(2.0));
+    }
+
+    #[test]
+    fn important_declarations_win() {
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(p_value("<p style='width: 1px'>", "p { width: 2px }", "width"), px(1.0));
+        assert_eq!(p_value("<p style='width: 1px'>", "p { width: 2px !important }", "width"), px(2.0));
+        assert_eq!(p_value("<p id=a>", "p { width: 2px !important } #a { width: 3px }", "width"), px(2.0));
+    }
+
+    #[test]
+    fn font_sizes() {
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(value("<div><p>", "div { font-size: 20px } p { font-size: 150% }", "p", "font-size"), px(30.0));
+        assert_eq!(value("<div><p>", "div { font-size: 20px } p { font-size: 2em }", "p", "font-size"), px(40.0));
+        assert_eq!(value("<div><p>", "div { font-size: 20px } p { font-size: larger }", "p", "font-size"), px(24.0));
+        // Lengths in ems are of the element's own font size.
+        assert_eq!(p_value("<p>", "p { font-size: 50%; width: 2em }", "width"), px(16.0));
+    }
+
+    #[test]
+    fn user_agent_stylesheet_defaults() {
+        let display = |tag| with_style("<title>t</title><script></script><p>", "", tag, |styled| styled.display());
+        assert_eq!(display("head"), Display::None);
+        assert_eq!(display("script"), Display::None);
+        assert_eq!(display("p"), Display::Block);
+        assert_eq!(display("body"), Display::Block);
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(p_value("<p>", "", "margin-top"), px(16.0));
+        assert_eq!(value("<h1>", "", "h1", "font-size"), px(32.0));
+        assert_eq!(value("<h1>", "", "h1", "margin-bottom"), px(0.67 * 32.0));
+        assert_eq!(value("<body>", "", "body", "margin-left"), px(8.0));
+    }
+
+    #[test]
+    fn presentational_hints() {
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(value("<img width=20 height=' 10.5px'>", "", "img", "width"), px(20.0));
+        assert_eq!(value("<img width=20 height=' 10.5px'>", "", "img", "height"), px(10.5));
+        assert_eq!(value("<iframe width=50%></iframe>", "", "iframe", "width"), Some(Value::Percentage(50.0)));
+        assert_eq!(value("<img width=x>", "", "img", "width"), None);
+        // Every author rule wins over them, however unspecific.
+        assert_eq!(value("<img width=20>", "* { width: 5px }", "img", "width"), px(5.0));
+        assert_eq!(value("<div width=20></div>", "", "div", "width"), None);
+    }
+}
@@ -0,0 +1,825 @@
+//! A parser for XML documents, including XHTML, producing the same `Node`
+//! trees as the HTML parser.
+//!
+//! Unlike HTML, XML isn't error-tolerant: the first well-formedness error
+//! stops parsing and is returned with its position.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+
+use html::entities::ENTITIES;
+use node::{self, Node, NodeType, ElementData, DoctypeData, ProcessingInstructionData};
+use node::{AttrMap, Namespace, QuirksMode};
+
+/// How deeply entity references may nest in replacement text.
+const MAX_ENTITY_DEPTH: usize = 16;
+
+/// How much replacement text, in bytes, a document may expand to in all,
+/// so that a few nested entities can't blow up into gigabytes.
+const MAX_EXPANSION: usize = 1 << 20;
+
+/// How deeply elements may nest, as in HTML, so that the tree stays shallow
+/// enough to walk recursively.
+const MAX_ELEMENT_DEPTH: usize = 512;
+
+/// A well-formedness error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// The 1-based line and column where the error was found.
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Parse an XML document, returning the document node.
+///
+/// Namespace declarations are resolved, so XHTML elements are in the HTML
+/// namespace and style and lay out like parsed HTML. Besides character
+/// references, the predefined entities and those declared in the internal
+/// DTD subset are expanded; documents with an XHTML DOCTYPE can also use
+/// HTML's named references, such as `&nbsp;`.
+pub fn parse(input: &str) -> Result<Node, Error> {
+    let input = input.replace("\r\n", "\n").replace('\r', "\n");
+    let mut parser = Parser {
+        input: &input,
+        pos: 0,
+        namespaces: vec![],
+        entities: HashMap::new(),
+        html_entities: false,
+        open_entities: vec![],
+        expanded: 0,
+    };
+    parser.parse_document()
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    /// The namespace prefixes declared on each open element, innermost last.
+    /// The default namespace has the empty prefix.
+    namespaces: Vec<HashMap<String, Namespace>>,
+    /// General entities declared in the internal DTD subset.
+    entities: HashMap<String, String>,
+    /// Whether HTML's named character references can be used.
+    html_entities: bool,
+    /// The entities whose replacement text is being parsed, innermost last.
+    open_entities: Vec<String>,
+    /// The length of all the replacement text expanded so far.
+    expanded: usize,
+}
+
+/// A reference in content or an attribute value.
+enum Reference {
+    /// A character reference or a predefined entity, which stands for text.
+    Text(String),
+    /// A declared entity, whose replacement text has to be parsed.
+    Entity(String),
+}
+
+impl<'a> Parser<'a> {
+    fn parse_document(&mut self) -> Result<Node, Error> {
+        if self.starts_with("<?xml") && self.input[self.pos + 5..].starts_with(is_whitespace) {
+            self.parse_xml_declaration()?;
+        }
+
+        let mut children = vec![];
+        let mut has_root = false;
+        loop {
+            self.consume_whitespace();
+            if self.eof() { break }
+            if self.starts_with("<!--") {
+                children.push(self.parse_comment()?);
+            } else if self.starts_with("<?") {
+                children.push(self.parse_processing_instruction()?);
+            } else if self.starts_with("<!DOCTYPE") {
+                if has_root || children.iter().any(|c| matches!(c.node_type, NodeType::Doctype(_))) {
+                    return Err(self.error("A DOCTYPE must come before the root element"));
+                }
+                children.push(self.parse_doctype()?);
+            } else if self.starts_with("<") {
+                if has_root {
+                    return Err(self.error("A document can only have one root element"));
+                }
+                children.push(self.parse_element()?);
+                has_root = true;
+            } else {
+                return Err(self.error("Text isn't allowed outside the root element"));
+            }
+        }
+        if !has_root {
+            return Err(self.error("The document has no root element"));
+        }
+
+        Ok(Node { children, node_type: NodeType::Document(QuirksMode::NoQuirks) })
+    }
+
+    /// Parse `<?xml version="1.0" ...?>`. The encoding is ignored, since the
+    /// input is already text.
+    fn parse_xml_declaration(&mut self) -> Result<(), Error> {
+        self.pos += 5;
+        let end = self.find("?>", "Unterminated XML declaration")?;
+        let declaration = &self.input[self.pos..end];
+        if !declaration.trim_start().starts_with("version") {
+            return Err(self.error("The XML declaration must start with a version"));
+        }
+        self.pos = end + 2;
+        Ok(())
+    }
+
+    fn parse_doctype(&mut self) -> Result<Node, Error> {
+        self.pos += "<!DOCTYPE".len();
+        self.expect_whitespace()?;
+        let name = self.parse_name()?;
+        self.consume_whitespace();
+        let mut doctype = DoctypeData { name, public_id: String::new(), system_id: String::new() };
+        if self.starts_with("PUBLIC") {
+            self.pos += 6;
+            self.expect_whitespace()?;
+            doctype.public_id = self.parse_quoted()?;
+            self.expect_whitespace()?;
+            doctype.system_id = self.parse_quoted()?;
+        } else if self.starts_with("SYSTEM") {
+            self.pos += 6;
+            self.expect_whitespace()?;
+            doctype.system_id = self.parse_quoted()?;
+        }
+        self.consume_whitespace();
+        if self.starts_with("[") {
+            self.consume_char();
+            self.parse_internal_subset()?;
+            self.consume_whitespace();
+        }
+        self.expect(">")?;
+
+        self.html_entities = doctype.public_id.starts_with("-//W3C//DTD XHTML");
+        Ok(Node { children: vec![], node_type: NodeType::Doctype(doctype) })
+    }
+
+    /// Parse the internal DTD subset up to its closing `]`, keeping the
+    /// general entity declarations and skipping everything else.
+    fn parse_internal_subset(&mut self) -> Result<(), Error> {
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                return Err(self.error("Unterminated internal DTD subset"));
+            }
+            if self.starts_with("]") {
+                self.consume_char();
+                return Ok(());
+            }
+            if self.starts_with("<!--") {
+                self.parse_comment()?;
+            } else if self.starts_with("<?") {
+                self.parse_processing_instruction()?;
+            } else if self.starts_with("<!ENTITY") {
+                self.pos += "<!ENTITY".len();
+                self.expect_whitespace()?;
+                let parameter = self.starts_with("%");
+                if parameter {
+                    self.consume_char();
+                    self.expect_whitespace()?;
+                }
+                let name = self.parse_name()?;
+                self.expect_whitespace()?;
+                if self.starts_with("\"") || self.starts_with("'") {
+                    let value = self.parse_entity_value()?;
+                    // The first declaration of an entity is binding.
+                    if !parameter && !self.entities.contains_key(&name) {
+                        self.entities.insert(name, value);
+                    }
+                }
+                self.skip_declaration()?;
+            } else if self.starts_with("<!") || self.starts_with("%") {
+                self.skip_declaration()?;
+            } else {
+                return Err(self.error("Unexpected content in the internal DTD subset"));
+            }
+        }
+    }
+
+    /// Skip to the end of a markup declaration or parameter entity
+    /// reference, stepping over quoted strings.
+    fn skip_declaration(&mut self) -> Result<(), Error> {
+        while !self.eof() {
+            match self.consume_char() {
+                '>' | ';' => return Ok(()),
+                quote @ '"' | quote @ '\'' => {
+                    self.consume_while(|c| c != quote);
+                    self.consume_char();
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("Unterminated markup declaration"))
+    }
+
+    /// Parse the quoted replacement text of an entity declaration, expanding
+    /// character references. Entity references are left to be expanded
+    /// where the entity is used.
+    fn parse_entity_value(&mut self) -> Result<String, Error> {
+        let quote = self.consume_char();
+        let mut value = String::new();
+        loop {
+            if self.eof() {
+                return Err(self.error("Unterminated entity value"));
+            }
+            match self.next_char() {
+                c if c == quote => {
+                    self.consume_char();
+                    return Ok(value);
+                }
+                '&' if self.starts_with("&#") => match self.parse_reference()? {
+                    Reference::Text(text) => value.push_str(&text),
+                    Reference::Entity(_) => unreachable!(),
+                },
+                c => {
+                    self.check_char(c)?;
+                    self.consume_char();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<Node, Error> {
+        let (name, elem, empty) = self.parse_start_tag()?;
+        let children = if empty { vec![] } else { self.parse_content(Some(&name))? };
+        self.namespaces.pop();
+        Ok(Node { children, node_type: NodeType::Element(elem) })
+    }
+
+    /// Parse a start tag or empty-element tag, returning the element's
+    /// qualified name, the element and whether it is empty. The namespaces
+    /// it declares are pushed, to be popped when the element ends.
+    fn parse_start_tag(&mut self) -> Result<(String, ElementData, bool), Error> {
+        let start = self.pos;
+        // Entities share the open elements' namespaces, so this counts
+        // elements opened in replacement text too.
+        if self.namespaces.len() >= MAX_ELEMENT_DEPTH {
+            return Err(self.error("Elements are nested too deeply"));
+        }
+        self.expect("<")?;
+        let name = self.parse_name()?;
+        let mut attributes: Vec<(String, String)> = vec![];
+        let empty;
+        loop {
+            let had_whitespace = self.consume_whitespace();
+            if self.starts_with("/>") {
+                self.pos += 2;
+                empty = true;
+                break;
+            }
+            if self.starts_with(">") {
+                self.pos += 1;
+                empty = false;
+                break;
+            }
+            if self.eof() {
+                return Err(self.error(&format!("Unterminated start tag <{}>", name)));
+            }
+            if !had_whitespace {
+                return Err(self.error("Expected whitespace before an attribute"));
+            }
+            let attribute_start = self.pos;
+            let attribute_name = self.parse_name()?;
+            self.consume_whitespace();
+            self.expect("=")?;
+            self.consume_whitespace();
+            let value = self.parse_attribute_value()?;
+            if attributes.iter().any(|(name, _)| *name == attribute_name) {
+                return Err(self.error_at(attribute_start,
+                                         &format!("Duplicate attribute {}", attribute_name)));
+            }
+            attributes.push((attribute_name, value));
+        }
+
+        let mut scope = HashMap::new();
+        for (name, value) in &attributes {
+            if name == "xmlns" {
+                scope.insert(String::new(), Namespace::from_url(value));
+            } else if let Some(prefix) = name.strip_prefix("xmlns:") {
+                if value.is_empty() {
+                    return Err(self.error_at(start, &format!("Namespace prefix {} can't be undeclared", prefix)));
+                }
+                scope.insert(prefix.to_string(), Namespace::from_url(value));
+            }
+        }
+        self.namespaces.push(scope);
+
+        let (prefix, local_name) = self.split_qualified_name(&name, start)?;
+        let namespace = self.resolve_prefix(prefix.unwrap_or(""), start)?;
+        let mut elem = ElementData::new_ns(namespace, local_name.to_string(), AttrMap::new());
+        for (name, value) in attributes {
+            let (prefix, _) = self.split_qualified_name(&name, start)?;
+            if name == "xmlns" || prefix == Some("xmlns") {
+                elem.set_attribute_ns(Namespace::Xmlns, &name, &value);
+            } else if let Some(prefix) = prefix {
+                let namespace = self.resolve_prefix(prefix, start)?;
+                elem.set_attribute_ns(namespace, &name, &value);
+            } else {
+                elem.set_attribute(&name, &value);
+            }
+        }
+
+        Ok((name, elem, empty))
+    }
+
+    /// Parse the content of the element `name` up to and including its end
+    /// tag, or if `name` is `None`, the replacement text of an entity,
+    /// which has to end outside any element.
+    ///
+    /// Elements in the content are parsed here too, with a stack rather than
+    /// by recursion, so that deep nesting can't overflow the call stack.
+    fn parse_content(&mut self, name: Option<&str>) -> Result<Vec<Node>, Error> {
+        // The elements opened in the content, innermost last, each with the
+        // children of its parent so far.
+        let mut open: Vec<(String, ElementData, Vec<Node>)> = vec![];
+        let mut children = vec![];
+        let mut text = String::new();
+        loop {
+            if self.eof() {
+                return match open.last().map(|open| &*open.0).or(name) {
+                    Some(name) => Err(self.error(&format!("Unclosed element <{}>", name))),
+                    None => {
+                        if !text.is_empty() {
+                            children.push(node::text(text));
+                        }
+                        Ok(children)
+                    }
+                };
+            }
+            if self.starts_with("<") && !self.starts_with("<![CDATA[") && !text.is_empty() {
+                children.push(node::text(mem::take(&mut text)));
+            }
+            if self.starts_with("</") {
+                let start = self.pos;
+                self.pos += 2;
+                let end_name = self.parse_name()?;
+                self.consume_whitespace();
+                self.expect(">")?;
+                let name = match open.last().map(|open| &*open.0).or(name) {
+                    Some(name) => name,
+                    None => return Err(self.error_at(start, &format!("End tag </{}> outside any element", end_name))),
+                };
+                if end_name != name {
+                    return Err(self.error_at(start, &format!(
+                        "Mismatched end tag: expected </{}>, found </{}>", name, end_name)));
+                }
+                match open.pop() {
+                    Some((_, elem, siblings)) => {
+                        let elem_children = mem::replace(&mut children, siblings);
+                        self.namespaces.pop();
+                        children.push(Node { children: elem_children, node_type: NodeType::Element(elem) });
+                    }
+                    None => return Ok(children),
+                }
+            } else if self.starts_with("<!--") {
+                children.push(self.parse_comment()?);
+            } else if self.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let end = self.find("]]>", "Unterminated CDATA section")?;
+                self.check_chars(end)?;
+                text.push_str(&self.input[self.pos..end]);
+                self.pos = end + 3;
+            } else if self.starts_with("<?") {
+                children.push(self.parse_processing_instruction()?);
+            } else if self.starts_with("<!") {
+                return Err(self.error("Unexpected markup declaration"));
+            } else if self.starts_with("<") {
+                let (name, elem, empty) = self.parse_start_tag()?;
+                if empty {
+                    self.namespaces.pop();
+                    children.push(Node { children: vec![], node_type: NodeType::Element(elem) });
+                } else {
+                    open.push((name, elem, mem::take(&mut children)));
+                }
+            } else if self.starts_with("&") {
+                let start = self.pos;
+                match self.parse_reference()? {
+                    Reference::Text(replacement) => text.push_str(&replacement),
+                    Reference::Entity(entity) => {
+                        for child in self.expand_entity(&entity, start, |parser| parser.parse_content(None))? {
+                            match child.node_type {
+                                NodeType::Text(ref replacement) => text.push_str(replacement),
+                                _ => {
+                                    if !text.is_empty() {
+                                        children.push(node::text(mem::take(&mut text)));
+                                    }
+                                    children.push(child);
+                                }
+                            }
+                        }
+                    }
+                }
+            } else if self.starts_with("]]>") {
+                return Err(self.error("]]> isn't allowed in text"));
+            } else {
+                let c = self.next_char();
+                self.check_char(c)?;
+                text.push(self.consume_char());
+            }
+        }
+    }
+
+    /// Parse the replacement text of the entity `name`, referred to at
+    /// `start`, with `parse`. Errors in it are reported at the reference.
+    fn expand_entity<T, F>(&mut self, name: &str, start: usize, parse: F) -> Result<T, Error>
+            where F: FnOnce(&mut Parser) -> Result<T, Error> {
+        if self.open_entities.iter().any(|open| open == name) {
+            return Err(self.error_at(start, &format!("Entity &{}; refers to itself", name)));
+        }
+        if self.open_entities.len() >= MAX_ENTITY_DEPTH {
+            return Err(self.error_at(start, "Entity references are nested too deeply"));
+        }
+        let text = self.entities[name].clone();
+        self.expanded += text.len();
+        if self.expanded > MAX_EXPANSION {
+            return Err(self.error_at(start, "Entities expand to too much text"));
+        }
+
+        // The nested parser borrows the state, which is handed back after.
+        self.open_entities.push(name.to_string());
+        let mut parser = Parser {
+            input: &text,
+            pos: 0,
+            namespaces: mem::take(&mut self.namespaces),
+            entities: mem::take(&mut self.entities),
+            html_entities: self.html_entities,
+            open_entities: mem::take(&mut self.open_entities),
+            expanded: self.expanded,
+        };
+        let result = parse(&mut parser);
+        self.namespaces = parser.namespaces;
+        self.entities = parser.entities;
+        self.open_entities = parser.open_entities;
+        self.open_entities.pop();
+        self.expanded = parser.expanded;
+        result.map_err(|error| self.error_at(start, &format!("In entity &{};: {}", name, error.message)))
+    }
+
+    fn parse_attribute_value(&mut self) -> Result<String, Error> {
+        let quote = match self.next_char_or_eof() {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return Err(self.error("Attribute values must be quoted")),
+        };
+        self.consume_char();
+        let mut value = String::new();
+        loop {
+            match self.next_char_or_eof() {
+                None => return Err(self.error("Unterminated attribute value")),
+                Some(c) if c == quote => {
+                    self.consume_char();
+                    return Ok(value);
+                }
+                Some(_) => self.parse_attribute_char(&mut value)?,
+            }
+        }
+    }
+
+    /// Parse the replacement text of an entity referred to in an attribute
+    /// value.
+    fn parse_attribute_text(&mut self) -> Result<String, Error> {
+        let mut value = String::new();
+        while !self.eof() {
+            self.parse_attribute_char(&mut value)?;
+        }
+        Ok(value)
+    }
+
+    /// Parse a character or reference of an attribute value onto `value`.
+    fn parse_attribute_char(&mut self, value: &mut String) -> Result<(), Error> {
+        match self.next_char() {
+            '<' => return Err(self.error("< isn't allowed in attribute values")),
+            '&' => {
+                let start = self.pos;
+                match self.parse_reference()? {
+                    Reference::Text(text) => value.push_str(&text),
+                    Reference::Entity(entity) => {
+                        value.push_str(&self.expand_entity(&entity, start, |parser| parser.parse_attribute_text())?)
+                    }
+                }
+            }
+            // Attribute value normalization turns whitespace into spaces.
+            '\t' | '\n' => {
+                self.consume_char();
+                value.push(' ');
+            }
+            c => {
+                self.check_char(c)?;
+                self.consume_char();
+                value.push(c);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a character or entity reference.
+    fn parse_reference(&mut self) -> Result<Reference, Error> {
+        let start = self.pos;
+        self.expect("&")?;
+        let code = if self.starts_with("#x") {
+            self.pos += 2;
+            u32::from_str_radix(&self.consume_while(|c| c.is_ascii_hexdigit()), 16).ok()
+        } else if self.starts_with("#") {
+            self.pos += 1;
+            self.consume_while(|c| c.is_ascii_digit()).parse().ok()
+        } else {
+            let name = self.parse_name()?;
+            self.expect(";")?;
+            let value = match &*name {
+                "lt" => Some("<".to_string()),
+                "gt" => Some(">".to_string()),
+                "amp" => Some("&".to_string()),
+                "apos" => Some("'".to_string()),
+                "quot" => Some("\"".to_string()),
+                _ if self.entities.contains_key(&name) => return Ok(Reference::Entity(name)),
+                _ => self.html_entity(&name),
+            };
+            return value.map(Reference::Text)
+                .ok_or_else(|| self.error_at(start, &format!("Undefined entity &{};", name)));
+        };
+        self.expect(";")?;
+        match code.and_then(::std::char::from_u32) {
+            Some(c) if is_xml_char(c) => Ok(Reference::Text(c.to_string())),
+            _ => Err(self.error_at(start, "Invalid character reference")),
+        }
+    }
+
+    fn html_entity(&self, name: &str) -> Option<String> {
+        if !self.html_entities {
+            return None;
+        }
+        let name = format!("{};", name);
+        ENTITIES.binary_search_by(|&(entity, _)| entity.cmp(&name))
+            .ok()
+            .map(|i| ENTITIES[i].1.to_string())
+    }
+
+    fn parse_comment(&mut self) -> Result<Node, Error> {
+        self.pos += "<!--".len();
+        let end = self.find("--", "Unterminated comment")?;
+        self.check_chars(end)?;
+        let text = self.input[self.pos..end].to_string();
+        self.pos = end;
+        if !self.starts_with("-->") {
+            return Err(self.error("-- isn't allowed in comments"));
+        }
+        self.pos += 3;
+        Ok(node::comment(text))
+    }
+
+    fn parse_processing_instruction(&mut self) -> Result<Node, Error> {
+        let start = self.pos;
+        self.pos += 2;
+        let target = self.parse_name()?;
+        if target.eq_ignore_ascii_case("xml") {
+            return Err(self.error_at(start, "The XML declaration is only allowed at the start"));
+        }
+        let data = if self.starts_with("?>") {
+            String::new()
+        } else {
+            self.expect_whitespace()?;
+            let end = self.find("?>", "Unterminated processing instruction")?;
+            self.check_chars(end)?;
+            let data = self.input[self.pos..end].to_string();
+            self.pos = end;
+            data
+        };
+        self.pos += 2;
+        Ok(Node {
+            children: vec![],
+            node_type: NodeType::ProcessingInstruction(ProcessingInstructionData { target, data }),
+        })
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, Error> {
+        let quote = match self.next_char_or_eof() {
+            Some(c @ '"') | Some(c @ '\'') => c,
+            _ => return Err(self.error("Expected a quoted string")),
+        };
+        self.consume_char();
+        let value = self.consume_while(|c| c != quote);
+        if self.eof() {
+            return Err(self.error("Unterminated string"));
+        }
+        self.consume_char();
+        Ok(value)
+    }
+
+    fn parse_name(&mut self) -> Result<String, Error> {
+        match self.next_char_or_eof() {
+            Some(c) if is_name_start_char(c) => Ok(self.consume_while(is_name_char)),
+            _ => Err(self.error("Expected a name")),
+        }
+    }
+
+    /// Split a qualified name into its prefix and local name.
+    fn split_qualified_name<'b>(&self, name: &'b str, pos: usize)
+            -> Result<(Option<&'b str>, &'b str), Error> {
+        let mut parts = name.splitn(2, ':');
+        let first = parts.next().unwrap_or("");
+        match parts.next() {
+            None => Ok((None, first)),
+            Some(local) if !first.is_empty() && !local.is_empty() && !local.contains(':') => {
+                Ok((Some(first), local))
+            }
+            Some(_) => Err(self.error_at(pos, &format!("Invalid qualified name {}", name))),
+        }
+    }
+
+    /// The namespace a prefix is bound to. The empty prefix gives the
+    /// default namespace, or no namespace if there is none.
+    fn resolve_prefix(&self, prefix: &str, pos: usize) -> Result<Namespace, Error> {
+        match prefix {
+            "xml" => return Ok(Namespace::Xml),
+            "xmlns" => return Ok(Namespace::Xmlns),
+            _ => {}
+        }
+        let declared = self.namespaces.iter().rev().filter_map(|scope| scope.get(prefix)).next();
+        match declared {
+            Some(namespace) => Ok(namespace.clone()),
+            None if prefix.is_empty() => Ok(Namespace::Other(String::new())),
+            None => Err(self.error_at(pos, &format!("Undeclared namespace prefix {}", prefix))),
+        }
+    }
+
+    // Input handling
+
+    /// Check that a character of text is allowed in XML.
+    fn check_char(&self, c: char) -> Result<(), Error> {
+        if is_xml_char(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Invalid character U+{:04X}", c as u32)))
+        }
+    }
+
+    /// Check the characters from here up to `end`.
+    fn check_chars(&self, end: usize) -> Result<(), Error> {
+        match self.input[self.pos..end].char_indices().find(|&(_, c)| !is_xml_char(c)) {
+            Some((offset, c)) => Err(self.error_at(self.pos + offset, &format!("Invalid character U+{:04X}", c as u32))),
+            None => Ok(()),
+        }
+    }
+
+    fn find(&self, s: &str, message: &str) -> Result<usize, Error> {
+        match self.input[self.pos..].find(s) {
+            Some(offset) => Ok(self.pos + offset),
+            None => Err(self.error(message)),
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), Error> {
+        if self.starts_with(s) {
+            self.pos += s.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {}", s)))
+        }
+    }
+
+    fn expect_whitespace(&mut self) -> Result<(), Error> {
+        if self.consume_whitespace() {
+            Ok(())
+        } else {
+            Err(self.error("Expected whitespace"))
+        }
+    }
+
+    /// Skip whitespace, returning whether there was any.
+    fn consume_whitespace(&mut self) -> bool {
+        !self.consume_while(is_whitespace).is_empty()
+    }
+
+    fn consume_while<F>(&mut self, test: F) -> String
+            where F: Fn(char) -> bool {
+        let rest = &self.input[self.pos..];
+        let len = rest.find(|c| !test(c)).unwrap_or(rest.len());
+        self.pos += len;
+        rest[..len].to_string()
+    }
+
+    fn consume_char(&mut self) -> char {
+        let c = self.next_char();
+        self.pos += c.len_utf8();
+        c
+    }
+
+    fn next_char(&self) -> char {
+        self.input[self.pos..].chars().next().unwrap()
+    }
+
+    fn next_char_or_eof(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn error(&self, message: &str) -> Error {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, pos: usize, message: &str) -> Error {
+        let before = &self.input[..pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rfind('\n').map_or(before, |i| &before[i + 1..]).chars().count() + 1;
+        Error { line, column, message: message.to_string() }
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+fn is_name_start_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == ':' || c as u32 >= 0xC0
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) || c.is_ascii_digit() || c == '-' || c == '.' || c == '\u{B7}'
+}
+
+/// Whether a character is allowed in an XML document.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..='\u{10FFFF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use html::serialize::serialize;
+    use node::Node;
+    use super::{parse, MAX_ELEMENT_DEPTH};
+
+    fn markup(input: &str) -> Result<String, String> {
+        parse(input).map(|node| serialize(&node)).map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn entities_are_parsed_as_content() {
+        assert_eq!(markup("<!DOCTYPE r [<!ENTITY e '<b>x</b>'>]><r>a&e;c</r>"),
+                   Ok("<!DOCTYPE r><r>a<b>x</b>c</r>".to_string()));
+        assert_eq!(markup("<!DOCTYPE r [<!ENTITY f 'y'><!ENTITY e 'x&f;'>]><r a='&e;'>&e;</r>"),
+                   Ok("<!DOCTYPE r><r a=\"xy\">xy</r>".to_string()));
+        assert_eq!(markup("<!DOCTYPE r [<!ENTITY e '&#60;i/>'>]><r>&e;</r>"),
+                   Ok("<!DOCTYPE r><r><i></i></r>".to_string()));
+        assert_eq!(markup("<!DOCTYPE r [<!ENTITY e '<b>x'>]><r>&e;</b></r>"),
+                   Err("1:37: In entity &e;: Unclosed element <b>".to_string()));
+        assert_eq!(markup("<!DOCTYPE r [<!ENTITY e '<'>]><r a='&e;'/>"),
+                   Err("1:37: In entity &e;: < isn't allowed in attribute values".to_string()));
+    }
+
+    #[test]
+    fn entity_expansion_is_limited() {
+        assert_eq!(markup("<!DOCTYPE r [<!ENTITY e '&e;'>]><r>&e;</r>"),
+                   Err("1:36: In entity &e;: Entity &e; refers to itself".to_string()));
+        let mut dtd = "<!ENTITY a 'aaaaaaaaaa'>".to_string();
+        for (name, previous) in "bcdefg".chars().zip("abcdef".chars()) {
+            dtd += &format!("<!ENTITY {} '{}'>", name, format!("&{};", previous).repeat(10));
+        }
+        let error = markup(&format!("<!DOCTYPE r [{}]><r>&g;</r>", dtd)).unwrap_err();
+        assert!(error.ends_with("Entities expand to too much text"), "{}", error);
+    }
+
+    #[test]
+    fn characters_must_be_xml_characters() {
+        assert_eq!(markup("<a>\u{1}</a>"), Err("1:4: Invalid character U+0001".to_string()));
+        assert_eq!(markup("<a b='\u{1}'/>"), Err("1:7: Invalid character U+0001".to_string()));
+        assert_eq!(markup("<a><![CDATA[\u{FFFE}]]></a>").map_err(|_| ()), Err(()));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        fn depth(node: &Node) -> usize {
+            1 + node.children.iter().map(depth).max().unwrap_or(0)
+        }
+        let depth_limit = MAX_ELEMENT_DEPTH;
+        let nested = format!("{}{}", "<a>".repeat(depth_limit), "</a>".repeat(depth_limit));
+        assert_eq!(parse(&nested).map(|node| depth(&node)), Ok(depth_limit + 1));
+        let too_deep = format!("{}{}", "<a>".repeat(depth_limit + 1), "</a>".repeat(depth_limit + 1));
+        assert_eq!(markup(&too_deep), Err(format!("1:{}: Elements are nested too deeply", depth_limit * 3 + 1)));
+        let error = markup(&format!("{}{}", "<a>".repeat(100_000), "</a>".repeat(100_000))).unwrap_err();
+        assert!(error.ends_with("Elements are nested too deeply"), "{}", error);
+
+        // Elements in entities count too.
+        let entity = format!("<!DOCTYPE a [<!ENTITY e '{}'>]>", "<a>".repeat(10) + &"</a>".repeat(10));
+        let inner = depth_limit - 10;
+        let fits = format!("{}{}&e;{}", entity, "<a>".repeat(inner), "</a>".repeat(inner));
+        assert!(markup(&fits).is_ok());
+        let too_deep = format!("{}{}&e;{}", entity, "<a>".repeat(inner + 1), "</a>".repeat(inner + 1));
+        let error = markup(&too_deep).unwrap_err();
+        assert!(error.ends_with("In entity &e;: Elements are nested too deeply"), "{}", error);
+    }
+}